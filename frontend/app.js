@@ -1,21 +1,25 @@
-// API base URL
-const API_BASE = 'http://127.0.0.1:8080';
+// API base URL: the page's own origin, so the session cookie goes along with every call
+const API_BASE = window.location.origin;
+
+// The session cookie is HttpOnly; the CSRF cookie beside it is read here and echoed in a header
+function csrfToken() {
+    const match = document.cookie.match(/(?:^|;\s*)library_csrf=([^;]*)/);
+    return match ? match[1] : '';
+}
 
 // API call wrapper
 async function apiCall(endpoint, method = 'GET', body = null, requiresAuth = false) {
     const options = {
         method,
+        credentials: 'same-origin',
         headers: {
             'Content-Type': 'application/json',
         },
     };
 
-    // Add authorization token if required
-    if (requiresAuth) {
-        const token = localStorage.getItem('token');
-        if (token) {
-            options.headers['Authorization'] = `Bearer ${token}`;
-        }
+    // Changes made with the session cookie must carry the CSRF token
+    if (method !== 'GET') {
+        options.headers['X-CSRF-Token'] = csrfToken();
     }
 
     // Add body for POST/PUT requests
@@ -38,8 +42,7 @@ async function apiCall(endpoint, method = 'GET', body = null, requiresAuth = fal
 
         if (!response.ok) {
             // If unauthorized, redirect to login
-            if (response.status === 401) {
-                localStorage.removeItem('token');
+            if (requiresAuth && response.status === 401) {
                 localStorage.removeItem('user');
                 window.location.href = '/';
                 throw new Error('Session expired. Please login again.');
@@ -55,12 +58,32 @@ async function apiCall(endpoint, method = 'GET', body = null, requiresAuth = fal
     }
 }
 
-// Check if user is authenticated and has correct role
+// Download an admin listing as CSV using the session cookie
+async function downloadCsv(endpoint, filename) {
+    try {
+        const separator = endpoint.includes('?') ? '&' : '?';
+        const response = await fetch(`${API_BASE}${endpoint}${separator}format=csv`, {
+            credentials: 'same-origin',
+        });
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}: ${response.statusText}`);
+        }
+        const url = URL.createObjectURL(await response.blob());
+        const link = document.createElement('a');
+        link.href = url;
+        link.download = filename;
+        link.click();
+        URL.revokeObjectURL(url);
+    } catch (error) {
+        alert('Export failed: ' + error.message);
+    }
+}
+
+// Check if user is authenticated and has correct role (one role or a list of allowed roles)
 function checkAuth(requiredRole = null) {
-    const token = localStorage.getItem('token');
     const userStr = localStorage.getItem('user');
     
-    if (!token || !userStr) {
+    if (!userStr) {
         window.location.href = '/';
         return false;
     }
@@ -68,7 +91,7 @@ function checkAuth(requiredRole = null) {
     try {
         const user = JSON.parse(userStr);
         
-        if (requiredRole && user.role !== requiredRole) {
+        if (requiredRole && ![].concat(requiredRole).includes(user.role)) {
             alert('Access denied. Insufficient permissions.');
             handleLogout();
             return false;
@@ -76,7 +99,6 @@ function checkAuth(requiredRole = null) {
         
         return true;
     } catch (e) {
-        localStorage.removeItem('token');
         localStorage.removeItem('user');
         window.location.href = '/';
         return false;
@@ -85,17 +107,12 @@ function checkAuth(requiredRole = null) {
 
 // Logout function
 async function handleLogout() {
-    const token = localStorage.getItem('token');
-    
-    if (token) {
-        try {
-            await apiCall('/api/auth/logout', 'POST', {}, true);
-        } catch (error) {
-            console.error('Logout error:', error);
-        }
+    try {
+        await apiCall('/api/auth/logout', 'POST', {}, true);
+    } catch (error) {
+        console.error('Logout error:', error);
     }
     
-    localStorage.removeItem('token');
     localStorage.removeItem('user');
     window.location.href = '/';
 }
@@ -135,3 +152,43 @@ function daysBetween(date1, date2) {
 function isOverdue(dueDate) {
     return new Date(dueDate) < new Date();
 }
+
+// Show active announcements as banners at the top of the page
+async function loadAnnouncements() {
+    try {
+        const announcements = await apiCall('/api/announcements/active', 'GET');
+        if (announcements.length === 0) return;
+
+        const colors = {
+            info: 'bg-blue-100 border-blue-500 text-blue-800',
+            warning: 'bg-yellow-100 border-yellow-500 text-yellow-800',
+            closure: 'bg-red-100 border-red-500 text-red-800',
+        };
+
+        const container = document.createElement('div');
+        container.className = 'container mx-auto px-6 pt-6 space-y-2';
+        announcements.forEach(a => {
+            const banner = document.createElement('div');
+            banner.className = `border-l-4 p-4 rounded ${colors[a.level] || colors.info}`;
+            const title = document.createElement('p');
+            title.className = 'font-bold';
+            title.textContent = a.title;
+            const message = document.createElement('p');
+            message.textContent = a.message;
+            banner.append(title, message);
+            container.appendChild(banner);
+        });
+        document.body.prepend(container);
+    } catch (error) {
+        console.error('Failed to load announcements:', error);
+    }
+}
+
+// Type line for anything that isn't a plain book, e.g. "Media (Blu-ray)" or "Periodical, issue 42"
+function itemTypeLine(book) {
+    if (!book.item_type || book.item_type === 'book') return '';
+    const label = book.item_type.charAt(0).toUpperCase() + book.item_type.slice(1);
+    const detail = book.issue_number ? `, issue ${escapeHtml(book.issue_number)}` :
+        book.platform ? ` (${escapeHtml(book.platform)})` : '';
+    return `<p class="text-sm text-gray-500">${label}${detail}</p>`;
+}
@@ -0,0 +1,14 @@
+// Service worker for Web Push: shows the library's alerts (holds ready, loans due soon) even
+// when no library page is open
+self.addEventListener('push', event => {
+    const data = event.data ? event.data.json() : {};
+    event.waitUntil(self.registration.showNotification(data.title || 'Library', {
+        body: data.body || '',
+        data: { url: data.url || '/lender.html' },
+    }));
+});
+
+self.addEventListener('notificationclick', event => {
+    event.notification.close();
+    event.waitUntil(clients.openWindow(event.notification.data.url));
+});
@@ -4,7 +4,25 @@ CREATE TABLE IF NOT EXISTS users (
     username TEXT UNIQUE NOT NULL,
     email TEXT UNIQUE NOT NULL,
     password_hash TEXT NOT NULL,
-    role TEXT NOT NULL CHECK(role IN ('admin', 'lender')),
+    role TEXT NOT NULL CHECK(role IN ('admin', 'reports', 'lender')),
+    display_name TEXT,
+    email_verified INTEGER NOT NULL DEFAULT 0,
+    is_active INTEGER NOT NULL DEFAULT 1,
+    anonymized_at TIMESTAMP,
+    card_number TEXT UNIQUE,
+    member_type TEXT NOT NULL DEFAULT 'community' REFERENCES member_types(name),
+    membership_expires_at TIMESTAMP,
+    membership_warning_sent_at TIMESTAMP,
+    suspended_at TIMESTAMP,
+    suspension_reason TEXT,
+    suspension_lifted_at TIMESTAMP,
+    calendar_feed_version INTEGER NOT NULL DEFAULT 0,
+    home_branch_id INTEGER REFERENCES branches(id),
+    language TEXT,
+    totp_secret TEXT,
+    totp_enabled_at TIMESTAMP,
+    totp_last_step INTEGER,
+    approval_status TEXT NOT NULL DEFAULT 'approved',
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
 );
 
@@ -18,22 +36,317 @@ CREATE TABLE IF NOT EXISTS books (
     genre TEXT,
     total_copies INTEGER NOT NULL,
     available_copies INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    on_order INTEGER NOT NULL DEFAULT 0,
+    item_type TEXT NOT NULL DEFAULT 'book' REFERENCES item_types(name),
+    issue_number TEXT,
+    platform TEXT,
+    loan_period_days INTEGER,
+    loanable INTEGER NOT NULL DEFAULT 1,
+    series_id INTEGER REFERENCES series(id),
+    series_position INTEGER,
+    version INTEGER NOT NULL DEFAULT 1
+);
+
+-- Series table
+CREATE TABLE IF NOT EXISTS series (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    description TEXT,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
 );
 
--- Lending records table
-CREATE TABLE IF NOT EXISTS lending_records (
+-- Wishlist table (titles members saved to read later)
+CREATE TABLE IF NOT EXISTS wishlist (
+    user_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    notify INTEGER NOT NULL DEFAULT 0,
+    notified_at TIMESTAMP,
+    added_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, book_id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+-- Book watches table (one-time availability notices, no copy held)
+CREATE TABLE IF NOT EXISTS book_watches (
+    user_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, book_id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+-- Favorites table
+CREATE TABLE IF NOT EXISTS favorites (
+    user_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    added_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, book_id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+-- Author follows table (members told about new books by these authors)
+CREATE TABLE IF NOT EXISTS author_follows (
+    user_id INTEGER NOT NULL,
+    author TEXT NOT NULL COLLATE NOCASE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, author),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Reading lists table
+CREATE TABLE IF NOT EXISTS reading_lists (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Reading list entries table
+CREATE TABLE IF NOT EXISTS reading_list_entries (
+    list_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (list_id, book_id),
+    FOREIGN KEY (list_id) REFERENCES reading_lists(id),
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+-- Periodical issues table (links each issue's catalog entry to its periodical title)
+CREATE TABLE IF NOT EXISTS periodical_issues (
+    book_id INTEGER PRIMARY KEY,
+    title_id INTEGER NOT NULL,
+    volume TEXT,
+    issue_date DATE NOT NULL,
+    received_at TIMESTAMP,
+    FOREIGN KEY (book_id) REFERENCES books(id),
+    FOREIGN KEY (title_id) REFERENCES books(id)
+);
+
+-- Book tags table
+CREATE TABLE IF NOT EXISTS book_tags (
+    book_id INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (book_id, tag),
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+-- Branches table
+CREATE TABLE IF NOT EXISTS branches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    code TEXT UNIQUE NOT NULL,
+    address TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT OR IGNORE INTO branches (id, name, code) VALUES (1, 'Main Library', 'MAIN');
+
+-- Book copies table (how many of a book's copies each branch holds)
+CREATE TABLE IF NOT EXISTS book_copies (
+    book_id INTEGER NOT NULL,
+    branch_id INTEGER NOT NULL,
+    copies INTEGER NOT NULL,
+    location_id INTEGER REFERENCES locations(id),
+    PRIMARY KEY (book_id, branch_id),
+    FOREIGN KEY (book_id) REFERENCES books(id),
+    FOREIGN KEY (branch_id) REFERENCES branches(id)
+);
+
+-- Locations table (shelves within a branch)
+CREATE TABLE IF NOT EXISTS locations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    branch_id INTEGER NOT NULL,
+    room TEXT NOT NULL,
+    shelf TEXT NOT NULL,
+    call_number_prefix TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (branch_id, room, shelf),
+    FOREIGN KEY (branch_id) REFERENCES branches(id)
+);
+
+-- Transfers table (copies sent between branches at a member's request)
+CREATE TABLE IF NOT EXISTS transfers (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
     user_id INTEGER NOT NULL,
+    from_branch_id INTEGER NOT NULL,
+    to_branch_id INTEGER NOT NULL,
+    status TEXT NOT NULL CHECK(status IN ('requested', 'in_transit', 'arrived', 'cancelled')),
+    handled_by INTEGER,
+    created_at TIMESTAMP NOT NULL,
+    shipped_at TIMESTAMP,
+    arrived_at TIMESTAMP,
+    cancelled_at TIMESTAMP,
+    FOREIGN KEY (book_id) REFERENCES books(id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (from_branch_id) REFERENCES branches(id),
+    FOREIGN KEY (to_branch_id) REFERENCES branches(id),
+    FOREIGN KEY (handled_by) REFERENCES users(id)
+);
+
+-- Lending records table (user_id is NULL once a loan is anonymized by the retention purge or privacy mode)
+CREATE TABLE IF NOT EXISTS lending_records (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER,
     book_id INTEGER NOT NULL,
     borrowed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     due_date TIMESTAMP NOT NULL,
     returned_at TIMESTAMP,
     status TEXT NOT NULL CHECK(status IN ('borrowed', 'returned', 'overdue')),
+    reminder_sent_at TIMESTAMP,
+    overdue_notice_sent_at TIMESTAMP,
+    escalation_level TEXT,
+    escalated_at TIMESTAMP,
+    recalled_at TIMESTAMP,
+    original_due_date TIMESTAMP,
+    branch_id INTEGER NOT NULL DEFAULT 1 REFERENCES branches(id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+-- Lending notes table (staff remarks on a loan)
+CREATE TABLE IF NOT EXISTS lending_notes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    lending_record_id INTEGER NOT NULL,
+    author_id INTEGER NOT NULL,
+    note TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (lending_record_id) REFERENCES lending_records(id),
+    FOREIGN KEY (author_id) REFERENCES users(id)
+);
+
+-- Extension requests table (borrower asks for more time, a librarian decides)
+CREATE TABLE IF NOT EXISTS extension_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    lending_record_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    requested_days INTEGER NOT NULL,
+    status TEXT NOT NULL CHECK(status IN ('pending', 'approved', 'denied')),
+    decision_note TEXT,
+    decided_by INTEGER,
+    decided_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (lending_record_id) REFERENCES lending_records(id),
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (decided_by) REFERENCES users(id)
+);
+
+-- Suggestions table (member asks the library to buy a book, a librarian decides)
+CREATE TABLE IF NOT EXISTS suggestions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    author TEXT NOT NULL,
+    isbn TEXT,
+    reason TEXT,
+    status TEXT NOT NULL CHECK(status IN ('pending', 'approved', 'declined')),
+    decision_note TEXT,
+    decided_by INTEGER,
+    decided_at TIMESTAMP,
+    book_id INTEGER,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (decided_by) REFERENCES users(id),
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+-- Donations table (member offers books, a librarian accepts or declines)
+CREATE TABLE IF NOT EXISTS donations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    author TEXT NOT NULL,
+    isbn TEXT NOT NULL,
+    publication_year INTEGER,
+    genre TEXT,
+    copies INTEGER NOT NULL,
+    notes TEXT,
+    status TEXT NOT NULL CHECK(status IN ('pending', 'accepted', 'declined')),
+    decision_note TEXT,
+    decided_by INTEGER,
+    decided_at TIMESTAMP,
+    book_id INTEGER,
+    created_at TIMESTAMP NOT NULL,
     FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (decided_by) REFERENCES users(id),
     FOREIGN KEY (book_id) REFERENCES books(id)
 );
 
+-- Acquisitions table (copies added to the collection, crediting donors)
+CREATE TABLE IF NOT EXISTS acquisitions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    copies INTEGER NOT NULL,
+    source TEXT NOT NULL CHECK(source IN ('donation')),
+    donor_id INTEGER,
+    donation_id INTEGER,
+    recorded_by INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (book_id) REFERENCES books(id),
+    FOREIGN KEY (donor_id) REFERENCES users(id),
+    FOREIGN KEY (donation_id) REFERENCES donations(id),
+    FOREIGN KEY (recorded_by) REFERENCES users(id)
+);
+
+-- Reservations table (queue for books with no available copies)
+CREATE TABLE IF NOT EXISTS reservations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    status TEXT NOT NULL CHECK(status IN ('waiting', 'ready', 'fulfilled', 'expired', 'cancelled')),
+    created_at TIMESTAMP NOT NULL,
+    ready_at TIMESTAMP,
+    expires_at TIMESTAMP,
+    fulfilled_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (book_id) REFERENCES books(id)
+);
+
+-- Fines table (assessed on late return)
+CREATE TABLE IF NOT EXISTS fines (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    lending_record_id INTEGER NOT NULL,
+    amount REAL NOT NULL,
+    amount_paid REAL NOT NULL DEFAULT 0,
+    status TEXT NOT NULL CHECK(status IN ('unpaid', 'paid')),
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    paid_at TIMESTAMP,
+    accruing INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (lending_record_id) REFERENCES lending_records(id)
+);
+
+-- Fine payments table
+CREATE TABLE IF NOT EXISTS fine_payments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    fine_id INTEGER NOT NULL,
+    amount REAL NOT NULL,
+    method TEXT NOT NULL CHECK(method IN ('cash', 'card', 'other')),
+    note TEXT,
+    recorded_by INTEGER NOT NULL,
+    paid_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (fine_id) REFERENCES fines(id),
+    FOREIGN KEY (recorded_by) REFERENCES users(id)
+);
+
+-- Closures table (days the library is shut; dates are YYYY-MM-DD, inclusive)
+CREATE TABLE IF NOT EXISTS closures (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    starts_on DATE NOT NULL,
+    ends_on DATE NOT NULL,
+    reason TEXT NOT NULL,
+    created_by INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
 -- Sessions table
 CREATE TABLE IF NOT EXISTS sessions (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -41,6 +354,298 @@ CREATE TABLE IF NOT EXISTS sessions (
     token TEXT UNIQUE NOT NULL,
     expires_at TIMESTAMP NOT NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    impersonator_id INTEGER,
+    max_expires_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    user_agent TEXT,
+    ip_address TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (impersonator_id) REFERENCES users(id)
+);
+
+-- Idempotency keys table (stored responses for retried borrows and returns)
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    user_id INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    request TEXT NOT NULL,
+    response TEXT,
+    created_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, key),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Recovery codes table (single-use two-factor backup codes, stored hashed)
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Login challenges table (pending two-factor sign-ins)
+CREATE TABLE IF NOT EXISTS login_challenges (
+    token TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE IF NOT EXISTS login_devices (
+    user_id INTEGER NOT NULL,
+    user_agent TEXT NOT NULL,
+    network TEXT NOT NULL,
+    first_seen_at TIMESTAMP NOT NULL,
+    last_seen_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, user_agent, network),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE IF NOT EXISTS email_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    new_email TEXT NOT NULL,
+    token TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    confirmed_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE IF NOT EXISTS login_failures (
+    key TEXT PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    last_failure_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS invites (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    code_hash TEXT UNIQUE NOT NULL,
+    role TEXT NOT NULL,
+    username TEXT,
+    email TEXT,
+    member_type TEXT,
+    card_number TEXT,
+    created_by INTEGER,
+    created_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    used_by INTEGER,
+    FOREIGN KEY (created_by) REFERENCES users(id),
+    FOREIGN KEY (used_by) REFERENCES users(id)
+);
+
+CREATE TABLE IF NOT EXISTS oauth_identities (
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    email TEXT,
+    created_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (provider, subject),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE IF NOT EXISTS oauth_states (
+    state TEXT PRIMARY KEY,
+    provider TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+);
+
+-- API keys table (long-lived scoped tokens; only the hash of the key is kept)
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    key_hash TEXT UNIQUE NOT NULL,
+    prefix TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Access log table (API calls, written only when LIBRARY_ACCESS_LOG is enabled)
+CREATE TABLE IF NOT EXISTS access_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    user_id INTEGER,
+    ip TEXT,
+    latency_ms INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_access_log_created_at ON access_log(created_at);
+
+-- Email verifications table
+CREATE TABLE IF NOT EXISTS email_verifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    email TEXT NOT NULL,
+    token TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Notifications table (outgoing message log)
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER,
+    channel TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    digest_status TEXT,                 -- NULL sent immediately, 'pending' held for the digest, 'sent' included in one
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Append-only feed of circulation and catalog events, polled through /api/admin/events
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_type TEXT NOT NULL,
+    data TEXT NOT NULL,                 -- JSON payload
+    created_at TIMESTAMP NOT NULL
+);
+
+-- Slack/Discord incoming webhooks that receive staff alerts
+CREATE TABLE IF NOT EXISTS webhooks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    service TEXT NOT NULL,              -- 'slack' or 'discord'
+    url TEXT NOT NULL,
+    events TEXT NOT NULL,               -- space-separated event keys
+    enabled INTEGER NOT NULL DEFAULT 1,
+    created_by INTEGER,
+    created_at TIMESTAMP NOT NULL,
+    last_delivery_at TIMESTAMP,
+    last_error TEXT,
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+-- Browsers subscribed to Web Push alerts
+CREATE TABLE IF NOT EXISTS push_subscriptions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    endpoint TEXT UNIQUE NOT NULL,
+    p256dh TEXT NOT NULL,
+    auth TEXT NOT NULL,
+    user_agent TEXT,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Per-member delivery choice for notices
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id INTEGER PRIMARY KEY,
+    digest INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Audit log table
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    actor_id INTEGER,
+    action TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    target_id INTEGER,
+    details TEXT,
+    ip TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (actor_id) REFERENCES users(id)
+);
+
+-- Member types table (per-class overrides; NULL inherits the global setting)
+CREATE TABLE IF NOT EXISTS member_types (
+    name TEXT PRIMARY KEY,
+    loan_period_days INTEGER,
+    max_concurrent_loans INTEGER,
+    fine_per_day REAL
+);
+
+INSERT OR IGNORE INTO member_types (name, loan_period_days, max_concurrent_loans, fine_per_day) VALUES
+    ('community', NULL, NULL, NULL),
+    ('student', NULL, 5, NULL),
+    ('faculty', 28, 25, 0);
+
+-- Item types table (kinds of catalog item; a NULL loan period falls back to the member's)
+CREATE TABLE IF NOT EXISTS item_types (
+    name TEXT PRIMARY KEY,
+    loan_period_days INTEGER
+);
+
+INSERT OR IGNORE INTO item_types (name, loan_period_days) VALUES
+    ('book', NULL),
+    ('periodical', 7),
+    ('media', 7),
+    ('equipment', 3);
+
+-- Settings table (runtime-tunable policies)
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Email templates edited by the library; built-in wording applies to the rest
+CREATE TABLE IF NOT EXISTS email_templates (
+    key TEXT PRIMARY KEY,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    updated_by INTEGER,
+    updated_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (updated_by) REFERENCES users(id)
+);
+
+-- Secrets table (server-generated keys)
+CREATE TABLE IF NOT EXISTS secrets (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Announcements table (banners shown between starts_at and ends_at)
+CREATE TABLE IF NOT EXISTS announcements (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    message TEXT NOT NULL,
+    level TEXT NOT NULL CHECK(level IN ('info', 'warning', 'closure')),
+    starts_at TIMESTAMP NOT NULL,
+    ends_at TIMESTAMP,
+    created_by INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+-- Broadcasts table (admin messages to a group of members, delivered in batches)
+CREATE TABLE IF NOT EXISTS broadcasts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    audience TEXT NOT NULL CHECK(audience IN ('all', 'overdue', 'holds_ready')),
+    status TEXT NOT NULL DEFAULT 'queued' CHECK(status IN ('queued', 'sending', 'completed')),
+    total INTEGER NOT NULL DEFAULT 0,
+    created_by INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    started_at TIMESTAMP,
+    completed_at TIMESTAMP,
+    FOREIGN KEY (created_by) REFERENCES users(id)
+);
+
+-- Broadcast recipients table (one row per member, snapshotted when the broadcast is queued)
+CREATE TABLE IF NOT EXISTS broadcast_recipients (
+    broadcast_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'sent', 'failed', 'skipped')),
+    sent_at TIMESTAMP,
+    PRIMARY KEY (broadcast_id, user_id),
+    FOREIGN KEY (broadcast_id) REFERENCES broadcasts(id),
     FOREIGN KEY (user_id) REFERENCES users(id)
 );
 
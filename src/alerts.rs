@@ -0,0 +1,82 @@
+use serde_json::json;
+use sqlx::SqlitePool;
+
+use crate::db;
+use crate::http;
+use crate::models::Webhook;
+
+// Staff alerts posted to Slack or Discord incoming webhooks. Each webhook picks which of these
+// events it receives
+pub struct Event {
+    pub key: &'static str,
+    pub description: &'static str,
+}
+
+pub const EVENTS: &[Event] = &[
+    Event {
+        key: "overdue_spike",
+        description: "At least overdue_spike_threshold loans became overdue in a single hourly sweep",
+    },
+    Event {
+        key: "job_failed",
+        description: "A background job such as the scheduled backup failed after previously succeeding",
+    },
+    Event {
+        key: "suggestion_created",
+        description: "A member suggested a book for the library to buy",
+    },
+];
+
+pub const SERVICES: &[&str] = &["slack", "discord"];
+
+pub fn is_event(key: &str) -> bool {
+    EVENTS.iter().any(|e| e.key == key)
+}
+
+// Slack reads `text` and bolds with *..*; Discord reads `content` and bolds with **..**
+fn payload(service: &str, title: &str, text: &str) -> String {
+    match service {
+        "discord" => json!({ "content": format!("**{}**\n{}", title, text) }),
+        _ => json!({ "text": format!("*{}*\n{}", title, text) }),
+    }
+    .to_string()
+}
+
+// Posts to every enabled webhook that wants `event`. Delivery happens in the background and
+// failures are only recorded on the webhook, so raising an alert never fails the caller
+pub async fn raise(pool: &SqlitePool, event: &str, title: &str, text: &str) {
+    let webhooks = match db::get_webhooks_for_event(pool, event).await {
+        Ok(webhooks) => webhooks,
+        Err(e) => {
+            eprintln!("Failed to load webhooks for {}: {:?}", event, e);
+            return;
+        }
+    };
+    for webhook in webhooks {
+        let pool = pool.clone();
+        let (title, text) = (title.to_string(), text.to_string());
+        tokio::spawn(async move {
+            if let Err(e) = deliver(&pool, &webhook, &title, &text).await {
+                eprintln!("Failed to post {} alert to webhook {}: {}", webhook.service, webhook.id, e);
+            }
+        });
+    }
+}
+
+pub async fn deliver(pool: &SqlitePool, webhook: &Webhook, title: &str, text: &str) -> Result<(), String> {
+    let url = webhook.url.clone();
+    let body = payload(&webhook.service, title, text);
+    let result = tokio::task::spawn_blocking(move || http::post(&url, &[("Content-Type", "application/json")], body.as_bytes()))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|posted| posted)
+        .and_then(|status| match status {
+            200..=299 => Ok(()),
+            _ => Err(format!("Webhook responded with status {}", status)),
+        });
+
+    if let Err(e) = db::record_webhook_delivery(pool, webhook.id, result.as_ref().err().map(String::as_str)).await {
+        eprintln!("Failed to record webhook delivery: {:?}", e);
+    }
+    result
+}
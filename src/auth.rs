@@ -1,14 +1,226 @@
-use bcrypt::{hash, verify, DEFAULT_COST};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use uuid::Uuid;
 
-pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
-    hash(password, DEFAULT_COST)
+use crate::config;
+
+// Passwords are hashed with Argon2id using the configured cost. Hashes from before the
+// switch are bcrypt; they still verify and are replaced at the user's next login
+fn argon2() -> Result<Argon2<'static>, argon2::password_hash::Error> {
+    let config = config::get();
+    let params = Params::new(config.argon2_memory_kib, config.argon2_iterations, config.argon2_parallelism, None)?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes())?;
+    Ok(argon2()?.hash_password(password.as_bytes(), &salt)?.to_string())
 }
 
-pub fn verify_password(password: &str, hash: &str) -> Result<bool, bcrypt::BcryptError> {
-    verify(password, hash)
+pub fn verify_password(password: &str, hash: &str) -> Result<bool, String> {
+    if hash.starts_with("$2") {
+        return bcrypt::verify(password, hash).map_err(|e| e.to_string());
+    }
+    let parsed = PasswordHash::new(hash).map_err(|e| e.to_string())?;
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// True for bcrypt hashes and for Argon2 hashes made with a different cost than configured now
+pub fn needs_rehash(hash: &str) -> bool {
+    let Ok(parsed) = PasswordHash::new(hash) else { return true };
+    let config = config::get();
+    let param = |name: &str| parsed.params.get_decimal(name);
+    parsed.algorithm != Algorithm::Argon2id.ident()
+        || param("m") != Some(config.argon2_memory_kib)
+        || param("t") != Some(config.argon2_iterations)
+        || param("p") != Some(config.argon2_parallelism)
 }
 
 pub fn generate_token() -> String {
     Uuid::new_v4().to_string()
 }
+
+// API keys carry a recognizable prefix so they can be told apart from session tokens.
+// Only their SHA-256 hash is stored
+const API_KEY_PREFIX: &str = "lbk_";
+
+pub fn generate_api_key() -> String {
+    format!("{}{}{}", API_KEY_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+pub fn hash_api_key(key: &str) -> String {
+    sha256_hex(key)
+}
+
+// Comparison whose running time doesn't reveal how much of a secret matched
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn sha256_hex(value: &str) -> String {
+    use sha2::Digest;
+    Sha256::digest(value.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Time-based one-time passwords (RFC 6238) as authenticator apps expect them:
+// HMAC-SHA1, six digits, 30-second steps, secret shared as base32
+const TOTP_STEP_SECONDS: i64 = 30;
+const TOTP_ISSUER: &str = "Library";
+
+pub fn generate_totp_secret() -> String {
+    let random = [Uuid::new_v4().into_bytes(), Uuid::new_v4().into_bytes()].concat();
+    base32_encode(&random[..20])
+}
+
+pub fn totp_uri(secret: &str, account: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits=6&period={}",
+        urlencoding::encode(TOTP_ISSUER),
+        urlencoding::encode(account),
+        secret,
+        urlencoding::encode(TOTP_ISSUER),
+        TOTP_STEP_SECONDS
+    )
+}
+
+// Returns the time step the code belongs to. One step either side of now is accepted to
+// allow for clock drift, but never a step at or before `last_step`, so a code works once
+pub fn verify_totp(secret: &str, code: &str, unix_time: i64, last_step: Option<i64>) -> Option<i64> {
+    let key = base32_decode(secret)?;
+    let code: u32 = code.trim().parse().ok().filter(|_| code.trim().len() == 6)?;
+    let now = unix_time / TOTP_STEP_SECONDS;
+    (now - 1..=now + 1)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| totp_at(&key, *step as u64) == code)
+}
+
+fn totp_at(key: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[19] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    value % 1_000_000
+}
+
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            out.push(BASE32_ALPHABET[((bits >> (35 - i * 5)) & 0x1f) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bits = 0u64;
+    let mut count = 0;
+    let mut out = Vec::new();
+    for c in text.trim_end_matches('=').chars().filter(|c| !c.is_whitespace()) {
+        let value = BASE32_ALPHABET.iter().position(|a| *a as char == c.to_ascii_uppercase())? as u64;
+        bits = (bits << 5) | value;
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+// Single-use codes for signing in without the authenticator, shown once as "xxxxx-xxxxx"
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let hex = Uuid::new_v4().simple().to_string();
+            format!("{}-{}", &hex[..5], &hex[5..10])
+        })
+        .collect()
+}
+
+// Recovery codes are random, so a fast hash is enough; dashes and case are ignored
+pub fn hash_recovery_code(code: &str) -> String {
+    sha256_hex(&code.trim().to_lowercase().replace('-', ""))
+}
+
+// Invite codes are handed out by admins for invitation-only registration
+pub fn generate_invite_code() -> String {
+    let hex = Uuid::new_v4().simple().to_string();
+    format!("{}-{}-{}-{}", &hex[..4], &hex[4..8], &hex[8..12], &hex[12..16])
+}
+
+// Given to members created by a bulk import, for their first sign-in
+pub fn generate_temporary_password() -> String {
+    let hex = Uuid::new_v4().simple().to_string();
+    format!("{}-{}-{}-{}", &hex[..4], &hex[4..8], &hex[8..12], &hex[12..16])
+}
+
+pub fn hash_invite_code(code: &str) -> String {
+    sha256_hex(&code.trim().to_lowercase().replace('-', ""))
+}
+
+// Calendar feed tokens are "<user_id>.<version>.<hmac>"; bumping the user's feed version
+// revokes every URL issued before it, without touching login sessions
+pub fn sign_feed_token(secret: &str, user_id: i64, version: i64) -> String {
+    let payload = format!("{}.{}", user_id, version);
+    let signature: String = feed_mac(secret, &payload)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{}.{}", payload, signature)
+}
+
+// Returns the (user_id, version) a valid token was issued for
+pub fn verify_feed_token(secret: &str, token: &str) -> Option<(i64, i64)> {
+    let (payload, signature) = token.rsplit_once('.')?;
+    let (user_id, version) = payload.split_once('.')?;
+    let bytes = (0..signature.len())
+        .step_by(2)
+        .map(|i| signature.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    feed_mac(secret, payload).verify_slice(&bytes).ok()?;
+    Some((user_id.parse().ok()?, version.parse().ok()?))
+}
+
+fn feed_mac(secret: &str, payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+// 14-digit library card number: fixed prefix, 12 random digits, Luhn check digit
+pub fn generate_card_number() -> String {
+    let random = Uuid::new_v4().as_u128();
+    let digits = format!("2{:012}", random % 1_000_000_000_000);
+    format!("{}{}", digits, luhn_check_digit(&digits))
+}
+
+fn luhn_check_digit(digits: &str) -> u32 {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { let d = d * 2; if d > 9 { d - 9 } else { d } } else { d })
+        .sum();
+    (10 - sum % 10) % 10
+}
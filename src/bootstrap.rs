@@ -0,0 +1,74 @@
+use std::sync::Mutex;
+
+use sqlx::SqlitePool;
+
+use crate::{auth, config, db, password};
+
+// The first admin account. A fresh database has none; it is created at startup from
+// LIBRARY_ADMIN_USERNAME and LIBRARY_ADMIN_PASSWORD, or else through POST /api/setup with a
+// one-time token printed to the log. Either way the password has to pass the password policy,
+// so there is no built-in default to forget to change
+
+// bcrypt hash of "123", the password of the `admin` account older versions created on every
+// database; still being able to sign in with it is worth a warning at every start
+const LEGACY_DEFAULT_HASH: &str = "$2a$12$rfyRaXCM.mNJgnV6t9pOI.EPDV5UhgezjOirtlqBDD2lIyR5BhWIG";
+
+// Only set while the database has no admin, and only for this run of the server
+static SETUP_TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+pub async fn run(pool: &SqlitePool) -> Result<(), String> {
+    let failed = |e: sqlx::Error| format!("Failed to check for an admin account: {}", e);
+    if db::count_active_admins(pool).await.map_err(failed)? > 0 {
+        if db::password_hash_in_use(pool, LEGACY_DEFAULT_HASH).await.map_err(failed)? {
+            eprintln!("Warning: an admin account still has the old default password \"123\"; change it now");
+        }
+        return Ok(());
+    }
+
+    let config = config::get();
+    match (&config.admin_username, &config.admin_password) {
+        (Some(username), Some(password)) => {
+            let email = config.admin_email.clone().unwrap_or_else(|| format!("{}@localhost", username));
+            create_admin(pool, username, &email, password).await.map_err(|e| format!("Cannot create the admin from LIBRARY_ADMIN_USERNAME: {}", e))?;
+            println!("Created admin account {}", username);
+        }
+        (None, None) => {
+            let token = auth::generate_token();
+            println!("No admin account exists yet. Create one with POST /api/setup using this one-time setup token:\n  {}", token);
+            *SETUP_TOKEN.lock().unwrap() = Some(token);
+        }
+        _ => return Err("Set both LIBRARY_ADMIN_USERNAME and LIBRARY_ADMIN_PASSWORD, or neither".to_string()),
+    }
+    Ok(())
+}
+
+// Takes the setup token if it matches, so it works once; a failed setup puts it back
+pub fn claim(token: &str) -> Option<String> {
+    let mut current = SETUP_TOKEN.lock().unwrap();
+    if current.as_deref().is_some_and(|expected| auth::constant_time_eq(expected, token)) {
+        return current.take();
+    }
+    None
+}
+
+pub fn restore(token: String) {
+    *SETUP_TOKEN.lock().unwrap() = Some(token);
+}
+
+pub async fn create_admin(pool: &SqlitePool, username: &str, email: &str, password: &str) -> Result<i64, String> {
+    let username = username.trim();
+    if username.is_empty() || !email.contains('@') {
+        return Err("A username and a valid email address are required".to_string());
+    }
+    password::check(password, username)?;
+    let hash = auth::hash_password(password).map_err(|_| "Failed to hash password".to_string())?;
+
+    let id = db::create_user(pool, username, email, &hash, "admin", "approved")
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => "Username or email already in use".to_string(),
+            e => format!("Failed to create admin: {}", e),
+        })?;
+    db::update_profile(pool, id, email, true, None).await.map_err(|e| format!("Failed to create admin: {}", e))?;
+    Ok(id)
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock};
+use sha2::{Digest, Sha256};
+
+// In-process cache of catalog responses, keyed by the route and the query parameters it reads.
+// Any successful write may change titles or availability, so writes clear the whole cache
+// rather than tracking which entries they touch; catalog reads far outnumber writes. Past
+// MAX_ENTRIES the least recently used entry makes way, so varied searches can't grow it unbounded.
+
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Clone)]
+pub struct Entry {
+    pub body: String,
+    pub etag: String,
+}
+
+struct Slot {
+    entry: Entry,
+    // The TICK of the latest hit, updated under the read lock
+    used: AtomicU64,
+}
+
+static CACHE: OnceLock<RwLock<HashMap<String, Slot>>> = OnceLock::new();
+
+// Bumped on every invalidation, so a read that started before a write can't store its
+// now-stale result after the write has cleared the cache
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+static TICK: AtomicU64 = AtomicU64::new(0);
+
+fn cache() -> &'static RwLock<HashMap<String, Slot>> {
+    CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+// Public catalog reads whose result depends only on the books tables
+pub fn is_cacheable(path: &str) -> bool {
+    path == "/api/books"
+        || path.starts_with("/api/books?")
+        || path.starts_with("/api/books/search?")
+        || path.starts_with("/api/books/suggest?")
+        || book_detail_id(path).is_some()
+}
+
+// /api/books/{id} and /api/books/{id}/branches
+fn book_detail_id(path: &str) -> Option<i64> {
+    let rest = path.strip_prefix("/api/books/")?;
+    rest.strip_suffix("/branches").unwrap_or(rest).parse().ok()
+}
+
+// Query parameters each cacheable route reads; the rest can't change the response
+fn recognized_params(route: &str) -> &'static [&'static str] {
+    match route {
+        "/api/books" => &["branch", "type", "format"],
+        "/api/books/search" => &["q", "type", "facets"],
+        "/api/books/suggest" => &["q"],
+        _ => &[],
+    }
+}
+
+// The cache key for a cacheable path: the route, then the recognized parameters in a fixed
+// order with their first value, decoded as the handlers read them. A bare route and one with
+// an empty query go to different handlers, so the `?` is kept
+pub fn key(path: &str) -> String {
+    let Some((route, query)) = path.split_once('?') else {
+        return path.to_string();
+    };
+    let mut key = format!("{}?", route);
+    for name in recognized_params(route) {
+        let value = query.split('&').find_map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (k == *name).then(|| urlencoding::decode(&v.replace('+', " ")).map(|v| v.into_owned()).unwrap_or_default())
+        });
+        if let Some(value) = value {
+            key.push_str(&format!("&{}={}", name, urlencoding::encode(&value)));
+        }
+    }
+    key
+}
+
+pub fn get(key: &str) -> Option<Entry> {
+    let cache = cache().read().unwrap();
+    let slot = cache.get(key)?;
+    slot.used.store(TICK.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+    Some(slot.entry.clone())
+}
+
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+// Stores the body unless the cache was invalidated since `generation` was read
+pub fn put(key: &str, generation: u64, body: &str) -> Entry {
+    let entry = Entry { body: body.to_string(), etag: etag(body) };
+    let mut cache = cache().write().unwrap();
+    if GENERATION.load(Ordering::SeqCst) != generation {
+        return entry;
+    }
+    if cache.len() >= MAX_ENTRIES && !cache.contains_key(key) {
+        let oldest = cache.iter().min_by_key(|(_, slot)| slot.used.load(Ordering::Relaxed)).map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    let used = AtomicU64::new(TICK.fetch_add(1, Ordering::Relaxed));
+    cache.insert(key.to_string(), Slot { entry: entry.clone(), used });
+    entry
+}
+
+// If-None-Match uses the weak comparison, so a tag matches with or without its W/ prefix
+pub fn matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match.split(',').any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+pub fn invalidate() {
+    let mut cache = cache().write().unwrap();
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    cache.clear();
+}
+
+// Weak, since the same body may go out gzip-encoded or not and the tag stands for both
+fn etag(body: &str) -> String {
+    let digest = Sha256::digest(body.as_bytes());
+    let hex: String = digest.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+    format!("W/\"{}\"", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_only_on_recognized_parameters() {
+        assert_eq!(key("/api/books"), "/api/books");
+        assert_eq!(key("/api/books?"), "/api/books?");
+        assert_eq!(key("/api/books?utm_source=x"), "/api/books?");
+        assert_eq!(key("/api/books/search?q=dune&x=1"), key("/api/books/search?x=2&q=dune"));
+        assert_eq!(key("/api/books/search?q=the+dune"), key("/api/books/search?q=the%20dune"));
+        assert_ne!(key("/api/books/search?q=dune"), key("/api/books/search?q=dune&facets=true"));
+        assert_ne!(key("/api/books?type="), key("/api/books?"));
+        assert_eq!(key("/api/books/7"), "/api/books/7");
+    }
+}
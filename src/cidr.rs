@@ -0,0 +1,38 @@
+use std::net::IpAddr;
+
+// An address range in CIDR notation, e.g. 10.0.0.0/8 or 2001:db8::/32; a bare address is a
+// range of one
+#[derive(Debug, Clone, Copy)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl Cidr {
+    pub fn parse(text: &str) -> Option<Cidr> {
+        let text = text.trim();
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u32>().ok()?)),
+            None => (text, None),
+        };
+        let network: IpAddr = address.parse().ok()?;
+        let width = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(width);
+        (prefix <= width).then_some(Cidr { network, prefix })
+    }
+
+    // IPv4 clients seen through an IPv6 socket (::ffff:a.b.c.d) match IPv4 ranges
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip, width) = match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => (u32::from(network) as u128, u32::from(ip) as u128, 32),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+        ((network ^ ip).checked_shr(width - self.prefix).unwrap_or(0)) == 0
+    }
+}
+
+// True when `ip` parses and falls in any of the ranges
+pub fn any_contains(ranges: &[Cidr], ip: &str) -> bool {
+    ip.parse::<IpAddr>().is_ok_and(|ip| ranges.iter().any(|range| range.contains(ip)))
+}
@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{ConnectOptions, Connection};
+
+// At-rest encryption of library.db with SQLCipher, built in with the `sqlcipher` feature. The
+// passphrase comes from LIBRARY_DB_KEY or LIBRARY_DB_KEY_FILE and is handed to every connection
+// the pool opens; `--rekey` re-encrypts the file under LIBRARY_DB_NEW_KEY. Backups made with
+// VACUUM INTO are encrypted with the same key as the database
+
+// sqlx runs the key pragma ahead of every other pragma on a new connection, as SQLCipher needs
+pub fn keyed(options: SqliteConnectOptions, key: Option<&str>) -> SqliteConnectOptions {
+    match key {
+        Some(key) => options.pragma("key", quote(key)),
+        None => options,
+    }
+}
+
+// Pragmas take no bound parameters, so the passphrase goes in as a string literal
+fn quote(key: &str) -> String {
+    format!("'{}'", key.replace('\'', "''"))
+}
+
+// Opens the database and reads from it, since SQLCipher only notices a wrong key at the first read.
+// Attached databases share the connection's open flags, hence create_if_missing for the export
+async fn open(path: &str, key: Option<&str>) -> Result<SqliteConnection, String> {
+    let mut conn = keyed(SqliteConnectOptions::new().filename(path).create_if_missing(true), key)
+        .connect()
+        .await
+        .map_err(|e| format!("Cannot open {}: {}", path, e))?;
+    match sqlx::query("SELECT count(*) FROM sqlite_master").execute(&mut conn).await {
+        Ok(_) => Ok(conn),
+        Err(_) if key.is_some() => Err(format!("The current key does not open {}", path)),
+        Err(_) => Err(format!("{} is encrypted; set LIBRARY_DB_KEY to its current key", path)),
+    }
+}
+
+// Exports the database into a new file under the new key and moves it over the old one, so a
+// failure part way leaves the original as it was. With no current key a plaintext database is
+// encrypted. Nothing else may have the database open meanwhile
+pub async fn rekey(path: &str, current_key: Option<&str>, new_key: &str) -> Result<(), String> {
+    if !Path::new(path).exists() {
+        return Err(format!("{} does not exist", path));
+    }
+    let mut conn = open(path, current_key).await?;
+
+    let target = format!("{}.rekey", path);
+    if Path::new(&target).exists() {
+        std::fs::remove_file(&target).map_err(|e| format!("Cannot remove leftover {}: {}", target, e))?;
+    }
+    let failed = |e: sqlx::Error| format!("Failed to re-encrypt {}: {}", path, e);
+    sqlx::query("ATTACH DATABASE ? AS rekeyed KEY ?")
+        .bind(&target)
+        .bind(new_key)
+        .execute(&mut conn)
+        .await
+        .map_err(failed)?;
+    sqlx::query("SELECT sqlcipher_export('rekeyed')").execute(&mut conn).await.map_err(failed)?;
+    sqlx::query("DETACH DATABASE rekeyed").execute(&mut conn).await.map_err(failed)?;
+    conn.close().await.map_err(failed)?;
+
+    std::fs::rename(&target, path).map_err(|e| format!("Cannot replace {} with {}: {}", path, target, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn count_books(path: &str, key: Option<&str>) -> Result<i64, String> {
+        let mut conn = open(path, key).await?;
+        sqlx::query_scalar("SELECT count(*) FROM books").fetch_one(&mut conn).await.map_err(|e| e.to_string())
+    }
+
+    #[tokio::test]
+    async fn encrypts_a_plaintext_database_and_changes_its_key() {
+        let path = std::env::temp_dir().join(format!("library-{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let mut conn = SqliteConnectOptions::new().filename(path).create_if_missing(true).connect().await.unwrap();
+        sqlx::query("CREATE TABLE books (title TEXT)").execute(&mut conn).await.unwrap();
+        sqlx::query("INSERT INTO books VALUES ('Dune'), ('Emma')").execute(&mut conn).await.unwrap();
+        conn.close().await.unwrap();
+
+        rekey(path, None, "first key").await.unwrap();
+        assert!(count_books(path, None).await.is_err());
+        assert_eq!(count_books(path, Some("first key")).await, Ok(2));
+
+        assert!(rekey(path, Some("wrong key"), "second key").await.is_err());
+        rekey(path, Some("first key"), "it's the second").await.unwrap();
+        assert!(count_books(path, Some("first key")).await.is_err());
+        assert_eq!(count_books(path, Some("it's the second")).await, Ok(2));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
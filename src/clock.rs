@@ -0,0 +1,151 @@
+use std::sync::Arc;
+#[cfg(test)]
+use std::{future::Future, sync::Mutex};
+
+#[cfg(test)]
+use chrono::Duration;
+use chrono::{DateTime, Utc};
+
+// The time that library rules run on: due dates, overdue detection, fines, reservations,
+// memberships, session expiry, TOTP steps and login throttling all read clock::now() instead of
+// Utc::now(). The system clock is used unless a task runs under scope() with another one, which is
+// how tests move time forward without sleeping. Times that must be real (push signatures,
+// scheduler waits) still use Utc::now()
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// A clock that stands still until set or advanced; clones share the same time
+#[cfg(test)]
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn starting_at(now: DateTime<Utc>) -> ManualClock {
+        ManualClock { now: Arc::new(Mutex::new(now)) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+tokio::task_local! {
+    static CLOCK: Arc<dyn Clock>;
+}
+
+// Runs a future with every clock::now() inside it answered by the given clock
+#[cfg(test)]
+pub async fn scope<F: Future>(clock: Arc<dyn Clock>, future: F) -> F::Output {
+    CLOCK.scope(clock, future).await
+}
+
+pub fn now() -> DateTime<Utc> {
+    CLOCK.try_with(|clock| clock.now()).unwrap_or_else(|_| SystemClock.now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::test_support::{call, pool, session, BookFactory, LoanFactory, UserFactory};
+
+    fn manual() -> (ManualClock, Arc<dyn Clock>) {
+        let clock = ManualClock::starting_at(Utc::now());
+        (clock.clone(), Arc::new(clock))
+    }
+
+    #[tokio::test]
+    async fn loan_turns_overdue_after_its_due_date() {
+        let (clock, scoped) = manual();
+        scope(scoped, async {
+            let pool = pool().await;
+            let user = UserFactory::default().insert(&pool).await;
+            let book = BookFactory::default().insert(&pool).await;
+            LoanFactory::new(&user, &book).with_loan_period(14).insert(&pool).await;
+
+            clock.advance(Duration::days(13));
+            assert_eq!(db::mark_overdue_records(&pool).await.unwrap(), 0);
+
+            clock.advance(Duration::days(2));
+            assert_eq!(db::mark_overdue_records(&pool).await.unwrap(), 1);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn idle_session_expires() {
+        let (clock, scoped) = manual();
+        scope(scoped, async {
+            let pool = pool().await;
+            let (_, token) = UserFactory::default().signed_in(&pool).await;
+            assert_eq!(call(&pool, "GET", "/api/auth/me", Some(&token), None).await.0, 200);
+
+            clock.advance(Duration::hours(crate::config::get().session_idle_hours + 1));
+            assert_eq!(call(&pool, "GET", "/api/auth/me", Some(&token), None).await.0, 401);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn fines_accrue_with_days_late() {
+        let (clock, scoped) = manual();
+        scope(scoped, async {
+            let pool = pool().await;
+            let user = UserFactory::default().insert(&pool).await;
+            let book = BookFactory::default().insert(&pool).await;
+            LoanFactory::new(&user, &book).with_loan_period(14).insert(&pool).await;
+
+            clock.advance(Duration::days(17));
+            crate::fine_accrual_job(pool.clone()).await.unwrap();
+            let three_days = db::get_unpaid_fine_balance(&pool, user.id).await.unwrap();
+            assert!(three_days > 0.0);
+
+            clock.advance(Duration::days(3));
+            crate::fine_accrual_job(pool.clone()).await.unwrap();
+            assert!(db::get_unpaid_fine_balance(&pool, user.id).await.unwrap() > three_days);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn expired_membership_blocks_borrowing() {
+        let (clock, scoped) = manual();
+        scope(scoped, async {
+            let pool = pool().await;
+            let user = UserFactory::default().insert(&pool).await;
+            let book = BookFactory::default().insert(&pool).await;
+
+            let expires_at = DateTime::parse_from_rfc3339(user.membership_expires_at.as_deref().unwrap()).unwrap();
+            clock.set(expires_at.with_timezone(&Utc) + Duration::minutes(1));
+            let token = session(&pool, &user).await;
+            let (status, body) = call(&pool, "POST", &format!("/api/lending/borrow/{}", book.id), Some(&token), None).await;
+            assert_eq!(status, 403);
+            assert!(body["error"].as_str().unwrap().starts_with("Membership expired"));
+        })
+        .await;
+    }
+}
@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use sqlx::sqlite::{SqliteJournalMode, SqliteSynchronous};
+
+use crate::cidr::Cidr;
+
+// Deployment-level configuration read once at startup from environment variables
+pub struct Config {
+    pub public_url: String,
+    pub backup_dir: Option<PathBuf>,
+    // Extra message catalogs, one <language>.json per language
+    pub locale_dir: Option<PathBuf>,
+    pub backup_interval_hours: u64,
+    pub feed_secret: Option<String>,
+    pub access_log: bool,
+    pub access_log_retention_days: i64,
+    pub event_retention_days: i64,
+    // A session ends after this many hours without use...
+    pub session_idle_hours: i64,
+    // ...and after this many hours in total, however active it is
+    pub session_max_hours: i64,
+    // Argon2id cost for password hashes
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    // Registration needs an admin-issued invite code
+    pub invite_only: bool,
+    // The first admin account, created at startup when the database has none
+    pub admin_username: Option<String>,
+    pub admin_password: Option<String>,
+    pub admin_email: Option<String>,
+    // New registrations wait for an admin to approve them before they can borrow
+    pub registration_approval: bool,
+    // Bloom filter of breached passwords checked at registration
+    pub password_breach_filter: Option<PathBuf>,
+    // (provider, (client id, client secret)) for each configured OAuth provider
+    pub oauth_clients: Vec<(String, (String, String))>,
+    // Web Push application server key (base64url P-256 scalar) and the contact given to push
+    // services, a mailto: or https: URL; the public URL is used when unset
+    pub vapid_private_key: Option<String>,
+    pub vapid_subject: Option<String>,
+    // Client address ranges allowed to reach /api/admin/; empty allows every address
+    pub admin_allowlist: Vec<Cidr>,
+    // gzip level for compressible responses, 1 (fastest) to 9 (smallest); 0 turns compression off
+    pub compression_level: u32,
+    // Connections handled at once; further ones are turned away with 503 until a slot frees up
+    pub max_connections: usize,
+    // Seconds a client gets to send its whole request and to accept the response, and the
+    // handler gets to produce it
+    pub read_timeout_secs: u64,
+    pub write_timeout_secs: u64,
+    pub handler_timeout_secs: u64,
+    // Where error reports go, https://<key>@<host>/<project>; only read when built with `sentry`
+    #[cfg(feature = "sentry")]
+    pub sentry_dsn: Option<String>,
+    // Proxies whose X-Forwarded-For / Forwarded headers are believed; loopback when unset
+    pub trusted_proxies: Vec<Cidr>,
+    // Database connections the pool opens at most, and how long a query waits for a free one
+    pub db_max_connections: u32,
+    pub db_acquire_timeout_secs: u64,
+    // How long a connection retries a locked database before failing with `database is locked`
+    pub db_busy_timeout_ms: u64,
+    // WAL lets readers carry on while a write is in progress; with WAL, NORMAL sync may lose the
+    // last commits on power loss but never corrupts the file
+    pub db_journal_mode: SqliteJournalMode,
+    pub db_synchronous: SqliteSynchronous,
+    // Anyone may browse the catalog and its feeds; when off, reading them needs a signed-in user
+    pub public_catalog: bool,
+    // Run the full integrity check before serving; a large database makes startup slower
+    pub startup_integrity_check: bool,
+    // SQLCipher passphrase library.db is encrypted with; only read when built with `sqlcipher`
+    #[cfg(feature = "sqlcipher")]
+    pub db_key: Option<String>,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::from_env)
+}
+
+impl Config {
+    fn from_env() -> Self {
+        Config {
+            public_url: std::env::var("LIBRARY_PUBLIC_URL").unwrap_or_else(|_| "http://127.0.0.1:8080".to_string()),
+            backup_dir: std::env::var("LIBRARY_BACKUP_DIR").ok().filter(|s| !s.is_empty()).map(PathBuf::from),
+            locale_dir: std::env::var("LIBRARY_LOCALE_DIR").ok().filter(|s| !s.is_empty()).map(PathBuf::from),
+            backup_interval_hours: env_parse("LIBRARY_BACKUP_INTERVAL_HOURS", 24),
+            feed_secret: std::env::var("LIBRARY_FEED_SECRET").ok().filter(|s| !s.is_empty()),
+            access_log: env_parse("LIBRARY_ACCESS_LOG", false),
+            access_log_retention_days: env_parse("LIBRARY_ACCESS_LOG_RETENTION_DAYS", 30),
+            event_retention_days: env_parse("LIBRARY_EVENT_RETENTION_DAYS", 90),
+            session_idle_hours: env_parse("LIBRARY_SESSION_IDLE_HOURS", 24).max(1),
+            session_max_hours: env_parse("LIBRARY_SESSION_MAX_HOURS", 720).max(1),
+            argon2_memory_kib: env_parse("LIBRARY_ARGON2_MEMORY_KIB", 19456),
+            argon2_iterations: env_parse("LIBRARY_ARGON2_ITERATIONS", 2),
+            argon2_parallelism: env_parse("LIBRARY_ARGON2_PARALLELISM", 1),
+            invite_only: env_parse("LIBRARY_INVITE_ONLY", false),
+            admin_username: std::env::var("LIBRARY_ADMIN_USERNAME").ok().filter(|s| !s.is_empty()),
+            admin_password: std::env::var("LIBRARY_ADMIN_PASSWORD").ok().filter(|s| !s.is_empty()),
+            admin_email: std::env::var("LIBRARY_ADMIN_EMAIL").ok().filter(|s| !s.is_empty()),
+            registration_approval: env_parse("LIBRARY_REGISTRATION_APPROVAL", false),
+            password_breach_filter: std::env::var("LIBRARY_PASSWORD_BREACH_FILTER").ok().filter(|s| !s.is_empty()).map(PathBuf::from),
+            oauth_clients: ["google", "github"].iter().filter_map(|provider| oauth_client(provider)).collect(),
+            vapid_private_key: std::env::var("LIBRARY_VAPID_PRIVATE_KEY").ok().filter(|s| !s.is_empty()),
+            vapid_subject: std::env::var("LIBRARY_VAPID_SUBJECT").ok().filter(|s| !s.is_empty()),
+            admin_allowlist: cidr_list("LIBRARY_ADMIN_ALLOWLIST"),
+            compression_level: env_parse("LIBRARY_COMPRESSION_LEVEL", 6).min(9),
+            max_connections: env_parse("LIBRARY_MAX_CONNECTIONS", 256).max(1),
+            read_timeout_secs: env_parse("LIBRARY_READ_TIMEOUT_SECS", 30).max(1),
+            write_timeout_secs: env_parse("LIBRARY_WRITE_TIMEOUT_SECS", 30).max(1),
+            handler_timeout_secs: env_parse("LIBRARY_HANDLER_TIMEOUT_SECS", 60).max(1),
+            #[cfg(feature = "sentry")]
+            sentry_dsn: std::env::var("LIBRARY_SENTRY_DSN").ok().filter(|s| !s.is_empty()),
+            trusted_proxies: match std::env::var("LIBRARY_TRUSTED_PROXIES") {
+                Ok(_) => cidr_list("LIBRARY_TRUSTED_PROXIES"),
+                Err(_) => ["127.0.0.0/8", "::1"].iter().filter_map(|range| Cidr::parse(range)).collect(),
+            },
+            db_max_connections: env_parse("LIBRARY_DB_MAX_CONNECTIONS", 10).max(1),
+            db_acquire_timeout_secs: env_parse("LIBRARY_DB_ACQUIRE_TIMEOUT_SECS", 30).max(1),
+            db_busy_timeout_ms: env_parse("LIBRARY_DB_BUSY_TIMEOUT_MS", 5000),
+            db_journal_mode: env_parse("LIBRARY_DB_JOURNAL_MODE", SqliteJournalMode::Wal),
+            db_synchronous: env_parse("LIBRARY_DB_SYNCHRONOUS", SqliteSynchronous::Normal),
+            public_catalog: env_parse("LIBRARY_PUBLIC_CATALOG", true),
+            startup_integrity_check: env_parse("LIBRARY_STARTUP_INTEGRITY_CHECK", true),
+            #[cfg(feature = "sqlcipher")]
+            db_key: secret("LIBRARY_DB_KEY"),
+        }
+    }
+}
+
+// LIBRARY_OAUTH_<PROVIDER>_CLIENT_ID and _CLIENT_SECRET; a provider needs both
+fn oauth_client(provider: &str) -> Option<(String, (String, String))> {
+    let var = |suffix: &str| std::env::var(format!("LIBRARY_OAUTH_{}_{}", provider.to_uppercase(), suffix)).ok().filter(|s| !s.is_empty());
+    Some((provider.to_string(), (var("CLIENT_ID")?, var("CLIENT_SECRET")?)))
+}
+
+// Comma-separated CIDR ranges. A malformed entry stops startup instead of being skipped, since
+// dropping it would quietly change who is let in
+fn cidr_list(name: &str) -> Vec<Cidr> {
+    std::env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| Cidr::parse(entry).unwrap_or_else(|| panic!("Invalid {} entry: {}", name, entry)))
+        .collect()
+}
+
+// A secret given either directly in NAME or as the contents of the file NAME_FILE, so it can stay
+// out of the environment. Surrounding whitespace in the file is ignored; an unreadable file stops
+// startup rather than running without the secret
+#[cfg_attr(not(feature = "sqlcipher"), allow(dead_code))]
+pub fn secret(name: &str) -> Option<String> {
+    if let Some(value) = std::env::var(name).ok().filter(|s| !s.is_empty()) {
+        return Some(value);
+    }
+    let path = std::env::var(format!("{}_FILE", name)).ok().filter(|s| !s.is_empty())?;
+    let contents = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Cannot read {}_FILE {}: {}", name, path, e));
+    Some(contents.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
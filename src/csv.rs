@@ -0,0 +1,90 @@
+use serde::Serialize;
+use serde_json::Value;
+
+// Parses CSV text into rows of fields (RFC 4180: quoted fields may contain separators,
+// doubled quotes, and line breaks). A leading byte-order mark is ignored.
+pub fn parse(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) | ('\r', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows.retain(|r| r.iter().any(|f| !f.trim().is_empty()));
+    rows
+}
+
+// Serializes rows to CSV with a header line; `columns` picks which serialized fields
+// to emit and in what order, so any listing that returns JSON can be exported as-is
+pub fn to_csv<T: Serialize>(rows: &[T], columns: &[&str]) -> String {
+    let mut out = String::new();
+    push_line(&mut out, columns.iter().map(|c| escape_field(c)));
+
+    for row in rows {
+        let value = serde_json::to_value(row).unwrap_or(Value::Null);
+        push_line(&mut out, columns.iter().map(|c| field_text(value.get(*c))));
+    }
+
+    out
+}
+
+fn push_line(out: &mut String, fields: impl Iterator<Item = String>) {
+    let fields: Vec<String> = fields.collect();
+    out.push_str(&fields.join(","));
+    out.push_str("\r\n");
+}
+
+fn field_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => escape_field(&neutralize_formula(s)),
+        // Lists such as a book's tags become one cell, items separated by "; "
+        Some(Value::Array(items)) => {
+            let items: Vec<String> = items.iter().map(|item| item.as_str().map(str::to_string).unwrap_or_else(|| item.to_string())).collect();
+            escape_field(&neutralize_formula(&items.join("; ")))
+        }
+        Some(other) => escape_field(&other.to_string()),
+    }
+}
+
+// Spreadsheets evaluate cells starting with these characters; prefix user-supplied
+// text so a title like "=HYPERLINK(...)" is shown rather than executed
+fn neutralize_formula(s: &str) -> String {
+    if s.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", s)
+    } else {
+        s.to_string()
+    }
+}
+
+// RFC 4180: quote fields containing separators, quotes or line breaks and double embedded quotes
+fn escape_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
@@ -1,19 +1,51 @@
 use sqlx::{SqlitePool, Row};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use crate::models::*;
-use chrono::{Utc, Duration};
+use crate::money;
+use chrono::{Datelike, Utc, Duration};
 
-pub async fn init_db() -> Result<SqlitePool, sqlx::Error> {
-    // Create database file if it doesn't exist
-    let db_path = "library.db";
-    
+pub const DB_PATH: &str = "library.db";
+
+// Stored in PRAGMA user_version once migrate has run; raise it with each change to the schema
+pub const SCHEMA_VERSION: i64 = 1;
+
+pub async fn init_db() -> Result<SqlitePool, String> {
     // Ensure the file can be created by touching it first
-    if !std::path::Path::new(db_path).exists() {
-        std::fs::File::create(db_path).expect("Failed to create database file");
+    if !std::path::Path::new(DB_PATH).exists() {
+        std::fs::File::create(DB_PATH).expect("Failed to create database file");
     }
     
-    let connection_string = format!("sqlite://{}?mode=rwc", db_path);
-    let pool = SqlitePool::connect(&connection_string).await?;
+    let config = crate::config::get();
+    let options = SqliteConnectOptions::new()
+        .filename(DB_PATH)
+        .create_if_missing(true)
+        .busy_timeout(std::time::Duration::from_millis(config.db_busy_timeout_ms))
+        .journal_mode(config.db_journal_mode)
+        .synchronous(config.db_synchronous);
+    #[cfg(feature = "sqlcipher")]
+    let options = crate::cipher::keyed(options, config.db_key.as_deref());
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.db_max_connections)
+        .acquire_timeout(std::time::Duration::from_secs(config.db_acquire_timeout_secs))
+        .connect_with(options)
+        .await
+        .map_err(|e| format!("Cannot open {}: {}", DB_PATH, e))?;
+    
+    // Migrating a database a newer version has written to could undo its changes
+    let version = schema_version(&pool).await.map_err(|e| format!("Cannot read {}: {}", DB_PATH, e))?;
+    if version > SCHEMA_VERSION {
+        return Err(format!("{} has schema version {}, but this build only knows up to {}; run a newer version", DB_PATH, version, SCHEMA_VERSION));
+    }
+    let pool = migrate(pool).await.map_err(|e| format!("Failed to migrate {}: {}", DB_PATH, e))?;
     
+    if config.startup_integrity_check {
+        crate::integrity::verify(&pool).await?;
+    }
+    Ok(pool)
+}
+
+// Creates missing tables, columns and indexes; tests run it against an in-memory database
+pub async fn migrate(pool: SqlitePool) -> Result<SqlitePool, sqlx::Error> {
     // Create tables
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS users (
@@ -21,7 +53,7 @@ pub async fn init_db() -> Result<SqlitePool, sqlx::Error> {
             username TEXT UNIQUE NOT NULL,
             email TEXT UNIQUE NOT NULL,
             password_hash TEXT NOT NULL,
-            role TEXT NOT NULL CHECK(role IN ('admin', 'lender')),
+            role TEXT NOT NULL CHECK(role IN ('admin', 'reports', 'lender')),
             created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
         )"
     ).execute(&pool).await?;
@@ -43,7 +75,7 @@ pub async fn init_db() -> Result<SqlitePool, sqlx::Error> {
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS lending_records (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            user_id INTEGER NOT NULL,
+            user_id INTEGER,
             book_id INTEGER NOT NULL,
             borrowed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
             due_date TIMESTAMP NOT NULL,
@@ -65,395 +97,6671 @@ pub async fn init_db() -> Result<SqlitePool, sqlx::Error> {
         )"
     ).execute(&pool).await?;
     
-    // Insert default admin user (password: 123)
-    let _ = sqlx::query(
-        "INSERT OR IGNORE INTO users (username, email, password_hash, role) 
-         VALUES ('admin', 'admin@library.com', '$2a$12$rfyRaXCM.mNJgnV6t9pOI.EPDV5UhgezjOirtlqBDD2lIyR5BhWIG', 'admin')"
-    ).execute(&pool).await;
-    
-    Ok(pool)
-}
-
-// User operations
-pub async fn create_user(pool: &SqlitePool, username: &str, email: &str, password_hash: &str, role: &str) -> Result<i64, sqlx::Error> {
-    let result = sqlx::query(
-        "INSERT INTO users (username, email, password_hash, role) VALUES (?, ?, ?, ?)"
-    )
-    .bind(username)
-    .bind(email)
-    .bind(password_hash)
-    .bind(role)
-    .execute(pool)
-    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS email_verifications (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            email TEXT NOT NULL,
+            token TEXT UNIQUE NOT NULL,
+            expires_at TIMESTAMP NOT NULL,
+            used_at TIMESTAMP,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    Ok(result.last_insert_rowid())
-}
-
-pub async fn get_user_by_username(pool: &SqlitePool, username: &str) -> Result<Option<User>, sqlx::Error> {
-    let user = sqlx::query_as::<_, User>(
-        "SELECT id, username, email, password_hash, role, created_at FROM users WHERE username = ?"
-    )
-    .bind(username)
-    .fetch_optional(pool)
-    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS notifications (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER,
+            channel TEXT NOT NULL,
+            recipient TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    Ok(user)
-}
-
-pub async fn get_user_by_id(pool: &SqlitePool, id: i64) -> Result<Option<User>, sqlx::Error> {
-    let user = sqlx::query_as::<_, User>(
-        "SELECT id, username, email, password_hash, role, created_at FROM users WHERE id = ?"
-    )
-    .bind(id)
-    .fetch_optional(pool)
-    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            actor_id INTEGER,
+            action TEXT NOT NULL,
+            target_type TEXT NOT NULL,
+            target_id INTEGER,
+            details TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (actor_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    Ok(user)
-}
-
-pub async fn get_all_users(pool: &SqlitePool) -> Result<Vec<User>, sqlx::Error> {
-    let users = sqlx::query_as::<_, User>(
-        "SELECT id, username, email, password_hash, role, created_at FROM users ORDER BY created_at DESC"
-    )
-    .fetch_all(pool)
-    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS member_types (
+            name TEXT PRIMARY KEY,
+            loan_period_days INTEGER,
+            max_concurrent_loans INTEGER,
+            fine_per_day REAL
+        )"
+    ).execute(&pool).await?;
     
-    Ok(users)
-}
-
-// Session operations
-pub async fn create_session(pool: &SqlitePool, user_id: i64, token: &str) -> Result<(), sqlx::Error> {
-    let expires_at = Utc::now() + Duration::hours(24);
+    // Default patron classes; NULL policy values fall back to the global settings
+    sqlx::query(
+        "INSERT OR IGNORE INTO member_types (name, loan_period_days, max_concurrent_loans, fine_per_day) VALUES 
+            ('community', NULL, NULL, NULL),
+            ('student', NULL, 5, NULL),
+            ('faculty', 28, 25, 0)"
+    ).execute(&pool).await?;
     
     sqlx::query(
-        "INSERT INTO sessions (user_id, token, expires_at) VALUES (?, ?, ?)"
-    )
-    .bind(user_id)
-    .bind(token)
-    .bind(expires_at.to_rfc3339())
-    .execute(pool)
-    .await?;
+        "CREATE TABLE IF NOT EXISTS item_types (
+            name TEXT PRIMARY KEY,
+            loan_period_days INTEGER
+        )"
+    ).execute(&pool).await?;
     
-    Ok(())
-}
-
-pub async fn get_user_by_token(pool: &SqlitePool, token: &str) -> Result<Option<User>, sqlx::Error> {
-    let now = Utc::now().to_rfc3339();
+    // Default kinds of catalog item; a NULL loan period falls back to the member's
+    sqlx::query(
+        "INSERT OR IGNORE INTO item_types (name, loan_period_days) VALUES 
+            ('book', NULL),
+            ('periodical', 7),
+            ('media', 7),
+            ('equipment', 3)"
+    ).execute(&pool).await?;
     
-    let user = sqlx::query_as::<_, User>(
-        "SELECT u.id, u.username, u.email, u.password_hash, u.role, u.created_at 
-         FROM users u 
-         INNER JOIN sessions s ON u.id = s.user_id 
-         WHERE s.token = ? AND s.expires_at > ?"
-    )
-    .bind(token)
-    .bind(now)
-    .fetch_optional(pool)
-    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS branches (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            code TEXT UNIQUE NOT NULL,
+            address TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )"
+    ).execute(&pool).await?;
     
-    Ok(user)
-}
-
-pub async fn delete_session(pool: &SqlitePool, token: &str) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM sessions WHERE token = ?")
-        .bind(token)
-        .execute(pool)
+    // Every library has at least its main branch; existing copies and loans belong to it
+    sqlx::query("INSERT OR IGNORE INTO branches (id, name, code) VALUES (1, 'Main Library', 'MAIN')")
+        .execute(&pool)
         .await?;
     
-    Ok(())
-}
-
-// Book operations
-pub async fn create_book(pool: &SqlitePool, req: &CreateBookRequest) -> Result<i64, sqlx::Error> {
-    let result = sqlx::query(
-        "INSERT INTO books (title, author, isbn, publication_year, genre, total_copies, available_copies) 
-         VALUES (?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&req.title)
-    .bind(&req.author)
-    .bind(&req.isbn)
-    .bind(req.publication_year)
-    .bind(&req.genre)
-    .bind(req.total_copies)
-    .bind(req.total_copies)
-    .execute(pool)
-    .await?;
-    
-    Ok(result.last_insert_rowid())
-}
-
-pub async fn get_all_books(pool: &SqlitePool) -> Result<Vec<Book>, sqlx::Error> {
-    let books = sqlx::query_as::<_, Book>(
-        "SELECT id, title, author, isbn, publication_year, genre, total_copies, available_copies, created_at 
-         FROM books ORDER BY title"
-    )
-    .fetch_all(pool)
-    .await?;
-    
-    Ok(books)
-}
-
-pub async fn get_book_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Book>, sqlx::Error> {
-    let book = sqlx::query_as::<_, Book>(
-        "SELECT id, title, author, isbn, publication_year, genre, total_copies, available_copies, created_at 
-         FROM books WHERE id = ?"
-    )
-    .bind(id)
-    .fetch_optional(pool)
-    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS book_copies (
+            book_id INTEGER NOT NULL,
+            branch_id INTEGER NOT NULL,
+            copies INTEGER NOT NULL,
+            PRIMARY KEY (book_id, branch_id),
+            FOREIGN KEY (book_id) REFERENCES books(id),
+            FOREIGN KEY (branch_id) REFERENCES branches(id)
+        )"
+    ).execute(&pool).await?;
     
-    Ok(book)
-}
-
-pub async fn update_book(pool: &SqlitePool, id: i64, req: &UpdateBookRequest) -> Result<(), sqlx::Error> {
-    let book = get_book_by_id(pool, id).await?;
-    if book.is_none() {
-        return Err(sqlx::Error::RowNotFound);
-    }
-    let book = book.unwrap();
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS locations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            branch_id INTEGER NOT NULL,
+            room TEXT NOT NULL,
+            shelf TEXT NOT NULL,
+            call_number_prefix TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (branch_id, room, shelf),
+            FOREIGN KEY (branch_id) REFERENCES branches(id)
+        )"
+    ).execute(&pool).await?;
     
-    let title = req.title.as_ref().unwrap_or(&book.title);
-    let author = req.author.as_ref().unwrap_or(&book.author);
-    let isbn = req.isbn.as_ref().unwrap_or(&book.isbn);
-    let publication_year = req.publication_year.or(book.publication_year);
-    let genre = req.genre.as_ref().or(book.genre.as_ref());
-    let total_copies = req.total_copies.unwrap_or(book.total_copies);
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS idempotency_keys (
+            user_id INTEGER NOT NULL,
+            key TEXT NOT NULL,
+            request TEXT NOT NULL,
+            response TEXT,
+            created_at TIMESTAMP NOT NULL,
+            PRIMARY KEY (user_id, key),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    // Update available copies if total copies changed
-    let available_diff = total_copies - book.total_copies;
-    let available_copies = book.available_copies + available_diff;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS recovery_codes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            code_hash TEXT NOT NULL,
+            used_at TIMESTAMP,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
     sqlx::query(
-        "UPDATE books SET title = ?, author = ?, isbn = ?, publication_year = ?, 
-         genre = ?, total_copies = ?, available_copies = ? WHERE id = ?"
-    )
-    .bind(title)
-    .bind(author)
-    .bind(isbn)
-    .bind(publication_year)
-    .bind(genre)
-    .bind(total_copies)
-    .bind(available_copies)
-    .bind(id)
-    .execute(pool)
-    .await?;
+        "CREATE TABLE IF NOT EXISTS login_challenges (
+            token TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            expires_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    Ok(())
-}
-
-pub async fn delete_book(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM books WHERE id = ?")
-        .bind(id)
-        .execute(pool)
-        .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS login_devices (
+            user_id INTEGER NOT NULL,
+            user_agent TEXT NOT NULL,
+            network TEXT NOT NULL,
+            first_seen_at TIMESTAMP NOT NULL,
+            last_seen_at TIMESTAMP NOT NULL,
+            PRIMARY KEY (user_id, user_agent, network),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    Ok(())
-}
-
-pub async fn search_books(pool: &SqlitePool, query: &str) -> Result<Vec<Book>, sqlx::Error> {
-    let search_pattern = format!("%{}%", query);
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS email_changes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            new_email TEXT NOT NULL,
+            token TEXT UNIQUE NOT NULL,
+            expires_at TIMESTAMP NOT NULL,
+            confirmed_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    let books = sqlx::query_as::<_, Book>(
-        "SELECT id, title, author, isbn, publication_year, genre, total_copies, available_copies, created_at 
-         FROM books 
-         WHERE title LIKE ? OR author LIKE ? OR isbn LIKE ? OR genre LIKE ?
-         ORDER BY title"
-    )
-    .bind(&search_pattern)
-    .bind(&search_pattern)
-    .bind(&search_pattern)
-    .bind(&search_pattern)
-    .fetch_all(pool)
-    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS login_failures (
+            key TEXT PRIMARY KEY,
+            failures INTEGER NOT NULL,
+            last_failure_at TIMESTAMP NOT NULL
+        )"
+    ).execute(&pool).await?;
     
-    Ok(books)
-}
-
-// Lending operations
-pub async fn borrow_book(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<i64, sqlx::Error> {
-    // Check if book is available
-    let book = get_book_by_id(pool, book_id).await?;
-    if book.is_none() {
-        return Err(sqlx::Error::RowNotFound);
-    }
-    let book = book.unwrap();
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS invites (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            code_hash TEXT UNIQUE NOT NULL,
+            role TEXT NOT NULL,
+            created_by INTEGER,
+            created_at TIMESTAMP NOT NULL,
+            expires_at TIMESTAMP NOT NULL,
+            used_at TIMESTAMP,
+            used_by INTEGER,
+            FOREIGN KEY (created_by) REFERENCES users(id),
+            FOREIGN KEY (used_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    if book.available_copies <= 0 {
-        return Err(sqlx::Error::RowNotFound); // Use as "not available" error
-    }
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS oauth_identities (
+            provider TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            user_id INTEGER NOT NULL,
+            email TEXT,
+            created_at TIMESTAMP NOT NULL,
+            PRIMARY KEY (provider, subject),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
     
-    // Create lending record
-    let borrowed_at = Utc::now();
-    let due_date = borrowed_at + Duration::days(14);
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS oauth_states (
+            state TEXT PRIMARY KEY,
+            provider TEXT NOT NULL,
+            expires_at TIMESTAMP NOT NULL
+        )"
+    ).execute(&pool).await?;
     
-    let result = sqlx::query(
-        "INSERT INTO lending_records (user_id, book_id, borrowed_at, due_date, status) 
-         VALUES (?, ?, ?, ?, 'borrowed')"
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS api_keys (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            key_hash TEXT UNIQUE NOT NULL,
+            prefix TEXT NOT NULL,
+            scopes TEXT NOT NULL,
+            created_at TIMESTAMP NOT NULL,
+            last_used_at TIMESTAMP,
+            revoked_at TIMESTAMP,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS access_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            method TEXT NOT NULL,
+            path TEXT NOT NULL,
+            status INTEGER NOT NULL,
+            user_id INTEGER,
+            ip TEXT,
+            latency_ms INTEGER NOT NULL,
+            created_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_access_log_created_at ON access_log(created_at)")
+        .execute(&pool)
+        .await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS series (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            description TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS wishlist (
+            user_id INTEGER NOT NULL,
+            book_id INTEGER NOT NULL,
+            notify INTEGER NOT NULL DEFAULT 0,
+            notified_at TIMESTAMP,
+            added_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (user_id, book_id),
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (book_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS book_watches (
+            user_id INTEGER NOT NULL,
+            book_id INTEGER NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (user_id, book_id),
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (book_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS favorites (
+            user_id INTEGER NOT NULL,
+            book_id INTEGER NOT NULL,
+            added_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (user_id, book_id),
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (book_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS author_follows (
+            user_id INTEGER NOT NULL,
+            author TEXT NOT NULL COLLATE NOCASE,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (user_id, author),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS reading_lists (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            description TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS reading_list_entries (
+            list_id INTEGER NOT NULL,
+            book_id INTEGER NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (list_id, book_id),
+            FOREIGN KEY (list_id) REFERENCES reading_lists(id),
+            FOREIGN KEY (book_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS periodical_issues (
+            book_id INTEGER PRIMARY KEY,
+            title_id INTEGER NOT NULL,
+            volume TEXT,
+            issue_date DATE NOT NULL,
+            received_at TIMESTAMP,
+            FOREIGN KEY (book_id) REFERENCES books(id),
+            FOREIGN KEY (title_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS transfers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            from_branch_id INTEGER NOT NULL,
+            to_branch_id INTEGER NOT NULL,
+            status TEXT NOT NULL CHECK(status IN ('requested', 'in_transit', 'arrived', 'cancelled')),
+            handled_by INTEGER,
+            created_at TIMESTAMP NOT NULL,
+            shipped_at TIMESTAMP,
+            arrived_at TIMESTAMP,
+            cancelled_at TIMESTAMP,
+            FOREIGN KEY (book_id) REFERENCES books(id),
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (from_branch_id) REFERENCES branches(id),
+            FOREIGN KEY (to_branch_id) REFERENCES branches(id),
+            FOREIGN KEY (handled_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS reservations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            book_id INTEGER NOT NULL,
+            status TEXT NOT NULL CHECK(status IN ('waiting', 'ready', 'fulfilled', 'expired', 'cancelled')),
+            created_at TIMESTAMP NOT NULL,
+            ready_at TIMESTAMP,
+            expires_at TIMESTAMP,
+            fulfilled_at TIMESTAMP,
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (book_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS fines (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            lending_record_id INTEGER NOT NULL,
+            amount REAL NOT NULL,
+            amount_paid REAL NOT NULL DEFAULT 0,
+            status TEXT NOT NULL CHECK(status IN ('unpaid', 'paid')),
+            reason TEXT NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            paid_at TIMESTAMP,
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (lending_record_id) REFERENCES lending_records(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS fine_payments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            fine_id INTEGER NOT NULL,
+            amount REAL NOT NULL,
+            method TEXT NOT NULL CHECK(method IN ('cash', 'card', 'other')),
+            note TEXT,
+            recorded_by INTEGER NOT NULL,
+            paid_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (fine_id) REFERENCES fines(id),
+            FOREIGN KEY (recorded_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS announcements (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            title TEXT NOT NULL,
+            message TEXT NOT NULL,
+            level TEXT NOT NULL CHECK(level IN ('info', 'warning', 'closure')),
+            starts_at TIMESTAMP NOT NULL,
+            ends_at TIMESTAMP,
+            created_by INTEGER NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (created_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS broadcasts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            subject TEXT NOT NULL,
+            body TEXT NOT NULL,
+            audience TEXT NOT NULL CHECK(audience IN ('all', 'overdue', 'holds_ready')),
+            status TEXT NOT NULL DEFAULT 'queued' CHECK(status IN ('queued', 'sending', 'completed')),
+            total INTEGER NOT NULL DEFAULT 0,
+            created_by INTEGER NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            started_at TIMESTAMP,
+            completed_at TIMESTAMP,
+            FOREIGN KEY (created_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS broadcast_recipients (
+            broadcast_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'sent', 'failed', 'skipped')),
+            sent_at TIMESTAMP,
+            PRIMARY KEY (broadcast_id, user_id),
+            FOREIGN KEY (broadcast_id) REFERENCES broadcasts(id),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS book_tags (
+            book_id INTEGER NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (book_id, tag),
+            FOREIGN KEY (book_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS lending_notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            lending_record_id INTEGER NOT NULL,
+            author_id INTEGER NOT NULL,
+            note TEXT NOT NULL,
+            created_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (lending_record_id) REFERENCES lending_records(id),
+            FOREIGN KEY (author_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS extension_requests (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            lending_record_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            reason TEXT NOT NULL,
+            requested_days INTEGER NOT NULL,
+            status TEXT NOT NULL CHECK(status IN ('pending', 'approved', 'denied')),
+            decision_note TEXT,
+            decided_by INTEGER,
+            decided_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (lending_record_id) REFERENCES lending_records(id),
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (decided_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS closures (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            starts_on DATE NOT NULL,
+            ends_on DATE NOT NULL,
+            reason TEXT NOT NULL,
+            created_by INTEGER NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (created_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS suggestions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            author TEXT NOT NULL,
+            isbn TEXT,
+            reason TEXT,
+            status TEXT NOT NULL CHECK(status IN ('pending', 'approved', 'declined')),
+            decision_note TEXT,
+            decided_by INTEGER,
+            decided_at TIMESTAMP,
+            book_id INTEGER,
+            created_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (decided_by) REFERENCES users(id),
+            FOREIGN KEY (book_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS donations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            author TEXT NOT NULL,
+            isbn TEXT NOT NULL,
+            publication_year INTEGER,
+            genre TEXT,
+            copies INTEGER NOT NULL,
+            notes TEXT,
+            status TEXT NOT NULL CHECK(status IN ('pending', 'accepted', 'declined')),
+            decision_note TEXT,
+            decided_by INTEGER,
+            decided_at TIMESTAMP,
+            book_id INTEGER,
+            created_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (decided_by) REFERENCES users(id),
+            FOREIGN KEY (book_id) REFERENCES books(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS acquisitions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL,
+            copies INTEGER NOT NULL,
+            source TEXT NOT NULL CHECK(source IN ('donation')),
+            donor_id INTEGER,
+            donation_id INTEGER,
+            recorded_by INTEGER NOT NULL,
+            created_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (book_id) REFERENCES books(id),
+            FOREIGN KEY (donor_id) REFERENCES users(id),
+            FOREIGN KEY (donation_id) REFERENCES donations(id),
+            FOREIGN KEY (recorded_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            event_type TEXT NOT NULL,
+            data TEXT NOT NULL,
+            created_at TIMESTAMP NOT NULL
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS webhooks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            service TEXT NOT NULL,
+            url TEXT NOT NULL,
+            events TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            created_by INTEGER,
+            created_at TIMESTAMP NOT NULL,
+            last_delivery_at TIMESTAMP,
+            last_error TEXT,
+            FOREIGN KEY (created_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS push_subscriptions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            endpoint TEXT UNIQUE NOT NULL,
+            p256dh TEXT NOT NULL,
+            auth TEXT NOT NULL,
+            user_agent TEXT,
+            created_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS notification_preferences (
+            user_id INTEGER PRIMARY KEY,
+            digest INTEGER NOT NULL DEFAULT 0,
+            updated_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS email_templates (
+            key TEXT PRIMARY KEY,
+            subject TEXT NOT NULL,
+            body TEXT NOT NULL,
+            updated_by INTEGER,
+            updated_at TIMESTAMP NOT NULL,
+            FOREIGN KEY (updated_by) REFERENCES users(id)
+        )"
+    ).execute(&pool).await?;
+    
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS secrets (
+            name TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )"
+    ).execute(&pool).await?;
+    
+    // Columns added after the original schema
+    ensure_column(&pool, "users", "display_name", "TEXT").await?;
+    ensure_column(&pool, "users", "email_verified", "INTEGER NOT NULL DEFAULT 0").await?;
+    ensure_column(&pool, "users", "is_active", "INTEGER NOT NULL DEFAULT 1").await?;
+    ensure_column(&pool, "users", "anonymized_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "users", "card_number", "TEXT").await?;
+    ensure_column(&pool, "users", "membership_expires_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "users", "membership_warning_sent_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "lending_records", "reminder_sent_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "lending_records", "overdue_notice_sent_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "lending_records", "escalation_level", "TEXT").await?;
+    ensure_column(&pool, "lending_records", "escalated_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "fines", "accruing", "INTEGER NOT NULL DEFAULT 0").await?;
+    ensure_column(&pool, "notifications", "digest_status", "TEXT").await?;
+    ensure_column(&pool, "lending_records", "recalled_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "lending_records", "original_due_date", "TIMESTAMP").await?;
+    ensure_column(&pool, "users", "suspended_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "users", "suspension_reason", "TEXT").await?;
+    ensure_column(&pool, "users", "suspension_lifted_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "users", "member_type", "TEXT NOT NULL DEFAULT 'community' REFERENCES member_types(name)").await?;
+    ensure_column(&pool, "users", "calendar_feed_version", "INTEGER NOT NULL DEFAULT 0").await?;
+    ensure_column(&pool, "books", "on_order", "INTEGER NOT NULL DEFAULT 0").await?;
+    ensure_column(&pool, "books", "item_type", "TEXT NOT NULL DEFAULT 'book' REFERENCES item_types(name)").await?;
+    ensure_column(&pool, "books", "issue_number", "TEXT").await?;
+    ensure_column(&pool, "books", "platform", "TEXT").await?;
+    ensure_column(&pool, "books", "loan_period_days", "INTEGER").await?;
+    ensure_column(&pool, "books", "loanable", "INTEGER NOT NULL DEFAULT 1").await?;
+    ensure_column(&pool, "books", "series_id", "INTEGER REFERENCES series(id)").await?;
+    ensure_column(&pool, "books", "series_position", "INTEGER").await?;
+    ensure_column(&pool, "books", "version", "INTEGER NOT NULL DEFAULT 1").await?;
+    ensure_column(&pool, "users", "home_branch_id", "INTEGER REFERENCES branches(id)").await?;
+    ensure_column(&pool, "users", "language", "TEXT").await?;
+    ensure_column(&pool, "users", "totp_secret", "TEXT").await?;
+    ensure_column(&pool, "users", "totp_enabled_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "users", "totp_last_step", "INTEGER").await?;
+    ensure_column(&pool, "users", "approval_status", "TEXT NOT NULL DEFAULT 'approved'").await?;
+    ensure_column(&pool, "sessions", "impersonator_id", "INTEGER REFERENCES users(id)").await?;
+    ensure_column(&pool, "sessions", "max_expires_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "sessions", "last_used_at", "TIMESTAMP").await?;
+    ensure_column(&pool, "sessions", "user_agent", "TEXT").await?;
+    ensure_column(&pool, "sessions", "ip_address", "TEXT").await?;
+    ensure_column(&pool, "invites", "username", "TEXT").await?;
+    ensure_column(&pool, "invites", "email", "TEXT").await?;
+    ensure_column(&pool, "invites", "member_type", "TEXT").await?;
+    ensure_column(&pool, "invites", "card_number", "TEXT").await?;
+    ensure_column(&pool, "lending_records", "branch_id", "INTEGER NOT NULL DEFAULT 1 REFERENCES branches(id)").await?;
+    
+    // Books catalogued before branches existed keep their copies at the main branch
+    sqlx::query(
+        "INSERT INTO book_copies (book_id, branch_id, copies) 
+         SELECT id, ?, total_copies FROM books 
+         WHERE total_copies > 0 AND id NOT IN (SELECT book_id FROM book_copies)"
+    )
+    .bind(MAIN_BRANCH_ID)
+    .execute(&pool)
+    .await?;
+    
+    // Loans noticed as overdue before escalation levels existed start at the first level
+    sqlx::query(
+        "UPDATE lending_records 
+         SET escalation_level = 'reminder', escalated_at = overdue_notice_sent_at, overdue_notice_sent_at = NULL
+         WHERE overdue_notice_sent_at IS NOT NULL AND escalation_level IS NULL"
+    )
+    .execute(&pool)
+    .await?;
+    
+    ensure_column(&pool, "book_copies", "location_id", "INTEGER REFERENCES locations(id)").await?;
+    ensure_column(&pool, "audit_log", "ip", "TEXT").await?;
+    allow_reports_role(&pool).await?;
+    allow_detached_loans(&pool).await?;
+    
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_users_card_number ON users(card_number)")
+        .execute(&pool)
+        .await?;
+    
+    // Case-insensitive indexes let prefix LIKE queries for autocomplete use an index
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_books_title_nocase ON books(title COLLATE NOCASE)")
+        .execute(&pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_books_author_nocase ON books(author COLLATE NOCASE)")
+        .execute(&pool)
+        .await?;
+    
+    // Give users created before card numbers existed a card
+    let without_card: Vec<i64> = sqlx::query_scalar("SELECT id FROM users WHERE card_number IS NULL AND anonymized_at IS NULL")
+        .fetch_all(&pool)
+        .await?;
+    for id in without_card {
+        sqlx::query("UPDATE users SET card_number = ? WHERE id = ?")
+            .bind(crate::auth::generate_card_number())
+            .bind(id)
+            .execute(&pool)
+            .await?;
+    }
+    
+    // PRAGMA takes no bound parameters; the value is a constant
+    sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+        .execute(&pool)
+        .await?;
+    
+    Ok(pool)
+}
+
+// Databases created before the reports role limit users.role to admin and lender. SQLite can't
+// change a CHECK constraint in place, so the table is rebuilt from its own stored definition
+async fn allow_reports_role(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    const OLD_CHECK: &str = "CHECK(role IN ('admin', 'lender'))";
+    const NEW_CHECK: &str = "CHECK(role IN ('admin', 'reports', 'lender'))";
+    
+    let definition: String = sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'users'")
+        .fetch_one(pool)
+        .await?;
+    if !definition.contains(OLD_CHECK) {
+        return Ok(());
+    }
+    let rebuilt = definition.replacen("CREATE TABLE users", "CREATE TABLE users_new", 1).replace(OLD_CHECK, NEW_CHECK);
+    
+    // Other tables reference users, so foreign keys are off while it is swapped out
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+    
+    let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+    sqlx::query(&rebuilt).execute(&mut *tx).await?;
+    sqlx::query("INSERT INTO users_new SELECT * FROM users").execute(&mut *tx).await?;
+    sqlx::query("DROP TABLE users").execute(&mut *tx).await?;
+    sqlx::query("ALTER TABLE users_new RENAME TO users").execute(&mut *tx).await?;
+    tx.commit().await?;
+    
+    sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await?;
+    Ok(())
+}
+
+// Loans past their retention period can be detached from the borrower, which needs
+// lending_records.user_id to be nullable; older databases are rebuilt the same way
+async fn allow_detached_loans(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    const OLD_COLUMN: &str = "user_id INTEGER NOT NULL,";
+    const NEW_COLUMN: &str = "user_id INTEGER,";
+    
+    let definition: String = sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'lending_records'")
+        .fetch_one(pool)
+        .await?;
+    if !definition.contains(OLD_COLUMN) {
+        return Ok(());
+    }
+    let rebuilt = definition
+        .replacen("CREATE TABLE lending_records", "CREATE TABLE lending_records_new", 1)
+        .replacen(OLD_COLUMN, NEW_COLUMN, 1);
+    
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+    
+    let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+    sqlx::query(&rebuilt).execute(&mut *tx).await?;
+    sqlx::query("INSERT INTO lending_records_new SELECT * FROM lending_records").execute(&mut *tx).await?;
+    sqlx::query("DROP TABLE lending_records").execute(&mut *tx).await?;
+    sqlx::query("ALTER TABLE lending_records_new RENAME TO lending_records").execute(&mut *tx).await?;
+    tx.commit().await?;
+    
+    sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await?;
+    Ok(())
+}
+
+// Add a column to an existing table when upgrading a database created by an older version
+async fn ensure_column(pool: &SqlitePool, table: &str, column: &str, definition: &str) -> Result<(), sqlx::Error> {
+    let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+        .fetch_all(pool)
+        .await?;
+    
+    if !columns.iter().any(|row| row.get::<String, _>("name") == column) {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+    
+    Ok(())
+}
+
+// User operations
+const USER_COLUMNS: &str = "u.id, u.username, u.email, u.password_hash, u.role, u.display_name, u.email_verified, u.is_active, u.anonymized_at, u.card_number, u.member_type, u.membership_expires_at, u.suspended_at, u.suspension_reason, u.home_branch_id, u.approval_status, u.created_at,
+    u.totp_enabled_at IS NOT NULL AS two_factor_enabled";
+
+pub async fn create_user(pool: &SqlitePool, username: &str, email: &str, password_hash: &str, role: &str, approval_status: &str) -> Result<i64, sqlx::Error> {
+    let membership_expires_at = crate::clock::now() + Duration::days(crate::settings::membership_period_days());
+    
+    let result = sqlx::query(
+        "INSERT INTO users (username, email, password_hash, role, approval_status, card_number, membership_expires_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(username)
+    .bind(email)
+    .bind(password_hash)
+    .bind(role)
+    .bind(approval_status)
+    .bind(crate::auth::generate_card_number())
+    .bind(membership_expires_at.to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+// An account from a bulk import: a lender, already approved, with the member type and card
+// number from the import file when it had them
+pub async fn create_imported_user(pool: &SqlitePool, member: &ImportedMember, password_hash: &str) -> Result<i64, sqlx::Error> {
+    let membership_expires_at = crate::clock::now() + Duration::days(crate::settings::membership_period_days());
+    
+    let result = sqlx::query(
+        "INSERT INTO users (username, email, password_hash, role, approval_status, member_type, card_number, membership_expires_at) 
+         VALUES (?, ?, ?, 'lender', 'approved', COALESCE(?, 'community'), ?, ?)"
+    )
+    .bind(&member.username)
+    .bind(&member.email)
+    .bind(password_hash)
+    .bind(&member.member_type)
+    .bind(member.card_number.clone().unwrap_or_else(crate::auth::generate_card_number))
+    .bind(membership_expires_at.to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_user_by_username(pool: &SqlitePool, username: &str) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as::<_, User>(
+        &format!("SELECT {} FROM users u WHERE u.username = ?", USER_COLUMNS)
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(user)
+}
+
+pub async fn get_user_by_id(pool: &SqlitePool, id: i64) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as::<_, User>(
+        &format!("SELECT {} FROM users u WHERE u.id = ?", USER_COLUMNS)
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(user)
+}
+
+pub async fn get_user_by_email(pool: &SqlitePool, email: &str) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as::<_, User>(
+        &format!("SELECT {} FROM users u WHERE u.email = ?", USER_COLUMNS)
+    )
+    .bind(email)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(user)
+}
+
+pub async fn get_user_by_card_number(pool: &SqlitePool, card_number: &str) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as::<_, User>(
+        &format!("SELECT {} FROM users u WHERE u.card_number = ?", USER_COLUMNS)
+    )
+    .bind(card_number)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(user)
+}
+
+pub async fn set_password_hash(pool: &SqlitePool, id: i64, password_hash: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+        .bind(password_hash)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn update_profile(pool: &SqlitePool, id: i64, email: &str, email_verified: bool, display_name: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE users SET email = ?, email_verified = ?, display_name = ? WHERE id = ?"
+    )
+    .bind(email)
+    .bind(email_verified)
+    .bind(display_name)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+// Deactivation keeps the user row (and its lending history) but ends every open session
+pub async fn get_pending_users(pool: &SqlitePool) -> Result<Vec<User>, sqlx::Error> {
+    sqlx::query_as::<_, User>(
+        &format!("SELECT {} FROM users u WHERE u.approval_status = 'pending' ORDER BY u.created_at", USER_COLUMNS)
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Settles a pending registration. A rejected account is also deactivated, which ends its sessions
+pub async fn decide_registration(pool: &SqlitePool, id: i64, approve: bool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE users SET approval_status = ?, is_active = CASE WHEN ? THEN is_active ELSE 0 END 
+         WHERE id = ? AND approval_status = 'pending'"
+    )
+    .bind(if approve { "approved" } else { "rejected" })
+    .bind(approve)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    
+    if !approve {
+        sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+    
+    Ok(true)
+}
+
+// Moves a duplicate account's borrowing history onto the primary account and deactivates the
+// duplicate, all or nothing. Where both accounts hold the same book, the duplicate's
+// reservation is cancelled so the primary doesn't queue twice
+// Also returns the books whose held copies the cancelled reservations gave back, for the caller
+// to offer to the next in their queues
+pub async fn merge_users(pool: &SqlitePool, primary_id: i64, duplicate_id: i64) -> Result<(UserMerge, Vec<i64>), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    // A ready hold being cancelled returns its copy to the shelf, as in close_reservation
+    let released_books: Vec<i64> = sqlx::query_scalar(
+        "SELECT book_id FROM reservations 
+         WHERE user_id = ? AND status = 'ready' 
+         AND book_id IN (SELECT book_id FROM reservations WHERE user_id = ? AND status IN ('waiting', 'ready'))"
+    )
+    .bind(duplicate_id)
+    .bind(primary_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    for book_id in &released_books {
+        sqlx::query("UPDATE books SET available_copies = available_copies + 1 WHERE id = ?")
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    
+    let cancelled_reservations = sqlx::query(
+        "UPDATE reservations SET status = 'cancelled' 
+         WHERE user_id = ? AND status IN ('waiting', 'ready') 
+         AND book_id IN (SELECT book_id FROM reservations WHERE user_id = ? AND status IN ('waiting', 'ready'))"
+    )
+    .bind(duplicate_id)
+    .bind(primary_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    
+    let mut moved = Vec::new();
+    for table in ["lending_records", "fines", "reservations", "extension_requests", "transfers"] {
+        let result = sqlx::query(&format!("UPDATE {} SET user_id = ? WHERE user_id = ?", table))
+            .bind(primary_id)
+            .bind(duplicate_id)
+            .execute(&mut *tx)
+            .await?;
+        moved.push(result.rows_affected());
+    }
+    
+    sqlx::query("UPDATE users SET is_active = 0 WHERE id = ?")
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query("DELETE FROM push_subscriptions WHERE user_id = ?")
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE user_id = ? AND revoked_at IS NULL")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    tx.commit().await?;
+    
+    let merge = UserMerge {
+        lending_records: moved[0],
+        fines: moved[1],
+        reservations: moved[2],
+        cancelled_reservations,
+        extension_requests: moved[3],
+        transfers: moved[4],
+    };
+    Ok((merge, released_books))
+}
+
+pub async fn set_user_active(pool: &SqlitePool, id: i64, active: bool) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET is_active = ? WHERE id = ?")
+        .bind(active)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    if !active {
+        sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+    
+    Ok(())
+}
+
+// Scrubs personal data from the user row while keeping the id, so lending_records
+// still count towards circulation statistics
+pub async fn anonymize_user(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    let placeholder = uuid::Uuid::new_v4().simple().to_string();
+    let mut tx = pool.begin().await?;
+    
+    let result = sqlx::query(
+        "UPDATE users SET username = ?, email = ?, password_hash = '', display_name = NULL, 
+         card_number = NULL, email_verified = 0, is_active = 0, anonymized_at = ?, 
+         totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL 
+         WHERE id = ? AND anonymized_at IS NULL"
+    )
+    .bind(format!("deleted-{}", placeholder))
+    .bind(format!("deleted-{}@invalid", placeholder))
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    for statement in [
+        "DELETE FROM sessions WHERE user_id = ?",
+        "DELETE FROM idempotency_keys WHERE user_id = ?",
+        "DELETE FROM access_log WHERE user_id = ?",
+        "DELETE FROM api_keys WHERE user_id = ?",
+        "DELETE FROM recovery_codes WHERE user_id = ?",
+        "DELETE FROM login_challenges WHERE user_id = ?",
+        "DELETE FROM oauth_identities WHERE user_id = ?",
+        "DELETE FROM email_verifications WHERE user_id = ?",
+        "DELETE FROM email_changes WHERE user_id = ?",
+        "DELETE FROM login_devices WHERE user_id = ?",
+        "DELETE FROM notification_preferences WHERE user_id = ?",
+        "DELETE FROM push_subscriptions WHERE user_id = ?",
+        "DELETE FROM notifications WHERE user_id = ?",
+        "DELETE FROM broadcast_recipients WHERE user_id = ?",
+        "DELETE FROM wishlist WHERE user_id = ?",
+        "DELETE FROM book_watches WHERE user_id = ?",
+        "DELETE FROM favorites WHERE user_id = ?",
+        "DELETE FROM author_follows WHERE user_id = ?",
+        "UPDATE events SET data = json_set(data, '$.user_id', NULL) WHERE json_extract(data, '$.user_id') = ?",
+        "UPDATE audit_log SET details = NULL WHERE target_type = 'user' AND target_id = ?",
+        "UPDATE audit_log SET ip = NULL WHERE actor_id = ?",
+    ] {
+        sqlx::query(statement).bind(id).execute(&mut *tx).await?;
+    }
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn set_member_type(pool: &SqlitePool, id: i64, member_type: &str) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET member_type = ? WHERE id = ?")
+        .bind(member_type)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+pub async fn set_card_number(pool: &SqlitePool, id: i64, card_number: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET card_number = ? WHERE id = ?")
+        .bind(card_number)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn set_user_role(pool: &SqlitePool, id: i64, role: &str) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET role = ? WHERE id = ? AND anonymized_at IS NULL")
+        .bind(role)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+pub async fn count_active_admins(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = 'admin' AND is_active = 1 AND anonymized_at IS NULL")
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn password_hash_in_use(pool: &SqlitePool, hash: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE password_hash = ? AND is_active = 1)")
+        .bind(hash)
+        .fetch_one(pool)
+        .await
+}
+
+// The language a member chose for messages; None means whatever their requests ask for
+pub async fn get_user_language(pool: &SqlitePool, id: i64) -> Result<Option<String>, sqlx::Error> {
+    let language: Option<Option<String>> = sqlx::query_scalar("SELECT language FROM users WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    
+    Ok(language.flatten())
+}
+
+pub async fn get_session_language(pool: &SqlitePool, token: &str) -> Result<Option<String>, sqlx::Error> {
+    let language: Option<Option<String>> = sqlx::query_scalar(
+        "SELECT u.language FROM sessions s INNER JOIN users u ON u.id = s.user_id WHERE s.token = ?"
+    )
+    .bind(token)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(language.flatten())
+}
+
+pub async fn set_user_language(pool: &SqlitePool, id: i64, language: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET language = ? WHERE id = ?")
+        .bind(language)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn set_home_branch(pool: &SqlitePool, id: i64, branch_id: Option<i64>) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET home_branch_id = ? WHERE id = ?")
+        .bind(branch_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+pub async fn renew_membership(pool: &SqlitePool, id: i64, expires_at: &str) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE users SET membership_expires_at = ?, membership_warning_sent_at = NULL WHERE id = ?"
+    )
+    .bind(expires_at)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+pub async fn get_users_with_expiring_membership(pool: &SqlitePool, before: &str) -> Result<Vec<User>, sqlx::Error> {
+    let users = sqlx::query_as::<_, User>(
+        &format!(
+            "SELECT {} FROM users u 
+             WHERE u.is_active = 1 AND u.membership_expires_at IS NOT NULL 
+               AND u.membership_expires_at <= ? AND u.membership_warning_sent_at IS NULL",
+            USER_COLUMNS
+        )
+    )
+    .bind(before)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(users)
+}
+
+pub async fn mark_membership_warning_sent(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET membership_warning_sent_at = ? WHERE id = ?")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Suspends a member holding more than `overdue_threshold` overdue items or owing more than
+// `fine_threshold` in unpaid fines. Items that were already overdue when an admin last lifted
+// a suspension don't count again.
+pub async fn apply_automatic_suspension(pool: &SqlitePool, user_id: i64, overdue_threshold: i64, fine_threshold: f64) -> Result<bool, sqlx::Error> {
+    let user = get_user_by_id(pool, user_id).await?;
+    if user.is_none_or(|u| u.suspended_at.is_some()) {
+        return Ok(false);
+    }
+    
+    let overdue: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM lending_records lr
+         INNER JOIN users u ON u.id = lr.user_id
+         WHERE lr.user_id = ? AND lr.status = 'overdue'
+           AND (u.suspension_lifted_at IS NULL OR lr.due_date > u.suspension_lifted_at)"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    let balance = get_unpaid_fine_balance(pool, user_id).await?;
+    
+    let reason = if overdue > overdue_threshold {
+        format!("{} overdue items (limit {})", overdue, overdue_threshold)
+    } else if balance > fine_threshold {
+        format!("Unpaid fines of {} (limit {})", money::format(balance), money::format(fine_threshold))
+    } else {
+        return Ok(false);
+    };
+    
+    sqlx::query("UPDATE users SET suspended_at = ?, suspension_reason = ? WHERE id = ? AND suspended_at IS NULL")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(reason)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(true)
+}
+
+pub async fn get_users_with_overdue_items_or_fines(pool: &SqlitePool) -> Result<Vec<i64>, sqlx::Error> {
+    let ids = sqlx::query_scalar(
+        "SELECT user_id FROM lending_records WHERE status = 'overdue'
+         UNION SELECT user_id FROM fines WHERE status = 'unpaid'"
+    )
+        .fetch_all(pool)
+        .await?;
+    
+    Ok(ids)
+}
+
+pub async fn lift_suspension(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE users SET suspended_at = NULL, suspension_reason = NULL, suspension_lifted_at = ? WHERE id = ?"
+    )
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Sort keys accepted for the admin user list and the column each orders by
+pub const USER_SORTS: &[(&str, &str)] = &[
+    ("created_at", "u.created_at"),
+    ("username", "u.username COLLATE NOCASE"),
+    ("email", "u.email COLLATE NOCASE"),
+    ("role", "u.role"),
+];
+
+// `text` matches a substring of the username, email or card number, case-insensitively
+pub async fn get_all_users(pool: &SqlitePool, query: &UserQuery) -> Result<Vec<User>, sqlx::Error> {
+    let text = query.text.as_deref().map(like_contains);
+    let sort = USER_SORTS.iter().find(|(key, _)| *key == query.sort).map(|(_, column)| *column).unwrap_or("u.created_at");
+    let direction = if query.descending { "DESC" } else { "ASC" };
+    
+    let users = sqlx::query_as::<_, User>(&format!(
+        "SELECT {} FROM users u 
+         WHERE (? IS NULL OR u.username LIKE ? ESCAPE '\\' OR u.email LIKE ? ESCAPE '\\' OR u.card_number LIKE ? ESCAPE '\\')
+           AND (? IS NULL OR u.role = ?)
+           AND (? IS NULL OR u.is_active = ?)
+         ORDER BY {} {}, u.id {}
+         LIMIT ? OFFSET ?",
+        USER_COLUMNS, sort, direction, direction
+    ))
+    .bind(&text)
+    .bind(&text)
+    .bind(&text)
+    .bind(&text)
+    .bind(&query.role)
+    .bind(&query.role)
+    .bind(query.active)
+    .bind(query.active)
+    .bind(query.limit.unwrap_or(-1))
+    .bind(query.offset)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(users)
+}
+
+// LIKE pattern matching `text` anywhere, with its wildcards escaped for ESCAPE '\'
+fn like_contains(text: &str) -> String {
+    format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"))
+}
+
+pub async fn get_calendar_feed_version(pool: &SqlitePool, user_id: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT calendar_feed_version FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+// Invalidates every calendar feed URL issued so far
+pub async fn rotate_calendar_feed(pool: &SqlitePool, user_id: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "UPDATE users SET calendar_feed_version = calendar_feed_version + 1 WHERE id = ? RETURNING calendar_feed_version"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
+
+// Session operations
+
+// A session starts with the idle timeout and slides forward on use, but never past
+// `max_expires_at`
+pub async fn create_session(pool: &SqlitePool, user_id: i64, token: &str, user_agent: Option<&str>, ip_address: Option<&str>) -> Result<(), sqlx::Error> {
+    let config = crate::config::get();
+    let now = crate::clock::now();
+    let max_expires_at = now + Duration::hours(config.session_max_hours);
+    let expires_at = (now + Duration::hours(config.session_idle_hours)).min(max_expires_at);
+    
+    sqlx::query(
+        "INSERT INTO sessions (user_id, token, expires_at, max_expires_at, last_used_at, user_agent, ip_address) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(token)
+    .bind(expires_at.to_rfc3339())
+    .bind(max_expires_at.to_rfc3339())
+    .bind(now.to_rfc3339())
+    .bind(user_agent)
+    .bind(ip_address)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+// A short-lived session acting as `user_id`, flagged with the staff member who asked for it
+pub async fn create_impersonation_session(pool: &SqlitePool, user_id: i64, token: &str, impersonator_id: i64, minutes: i64) -> Result<String, sqlx::Error> {
+    let expires_at = (crate::clock::now() + Duration::minutes(minutes)).to_rfc3339();
+    
+    // Capped at its initial expiry, so use never extends it
+    sqlx::query(
+        "INSERT INTO sessions (user_id, token, expires_at, max_expires_at, impersonator_id) VALUES (?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(token)
+    .bind(&expires_at)
+    .bind(&expires_at)
+    .bind(impersonator_id)
+    .execute(pool)
+    .await?;
+    
+    Ok(expires_at)
+}
+
+// The (id, username) of the staff member behind a live impersonation session
+pub async fn get_session_impersonator(pool: &SqlitePool, token: &str) -> Result<Option<(i64, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (i64, String)>(
+        "SELECT i.id, i.username 
+         FROM sessions s 
+         INNER JOIN users i ON i.id = s.impersonator_id 
+         WHERE s.token = ? AND s.expires_at > ?"
+    )
+    .bind(token)
+    .bind(crate::clock::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get_user_by_token(pool: &SqlitePool, token: &str) -> Result<Option<User>, sqlx::Error> {
+    let now = crate::clock::now();
+    
+    let user = sqlx::query_as::<_, User>(
+        &format!(
+            "SELECT {} 
+             FROM users u 
+             INNER JOIN sessions s ON u.id = s.user_id 
+             WHERE s.token = ? AND s.expires_at > ?",
+            USER_COLUMNS
+        )
+    )
+    .bind(token)
+    .bind(now.to_rfc3339())
+    .fetch_optional(pool)
+    .await?;
+    
+    // Record the use and slide the idle timeout forward. Skipped while less than a minute has
+    // passed since the last use, so a burst of requests doesn't write on every one. Sessions
+    // from before max_expires_at existed keep their original expiry
+    if user.is_some() {
+        let renewed = now + Duration::hours(crate::config::get().session_idle_hours);
+        sqlx::query(
+            "UPDATE sessions SET expires_at = MIN(?, COALESCE(max_expires_at, expires_at)), last_used_at = ? 
+             WHERE token = ? AND (last_used_at IS NULL OR last_used_at < ?)"
+        )
+        .bind(renewed.to_rfc3339())
+        .bind(now.to_rfc3339())
+        .bind(token)
+        .bind((now - Duration::minutes(1)).to_rfc3339())
+        .execute(pool)
+        .await?;
+    }
+    
+    Ok(user)
+}
+
+pub async fn get_user_by_api_key(pool: &SqlitePool, key_hash: &str) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as::<_, User>(
+        &format!(
+            "SELECT {} 
+             FROM users u 
+             INNER JOIN api_keys k ON u.id = k.user_id 
+             WHERE k.key_hash = ? AND k.revoked_at IS NULL",
+            USER_COLUMNS
+        )
+    )
+    .bind(key_hash)
+    .fetch_optional(pool)
+    .await?;
+    
+    if user.is_some() {
+        sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE key_hash = ?")
+            .bind(crate::clock::now().to_rfc3339())
+            .bind(key_hash)
+            .execute(pool)
+            .await?;
+    }
+    
+    Ok(user)
+}
+
+pub async fn get_session_id(pool: &SqlitePool, token: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar("SELECT id FROM sessions WHERE token = ?")
+        .bind(token)
+        .fetch_optional(pool)
+        .await
+}
+
+// Revokes one of the user's own sessions; false if it isn't theirs or is already gone
+pub async fn delete_user_session(pool: &SqlitePool, user_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM sessions WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Remembers the device and network of a sign-in. Returns true when the user has signed in
+// before but never with this user agent, or never from this network
+pub async fn record_login_device(pool: &SqlitePool, user_id: i64, user_agent: &str, network: &str) -> Result<bool, sqlx::Error> {
+    let (known_before, seen_agent, seen_network): (bool, bool, bool) = sqlx::query_as(
+        "SELECT COUNT(*) > 0, COALESCE(SUM(user_agent = ?), 0) > 0, COALESCE(SUM(network = ?), 0) > 0 
+         FROM login_devices WHERE user_id = ?"
+    )
+    .bind(user_agent)
+    .bind(network)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    
+    let now = crate::clock::now().to_rfc3339();
+    sqlx::query(
+        "INSERT INTO login_devices (user_id, user_agent, network, first_seen_at, last_seen_at) VALUES (?, ?, ?, ?, ?) 
+         ON CONFLICT (user_id, user_agent, network) DO UPDATE SET last_seen_at = excluded.last_seen_at"
+    )
+    .bind(user_id)
+    .bind(user_agent)
+    .bind(network)
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await?;
+    
+    Ok(known_before && !(seen_agent && seen_network))
+}
+
+pub async fn delete_session(pool: &SqlitePool, token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM sessions WHERE token = ?")
+        .bind(token)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn delete_expired_sessions(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    let result = sqlx::query("DELETE FROM sessions WHERE expires_at <= ?")
+        .bind(&now)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM login_challenges WHERE expires_at <= ?")
+        .bind(&now)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM email_changes WHERE confirmed_at IS NULL AND expires_at <= ?")
+        .bind(&now)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM oauth_states WHERE expires_at <= ?")
+        .bind(&now)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected())
+}
+
+// Two-factor operations
+// (secret, enabled, last accepted time step); the secret is pending until enabled
+pub async fn get_totp_state(pool: &SqlitePool, user_id: i64) -> Result<(Option<String>, bool, Option<i64>), sqlx::Error> {
+    let row = sqlx::query("SELECT totp_secret, totp_enabled_at IS NOT NULL AS enabled, totp_last_step FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    
+    Ok((row.get("totp_secret"), row.get("enabled"), row.get("totp_last_step")))
+}
+
+// Stores a new pending secret; does nothing once two-factor sign-in is enabled
+pub async fn set_pending_totp_secret(pool: &SqlitePool, user_id: i64, secret: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET totp_secret = ?, totp_last_step = NULL WHERE id = ? AND totp_enabled_at IS NULL")
+        .bind(secret)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Turns on two-factor sign-in and replaces any earlier recovery codes
+pub async fn enable_totp(pool: &SqlitePool, user_id: i64, step: i64, code_hashes: &[String]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    sqlx::query("UPDATE users SET totp_enabled_at = ?, totp_last_step = ? WHERE id = ?")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(step)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    for hash in code_hashes {
+        sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)")
+            .bind(user_id)
+            .bind(hash)
+            .execute(&mut *tx)
+            .await?;
+    }
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn disable_totp(pool: &SqlitePool, user_id: i64) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    sqlx::query("UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL WHERE id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+// Records the time step of an accepted code; false if it (or a later one) was already used,
+// which happens when two requests race with the same code
+pub async fn record_totp_step(pool: &SqlitePool, user_id: i64, step: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET totp_last_step = ? WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)")
+        .bind(step)
+        .bind(user_id)
+        .bind(step)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Marks an unused recovery code as used; false if there was none
+pub async fn use_recovery_code(pool: &SqlitePool, user_id: i64, code_hash: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE recovery_codes SET used_at = ? 
+         WHERE id = (SELECT id FROM recovery_codes WHERE user_id = ? AND code_hash = ? AND used_at IS NULL LIMIT 1)"
+    )
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(user_id)
+    .bind(code_hash)
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// OAuth operations
+pub async fn create_oauth_state(pool: &SqlitePool, state: &str, provider: &str, minutes: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO oauth_states (state, provider, expires_at) VALUES (?, ?, ?)")
+        .bind(state)
+        .bind(provider)
+        .bind((crate::clock::now() + Duration::minutes(minutes)).to_rfc3339())
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Consumes a state issued for `provider`; false if it is unknown, expired or already used
+pub async fn take_oauth_state(pool: &SqlitePool, state: &str, provider: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM oauth_states WHERE state = ? AND provider = ? AND expires_at > ?")
+        .bind(state)
+        .bind(provider)
+        .bind(crate::clock::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_user_by_oauth_identity(pool: &SqlitePool, provider: &str, subject: &str) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as::<_, User>(
+        &format!(
+            "SELECT {} 
+             FROM users u 
+             INNER JOIN oauth_identities o ON u.id = o.user_id 
+             WHERE o.provider = ? AND o.subject = ?",
+            USER_COLUMNS
+        )
+    )
+    .bind(provider)
+    .bind(subject)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(user)
+}
+
+pub async fn link_oauth_identity(pool: &SqlitePool, user_id: i64, provider: &str, subject: &str, email: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO oauth_identities (provider, subject, user_id, email, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(provider)
+        .bind(subject)
+        .bind(user_id)
+        .bind(email)
+        .bind(crate::clock::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn get_oauth_identities(pool: &SqlitePool, user_id: i64) -> Result<Vec<(String, Option<String>, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, Option<String>, String)>("SELECT provider, email, created_at FROM oauth_identities WHERE user_id = ? ORDER BY created_at")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+}
+
+// Login challenges bridge the password step and the code step of a two-factor sign-in
+pub async fn create_login_challenge(pool: &SqlitePool, user_id: i64, token: &str, minutes: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO login_challenges (token, user_id, expires_at) VALUES (?, ?, ?)")
+        .bind(token)
+        .bind(user_id)
+        .bind((crate::clock::now() + Duration::minutes(minutes)).to_rfc3339())
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn get_login_challenge_user(pool: &SqlitePool, token: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar("SELECT user_id FROM login_challenges WHERE token = ? AND expires_at > ?")
+        .bind(token)
+        .bind(crate::clock::now().to_rfc3339())
+        .fetch_optional(pool)
+        .await
+}
+
+// Counts a wrong code; the challenge is dropped after `max_attempts`, forcing a fresh login
+pub async fn fail_login_challenge(pool: &SqlitePool, token: &str, max_attempts: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE login_challenges SET attempts = attempts + 1 WHERE token = ?")
+        .bind(token)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM login_challenges WHERE token = ? AND attempts >= ?")
+        .bind(token)
+        .bind(max_attempts)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn delete_login_challenge(pool: &SqlitePool, token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM login_challenges WHERE token = ?")
+        .bind(token)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Idempotency key operations
+// Claims the key for a request before it runs; returns false when the key is already taken
+pub async fn claim_idempotency_key(pool: &SqlitePool, user_id: i64, key: &str, request: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT OR IGNORE INTO idempotency_keys (user_id, key, request, created_at) VALUES (?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(request)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// The request a key was claimed for and its stored response, None while it is still running
+pub async fn get_idempotency_key(pool: &SqlitePool, user_id: i64, key: &str) -> Result<Option<(String, Option<String>)>, sqlx::Error> {
+    let row = sqlx::query_as::<_, (String, Option<String>)>(
+        "SELECT request, response FROM idempotency_keys WHERE user_id = ? AND key = ?"
+    )
+    .bind(user_id)
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(row)
+}
+
+pub async fn save_idempotent_response(pool: &SqlitePool, user_id: i64, key: &str, response: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE idempotency_keys SET response = ? WHERE user_id = ? AND key = ?")
+        .bind(response)
+        .bind(user_id)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn release_idempotency_key(pool: &SqlitePool, user_id: i64, key: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE user_id = ? AND key = ?")
+        .bind(user_id)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn delete_expired_idempotency_keys(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE created_at <= ?")
+        .bind(before)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected())
+}
+
+// API key operations
+const API_KEY_COLUMNS: &str = "id, name, prefix, scopes, created_at, last_used_at, revoked_at";
+
+pub async fn create_api_key(pool: &SqlitePool, user_id: i64, name: &str, key_hash: &str, prefix: &str, scopes: &[String]) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO api_keys (user_id, name, key_hash, prefix, scopes, created_at) VALUES (?, ?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(name)
+    .bind(key_hash)
+    .bind(prefix)
+    .bind(scopes.join(" "))
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_api_key(pool: &SqlitePool, user_id: i64, id: i64) -> Result<Option<ApiKey>, sqlx::Error> {
+    let key = sqlx::query_as::<_, ApiKey>(&format!("SELECT {} FROM api_keys WHERE user_id = ? AND id = ?", API_KEY_COLUMNS))
+        .bind(user_id)
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    
+    Ok(key)
+}
+
+pub async fn get_api_keys(pool: &SqlitePool, user_id: i64) -> Result<Vec<ApiKey>, sqlx::Error> {
+    let keys = sqlx::query_as::<_, ApiKey>(&format!("SELECT {} FROM api_keys WHERE user_id = ? ORDER BY id DESC", API_KEY_COLUMNS))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+    
+    Ok(keys)
+}
+
+// Scopes of a key that is still live, or None for an unknown or revoked key
+pub async fn get_api_key_scopes(pool: &SqlitePool, key_hash: &str) -> Result<Option<Vec<String>>, sqlx::Error> {
+    let scopes: Option<String> = sqlx::query_scalar("SELECT scopes FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL")
+        .bind(key_hash)
+        .fetch_optional(pool)
+        .await?;
+    
+    Ok(scopes.map(|s| s.split_whitespace().map(str::to_string).collect()))
+}
+
+pub async fn revoke_api_key(pool: &SqlitePool, user_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE user_id = ? AND id = ? AND revoked_at IS NULL")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(user_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Login throttle operations
+pub async fn get_login_failures(pool: &SqlitePool) -> Result<Vec<(String, i64, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, i64, String)>("SELECT key, failures, last_failure_at FROM login_failures")
+        .fetch_all(pool)
+        .await
+}
+
+// Saves the in-memory failure counts, replacing the previous copy
+pub async fn replace_login_failures(pool: &SqlitePool, entries: &[(String, i64, String)]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    sqlx::query("DELETE FROM login_failures").execute(&mut *tx).await?;
+    for (key, failures, last_failure_at) in entries {
+        sqlx::query("INSERT INTO login_failures (key, failures, last_failure_at) VALUES (?, ?, ?)")
+            .bind(key)
+            .bind(failures)
+            .bind(last_failure_at)
+            .execute(&mut *tx)
+            .await?;
+    }
+    
+    tx.commit().await
+}
+
+// Invite operations
+const INVITE_COLUMNS: &str = "id, role, username, email, member_type, card_number, created_by, created_at, expires_at, used_at, used_by";
+
+pub async fn create_invite(pool: &SqlitePool, code_hash: &str, role: &str, created_by: i64, days: i64) -> Result<i64, sqlx::Error> {
+    let now = crate::clock::now();
+    let result = sqlx::query(
+        "INSERT INTO invites (code_hash, role, created_by, created_at, expires_at) VALUES (?, ?, ?, ?, ?)"
+    )
+    .bind(code_hash)
+    .bind(role)
+    .bind(created_by)
+    .bind(now.to_rfc3339())
+    .bind((now + Duration::days(days)).to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+// An invite issued for one particular account, whose details registration then fills in
+pub async fn create_account_invite(pool: &SqlitePool, code_hash: &str, created_by: i64, days: i64, member: &ImportedMember) -> Result<i64, sqlx::Error> {
+    let now = crate::clock::now();
+    let result = sqlx::query(
+        "INSERT INTO invites (code_hash, role, username, email, member_type, card_number, created_by, created_at, expires_at) 
+         VALUES (?, 'lender', ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(code_hash)
+    .bind(&member.username)
+    .bind(&member.email)
+    .bind(&member.member_type)
+    .bind(&member.card_number)
+    .bind(created_by)
+    .bind(now.to_rfc3339())
+    .bind((now + Duration::days(days)).to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+// Whether an unused, unexpired invite was already issued for this username, email or card number
+pub async fn has_open_account_invite(pool: &SqlitePool, username: &str, email: &str, card_number: Option<&str>) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM invites 
+         WHERE used_at IS NULL AND expires_at > ? AND (username = ? OR email = ? OR card_number = ?))"
+    )
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(username)
+    .bind(email)
+    .bind(card_number)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_invite(pool: &SqlitePool, id: i64) -> Result<Option<Invite>, sqlx::Error> {
+    sqlx::query_as::<_, Invite>(&format!("SELECT {} FROM invites WHERE id = ?", INVITE_COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn get_invites(pool: &SqlitePool) -> Result<Vec<Invite>, sqlx::Error> {
+    sqlx::query_as::<_, Invite>(&format!("SELECT {} FROM invites ORDER BY created_at DESC", INVITE_COLUMNS))
+        .fetch_all(pool)
+        .await
+}
+
+// Only unused invites can be withdrawn; used ones stay as a record of who joined through them
+pub async fn delete_invite(pool: &SqlitePool, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM invites WHERE id = ? AND used_at IS NULL")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Marks a live invite as used and returns its (id, role), so two sign-ups can't share a code
+pub async fn claim_invite(pool: &SqlitePool, code_hash: &str) -> Result<Option<Invite>, sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    sqlx::query_as::<_, Invite>(&format!(
+        "UPDATE invites SET used_at = ? 
+         WHERE code_hash = ? AND used_at IS NULL AND expires_at > ? 
+         RETURNING {}",
+        INVITE_COLUMNS
+    ))
+    .bind(&now)
+    .bind(code_hash)
+    .bind(&now)
+    .fetch_optional(pool)
+    .await
+}
+
+// Undoes a claim when the registration it was for fails
+pub async fn release_invite(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE invites SET used_at = NULL WHERE id = ? AND used_by IS NULL")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn set_invite_user(pool: &SqlitePool, id: i64, user_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE invites SET used_by = ? WHERE id = ?")
+        .bind(user_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Access log operations
+pub async fn record_access(pool: &SqlitePool, method: &str, path: &str, status: i64, user_id: Option<i64>, ip: Option<&str>, latency_ms: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO access_log (method, path, status, user_id, ip, latency_ms, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(method)
+    .bind(path)
+    .bind(status)
+    .bind(user_id)
+    .bind(ip)
+    .bind(latency_ms)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+// Newest first; `path` matches as a prefix, `from` and `to` bound created_at inclusively
+pub async fn get_access_log(pool: &SqlitePool, query: &AccessLogQuery) -> Result<Vec<AccessLogEntry>, sqlx::Error> {
+    let path_prefix = query.path.as_ref().map(|p| format!("{}%", p.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")));
+    
+    let entries = sqlx::query_as::<_, AccessLogEntry>(
+        "SELECT a.id, a.method, a.path, a.status, a.user_id, u.username, a.ip, a.latency_ms, a.created_at 
+         FROM access_log a
+         LEFT JOIN users u ON a.user_id = u.id
+         WHERE (? IS NULL OR a.user_id = ?)
+           AND (? IS NULL OR a.method = ?)
+           AND (? IS NULL OR a.path LIKE ? ESCAPE '\\')
+           AND (? IS NULL OR a.status = ?)
+           AND (? IS NULL OR a.ip = ?)
+           AND (? IS NULL OR a.created_at >= ?)
+           AND (? IS NULL OR a.created_at <= ?)
+         ORDER BY a.id DESC
+         LIMIT ?"
+    )
+    .bind(query.user_id)
+    .bind(query.user_id)
+    .bind(&query.method)
+    .bind(&query.method)
+    .bind(&path_prefix)
+    .bind(&path_prefix)
+    .bind(query.status)
+    .bind(query.status)
+    .bind(&query.ip)
+    .bind(&query.ip)
+    .bind(&query.from)
+    .bind(&query.from)
+    .bind(&query.to)
+    .bind(&query.to)
+    .bind(query.limit)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(entries)
+}
+
+pub async fn count_access_log_before(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM access_log WHERE created_at < ?")
+        .bind(before)
+        .fetch_one(pool)
+        .await?;
+    
+    Ok(count as u64)
+}
+
+pub async fn delete_access_log_before(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM access_log WHERE created_at < ?")
+        .bind(before)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected())
+}
+
+// Email verification operations
+pub async fn create_email_verification(pool: &SqlitePool, user_id: i64, email: &str, token: &str) -> Result<(), sqlx::Error> {
+    let expires_at = crate::clock::now() + Duration::hours(48);
+    
+    sqlx::query(
+        "INSERT INTO email_verifications (user_id, email, token, expires_at) VALUES (?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(email)
+    .bind(token)
+    .bind(expires_at.to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+// Marks the user's email verified if the token is valid and still matches their current address
+pub async fn confirm_email_verification(pool: &SqlitePool, token: &str) -> Result<i64, sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    
+    let record = sqlx::query(
+        "SELECT v.id, v.user_id FROM email_verifications v
+         INNER JOIN users u ON u.id = v.user_id
+         WHERE v.token = ? AND v.used_at IS NULL AND v.expires_at > ? AND v.email = u.email"
+    )
+    .bind(token)
+    .bind(&now)
+    .fetch_optional(pool)
+    .await?;
+    
+    let record = record.ok_or(sqlx::Error::RowNotFound)?;
+    let id: i64 = record.get("id");
+    let user_id: i64 = record.get("user_id");
+    
+    sqlx::query("UPDATE email_verifications SET used_at = ? WHERE id = ?")
+        .bind(&now)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("UPDATE users SET email_verified = 1 WHERE id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(user_id)
+}
+
+// A new request replaces any earlier unconfirmed one, so only the latest link works
+pub async fn create_email_change(pool: &SqlitePool, user_id: i64, new_email: &str, token: &str) -> Result<(), sqlx::Error> {
+    let now = crate::clock::now();
+    let mut tx = pool.begin().await?;
+    
+    sqlx::query("DELETE FROM email_changes WHERE user_id = ? AND confirmed_at IS NULL")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query(
+        "INSERT INTO email_changes (user_id, new_email, token, expires_at, created_at) VALUES (?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(new_email)
+    .bind(token)
+    .bind((now + Duration::hours(48)).to_rfc3339())
+    .bind(now.to_rfc3339())
+    .execute(&mut *tx)
+    .await?;
+    
+    tx.commit().await
+}
+
+pub async fn get_pending_email_change(pool: &SqlitePool, user_id: i64) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT new_email FROM email_changes WHERE user_id = ? AND confirmed_at IS NULL AND expires_at > ?"
+    )
+    .bind(user_id)
+    .bind(crate::clock::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+}
+
+// Switches the user to the new address, which the click has just proven they own.
+// Returns (user_id, old email, new email); None if the link is unknown, used or expired
+pub async fn confirm_email_change(pool: &SqlitePool, token: &str) -> Result<Option<(i64, String, String)>, sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    
+    let change = sqlx::query_as::<_, (i64, i64, String, String)>(
+        "SELECT c.id, c.user_id, u.email, c.new_email FROM email_changes c
+         INNER JOIN users u ON u.id = c.user_id
+         WHERE c.token = ? AND c.confirmed_at IS NULL AND c.expires_at > ?"
+    )
+    .bind(token)
+    .bind(&now)
+    .fetch_optional(&mut *tx)
+    .await?;
+    
+    let Some((id, user_id, old_email, new_email)) = change else {
+        return Ok(None);
+    };
+    
+    sqlx::query("UPDATE users SET email = ?, email_verified = 1 WHERE id = ?")
+        .bind(&new_email)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query("UPDATE email_changes SET confirmed_at = ? WHERE id = ?")
+        .bind(&now)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    
+    tx.commit().await?;
+    Ok(Some((user_id, old_email, new_email)))
+}
+
+// Notification operations
+pub async fn create_notification(pool: &SqlitePool, user_id: Option<i64>, channel: &str, recipient: &str, subject: &str, body: &str) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO notifications (user_id, channel, recipient, subject, body) VALUES (?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(channel)
+    .bind(recipient)
+    .bind(subject)
+    .bind(body)
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+// Webhook operations
+const WEBHOOK_COLUMNS: &str = "id, service, url, events, enabled, created_by, created_at, last_delivery_at, last_error";
+
+pub async fn create_webhook(pool: &SqlitePool, req: &CreateWebhookRequest, created_by: i64) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO webhooks (service, url, events, created_by, created_at) VALUES (?, ?, ?, ?, ?)"
+    )
+    .bind(&req.service)
+    .bind(&req.url)
+    .bind(req.events.join(" "))
+    .bind(created_by)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_webhooks(pool: &SqlitePool) -> Result<Vec<Webhook>, sqlx::Error> {
+    sqlx::query_as::<_, Webhook>(&format!("SELECT {} FROM webhooks ORDER BY id", WEBHOOK_COLUMNS))
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_webhook(pool: &SqlitePool, id: i64) -> Result<Option<Webhook>, sqlx::Error> {
+    sqlx::query_as::<_, Webhook>(&format!("SELECT {} FROM webhooks WHERE id = ?", WEBHOOK_COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn get_webhooks_for_event(pool: &SqlitePool, event: &str) -> Result<Vec<Webhook>, sqlx::Error> {
+    sqlx::query_as::<_, Webhook>(&format!(
+        "SELECT {} FROM webhooks WHERE enabled = 1 AND instr(' ' || events || ' ', ' ' || ? || ' ') > 0",
+        WEBHOOK_COLUMNS
+    ))
+    .bind(event)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn update_webhook(pool: &SqlitePool, id: i64, events: &[String], enabled: bool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE webhooks SET events = ?, enabled = ? WHERE id = ?")
+        .bind(events.join(" "))
+        .bind(enabled)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_webhook(pool: &SqlitePool, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM webhooks WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// `error` is None for a successful delivery, which also clears an earlier error
+pub async fn record_webhook_delivery(pool: &SqlitePool, id: i64, error: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE webhooks SET last_delivery_at = ?, last_error = ? WHERE id = ?")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Push subscription operations
+
+// A browser has one subscription per site, so re-subscribing (possibly as another member
+// signing in on the same browser) replaces the stored one
+pub async fn upsert_push_subscription(pool: &SqlitePool, user_id: i64, req: &PushSubscriptionRequest, user_agent: Option<&str>) -> Result<PushSubscription, sqlx::Error> {
+    sqlx::query_as::<_, PushSubscription>(
+        "INSERT INTO push_subscriptions (user_id, endpoint, p256dh, auth, user_agent, created_at) VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT(endpoint) DO UPDATE SET user_id = excluded.user_id, p256dh = excluded.p256dh, auth = excluded.auth, 
+             user_agent = excluded.user_agent, created_at = excluded.created_at
+         RETURNING id, endpoint, p256dh, auth, user_agent, created_at"
+    )
+    .bind(user_id)
+    .bind(&req.endpoint)
+    .bind(&req.keys.p256dh)
+    .bind(&req.keys.auth)
+    .bind(user_agent)
+    .bind(crate::clock::now().to_rfc3339())
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_push_subscriptions(pool: &SqlitePool, user_id: i64) -> Result<Vec<PushSubscription>, sqlx::Error> {
+    sqlx::query_as::<_, PushSubscription>(
+        "SELECT id, endpoint, p256dh, auth, user_agent, created_at FROM push_subscriptions 
+         WHERE user_id = ? ORDER BY created_at DESC, id DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn delete_push_subscription(pool: &SqlitePool, user_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM push_subscriptions WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// For subscriptions the push service reports as expired or unsubscribed
+pub async fn delete_push_subscription_by_endpoint(pool: &SqlitePool, endpoint: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM push_subscriptions WHERE endpoint = ?")
+        .bind(endpoint)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Notification preference and digest operations
+pub async fn wants_digest(pool: &SqlitePool, user_id: i64) -> Result<bool, sqlx::Error> {
+    let digest: Option<bool> = sqlx::query_scalar("SELECT digest FROM notification_preferences WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    
+    Ok(digest.unwrap_or(false))
+}
+
+pub async fn set_digest_preference(pool: &SqlitePool, user_id: i64, digest: bool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO notification_preferences (user_id, digest, updated_at) VALUES (?, ?, ?)
+         ON CONFLICT(user_id) DO UPDATE SET digest = excluded.digest, updated_at = excluded.updated_at"
+    )
+    .bind(user_id)
+    .bind(digest)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+// Held back for the user's next digest instead of being sent now
+pub async fn queue_digest_notification(pool: &SqlitePool, user_id: i64, recipient: &str, subject: &str, body: &str) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO notifications (user_id, channel, recipient, subject, body, digest_status) VALUES (?, 'email', ?, ?, ?, 'pending')"
+    )
+    .bind(user_id)
+    .bind(recipient)
+    .bind(subject)
+    .bind(body)
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+// Every queued notification as (id, user_id, subject, body), grouped by user, oldest first
+pub async fn get_pending_digest_notifications(pool: &SqlitePool) -> Result<Vec<(i64, i64, String, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (i64, i64, String, String)>(
+        "SELECT id, user_id, subject, body FROM notifications 
+         WHERE digest_status = 'pending' AND user_id IS NOT NULL 
+         ORDER BY user_id, id"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Marks the user's queued notifications up to `last_id` as delivered in a digest
+pub async fn mark_digest_sent(pool: &SqlitePool, user_id: i64, last_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE notifications SET digest_status = 'sent' WHERE user_id = ? AND digest_status = 'pending' AND id <= ?")
+        .bind(user_id)
+        .bind(last_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Audit log operations
+// The client address comes from the request being handled; entries written by background jobs have none
+pub async fn record_audit(pool: &SqlitePool, actor_id: Option<i64>, action: &str, target_type: &str, target_id: Option<i64>, details: &serde_json::Value) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_log (actor_id, action, target_type, target_id, details, ip) VALUES (?, ?, ?, ?, ?, ?)"
+    )
+    .bind(actor_id)
+    .bind(action)
+    .bind(target_type)
+    .bind(target_id)
+    .bind(details.to_string())
+    .bind(crate::proxy::current_ip())
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+// Event feed operations. Rows are appended, and only the retention purge and erasure change or
+// remove them; AUTOINCREMENT keeps ids increasing even past deleted rows, and SQLite's single writer means ids become visible in order, so a
+// poller that resumes after the last id it saw never skips an event
+pub async fn record_event(pool: &SqlitePool, event_type: &str, data: &serde_json::Value) -> Result<i64, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    insert_event(&mut conn, event_type, data).await
+}
+
+// For writes whose event must commit or roll back with them
+async fn insert_event(tx: &mut sqlx::SqliteConnection, event_type: &str, data: &serde_json::Value) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO events (event_type, data, created_at) VALUES (?, ?, ?)")
+        .bind(event_type)
+        .bind(data.to_string())
+        .bind(crate::clock::now().to_rfc3339())
+        .execute(tx)
+        .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_events_after(pool: &SqlitePool, since_id: i64, event_type: Option<&str>, limit: i64) -> Result<Vec<Event>, sqlx::Error> {
+    sqlx::query_as::<_, Event>(
+        "SELECT id, event_type, data, created_at FROM events 
+         WHERE id > ? AND (? IS NULL OR event_type = ? OR event_type LIKE ? || '.%') 
+         ORDER BY id LIMIT ?"
+    )
+    .bind(since_id)
+    .bind(event_type)
+    .bind(event_type)
+    .bind(event_type)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_user_sessions(pool: &SqlitePool, user_id: i64) -> Result<Vec<SessionInfo>, sqlx::Error> {
+    let sessions = sqlx::query_as::<_, SessionInfo>(
+        "SELECT id, created_at, expires_at, last_used_at, user_agent, ip_address, impersonator_id IS NOT NULL AS impersonated 
+         FROM sessions WHERE user_id = ? ORDER BY created_at DESC, id DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(sessions)
+}
+
+pub async fn get_user_notifications(pool: &SqlitePool, user_id: i64) -> Result<Vec<Notification>, sqlx::Error> {
+    let notifications = sqlx::query_as::<_, Notification>(
+        "SELECT id, channel, recipient, subject, body, created_at 
+         FROM notifications WHERE user_id = ? ORDER BY created_at DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(notifications)
+}
+
+pub async fn get_audit_entries_for_user(pool: &SqlitePool, user_id: i64) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    let entries = sqlx::query_as::<_, AuditEntry>(
+        "SELECT id, actor_id, action, target_type, target_id, details, ip, created_at 
+         FROM audit_log 
+         WHERE actor_id = ? OR (target_type = 'user' AND target_id = ?)
+         ORDER BY created_at DESC"
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(entries)
+}
+
+// Book operations
+// Tags are folded into one column, separated by the ASCII unit separator
+const BOOK_COLUMNS: &str = "id, title, author, isbn, publication_year, genre, total_copies, available_copies, created_at, on_order,
+    item_type, issue_number, platform, loan_period_days, loanable, series_id, series_position, version,
+    (SELECT GROUP_CONCAT(tag, char(31)) FROM book_tags t WHERE t.book_id = books.id) AS tags";
+
+pub async fn create_book(pool: &SqlitePool, req: &CreateBookRequest) -> Result<i64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let book_id = insert_book(&mut tx, req).await?;
+    tx.commit().await?;
+    Ok(book_id)
+}
+
+async fn insert_book(tx: &mut sqlx::SqliteConnection, req: &CreateBookRequest) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO books (title, author, isbn, publication_year, genre, total_copies, available_copies, item_type, issue_number, platform) 
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&req.title)
+    .bind(&req.author)
+    .bind(&req.isbn)
+    .bind(req.publication_year)
+    .bind(&req.genre)
+    .bind(req.total_copies)
+    .bind(req.total_copies)
+    .bind(req.item_type.as_deref().unwrap_or("book"))
+    .bind(&req.issue_number)
+    .bind(&req.platform)
+    .execute(&mut *tx)
+    .await?;
+    
+    let book_id = result.last_insert_rowid();
+    add_branch_copies(&mut *tx, book_id, MAIN_BRANCH_ID, req.total_copies as i64).await?;
+    
+    Ok(book_id)
+}
+
+// Imports a batch of books in one transaction: each is created with its book.created event
+// unless its ISBN is already catalogued, and its tags are merged in either way. A record the
+// database rejects is reported and the rest still go in
+pub async fn import_books(pool: &SqlitePool, books: &[(CreateBookRequest, Vec<String>)]) -> Result<Vec<ImportOutcome>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut outcomes = Vec::with_capacity(books.len());
+    
+    for (req, tags) in books {
+        let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM books WHERE isbn = ?")
+            .bind(&req.isbn)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let (book_id, created) = match existing {
+            Some(book_id) => (book_id, false),
+            // A savepoint, so a record that fails half way leaves nothing behind
+            None => {
+                let mut record = sqlx::Connection::begin(&mut *tx).await?;
+                match insert_book(&mut record, req).await {
+                    Ok(book_id) => {
+                        record.commit().await?;
+                        (book_id, true)
+                    }
+                    Err(e) => {
+                        outcomes.push(ImportOutcome::Failed(e.to_string()));
+                        continue;
+                    }
+                }
+            }
+        };
+        
+        add_book_tags(&mut tx, book_id, tags).await?;
+        if created {
+            let book = sqlx::query_as::<_, Book>(&format!("SELECT {} FROM books WHERE id = ?", BOOK_COLUMNS))
+                .bind(book_id)
+                .fetch_one(&mut *tx)
+                .await?;
+            insert_event(&mut tx, "book.created", &serde_json::json!({ "book": book })).await?;
+        }
+        outcomes.push(if created { ImportOutcome::Created(book_id) } else { ImportOutcome::Skipped(book_id) });
+    }
+    
+    tx.commit().await?;
+    Ok(outcomes)
+}
+
+pub async fn get_all_books(pool: &SqlitePool) -> Result<Vec<Book>, sqlx::Error> {
+    let books = sqlx::query_as::<_, Book>(
+        &format!(
+            "SELECT {} FROM books ORDER BY title",
+            BOOK_COLUMNS
+        )
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(books)
+}
+
+pub async fn get_book_by_isbn(pool: &SqlitePool, isbn: &str) -> Result<Option<Book>, sqlx::Error> {
+    let book = sqlx::query_as::<_, Book>(
+        &format!(
+            "SELECT {} FROM books WHERE isbn = ?",
+            BOOK_COLUMNS
+        )
+    )
+    .bind(isbn)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(book)
+}
+
+pub async fn get_recent_books(pool: &SqlitePool, limit: i64) -> Result<Vec<Book>, sqlx::Error> {
+    let books = sqlx::query_as::<_, Book>(
+        &format!(
+            "SELECT {} FROM books ORDER BY created_at DESC, id DESC LIMIT ?",
+            BOOK_COLUMNS
+        )
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(books)
+}
+
+pub async fn get_book_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Book>, sqlx::Error> {
+    let book = sqlx::query_as::<_, Book>(
+        &format!(
+            "SELECT {} FROM books WHERE id = ?",
+            BOOK_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(book)
+}
+
+// Returns false without changing anything when the book's version no longer matches the
+// caller's (or, without one, the version read here), so concurrent edits can't overwrite each other
+pub async fn update_book(pool: &SqlitePool, id: i64, req: &UpdateBookRequest) -> Result<bool, sqlx::Error> {
+    let book = get_book_by_id(pool, id).await?;
+    if book.is_none() {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    let book = book.unwrap();
+    
+    let title = req.title.as_ref().unwrap_or(&book.title);
+    let author = req.author.as_ref().unwrap_or(&book.author);
+    let isbn = req.isbn.as_ref().unwrap_or(&book.isbn);
+    let publication_year = req.publication_year.or(book.publication_year);
+    let genre = req.genre.as_ref().or(book.genre.as_ref());
+    let total_copies = req.total_copies.unwrap_or(book.total_copies);
+    
+    // Type-specific fields are dropped when an item changes to a type without them
+    let item_type = req.item_type.as_ref().unwrap_or(&book.item_type);
+    let issue_number = req.issue_number.as_ref().or(book.issue_number.as_ref()).filter(|v| item_type == "periodical" && !v.is_empty());
+    let platform = req.platform.as_ref().or(book.platform.as_ref()).filter(|v| item_type == "media" && !v.is_empty());
+    
+    // A loan period of 0 clears the item's own period
+    let loan_period_days = req.loan_period_days.or(book.loan_period_days).filter(|days| *days > 0);
+    let loanable = req.loanable.unwrap_or(book.loanable);
+    
+    // A series_id of 0 takes the book out of its series
+    let series_id = req.series_id.or(book.series_id).filter(|id| *id > 0);
+    let series_position = req.series_position.or(book.series_position).filter(|_| series_id.is_some());
+    
+    // Update available copies if total copies changed; relative, so loans made meanwhile still count
+    let available_diff = total_copies - book.total_copies;
+    let version = req.version.unwrap_or(book.version);
+    
+    // A book on order stops being on order once copies are added
+    let result = sqlx::query(
+        "UPDATE books SET title = ?, author = ?, isbn = ?, publication_year = ?, 
+         genre = ?, total_copies = ?, available_copies = available_copies + ?, on_order = on_order AND ? = 0, 
+         item_type = ?, issue_number = ?, platform = ?, loan_period_days = ?, loanable = ?, 
+         series_id = ?, series_position = ?, version = version + 1 WHERE id = ? AND version = ?"
+    )
+    .bind(title)
+    .bind(author)
+    .bind(isbn)
+    .bind(publication_year)
+    .bind(genre)
+    .bind(total_copies)
+    .bind(available_diff)
+    .bind(total_copies)
+    .bind(item_type)
+    .bind(issue_number)
+    .bind(platform)
+    .bind(loan_period_days)
+    .bind(loanable)
+    .bind(series_id)
+    .bind(series_position)
+    .bind(id)
+    .bind(version)
+    .execute(pool)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    
+    // Copies added or removed through the book itself are counted at the main branch
+    add_branch_copies(&mut *pool.acquire().await?, id, MAIN_BRANCH_ID, (total_copies - book.total_copies) as i64).await?;
+    
+    Ok(true)
+}
+
+// Copies that are out of the library's hands: on loan, held for pickup, or in transit between branches
+const COMMITTED_COPIES_SQL: &str = "(SELECT COUNT(*) FROM lending_records lr WHERE lr.book_id = b.id AND lr.status IN ('borrowed', 'overdue'))
+     + (SELECT COUNT(*) FROM reservations r WHERE r.book_id = b.id AND r.status = 'ready')
+     + (SELECT COUNT(*) FROM transfers t WHERE t.book_id = b.id AND t.status = 'in_transit')";
+
+// Recomputes available_copies from the lending, hold and transfer records and corrects the
+// books where the stored count had drifted. A book with more copies committed than it owns
+// is set to 0 available rather than a negative count.
+pub async fn reconcile_availability(pool: &SqlitePool) -> Result<Vec<AvailabilityCorrection>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    let corrections = sqlx::query_as::<_, AvailabilityCorrection>(&format!(
+        "SELECT book_id, title, total_copies, recorded, actual FROM (
+             SELECT b.id AS book_id, b.title, b.total_copies, b.available_copies AS recorded,
+                    MAX(0, b.total_copies - ({})) AS actual
+             FROM books b
+         ) WHERE recorded != actual
+         ORDER BY book_id",
+        COMMITTED_COPIES_SQL
+    ))
+    .fetch_all(&mut *tx)
+    .await?;
+    
+    for correction in &corrections {
+        sqlx::query("UPDATE books SET available_copies = ? WHERE id = ?")
+            .bind(correction.actual)
+            .bind(correction.book_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    
+    tx.commit().await?;
+    Ok(corrections)
+}
+
+pub async fn delete_book(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM book_tags WHERE book_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM book_copies WHERE book_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM transfers WHERE book_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM reading_list_entries WHERE book_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM wishlist WHERE book_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM book_watches WHERE book_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM favorites WHERE book_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    // Issues of a deleted title stay in the catalog as standalone periodicals
+    sqlx::query("DELETE FROM periodical_issues WHERE book_id = ? OR title_id = ?")
+        .bind(id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("UPDATE suggestions SET book_id = NULL WHERE book_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM books WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+async fn add_book_tags(tx: &mut sqlx::SqliteConnection, book_id: i64, tags: &[String]) -> Result<(), sqlx::Error> {
+    for tag in tags {
+        sqlx::query("INSERT OR IGNORE INTO book_tags (book_id, tag) VALUES (?, ?)")
+            .bind(book_id)
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+    }
+    
+    Ok(())
+}
+
+// Matches any text field or tag, optionally limited to one item type
+// Free text matches any of title, author, ISBN, genre, and tags; each filter that is set
+// must also hold. Author, title, and ISBN filters match substrings, genre and tag whole values
+pub async fn search_books(pool: &SqlitePool, query: &BookQuery) -> Result<Vec<Book>, sqlx::Error> {
+    // % and _ typed by the user are matched literally
+    let search_pattern = like_contains(&query.text);
+    let author = query.author.as_deref().map(like_contains);
+    let title = query.title.as_deref().map(like_contains);
+    let isbn = query.isbn.as_deref().map(like_contains);
+    
+    let books = sqlx::query_as::<_, Book>(
+        &format!(
+            "SELECT {} FROM books 
+             WHERE (title LIKE ? ESCAPE '\\' OR author LIKE ? ESCAPE '\\' OR isbn LIKE ? ESCAPE '\\' OR genre LIKE ? ESCAPE '\\'
+                OR EXISTS (SELECT 1 FROM book_tags t WHERE t.book_id = books.id AND t.tag LIKE ? ESCAPE '\\'))
+               AND (? IS NULL OR author LIKE ? ESCAPE '\\')
+               AND (? IS NULL OR title LIKE ? ESCAPE '\\')
+               AND (? IS NULL OR isbn LIKE ? ESCAPE '\\')
+               AND (? IS NULL OR genre = ? COLLATE NOCASE)
+               AND (? IS NULL OR EXISTS (SELECT 1 FROM book_tags t WHERE t.book_id = books.id AND t.tag = ? COLLATE NOCASE))
+               AND (? IS NULL OR item_type = ?)
+               AND (? IS NULL OR publication_year >= ?)
+               AND (? IS NULL OR publication_year <= ?)
+               AND (? IS NULL OR (available_copies > 0) = ?)
+             ORDER BY title",
+            BOOK_COLUMNS
+        )
+    )
+    .bind(&search_pattern)
+    .bind(&search_pattern)
+    .bind(&search_pattern)
+    .bind(&search_pattern)
+    .bind(&search_pattern)
+    .bind(&author)
+    .bind(&author)
+    .bind(&title)
+    .bind(&title)
+    .bind(&isbn)
+    .bind(&isbn)
+    .bind(&query.genre)
+    .bind(&query.genre)
+    .bind(&query.tag)
+    .bind(&query.tag)
+    .bind(&query.item_type)
+    .bind(&query.item_type)
+    .bind(query.year_from)
+    .bind(query.year_from)
+    .bind(query.year_to)
+    .bind(query.year_to)
+    .bind(query.available)
+    .bind(query.available)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(books)
+}
+
+// Titles and authors starting with the prefix come first, then those with a later word
+// starting with it
+pub async fn suggest_completions(pool: &SqlitePool, prefix: &str, limit: i64) -> Result<Vec<SearchSuggestion>, sqlx::Error> {
+    let escaped = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    let starts_with = format!("{}%", escaped);
+    let word_starts_with = format!("% {}%", escaped);
+    
+    let suggestions = sqlx::query_as::<_, SearchSuggestion>(
+        "SELECT text, kind FROM (
+            SELECT title AS text, 'title' AS kind, 0 AS rank FROM books WHERE title LIKE ?1 ESCAPE '\\'
+            UNION ALL
+            SELECT author, 'author', 0 FROM books WHERE author LIKE ?1 ESCAPE '\\'
+            UNION ALL
+            SELECT title, 'title', 1 FROM books WHERE title LIKE ?2 ESCAPE '\\'
+            UNION ALL
+            SELECT author, 'author', 1 FROM books WHERE author LIKE ?2 ESCAPE '\\'
+         )
+         GROUP BY text COLLATE NOCASE, kind
+         ORDER BY MIN(rank), kind DESC, text COLLATE NOCASE
+         LIMIT ?3"
+    )
+    .bind(&starts_with)
+    .bind(&word_starts_with)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(suggestions)
+}
+
+// Lending operations
+pub async fn borrow_book(pool: &SqlitePool, user_id: i64, book_id: i64, branch_id: i64, loan_period_days: i64) -> Result<i64, sqlx::Error> {
+    // Check if book is available
+    let book = get_book_by_id(pool, book_id).await?;
+    if book.is_none() {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    let book = book.unwrap();
+    
+    // A copy held for this user on a ready reservation doesn't count against availability
+    let held: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM reservations WHERE user_id = ? AND book_id = ? AND status = 'ready'"
+    )
+    .bind(user_id)
+    .bind(book_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    if held.is_none() && book.available_copies <= 0 {
+        return Err(sqlx::Error::RowNotFound); // Use as "not available" error
+    }
+    
+    let at_branch = get_book_holdings(pool, book_id).await?.into_iter().find(|h| h.branch_id == branch_id);
+    if at_branch.is_none_or(|h| h.available_copies <= 0) {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    // Create lending record, with its loan.borrowed event in the same transaction
+    let borrowed_at = crate::clock::now();
+    let due_date = roll_past_closures(pool, borrowed_at + Duration::days(loan_period_days)).await?;
+    let mut tx = pool.begin().await?;
+    
+    let result = sqlx::query(
+        "INSERT INTO lending_records (user_id, book_id, branch_id, borrowed_at, due_date, status) 
+         VALUES (?, ?, ?, ?, ?, 'borrowed')"
+    )
+    .bind(user_id)
+    .bind(book_id)
+    .bind(branch_id)
+    .bind(borrowed_at.to_rfc3339())
+    .bind(due_date.to_rfc3339())
+    .execute(&mut *tx)
+    .await?;
+    let record_id = result.last_insert_rowid();
+    
+    if let Some(reservation_id) = held {
+        sqlx::query("UPDATE reservations SET status = 'fulfilled', fulfilled_at = ? WHERE id = ?")
+            .bind(borrowed_at.to_rfc3339())
+            .bind(reservation_id)
+            .execute(&mut *tx)
+            .await?;
+    } else {
+        // Decrease available copies
+        sqlx::query("UPDATE books SET available_copies = available_copies - 1 WHERE id = ?")
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    
+    let data = serde_json::json!({ "record_id": record_id, "user_id": user_id, "book_id": book_id, "branch_id": branch_id });
+    insert_event(&mut tx, "loan.borrowed", &data).await?;
+    
+    tx.commit().await?;
+    Ok(record_id)
+}
+
+// Reports the fine assessed for a late return (0 when returned on time)
+pub async fn return_book(pool: &SqlitePool, record_id: i64, user_id: i64, fine_per_day: f64, fine_cap: f64) -> Result<ReturnOutcome, sqlx::Error> {
+    // Get lending record
+    let record = sqlx::query(
+        "SELECT id, user_id, book_id, due_date, status FROM lending_records WHERE id = ?"
+    )
+    .bind(record_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    if record.is_none() {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    let record = record.unwrap();
+    let record_user_id: Option<i64> = record.get("user_id");
+    let book_id: i64 = record.get("book_id");
+    let due_date: String = record.get("due_date");
+    let status: String = record.get("status");
+    
+    if record_user_id != Some(user_id) {
+        return Err(sqlx::Error::RowNotFound); // Not authorized
+    }
+    
+    if status != "borrowed" && status != "overdue" {
+        return Err(sqlx::Error::RowNotFound); // Already returned
+    }
+    
+    // Settle the fine accrued while the loan was overdue, or assess it now if none was
+    let returned_at = crate::clock::now();
+    let due = chrono::DateTime::parse_from_rfc3339(&due_date).map(|d| d.with_timezone(&Utc)).unwrap_or(returned_at);
+    let (days_late, amount) = late_fine(pool, due, returned_at, fine_per_day, fine_cap).await?;
+    let reason = format!("Returned {} days late", days_late);
+    
+    // The return and its loan.returned event commit together. The status check makes a second
+    // return of the same loan racing this one a no-op
+    let mut tx = pool.begin().await?;
+    let updated = sqlx::query(
+        "UPDATE lending_records SET returned_at = ?, status = 'returned' WHERE id = ? AND status IN ('borrowed', 'overdue')"
+    )
+    .bind(returned_at.to_rfc3339())
+    .bind(record_id)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    // Increase available copies
+    sqlx::query("UPDATE books SET available_copies = available_copies + 1 WHERE id = ?")
+        .bind(book_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    let settled = sqlx::query(
+        "UPDATE fines SET amount = ?, reason = ?, accruing = 0,
+            status = CASE WHEN amount_paid >= ? THEN 'paid' ELSE 'unpaid' END,
+            paid_at = CASE WHEN amount_paid >= ? THEN COALESCE(paid_at, ?) ELSE NULL END
+         WHERE lending_record_id = ? AND accruing = 1"
+    )
+    .bind(amount)
+    .bind(&reason)
+    .bind(amount)
+    .bind(amount)
+    .bind(returned_at.to_rfc3339())
+    .bind(record_id)
+    .execute(&mut *tx)
+    .await?;
+    
+    if amount > 0.0 && settled.rows_affected() == 0 {
+        sqlx::query(
+            "INSERT INTO fines (user_id, lending_record_id, amount, status, reason) VALUES (?, ?, ?, 'unpaid', ?)"
+        )
+        .bind(user_id)
+        .bind(record_id)
+        .bind(amount)
+        .bind(&reason)
+        .execute(&mut *tx)
+        .await?;
+    }
+    
+    let data = serde_json::json!({ "record_id": record_id, "user_id": user_id, "book_id": book_id, "fine": amount });
+    insert_event(&mut tx, "loan.returned", &data).await?;
+    
+    tx.commit().await?;
+    Ok(ReturnOutcome { fine: amount, book_id })
+}
+
+// Days late and the fine for them as of `until`: every started day past the due date, skipping
+// days the library was closed, at `fine_per_day` and capped at `cap` (0 for no cap)
+pub async fn late_fine(pool: &SqlitePool, due: chrono::DateTime<Utc>, until: chrono::DateTime<Utc>, fine_per_day: f64, cap: f64) -> Result<(i64, f64), sqlx::Error> {
+    let seconds_late = (until - due).num_seconds();
+    let started_days = if seconds_late > 0 { (seconds_late + 86_399) / 86_400 } else { 0 };
+    let closures = get_closures_between(pool, &date_of(due), &date_of(until)).await?;
+    let days_late = (0..started_days)
+        .filter(|day| !is_closed(&closures, &date_of(due + Duration::days(*day))))
+        .count() as i64;
+    let amount = days_late as f64 * fine_per_day;
+    let amount = if cap > 0.0 { amount.min(cap) } else { amount };
+    
+    Ok((days_late, round_cents(amount)))
+}
+
+// Overdue loans with the borrower's member type, for the nightly fine accrual
+pub async fn get_loans_for_fine_accrual(pool: &SqlitePool) -> Result<Vec<(i64, i64, String, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (i64, i64, String, String)>(
+        "SELECT lr.id, lr.user_id, lr.due_date, u.member_type
+         FROM lending_records lr
+         INNER JOIN users u ON u.id = lr.user_id
+         WHERE lr.status = 'overdue'
+         ORDER BY lr.id"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Brings the running fine of an overdue loan up to `amount`, opening it on the first night.
+// A running fine that was paid off becomes unpaid again as it grows
+pub async fn accrue_fine(pool: &SqlitePool, record_id: i64, user_id: i64, amount: f64, days_late: i64) -> Result<(), sqlx::Error> {
+    let reason = format!("Overdue {} days so far", days_late);
+    let updated = sqlx::query(
+        "UPDATE fines SET amount = ?, reason = ?,
+            status = CASE WHEN amount_paid >= ? THEN 'paid' ELSE 'unpaid' END,
+            paid_at = CASE WHEN amount_paid >= ? THEN paid_at ELSE NULL END
+         WHERE lending_record_id = ? AND accruing = 1"
+    )
+    .bind(amount)
+    .bind(&reason)
+    .bind(amount)
+    .bind(amount)
+    .bind(record_id)
+    .execute(pool)
+    .await?;
+    
+    if updated.rows_affected() == 0 {
+        sqlx::query(
+            "INSERT INTO fines (user_id, lending_record_id, amount, status, reason, accruing) VALUES (?, ?, ?, 'unpaid', ?, 1)"
+        )
+        .bind(user_id)
+        .bind(record_id)
+        .bind(amount)
+        .bind(&reason)
+        .execute(pool)
+        .await?;
+    }
+    
+    Ok(())
+}
+
+fn date_of(t: chrono::DateTime<Utc>) -> String {
+    t.format("%Y-%m-%d").to_string()
+}
+
+fn is_closed(closures: &[Closure], date: &str) -> bool {
+    closures.iter().any(|c| c.starts_on.as_str() <= date && date <= c.ends_on.as_str())
+}
+
+// Moves a due date that lands on a closure day to the same time on the next open day
+pub async fn roll_past_closures(pool: &SqlitePool, due: chrono::DateTime<Utc>) -> Result<chrono::DateTime<Utc>, sqlx::Error> {
+    let closures = get_closures_between(pool, &date_of(due), "9999-12-31").await?;
+    let mut due = due;
+    while is_closed(&closures, &date_of(due)) {
+        due += Duration::days(1);
+    }
+    
+    Ok(due)
+}
+
+pub fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+pub async fn count_active_loans(pool: &SqlitePool, user_id: i64) -> Result<i64, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM lending_records WHERE user_id = ? AND status IN ('borrowed', 'overdue')"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(count)
+}
+
+pub async fn get_user_borrowed_books(pool: &SqlitePool, user_id: i64) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
+    let records = sqlx::query_as::<_, LendingRecordWithDetails>(
+        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
+                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status, lr.recalled_at, lr.branch_id, lr.escalation_level
+         FROM lending_records lr
+         INNER JOIN users u ON lr.user_id = u.id
+         INNER JOIN books b ON lr.book_id = b.id
+         WHERE lr.user_id = ? AND lr.status IN ('borrowed', 'overdue')
+         ORDER BY lr.borrowed_at DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(records)
+}
+
+pub async fn get_user_lending_history(pool: &SqlitePool, user_id: i64) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
+    let records = sqlx::query_as::<_, LendingRecordWithDetails>(
+        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
+                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status, lr.recalled_at, lr.branch_id, lr.escalation_level
+         FROM lending_records lr
+         INNER JOIN users u ON lr.user_id = u.id
+         INNER JOIN books b ON lr.book_id = b.id
+         WHERE lr.user_id = ?
+         ORDER BY lr.borrowed_at DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(records)
+}
+
+pub async fn get_loans_due_for_reminder(pool: &SqlitePool, before: &str) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
+    let records = sqlx::query_as::<_, LendingRecordWithDetails>(
+        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
+                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status, lr.recalled_at, lr.branch_id, lr.escalation_level
+         FROM lending_records lr
+         INNER JOIN users u ON lr.user_id = u.id
+         INNER JOIN books b ON lr.book_id = b.id
+         WHERE lr.status = 'borrowed' AND lr.due_date <= ? AND lr.reminder_sent_at IS NULL
+         ORDER BY lr.due_date ASC"
+    )
+    .bind(before)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(records)
+}
+
+// Overdue loans that have not yet reached the last escalation level
+pub async fn get_loans_to_escalate(pool: &SqlitePool) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
+    sqlx::query_as::<_, LendingRecordWithDetails>(
+        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
+                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status, lr.recalled_at, lr.branch_id, lr.escalation_level
+         FROM lending_records lr
+         INNER JOIN users u ON lr.user_id = u.id
+         INNER JOIN books b ON lr.book_id = b.id
+         WHERE lr.status = 'overdue' AND (lr.escalation_level IS NULL OR lr.escalation_level != 'billed')
+         ORDER BY lr.due_date ASC"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn set_escalation_level(pool: &SqlitePool, record_id: i64, level: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE lending_records SET escalation_level = ?, escalated_at = ? WHERE id = ?")
+        .bind(level)
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(record_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Moves an overdue loan to the billed level and charges the member `amount` for the item
+pub async fn bill_overdue_loan(pool: &SqlitePool, record: &LendingRecordWithDetails, amount: f64, reason: &str) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    sqlx::query("UPDATE lending_records SET escalation_level = 'billed', escalated_at = ? WHERE id = ?")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(record.id)
+        .execute(&mut *tx)
+        .await?;
+    
+    if amount > 0.0 {
+        sqlx::query(
+            "INSERT INTO fines (user_id, lending_record_id, amount, status, reason) VALUES (?, ?, ?, 'unpaid', ?)"
+        )
+        .bind(record.user_id)
+        .bind(record.id)
+        .bind(round_cents(amount))
+        .bind(reason)
+        .execute(&mut *tx)
+        .await?;
+    }
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn mark_reminder_sent(pool: &SqlitePool, record_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE lending_records SET reminder_sent_at = ? WHERE id = ?")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(record_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Sort keys accepted for the active loan listing and the column each orders by
+pub const LENDING_SORTS: &[(&str, &str)] = &[
+    ("borrowed_at", "lr.borrowed_at"),
+    ("due_date", "lr.due_date"),
+    ("username", "u.username COLLATE NOCASE"),
+    ("title", "b.title COLLATE NOCASE"),
+];
+
+// Username and title match substrings, case-insensitively; `borrowed_from` and `borrowed_to`
+// are YYYY-MM-DD and bound the borrowing day inclusively
+pub async fn get_all_active_lending(pool: &SqlitePool, query: &LendingQuery) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
+    if query.overdue_only {
+        mark_overdue_records(pool).await?;
+    }
+    let username = query.username.as_deref().map(like_contains);
+    let title = query.title.as_deref().map(like_contains);
+    let sort = LENDING_SORTS.iter().find(|(key, _)| *key == query.sort).map(|(_, column)| *column).unwrap_or("lr.borrowed_at");
+    let direction = if query.descending { "DESC" } else { "ASC" };
+    
+    let records = sqlx::query_as::<_, LendingRecordWithDetails>(&format!(
+        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
+                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status, lr.recalled_at, lr.branch_id, lr.escalation_level
+         FROM lending_records lr
+         INNER JOIN users u ON lr.user_id = u.id
+         INNER JOIN books b ON lr.book_id = b.id
+         WHERE lr.status IN ('borrowed', 'overdue')
+           AND (? IS NULL OR lr.branch_id = ?)
+           AND (? IS NULL OR u.username LIKE ? ESCAPE '\\')
+           AND (? IS NULL OR b.title LIKE ? ESCAPE '\\')
+           AND (? IS NULL OR substr(lr.borrowed_at, 1, 10) >= ?)
+           AND (? IS NULL OR substr(lr.borrowed_at, 1, 10) <= ?)
+           AND (? = 0 OR lr.status = 'overdue')
+         ORDER BY {} {}, lr.id {}
+         LIMIT ? OFFSET ?",
+        sort, direction, direction
+    ))
+    .bind(query.branch_id)
+    .bind(query.branch_id)
+    .bind(&username)
+    .bind(&username)
+    .bind(&title)
+    .bind(&title)
+    .bind(&query.borrowed_from)
+    .bind(&query.borrowed_from)
+    .bind(&query.borrowed_to)
+    .bind(&query.borrowed_to)
+    .bind(query.overdue_only)
+    .bind(query.limit.unwrap_or(-1))
+    .bind(query.offset)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(records)
+}
+
+// Reading statistics for the member's "year in books" view; `year_start` is YYYY-01-01
+pub async fn get_reading_stats(pool: &SqlitePool, user_id: i64, year_start: &str) -> Result<ReadingStats, sqlx::Error> {
+    let books_read_this_year: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM lending_records WHERE user_id = ? AND status = 'returned' AND returned_at >= ?"
+    )
+    .bind(user_id)
+    .bind(year_start)
+    .fetch_one(pool)
+    .await?;
+    
+    let total_loans: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM lending_records WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    
+    let average_loan_days: Option<f64> = sqlx::query_scalar(
+        "SELECT ROUND(AVG(julianday(returned_at) - julianday(borrowed_at)), 1) 
+         FROM lending_records WHERE user_id = ? AND returned_at IS NOT NULL"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    
+    let favorite_genres = get_top_genres(pool, user_id, 3).await?;
+    
+    let months: Vec<String> = sqlx::query_scalar(
+        "SELECT DISTINCT substr(borrowed_at, 1, 7) FROM lending_records WHERE user_id = ? ORDER BY 1 DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(ReadingStats {
+        year: year_start[..4].to_string(),
+        books_read_this_year,
+        total_loans,
+        average_loan_days,
+        favorite_genres,
+        current_streak_months: month_streak(&months),
+    })
+}
+
+// Total, current and overdue loan counts; loans past due count as overdue even before the sweep marks them
+pub async fn get_loan_counts(pool: &SqlitePool, user_id: i64) -> Result<(i64, i64, i64), sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT COUNT(*) AS total,
+                COALESCE(SUM(CASE WHEN status IN ('borrowed', 'overdue') THEN 1 ELSE 0 END), 0) AS current,
+                COALESCE(SUM(CASE WHEN status = 'overdue' OR (status = 'borrowed' AND due_date < ?) THEN 1 ELSE 0 END), 0) AS overdue
+         FROM lending_records WHERE user_id = ?"
+    )
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok((row.get("total"), row.get("current"), row.get("overdue")))
+}
+
+pub async fn get_top_genres(pool: &SqlitePool, user_id: i64, limit: i64) -> Result<Vec<GenreCount>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT b.genre, COUNT(*) AS loans FROM lending_records lr
+         INNER JOIN books b ON lr.book_id = b.id
+         WHERE lr.user_id = ? AND b.genre IS NOT NULL AND b.genre <> ''
+         GROUP BY b.genre
+         ORDER BY loans DESC, b.genre ASC
+         LIMIT ?"
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(rows.iter().map(|row| GenreCount { genre: row.get("genre"), loans: row.get("loans") }).collect())
+}
+
+// Consecutive calendar months with at least one loan, counting back from this month
+// (or last month, so the streak isn't lost on the first of the month)
+fn month_streak(months_desc: &[String]) -> i64 {
+    let mut expected = crate::clock::now().date_naive().with_day(1).unwrap_or_default();
+    let mut streak = 0;
+    
+    for (i, month) in months_desc.iter().enumerate() {
+        let Ok(date) = chrono::NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d") else { break };
+        if i == 0 && date < expected {
+            expected = expected.pred_opt().and_then(|d| d.with_day(1)).unwrap_or(expected);
+        }
+        if date != expected {
+            break;
+        }
+        streak += 1;
+        expected = expected.pred_opt().and_then(|d| d.with_day(1)).unwrap_or(expected);
+    }
+    
+    streak
+}
+
+pub async fn get_lending_record_by_id(pool: &SqlitePool, id: i64) -> Result<Option<LendingRecordWithDetails>, sqlx::Error> {
+    let record = sqlx::query_as::<_, LendingRecordWithDetails>(
+        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
+                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status, lr.recalled_at, lr.branch_id, lr.escalation_level
+         FROM lending_records lr
+         INNER JOIN users u ON lr.user_id = u.id
+         INNER JOIN books b ON lr.book_id = b.id
+         WHERE lr.id = ?"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(record)
+}
+
+// Lending note operations
+pub async fn create_lending_note(pool: &SqlitePool, record_id: i64, author_id: i64, note: &str) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO lending_notes (lending_record_id, author_id, note, created_at) VALUES (?, ?, ?, ?)"
+    )
+    .bind(record_id)
+    .bind(author_id)
+    .bind(note)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_lending_notes(pool: &SqlitePool, record_id: i64) -> Result<Vec<LendingNote>, sqlx::Error> {
+    let notes = sqlx::query_as::<_, LendingNote>(
+        "SELECT n.id, n.lending_record_id, n.author_id, u.username AS author, n.note, n.created_at
+         FROM lending_notes n
+         INNER JOIN users u ON n.author_id = u.id
+         WHERE n.lending_record_id = ?
+         ORDER BY n.created_at ASC"
+    )
+    .bind(record_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(notes)
+}
+
+pub async fn get_lending_notes_for_borrower(pool: &SqlitePool, user_id: i64) -> Result<Vec<LendingNote>, sqlx::Error> {
+    let notes = sqlx::query_as::<_, LendingNote>(
+        "SELECT n.id, n.lending_record_id, n.author_id, u.username AS author, n.note, n.created_at
+         FROM lending_notes n
+         INNER JOIN users u ON n.author_id = u.id
+         INNER JOIN lending_records lr ON n.lending_record_id = lr.id
+         WHERE lr.user_id = ?
+         ORDER BY n.created_at ASC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(notes)
+}
+
+// Pulls the due date of an active loan forward to `due_date`, keeping the original
+// date for reference. The overdue sweep then treats the recall deadline like any due date.
+pub async fn recall_loan(pool: &SqlitePool, id: i64, due_date: &str) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE lending_records 
+         SET original_due_date = due_date, due_date = ?, recalled_at = ?, escalation_level = NULL, escalated_at = NULL
+         WHERE id = ? AND status IN ('borrowed', 'overdue') AND recalled_at IS NULL"
+    )
+    .bind(due_date)
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+pub async fn mark_overdue_records(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    
+    let result = sqlx::query(
+        "UPDATE lending_records SET status = 'overdue' 
+         WHERE status = 'borrowed' AND due_date < ?"
+    )
+    .bind(&now)
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected())
+}
+
+// Moves a loan's start and due date back by the given number of days, keeping its length; used
+// to give demo data loans of different ages
+pub async fn backdate_loan(pool: &SqlitePool, record_id: i64, days: i64) -> Result<(), sqlx::Error> {
+    let (borrowed_at, due_date): (String, String) = sqlx::query_as("SELECT borrowed_at, due_date FROM lending_records WHERE id = ?")
+        .bind(record_id)
+        .fetch_one(pool)
+        .await?;
+    let shift = |timestamp: &str| {
+        chrono::DateTime::parse_from_rfc3339(timestamp).map(|t| (t.with_timezone(&Utc) - Duration::days(days)).to_rfc3339())
+    };
+    let (Ok(borrowed_at), Ok(due_date)) = (shift(&borrowed_at), shift(&due_date)) else {
+        return Err(sqlx::Error::Protocol("Unparsable loan dates".to_string()));
+    };
+    
+    sqlx::query("UPDATE lending_records SET borrowed_at = ?, due_date = ? WHERE id = ?")
+        .bind(borrowed_at)
+        .bind(due_date)
+        .bind(record_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Overdue loans, optionally only those at one escalation level ("none" for not yet escalated)
+pub async fn get_overdue_books(pool: &SqlitePool, branch_id: Option<i64>, level: Option<&str>) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
+    // First update overdue status
+    mark_overdue_records(pool).await?;
+    
+    let records = sqlx::query_as::<_, LendingRecordWithDetails>(
+        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
+                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status, lr.recalled_at, lr.branch_id, lr.escalation_level
+         FROM lending_records lr
+         INNER JOIN users u ON lr.user_id = u.id
+         INNER JOIN books b ON lr.book_id = b.id
+         WHERE lr.status = 'overdue' AND (? IS NULL OR lr.branch_id = ?)
+           AND (? IS NULL OR COALESCE(lr.escalation_level, 'none') = ?)
+         ORDER BY lr.due_date ASC"
+    )
+    .bind(branch_id)
+    .bind(branch_id)
+    .bind(level)
+    .bind(level)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(records)
+}
+
+// Reservation operations
+const RESERVATION_COLUMNS: &str = "r.id, r.user_id, u.username, u.email, r.book_id, b.title, b.author, r.status, 
+    r.created_at, r.ready_at, r.expires_at, r.fulfilled_at,
+    CASE WHEN r.status = 'waiting' THEN (
+        SELECT COUNT(*) FROM reservations q 
+        WHERE q.book_id = r.book_id AND q.status = 'waiting' AND q.id <= r.id
+    ) END AS queue_position";
+
+pub async fn create_reservation(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO reservations (user_id, book_id, status, created_at) VALUES (?, ?, 'waiting', ?)"
+    )
+    .bind(user_id)
+    .bind(book_id)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_reservation_by_id(pool: &SqlitePool, id: i64) -> Result<Option<ReservationWithDetails>, sqlx::Error> {
+    let reservation = sqlx::query_as::<_, ReservationWithDetails>(
+        &format!(
+            "SELECT {} FROM reservations r
+             INNER JOIN users u ON u.id = r.user_id
+             INNER JOIN books b ON b.id = r.book_id
+             WHERE r.id = ?",
+            RESERVATION_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(reservation)
+}
+
+pub async fn has_active_reservation(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM reservations WHERE user_id = ? AND book_id = ? AND status IN ('waiting', 'ready')"
+    )
+    .bind(user_id)
+    .bind(book_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(count > 0)
+}
+
+pub async fn count_active_reservations(pool: &SqlitePool, user_id: i64) -> Result<i64, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM reservations WHERE user_id = ? AND status IN ('waiting', 'ready')"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(count)
+}
+
+pub async fn get_user_reservations(pool: &SqlitePool, user_id: i64, active_only: bool) -> Result<Vec<ReservationWithDetails>, sqlx::Error> {
+    let reservations = sqlx::query_as::<_, ReservationWithDetails>(
+        &format!(
+            "SELECT {} FROM reservations r
+             INNER JOIN users u ON u.id = r.user_id
+             INNER JOIN books b ON b.id = r.book_id
+             WHERE r.user_id = ? AND (? = 0 OR r.status IN ('waiting', 'ready'))
+             ORDER BY r.created_at DESC",
+            RESERVATION_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .bind(active_only)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(reservations)
+}
+
+pub async fn get_all_active_reservations(pool: &SqlitePool) -> Result<Vec<ReservationWithDetails>, sqlx::Error> {
+    let reservations = sqlx::query_as::<_, ReservationWithDetails>(
+        &format!(
+            "SELECT {} FROM reservations r
+             INNER JOIN users u ON u.id = r.user_id
+             INNER JOIN books b ON b.id = r.book_id
+             WHERE r.status IN ('waiting', 'ready')
+             ORDER BY r.book_id, r.id",
+            RESERVATION_COLUMNS
+        )
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(reservations)
+}
+
+// Moves a waiting or ready reservation to a terminal status. A copy that was being held
+// goes back to the shelf; call allocate_holds afterwards to pass it down the queue.
+pub async fn close_reservation(pool: &SqlitePool, id: i64, status: &str) -> Result<(), sqlx::Error> {
+    let reservation = sqlx::query("SELECT book_id, status FROM reservations WHERE id = ? AND status IN ('waiting', 'ready')")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+    let book_id: i64 = reservation.get("book_id");
+    let previous: String = reservation.get("status");
+    
+    sqlx::query("UPDATE reservations SET status = ? WHERE id = ?")
+        .bind(status)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if previous == "ready" {
+        sqlx::query("UPDATE books SET available_copies = available_copies + 1 WHERE id = ?")
+            .bind(book_id)
+            .execute(pool)
+            .await?;
+    }
+    
+    Ok(())
+}
+
+// Hands available copies of a book to the front of its reservation queue, holding each
+// one for `hold_days`. Returns the reservations that just became ready for pickup.
+pub async fn allocate_holds(pool: &SqlitePool, book_id: i64, hold_days: i64) -> Result<Vec<ReservationWithDetails>, sqlx::Error> {
+    let mut promoted = Vec::new();
+    
+    loop {
+        let now = crate::clock::now();
+        let result = sqlx::query(
+            "UPDATE reservations SET status = 'ready', ready_at = ?, expires_at = ?
+             WHERE id = (SELECT id FROM reservations WHERE book_id = ? AND status = 'waiting' ORDER BY id LIMIT 1)
+               AND (SELECT available_copies FROM books WHERE id = ?) > 0
+             RETURNING id"
+        )
+        .bind(now.to_rfc3339())
+        .bind((now + Duration::days(hold_days)).to_rfc3339())
+        .bind(book_id)
+        .bind(book_id)
+        .fetch_optional(pool)
+        .await?;
+        
+        let Some(row) = result else { break };
+        let id: i64 = row.get("id");
+        
+        sqlx::query("UPDATE books SET available_copies = available_copies - 1 WHERE id = ?")
+            .bind(book_id)
+            .execute(pool)
+            .await?;
+        
+        if let Some(reservation) = get_reservation_by_id(pool, id).await? {
+            promoted.push(reservation);
+        }
+    }
+    
+    Ok(promoted)
+}
+
+pub async fn get_expired_holds(pool: &SqlitePool) -> Result<Vec<ReservationWithDetails>, sqlx::Error> {
+    let reservations = sqlx::query_as::<_, ReservationWithDetails>(
+        &format!(
+            "SELECT {} FROM reservations r
+             INNER JOIN users u ON u.id = r.user_id
+             INNER JOIN books b ON b.id = r.book_id
+             WHERE r.status = 'ready' AND r.expires_at <= ?",
+            RESERVATION_COLUMNS
+        )
+    )
+    .bind(crate::clock::now().to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(reservations)
+}
+
+// Extension request operations
+const EXTENSION_REQUEST_COLUMNS: &str = "e.id, e.lending_record_id, e.user_id, u.username, u.email, b.title, lr.due_date, 
+    e.reason, e.requested_days, e.status, e.decision_note, e.decided_by, e.decided_at, e.created_at";
+
+pub async fn create_extension_request(pool: &SqlitePool, record_id: i64, user_id: i64, reason: &str, requested_days: i64) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO extension_requests (lending_record_id, user_id, reason, requested_days, status, created_at) 
+         VALUES (?, ?, ?, ?, 'pending', ?)"
+    )
+    .bind(record_id)
+    .bind(user_id)
+    .bind(reason)
+    .bind(requested_days)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn has_pending_extension_request(pool: &SqlitePool, record_id: i64) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM extension_requests WHERE lending_record_id = ? AND status = 'pending'"
+    )
+    .bind(record_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(count > 0)
+}
+
+pub async fn get_extension_request_by_id(pool: &SqlitePool, id: i64) -> Result<Option<ExtensionRequest>, sqlx::Error> {
+    let request = sqlx::query_as::<_, ExtensionRequest>(
+        &format!(
+            "SELECT {} FROM extension_requests e
+             INNER JOIN users u ON u.id = e.user_id
+             INNER JOIN lending_records lr ON lr.id = e.lending_record_id
+             INNER JOIN books b ON b.id = lr.book_id
+             WHERE e.id = ?",
+            EXTENSION_REQUEST_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(request)
+}
+
+pub async fn get_extension_requests(pool: &SqlitePool, status: &str) -> Result<Vec<ExtensionRequest>, sqlx::Error> {
+    let requests = sqlx::query_as::<_, ExtensionRequest>(
+        &format!(
+            "SELECT {} FROM extension_requests e
+             INNER JOIN users u ON u.id = e.user_id
+             INNER JOIN lending_records lr ON lr.id = e.lending_record_id
+             INNER JOIN books b ON b.id = lr.book_id
+             WHERE e.status = ?
+             ORDER BY e.created_at ASC",
+            EXTENSION_REQUEST_COLUMNS
+        )
+    )
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(requests)
+}
+
+pub async fn get_user_extension_requests(pool: &SqlitePool, user_id: i64) -> Result<Vec<ExtensionRequest>, sqlx::Error> {
+    let requests = sqlx::query_as::<_, ExtensionRequest>(
+        &format!(
+            "SELECT {} FROM extension_requests e
+             INNER JOIN users u ON u.id = e.user_id
+             INNER JOIN lending_records lr ON lr.id = e.lending_record_id
+             INNER JOIN books b ON b.id = lr.book_id
+             WHERE e.user_id = ?
+             ORDER BY e.created_at DESC",
+            EXTENSION_REQUEST_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(requests)
+}
+
+// Records the librarian's decision; an approval moves the loan's due date to `new_due_date`
+// and clears the overdue flag and due-soon reminder so both apply to the new date
+pub async fn decide_extension_request(
+    pool: &SqlitePool,
+    id: i64,
+    decided_by: i64,
+    note: Option<&str>,
+    new_due_date: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    let status = if new_due_date.is_some() { "approved" } else { "denied" };
+    let mut tx = pool.begin().await?;
+    
+    let result = sqlx::query(
+        "UPDATE extension_requests SET status = ?, decision_note = ?, decided_by = ?, decided_at = ? 
+         WHERE id = ? AND status = 'pending'"
+    )
+    .bind(status)
+    .bind(note)
+    .bind(decided_by)
+    .bind(&now)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    if let Some(due_date) = new_due_date {
+        sqlx::query(
+            "UPDATE lending_records 
+             SET due_date = ?, reminder_sent_at = NULL, escalation_level = NULL, escalated_at = NULL,
+                 status = CASE WHEN status = 'overdue' AND ? > ? THEN 'borrowed' ELSE status END
+             WHERE id = (SELECT lending_record_id FROM extension_requests WHERE id = ?)
+               AND status IN ('borrowed', 'overdue')"
+        )
+        .bind(due_date)
+        .bind(due_date)
+        .bind(&now)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+// Suggestion operations
+const SUGGESTION_COLUMNS: &str = "s.id, s.user_id, u.username, u.email, s.title, s.author, s.isbn, s.reason, 
+    s.status, s.decision_note, s.decided_by, s.decided_at, s.book_id, s.created_at";
+
+pub async fn create_suggestion(pool: &SqlitePool, user_id: i64, req: &CreateSuggestionRequest) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO suggestions (user_id, title, author, isbn, reason, status, created_at) 
+         VALUES (?, ?, ?, ?, ?, 'pending', ?)"
+    )
+    .bind(user_id)
+    .bind(&req.title)
+    .bind(&req.author)
+    .bind(&req.isbn)
+    .bind(&req.reason)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_suggestion_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Suggestion>, sqlx::Error> {
+    let suggestion = sqlx::query_as::<_, Suggestion>(
+        &format!(
+            "SELECT {} FROM suggestions s
+             INNER JOIN users u ON u.id = s.user_id
+             WHERE s.id = ?",
+            SUGGESTION_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(suggestion)
+}
+
+pub async fn get_suggestions(pool: &SqlitePool, status: &str) -> Result<Vec<Suggestion>, sqlx::Error> {
+    let suggestions = sqlx::query_as::<_, Suggestion>(
+        &format!(
+            "SELECT {} FROM suggestions s
+             INNER JOIN users u ON u.id = s.user_id
+             WHERE s.status = ?
+             ORDER BY s.created_at ASC",
+            SUGGESTION_COLUMNS
+        )
+    )
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(suggestions)
+}
+
+pub async fn get_user_suggestions(pool: &SqlitePool, user_id: i64) -> Result<Vec<Suggestion>, sqlx::Error> {
+    let suggestions = sqlx::query_as::<_, Suggestion>(
+        &format!(
+            "SELECT {} FROM suggestions s
+             INNER JOIN users u ON u.id = s.user_id
+             WHERE s.user_id = ?
+             ORDER BY s.created_at DESC",
+            SUGGESTION_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(suggestions)
+}
+
+// Records the decision; `order` adds the book to the catalog with no copies, marked on order,
+// or links the suggestion to the existing book when the ISBN is already catalogued
+pub async fn decide_suggestion(
+    pool: &SqlitePool,
+    id: i64,
+    decided_by: i64,
+    status: &str,
+    note: Option<&str>,
+    order: Option<&CreateBookRequest>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    let book_id = match order {
+        Some(book) => {
+            let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM books WHERE isbn = ?")
+                .bind(&book.isbn)
+                .fetch_optional(&mut *tx)
+                .await?;
+            match existing {
+                Some(book_id) => Some(book_id),
+                None => {
+                    let result = sqlx::query(
+                        "INSERT INTO books (title, author, isbn, publication_year, genre, total_copies, available_copies, on_order) 
+                         VALUES (?, ?, ?, ?, ?, 0, 0, 1)"
+                    )
+                    .bind(&book.title)
+                    .bind(&book.author)
+                    .bind(&book.isbn)
+                    .bind(book.publication_year)
+                    .bind(&book.genre)
+                    .execute(&mut *tx)
+                    .await?;
+                    Some(result.last_insert_rowid())
+                }
+            }
+        }
+        None => None,
+    };
+    
+    let result = sqlx::query(
+        "UPDATE suggestions SET status = ?, decision_note = ?, decided_by = ?, decided_at = ?, book_id = ? 
+         WHERE id = ? AND status = 'pending'"
+    )
+    .bind(status)
+    .bind(note)
+    .bind(decided_by)
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(book_id)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    tx.commit().await?;
+    Ok(book_id)
+}
+
+// Donation operations
+const DONATION_COLUMNS: &str = "d.id, d.user_id, u.username, u.email, d.title, d.author, d.isbn, d.publication_year, d.genre, 
+    d.copies, d.notes, d.status, d.decision_note, d.decided_by, d.decided_at, d.book_id, d.created_at";
+
+pub async fn create_donation(pool: &SqlitePool, user_id: i64, req: &CreateDonationRequest, copies: i64) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO donations (user_id, title, author, isbn, publication_year, genre, copies, notes, status, created_at) 
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'pending', ?)"
+    )
+    .bind(user_id)
+    .bind(&req.title)
+    .bind(&req.author)
+    .bind(&req.isbn)
+    .bind(req.publication_year)
+    .bind(&req.genre)
+    .bind(copies)
+    .bind(&req.notes)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_donation_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Donation>, sqlx::Error> {
+    let donation = sqlx::query_as::<_, Donation>(
+        &format!(
+            "SELECT {} FROM donations d
+             INNER JOIN users u ON u.id = d.user_id
+             WHERE d.id = ?",
+            DONATION_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(donation)
+}
+
+pub async fn get_donations(pool: &SqlitePool, status: &str) -> Result<Vec<Donation>, sqlx::Error> {
+    let donations = sqlx::query_as::<_, Donation>(
+        &format!(
+            "SELECT {} FROM donations d
+             INNER JOIN users u ON u.id = d.user_id
+             WHERE d.status = ?
+             ORDER BY d.created_at ASC",
+            DONATION_COLUMNS
+        )
+    )
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(donations)
+}
+
+pub async fn get_user_donations(pool: &SqlitePool, user_id: i64) -> Result<Vec<Donation>, sqlx::Error> {
+    let donations = sqlx::query_as::<_, Donation>(
+        &format!(
+            "SELECT {} FROM donations d
+             INNER JOIN users u ON u.id = d.user_id
+             WHERE d.user_id = ?
+             ORDER BY d.created_at DESC",
+            DONATION_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(donations)
+}
+
+pub async fn decline_donation(pool: &SqlitePool, id: i64, decided_by: i64, note: Option<&str>) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE donations SET status = 'declined', decision_note = ?, decided_by = ?, decided_at = ? 
+         WHERE id = ? AND status = 'pending'"
+    )
+    .bind(note)
+    .bind(decided_by)
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Adds the received copies to the catalog record with the donation's ISBN, creating it if needed,
+// and credits the donor in the acquisitions log. Returns the book id.
+pub async fn accept_donation(pool: &SqlitePool, id: i64, decided_by: i64, copies: i64, branch_id: i64, note: Option<&str>) -> Result<i64, sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    
+    let donation = sqlx::query_as::<_, Donation>(
+        &format!(
+            "SELECT {} FROM donations d
+             INNER JOIN users u ON u.id = d.user_id
+             WHERE d.id = ? AND d.status = 'pending'",
+            DONATION_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)?;
+    
+    let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM books WHERE isbn = ?")
+        .bind(&donation.isbn)
+        .fetch_optional(&mut *tx)
+        .await?;
+    
+    let book_id = match existing {
+        Some(book_id) => {
+            sqlx::query(
+                "UPDATE books SET total_copies = total_copies + ?, available_copies = available_copies + ?, on_order = 0, 
+                 version = version + 1 WHERE id = ?"
+            )
+            .bind(copies)
+            .bind(copies)
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+            book_id
+        }
+        None => {
+            let result = sqlx::query(
+                "INSERT INTO books (title, author, isbn, publication_year, genre, total_copies, available_copies) 
+                 VALUES (?, ?, ?, ?, ?, ?, ?)"
+            )
+            .bind(&donation.title)
+            .bind(&donation.author)
+            .bind(&donation.isbn)
+            .bind(donation.publication_year)
+            .bind(&donation.genre)
+            .bind(copies)
+            .bind(copies)
+            .execute(&mut *tx)
+            .await?;
+            result.last_insert_rowid()
+        }
+    };
+    
+    sqlx::query(
+        "INSERT INTO book_copies (book_id, branch_id, copies) VALUES (?, ?, ?) 
+         ON CONFLICT(book_id, branch_id) DO UPDATE SET copies = copies + excluded.copies"
+    )
+    .bind(book_id)
+    .bind(branch_id)
+    .bind(copies)
+    .execute(&mut *tx)
+    .await?;
+    
+    sqlx::query(
+        "UPDATE donations SET status = 'accepted', copies = ?, decision_note = ?, decided_by = ?, decided_at = ?, book_id = ? 
+         WHERE id = ?"
+    )
+    .bind(copies)
+    .bind(note)
+    .bind(decided_by)
+    .bind(&now)
+    .bind(book_id)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    
+    sqlx::query(
+        "INSERT INTO acquisitions (book_id, copies, source, donor_id, donation_id, recorded_by, created_at) 
+         VALUES (?, ?, 'donation', ?, ?, ?, ?)"
+    )
+    .bind(book_id)
+    .bind(copies)
+    .bind(donation.user_id)
+    .bind(id)
+    .bind(decided_by)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    Ok(book_id)
+}
+
+pub async fn get_acquisitions(pool: &SqlitePool) -> Result<Vec<Acquisition>, sqlx::Error> {
+    let acquisitions = sqlx::query_as::<_, Acquisition>(
+        "SELECT a.id, a.book_id, b.title, a.copies, a.source, a.donor_id, u.username AS donor, 
+                a.donation_id, a.recorded_by, a.created_at
+         FROM acquisitions a
+         INNER JOIN books b ON b.id = a.book_id
+         LEFT JOIN users u ON u.id = a.donor_id
+         ORDER BY a.created_at DESC"
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(acquisitions)
+}
+
+// Fine operations
+const FINE_COLUMNS: &str = "f.id, f.user_id, f.lending_record_id, b.title, f.amount, f.amount_paid, f.status, f.reason, f.created_at, f.paid_at, f.accruing";
+
+pub async fn get_fine_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Fine>, sqlx::Error> {
+    let fine = sqlx::query_as::<_, Fine>(
+        &format!(
+            "SELECT {} FROM fines f
+             INNER JOIN lending_records lr ON lr.id = f.lending_record_id
+             INNER JOIN books b ON b.id = lr.book_id
+             WHERE f.id = ?",
+            FINE_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(fine)
+}
+
+pub async fn get_user_fines(pool: &SqlitePool, user_id: i64) -> Result<Vec<Fine>, sqlx::Error> {
+    let fines = sqlx::query_as::<_, Fine>(
+        &format!(
+            "SELECT {} FROM fines f
+             INNER JOIN lending_records lr ON lr.id = f.lending_record_id
+             INNER JOIN books b ON b.id = lr.book_id
+             WHERE f.user_id = ?
+             ORDER BY f.created_at DESC",
+            FINE_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(fines)
+}
+
+pub async fn get_unpaid_fine_balance(pool: &SqlitePool, user_id: i64) -> Result<f64, sqlx::Error> {
+    let balance: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(amount - amount_paid), 0.0) FROM fines WHERE user_id = ? AND status = 'unpaid'"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(round_cents(balance))
+}
+
+// Records a (possibly partial) payment and marks the fine paid once nothing is owed
+// The balance is checked by the update itself, so two payments racing for the same fine can't
+// both go through; RowNotFound when the fine no longer owes that much
+pub async fn record_fine_payment(pool: &SqlitePool, fine_id: i64, req: &PayFineRequest, recorded_by: i64) -> Result<i64, sqlx::Error> {
+    let paid_at = crate::clock::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    
+    let updated = sqlx::query(
+        "UPDATE fines SET amount_paid = ROUND(amount_paid + ?, 2),
+            status = CASE WHEN ROUND(amount_paid + ?, 2) >= amount THEN 'paid' ELSE 'unpaid' END,
+            paid_at = CASE WHEN ROUND(amount_paid + ?, 2) >= amount THEN ? ELSE NULL END
+         WHERE id = ? AND status != 'paid' AND ROUND(amount - amount_paid, 2) >= ?"
+    )
+    .bind(req.amount)
+    .bind(req.amount)
+    .bind(req.amount)
+    .bind(&paid_at)
+    .bind(fine_id)
+    .bind(req.amount)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    let result = sqlx::query(
+        "INSERT INTO fine_payments (fine_id, amount, method, note, recorded_by, paid_at) VALUES (?, ?, ?, ?, ?, ?)"
+    )
+    .bind(fine_id)
+    .bind(req.amount)
+    .bind(&req.method)
+    .bind(&req.note)
+    .bind(recorded_by)
+    .bind(&paid_at)
+    .execute(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_fine_payment_by_id(pool: &SqlitePool, id: i64) -> Result<Option<FinePayment>, sqlx::Error> {
+    let payment = sqlx::query_as::<_, FinePayment>(
+        "SELECT id, fine_id, amount, method, note, recorded_by, paid_at FROM fine_payments WHERE id = ?"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(payment)
+}
+
+pub async fn get_user_fine_payments(pool: &SqlitePool, user_id: i64) -> Result<Vec<FinePayment>, sqlx::Error> {
+    let payments = sqlx::query_as::<_, FinePayment>(
+        "SELECT p.id, p.fine_id, p.amount, p.method, p.note, p.recorded_by, p.paid_at 
+         FROM fine_payments p
+         INNER JOIN fines f ON f.id = p.fine_id
+         WHERE f.user_id = ?
+         ORDER BY p.paid_at DESC"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(payments)
+}
+
+// Member type operations
+pub async fn get_member_type(pool: &SqlitePool, name: &str) -> Result<Option<MemberType>, sqlx::Error> {
+    let member_type = sqlx::query_as::<_, MemberType>(
+        "SELECT name, loan_period_days, max_concurrent_loans, fine_per_day FROM member_types WHERE name = ?"
+    )
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(member_type)
+}
+
+pub async fn get_all_member_types(pool: &SqlitePool) -> Result<Vec<MemberType>, sqlx::Error> {
+    let member_types = sqlx::query_as::<_, MemberType>(
+        "SELECT name, loan_period_days, max_concurrent_loans, fine_per_day FROM member_types ORDER BY name"
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(member_types)
+}
+
+pub async fn get_item_type(pool: &SqlitePool, name: &str) -> Result<Option<ItemType>, sqlx::Error> {
+    let item_type = sqlx::query_as::<_, ItemType>("SELECT name, loan_period_days FROM item_types WHERE name = ?")
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+    
+    Ok(item_type)
+}
+
+pub async fn get_all_item_types(pool: &SqlitePool) -> Result<Vec<ItemType>, sqlx::Error> {
+    let item_types = sqlx::query_as::<_, ItemType>("SELECT name, loan_period_days FROM item_types ORDER BY name")
+        .fetch_all(pool)
+        .await?;
+    
+    Ok(item_types)
+}
+
+pub async fn upsert_item_type(pool: &SqlitePool, item_type: &ItemType) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO item_types (name, loan_period_days) VALUES (?, ?)
+         ON CONFLICT(name) DO UPDATE SET loan_period_days = excluded.loan_period_days"
+    )
+    .bind(&item_type.name)
+    .bind(item_type.loan_period_days)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+pub async fn upsert_member_type(pool: &SqlitePool, member_type: &MemberType) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO member_types (name, loan_period_days, max_concurrent_loans, fine_per_day) VALUES (?, ?, ?, ?)
+         ON CONFLICT(name) DO UPDATE SET loan_period_days = excluded.loan_period_days, 
+            max_concurrent_loans = excluded.max_concurrent_loans, fine_per_day = excluded.fine_per_day"
+    )
+    .bind(&member_type.name)
+    .bind(member_type.loan_period_days)
+    .bind(member_type.max_concurrent_loans)
+    .bind(member_type.fine_per_day)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+// Settings operations
+pub async fn get_all_settings(pool: &SqlitePool) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let rows = sqlx::query("SELECT key, value FROM settings")
+        .fetch_all(pool)
+        .await?;
+    
+    Ok(rows.iter().map(|row| (row.get("key"), row.get("value"))).collect())
+}
+
+// All or nothing, so a failure part way doesn't leave some of the values saved
+pub async fn upsert_settings(pool: &SqlitePool, values: &[(&str, String)]) -> Result<(), sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    for (key, value) in values {
+        sqlx::query(
+            "INSERT INTO settings (key, value, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at"
+        )
+        .bind(key)
+        .bind(value)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    
+    Ok(())
+}
+
+// Email template operations; only templates a library has edited are stored
+pub async fn get_email_template(pool: &SqlitePool, key: &str) -> Result<Option<EmailTemplate>, sqlx::Error> {
+    sqlx::query_as::<_, EmailTemplate>(
+        "SELECT key, subject, body, updated_by, updated_at FROM email_templates WHERE key = ?"
+    )
+    .bind(key)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn upsert_email_template(pool: &SqlitePool, key: &str, subject: &str, body: &str, updated_by: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO email_templates (key, subject, body, updated_by, updated_at) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(key) DO UPDATE SET subject = excluded.subject, body = excluded.body, 
+             updated_by = excluded.updated_by, updated_at = excluded.updated_at"
+    )
+    .bind(key)
+    .bind(subject)
+    .bind(body)
+    .bind(updated_by)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+pub async fn delete_email_template(pool: &SqlitePool, key: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM email_templates WHERE key = ?")
+        .bind(key)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+// Branch operations
+pub const MAIN_BRANCH_ID: i64 = 1;
+
+pub async fn get_all_branches(pool: &SqlitePool) -> Result<Vec<Branch>, sqlx::Error> {
+    let branches = sqlx::query_as::<_, Branch>(
+        "SELECT id, name, code, address, created_at FROM branches ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(branches)
+}
+
+pub async fn get_branch_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Branch>, sqlx::Error> {
+    let branch = sqlx::query_as::<_, Branch>(
+        "SELECT id, name, code, address, created_at FROM branches WHERE id = ?"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(branch)
+}
+
+pub async fn create_branch(pool: &SqlitePool, req: &CreateBranchRequest) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO branches (name, code, address) VALUES (?, ?, ?)")
+        .bind(&req.name)
+        .bind(&req.code)
+        .bind(&req.address)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn update_branch(pool: &SqlitePool, id: i64, name: &str, code: &str, address: Option<&str>) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE branches SET name = ?, code = ?, address = ? WHERE id = ?")
+        .bind(name)
+        .bind(code)
+        .bind(address)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Copies, members, and loans that still reference the branch; it can only be deleted when all are zero
+pub async fn count_branch_references(pool: &SqlitePool, id: i64) -> Result<(i64, i64, i64), sqlx::Error> {
+    let copies: i64 = sqlx::query_scalar("SELECT COALESCE(SUM(copies), 0) FROM book_copies WHERE branch_id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+    let members: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE home_branch_id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+    let loans: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM lending_records WHERE branch_id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+    
+    Ok((copies, members, loans))
+}
+
+pub async fn delete_branch(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM book_copies WHERE branch_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM transfers WHERE from_branch_id = ? OR to_branch_id = ?")
+        .bind(id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query("DELETE FROM locations WHERE branch_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    let result = sqlx::query("DELETE FROM branches WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Copies of a book at each branch; availability there is the copies minus loans taken out from it
+// and copies still in transit to it
+pub async fn get_book_holdings(pool: &SqlitePool, book_id: i64) -> Result<Vec<BranchHolding>, sqlx::Error> {
+    let holdings = sqlx::query_as::<_, BranchHolding>(
+        "SELECT bc.branch_id, br.name AS branch_name, bc.copies, bc.location_id,
+                l.room || ', shelf ' || l.shelf AS location, l.call_number_prefix,
+                bc.copies - (
+                    SELECT COUNT(*) FROM lending_records lr 
+                    WHERE lr.book_id = bc.book_id AND lr.branch_id = bc.branch_id AND lr.status IN ('borrowed', 'overdue')
+                ) - (
+                    SELECT COUNT(*) FROM transfers t 
+                    WHERE t.book_id = bc.book_id AND t.to_branch_id = bc.branch_id AND t.status = 'in_transit'
+                ) AS available_copies
+         FROM book_copies bc
+         INNER JOIN branches br ON br.id = bc.branch_id
+         LEFT JOIN locations l ON l.id = bc.location_id
+         WHERE bc.book_id = ? AND bc.copies > 0
+         ORDER BY bc.branch_id"
+    )
+    .bind(book_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(holdings)
+}
+
+// Books held at a branch, with the copy counts of that branch in place of the library-wide totals
+pub async fn get_books_at_branch(pool: &SqlitePool, branch_id: i64) -> Result<Vec<Book>, sqlx::Error> {
+    let books = sqlx::query_as::<_, Book>(
+        "SELECT b.id, b.title, b.author, b.isbn, b.publication_year, b.genre, bc.copies AS total_copies,
+                bc.copies - (
+                    SELECT COUNT(*) FROM lending_records lr 
+                    WHERE lr.book_id = b.id AND lr.branch_id = bc.branch_id AND lr.status IN ('borrowed', 'overdue')
+                ) - (
+                    SELECT COUNT(*) FROM transfers t 
+                    WHERE t.book_id = b.id AND t.to_branch_id = bc.branch_id AND t.status = 'in_transit'
+                ) AS available_copies,
+                b.created_at, b.on_order, b.item_type, b.issue_number, b.platform, b.loan_period_days, b.loanable,
+                b.series_id, b.series_position, b.version,
+                (SELECT GROUP_CONCAT(tag, char(31)) FROM book_tags t WHERE t.book_id = b.id) AS tags
+         FROM books b
+         INNER JOIN book_copies bc ON bc.book_id = b.id
+         WHERE bc.branch_id = ? AND bc.copies > 0
+         ORDER BY b.title"
+    )
+    .bind(branch_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(books)
+}
+
+async fn add_branch_copies(conn: &mut sqlx::SqliteConnection, book_id: i64, branch_id: i64, delta: i64) -> Result<(), sqlx::Error> {
+    if delta == 0 {
+        return Ok(());
+    }
+    
+    sqlx::query(
+        "INSERT INTO book_copies (book_id, branch_id, copies) VALUES (?, ?, ?) 
+         ON CONFLICT(book_id, branch_id) DO UPDATE SET copies = copies + excluded.copies"
+    )
+    .bind(book_id)
+    .bind(branch_id)
+    .bind(delta)
+    .execute(conn)
+    .await?;
+    
+    Ok(())
+}
+
+// Sets how many copies a branch holds, adjusting the book's library-wide counts by the difference
+pub async fn set_branch_copies(pool: &SqlitePool, book_id: i64, branch_id: i64, copies: i64) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    let current: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(copies), 0) FROM book_copies WHERE book_id = ? AND branch_id = ?")
+        .bind(book_id)
+        .bind(branch_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    
+    sqlx::query(
+        "INSERT INTO book_copies (book_id, branch_id, copies) VALUES (?, ?, ?) 
+         ON CONFLICT(book_id, branch_id) DO UPDATE SET copies = excluded.copies"
+    )
+    .bind(book_id)
+    .bind(branch_id)
+    .bind(copies)
+    .execute(&mut *tx)
+    .await?;
+    
+    let diff = copies - current;
+    sqlx::query(
+        "UPDATE books SET total_copies = total_copies + ?, available_copies = available_copies + ?, 
+         on_order = on_order AND total_copies + ? = 0, version = version + 1 WHERE id = ?"
+    )
+    .bind(diff)
+    .bind(diff)
+    .bind(diff)
+    .bind(book_id)
+    .execute(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+// Series operations
+const SERIES_COLUMNS: &str = "s.id, s.name, s.description, s.created_at,
+    (SELECT COUNT(*) FROM books b WHERE b.series_id = s.id) AS volume_count";
+
+pub async fn get_all_series(pool: &SqlitePool) -> Result<Vec<Series>, sqlx::Error> {
+    let series = sqlx::query_as::<_, Series>(
+        &format!("SELECT {} FROM series s ORDER BY s.name", SERIES_COLUMNS)
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(series)
+}
+
+pub async fn get_series_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Series>, sqlx::Error> {
+    let series = sqlx::query_as::<_, Series>(
+        &format!("SELECT {} FROM series s WHERE s.id = ?", SERIES_COLUMNS)
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(series)
+}
+
+// Volumes in reading order; unnumbered volumes come last
+pub async fn get_series_volumes(pool: &SqlitePool, id: i64) -> Result<Vec<Book>, sqlx::Error> {
+    let books = sqlx::query_as::<_, Book>(
+        &format!(
+            "SELECT {} FROM books WHERE series_id = ? 
+             ORDER BY series_position IS NULL, series_position, publication_year, title",
+            BOOK_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(books)
+}
+
+pub async fn create_series(pool: &SqlitePool, req: &CreateSeriesRequest) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO series (name, description) VALUES (?, ?)")
+        .bind(&req.name)
+        .bind(&req.description)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn update_series(pool: &SqlitePool, id: i64, name: &str, description: Option<&str>) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE series SET name = ?, description = ? WHERE id = ?")
+        .bind(name)
+        .bind(description)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Deleting a series leaves its books in the catalog, just no longer grouped
+pub async fn delete_series(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE books SET series_id = NULL, series_position = NULL, version = version + 1 WHERE series_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    let result = sqlx::query("DELETE FROM series WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Wishlist operations
+const WISHLIST_COLUMNS: &str = "w.book_id, b.title, b.author, b.available_copies, w.notify, w.notified_at, w.added_at";
+
+pub async fn get_wishlist(pool: &SqlitePool, user_id: i64) -> Result<Vec<WishlistEntry>, sqlx::Error> {
+    let entries = sqlx::query_as::<_, WishlistEntry>(
+        &format!(
+            "SELECT {} FROM wishlist w JOIN books b ON w.book_id = b.id 
+             WHERE w.user_id = ? ORDER BY w.added_at DESC",
+            WISHLIST_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(entries)
+}
+
+pub async fn get_wishlist_entry(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<Option<WishlistEntry>, sqlx::Error> {
+    let entry = sqlx::query_as::<_, WishlistEntry>(
+        &format!(
+            "SELECT {} FROM wishlist w JOIN books b ON w.book_id = b.id 
+             WHERE w.user_id = ? AND w.book_id = ?",
+            WISHLIST_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .bind(book_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(entry)
+}
+
+// Saving a title again updates its notify flag and re-arms the availability notice
+pub async fn save_to_wishlist(pool: &SqlitePool, user_id: i64, book_id: i64, notify: bool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO wishlist (user_id, book_id, notify) VALUES (?, ?, ?) 
+         ON CONFLICT(user_id, book_id) DO UPDATE SET notify = excluded.notify, notified_at = NULL"
+    )
+    .bind(user_id)
+    .bind(book_id)
+    .bind(notify)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+pub async fn remove_from_wishlist(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("DELETE FROM wishlist WHERE user_id = ? AND book_id = ?")
+        .bind(user_id)
+        .bind(book_id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Members who asked to hear when the book is available and have not been told yet
+pub async fn get_wishlist_watchers(pool: &SqlitePool, book_id: i64) -> Result<Vec<i64>, sqlx::Error> {
+    let user_ids = sqlx::query_scalar::<_, i64>(
+        "SELECT user_id FROM wishlist WHERE book_id = ? AND notify = 1 AND notified_at IS NULL"
+    )
+    .bind(book_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(user_ids)
+}
+
+pub async fn mark_wishlist_notified(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE wishlist SET notified_at = ? WHERE user_id = ? AND book_id = ?")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(user_id)
+        .bind(book_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+// Book watch operations
+const BOOK_WATCH_COLUMNS: &str = "bw.book_id, b.title, b.author, b.available_copies, bw.created_at";
+
+pub async fn get_user_watches(pool: &SqlitePool, user_id: i64) -> Result<Vec<BookWatch>, sqlx::Error> {
+    let watches = sqlx::query_as::<_, BookWatch>(
+        &format!(
+            "SELECT {} FROM book_watches bw JOIN books b ON bw.book_id = b.id 
+             WHERE bw.user_id = ? ORDER BY bw.created_at DESC",
+            BOOK_WATCH_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(watches)
+}
+
+pub async fn get_watch(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<Option<BookWatch>, sqlx::Error> {
+    let watch = sqlx::query_as::<_, BookWatch>(
+        &format!(
+            "SELECT {} FROM book_watches bw JOIN books b ON bw.book_id = b.id 
+             WHERE bw.user_id = ? AND bw.book_id = ?",
+            BOOK_WATCH_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .bind(book_id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(watch)
+}
+
+pub async fn create_watch(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO book_watches (user_id, book_id) VALUES (?, ?)")
+        .bind(user_id)
+        .bind(book_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub async fn delete_watch(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("DELETE FROM book_watches WHERE user_id = ? AND book_id = ?")
+        .bind(user_id)
+        .bind(book_id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+pub async fn get_book_watchers(pool: &SqlitePool, book_id: i64) -> Result<Vec<i64>, sqlx::Error> {
+    let user_ids = sqlx::query_scalar::<_, i64>("SELECT user_id FROM book_watches WHERE book_id = ?")
+        .bind(book_id)
+        .fetch_all(pool)
+        .await?;
+    
+    Ok(user_ids)
+}
+
+// Favorite and author follow operations
+pub async fn get_favorites(pool: &SqlitePool, user_id: i64) -> Result<Vec<Book>, sqlx::Error> {
+    let books = sqlx::query_as::<_, Book>(
+        &format!(
+            "SELECT {} FROM books 
+             JOIN favorites f ON f.book_id = books.id 
+             WHERE f.user_id = ? ORDER BY f.added_at DESC",
+            BOOK_COLUMNS
+        )
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(books)
+}
+
+// Returns false when the book was already a favorite
+pub async fn add_favorite(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("INSERT OR IGNORE INTO favorites (user_id, book_id) VALUES (?, ?)")
+        .bind(user_id)
+        .bind(book_id)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_favorite(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("DELETE FROM favorites WHERE user_id = ? AND book_id = ?")
+        .bind(user_id)
+        .bind(book_id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+pub async fn get_followed_authors(pool: &SqlitePool, user_id: i64) -> Result<Vec<AuthorFollow>, sqlx::Error> {
+    let follows = sqlx::query_as::<_, AuthorFollow>(
+        "SELECT af.author, af.created_at, 
+                (SELECT COUNT(*) FROM books b WHERE b.author = af.author COLLATE NOCASE) AS book_count 
+         FROM author_follows af WHERE af.user_id = ? ORDER BY af.author"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(follows)
+}
+
+// Returns false when the author was already followed
+pub async fn follow_author(pool: &SqlitePool, user_id: i64, author: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("INSERT OR IGNORE INTO author_follows (user_id, author) VALUES (?, ?)")
+        .bind(user_id)
+        .bind(author)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn unfollow_author(pool: &SqlitePool, user_id: i64, author: &str) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("DELETE FROM author_follows WHERE user_id = ? AND author = ?")
+        .bind(user_id)
+        .bind(author)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Author names are matched case-insensitively
+pub async fn get_author_followers(pool: &SqlitePool, author: &str) -> Result<Vec<i64>, sqlx::Error> {
+    let user_ids = sqlx::query_scalar::<_, i64>("SELECT user_id FROM author_follows WHERE author = ?")
+        .bind(author)
+        .fetch_all(pool)
+        .await?;
+    
+    Ok(user_ids)
+}
+
+// Reading list operations
+const READING_LIST_COLUMNS: &str = "rl.id, rl.name, rl.description, rl.created_at,
+    (SELECT COUNT(*) FROM reading_list_entries e WHERE e.list_id = rl.id) AS entry_count";
+
+pub async fn get_reading_lists(pool: &SqlitePool) -> Result<Vec<ReadingList>, sqlx::Error> {
+    let lists = sqlx::query_as::<_, ReadingList>(
+        &format!("SELECT {} FROM reading_lists rl ORDER BY rl.created_at DESC, rl.id DESC", READING_LIST_COLUMNS)
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(lists)
+}
+
+pub async fn get_reading_list_by_id(pool: &SqlitePool, id: i64) -> Result<Option<ReadingList>, sqlx::Error> {
+    let list = sqlx::query_as::<_, ReadingList>(
+        &format!("SELECT {} FROM reading_lists rl WHERE rl.id = ?", READING_LIST_COLUMNS)
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(list)
+}
+
+pub async fn get_reading_list_entries(pool: &SqlitePool, id: i64) -> Result<Vec<Book>, sqlx::Error> {
+    let books = sqlx::query_as::<_, Book>(
+        &format!(
+            "SELECT {} FROM books 
+             JOIN reading_list_entries e ON e.book_id = books.id 
+             WHERE e.list_id = ? ORDER BY e.position",
+            BOOK_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(books)
+}
+
+async fn set_reading_list_entries(tx: &mut sqlx::SqliteConnection, id: i64, book_ids: &[i64]) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM reading_list_entries WHERE list_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    
+    for (position, book_id) in book_ids.iter().enumerate() {
+        sqlx::query("INSERT INTO reading_list_entries (list_id, book_id, position) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(book_id)
+            .bind(position as i64 + 1)
+            .execute(&mut *tx)
+            .await?;
+    }
+    
+    Ok(())
+}
+
+pub async fn create_reading_list(pool: &SqlitePool, req: &CreateReadingListRequest) -> Result<i64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    let result = sqlx::query("INSERT INTO reading_lists (name, description) VALUES (?, ?)")
+        .bind(&req.name)
+        .bind(&req.description)
+        .execute(&mut *tx)
+        .await?;
+    let id = result.last_insert_rowid();
+    
+    set_reading_list_entries(&mut tx, id, &req.book_ids).await?;
+    
+    tx.commit().await?;
+    Ok(id)
+}
+
+pub async fn update_reading_list(
+    pool: &SqlitePool,
+    id: i64,
+    name: &str,
+    description: Option<&str>,
+    book_ids: Option<&[i64]>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    let result = sqlx::query("UPDATE reading_lists SET name = ?, description = ? WHERE id = ?")
+        .bind(name)
+        .bind(description)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    if let Some(book_ids) = book_ids {
+        set_reading_list_entries(&mut tx, id, book_ids).await?;
+    }
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn delete_reading_list(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM reading_list_entries WHERE list_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    let result = sqlx::query("DELETE FROM reading_lists WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Periodical issue operations
+const ISSUE_COLUMNS: &str = "b.id, pi.title_id, b.title, pi.volume, b.issue_number AS number, pi.issue_date, 
+    CASE WHEN pi.received_at IS NULL THEN 'expected' ELSE 'received' END AS status, pi.received_at, 
+    b.total_copies, b.available_copies, b.loan_period_days, b.loanable";
+
+// Catalogs an expected issue of a periodical title. The issue starts with no copies and
+// inherits the title's descriptive fields and lending rules.
+pub async fn create_issue(pool: &SqlitePool, title: &Book, req: &CreateIssueRequest) -> Result<i64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    // Issues get an identifier derived from the title's ISSN/ISBN, since they have none of their own
+    let identifier = match &req.volume {
+        Some(volume) => format!("{}/v{}n{}", title.isbn, volume, req.number),
+        None => format!("{}/n{}", title.isbn, req.number),
+    };
+    
+    let result = sqlx::query(
+        "INSERT INTO books (title, author, isbn, publication_year, genre, total_copies, available_copies, 
+                            item_type, issue_number, loan_period_days, loanable) 
+         VALUES (?, ?, ?, ?, ?, 0, 0, 'periodical', ?, ?, ?)"
+    )
+    .bind(&title.title)
+    .bind(&title.author)
+    .bind(&identifier)
+    .bind(req.issue_date[..4].parse::<i32>().ok())
+    .bind(&title.genre)
+    .bind(&req.number)
+    .bind(title.loan_period_days)
+    .bind(title.loanable)
+    .execute(&mut *tx)
+    .await?;
+    let book_id = result.last_insert_rowid();
+    
+    sqlx::query("INSERT INTO periodical_issues (book_id, title_id, volume, issue_date) VALUES (?, ?, ?, ?)")
+        .bind(book_id)
+        .bind(title.id)
+        .bind(&req.volume)
+        .bind(&req.issue_date)
+        .execute(&mut *tx)
+        .await?;
+    
+    tx.commit().await?;
+    Ok(book_id)
+}
+
+pub async fn get_issue(pool: &SqlitePool, id: i64) -> Result<Option<PeriodicalIssue>, sqlx::Error> {
+    let issue = sqlx::query_as::<_, PeriodicalIssue>(
+        &format!(
+            "SELECT {} FROM periodical_issues pi
+             INNER JOIN books b ON b.id = pi.book_id
+             WHERE pi.book_id = ?",
+            ISSUE_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(issue)
+}
+
+// A title's issues, newest first
+pub async fn get_issues(pool: &SqlitePool, title_id: i64) -> Result<Vec<PeriodicalIssue>, sqlx::Error> {
+    let issues = sqlx::query_as::<_, PeriodicalIssue>(
+        &format!(
+            "SELECT {} FROM periodical_issues pi
+             INNER JOIN books b ON b.id = pi.book_id
+             WHERE pi.title_id = ?
+             ORDER BY pi.issue_date DESC, b.id DESC",
+            ISSUE_COLUMNS
+        )
+    )
+    .bind(title_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(issues)
+}
+
+// Marks an expected issue as received and shelves its copies at the given branch
+pub async fn receive_issue(pool: &SqlitePool, id: i64, copies: i64, branch_id: i64) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    let result = sqlx::query("UPDATE periodical_issues SET received_at = ? WHERE book_id = ? AND received_at IS NULL")
+        .bind(crate::clock::now().to_rfc3339())
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    sqlx::query("UPDATE books SET total_copies = total_copies + ?, available_copies = available_copies + ?, version = version + 1 WHERE id = ?")
+        .bind(copies)
+        .bind(copies)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query(
+        "INSERT INTO book_copies (book_id, branch_id, copies) VALUES (?, ?, ?) 
+         ON CONFLICT(book_id, branch_id) DO UPDATE SET copies = copies + excluded.copies"
+    )
+    .bind(id)
+    .bind(branch_id)
+    .bind(copies)
+    .execute(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+// Location operations
+const LOCATION_COLUMNS: &str = "l.id, l.branch_id, br.name AS branch_name, l.room, l.shelf, l.call_number_prefix, l.created_at";
+
+pub async fn get_locations(pool: &SqlitePool, branch_id: Option<i64>) -> Result<Vec<Location>, sqlx::Error> {
+    let locations = sqlx::query_as::<_, Location>(
+        &format!(
+            "SELECT {} FROM locations l
+             INNER JOIN branches br ON br.id = l.branch_id
+             WHERE (? IS NULL OR l.branch_id = ?)
+             ORDER BY l.branch_id, l.room, l.shelf",
+            LOCATION_COLUMNS
+        )
+    )
+    .bind(branch_id)
+    .bind(branch_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(locations)
+}
+
+pub async fn get_location_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Location>, sqlx::Error> {
+    let location = sqlx::query_as::<_, Location>(
+        &format!(
+            "SELECT {} FROM locations l
+             INNER JOIN branches br ON br.id = l.branch_id
+             WHERE l.id = ?",
+            LOCATION_COLUMNS
+        )
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(location)
+}
+
+pub async fn create_location(pool: &SqlitePool, req: &CreateLocationRequest) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO locations (branch_id, room, shelf, call_number_prefix) VALUES (?, ?, ?, ?)")
+        .bind(req.branch_id)
+        .bind(&req.room)
+        .bind(&req.shelf)
+        .bind(&req.call_number_prefix)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn update_location(pool: &SqlitePool, id: i64, room: &str, shelf: &str, call_number_prefix: Option<&str>) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE locations SET room = ?, shelf = ?, call_number_prefix = ? WHERE id = ?")
+        .bind(room)
+        .bind(shelf)
+        .bind(call_number_prefix)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Number of titles shelved at the location; it can only be deleted when none are
+pub async fn count_location_titles(pool: &SqlitePool, id: i64) -> Result<i64, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM book_copies WHERE location_id = ? AND copies > 0")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+    
+    Ok(count)
+}
+
+pub async fn delete_location(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE book_copies SET location_id = NULL WHERE location_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    let result = sqlx::query("DELETE FROM locations WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Shelves a branch's copies of a book at a location (None takes them off any shelf)
+pub async fn set_copies_location(pool: &SqlitePool, book_id: i64, branch_id: i64, location_id: Option<i64>) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE book_copies SET location_id = ? WHERE book_id = ? AND branch_id = ? AND copies > 0")
+        .bind(location_id)
+        .bind(book_id)
+        .bind(branch_id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Everything shelved at a location with how many copies should be found there right now
+pub async fn get_shelf_list(pool: &SqlitePool, location_id: i64) -> Result<Vec<ShelfListEntry>, sqlx::Error> {
+    let entries = sqlx::query_as::<_, ShelfListEntry>(
+        "SELECT book_id, title, author, isbn, copies, on_loan, in_transit, copies - on_loan - in_transit AS expected_on_shelf
+         FROM (
+             SELECT b.id AS book_id, b.title, b.author, b.isbn, bc.copies,
+                    (SELECT COUNT(*) FROM lending_records lr 
+                     WHERE lr.book_id = bc.book_id AND lr.branch_id = bc.branch_id AND lr.status IN ('borrowed', 'overdue')) AS on_loan,
+                    (SELECT COUNT(*) FROM transfers t 
+                     WHERE t.book_id = bc.book_id AND t.to_branch_id = bc.branch_id AND t.status = 'in_transit') AS in_transit
+             FROM book_copies bc
+             INNER JOIN books b ON b.id = bc.book_id
+             WHERE bc.location_id = ? AND bc.copies > 0
+         )
+         ORDER BY author, title"
+    )
+    .bind(location_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(entries)
+}
+
+// Transfer operations
+const TRANSFER_COLUMNS: &str = "t.id, t.book_id, b.title, b.author, t.user_id, u.username, u.email, 
+    t.from_branch_id, fb.name AS from_branch, t.to_branch_id, tb.name AS to_branch, t.status, t.handled_by, 
+    t.created_at, t.shipped_at, t.arrived_at, t.cancelled_at";
+
+const TRANSFER_JOINS: &str = "INNER JOIN books b ON b.id = t.book_id
+     INNER JOIN users u ON u.id = t.user_id
+     INNER JOIN branches fb ON fb.id = t.from_branch_id
+     INNER JOIN branches tb ON tb.id = t.to_branch_id";
+
+pub async fn create_transfer(pool: &SqlitePool, user_id: i64, book_id: i64, from_branch_id: i64, to_branch_id: i64) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO transfers (book_id, user_id, from_branch_id, to_branch_id, status, created_at) 
+         VALUES (?, ?, ?, ?, 'requested', ?)"
+    )
+    .bind(book_id)
+    .bind(user_id)
+    .bind(from_branch_id)
+    .bind(to_branch_id)
+    .bind(crate::clock::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_transfer_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Transfer>, sqlx::Error> {
+    let transfer = sqlx::query_as::<_, Transfer>(
+        &format!("SELECT {} FROM transfers t {} WHERE t.id = ?", TRANSFER_COLUMNS, TRANSFER_JOINS)
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(transfer)
+}
+
+pub async fn has_open_transfer(pool: &SqlitePool, user_id: i64, book_id: i64) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM transfers WHERE user_id = ? AND book_id = ? AND status IN ('requested', 'in_transit')"
+    )
+    .bind(user_id)
+    .bind(book_id)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(count > 0)
+}
+
+// Transfers with the given status (all when None) touching the given branch (all when None), oldest first
+pub async fn get_transfers(pool: &SqlitePool, status: Option<&str>, branch_id: Option<i64>) -> Result<Vec<Transfer>, sqlx::Error> {
+    let transfers = sqlx::query_as::<_, Transfer>(
+        &format!(
+            "SELECT {} FROM transfers t {}
+             WHERE (? IS NULL OR t.status = ?) AND (? IS NULL OR t.from_branch_id = ? OR t.to_branch_id = ?)
+             ORDER BY t.created_at",
+            TRANSFER_COLUMNS, TRANSFER_JOINS
+        )
+    )
+    .bind(status)
+    .bind(status)
+    .bind(branch_id)
+    .bind(branch_id)
+    .bind(branch_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(transfers)
+}
+
+pub async fn get_user_transfers(pool: &SqlitePool, user_id: i64) -> Result<Vec<Transfer>, sqlx::Error> {
+    let transfers = sqlx::query_as::<_, Transfer>(
+        &format!(
+            "SELECT {} FROM transfers t {} WHERE t.user_id = ? ORDER BY t.created_at DESC",
+            TRANSFER_COLUMNS, TRANSFER_JOINS
+        )
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(transfers)
+}
+
+pub async fn cancel_transfer(pool: &SqlitePool, id: i64, handled_by: Option<i64>) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE transfers SET status = 'cancelled', handled_by = COALESCE(?, handled_by), cancelled_at = ? 
+         WHERE id = ? AND status = 'requested'"
+    )
+    .bind(handled_by)
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Sends a copy on its way: it moves to the destination's count but stays off the shelf until it arrives
+pub async fn ship_transfer(pool: &SqlitePool, id: i64, handled_by: i64) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    
+    let row = sqlx::query(
+        "UPDATE transfers SET status = 'in_transit', handled_by = ?, shipped_at = ? 
+         WHERE id = ? AND status = 'requested' 
+         RETURNING book_id, from_branch_id, to_branch_id"
+    )
+    .bind(handled_by)
+    .bind(crate::clock::now().to_rfc3339())
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)?;
+    let book_id: i64 = row.get("book_id");
+    
+    sqlx::query("UPDATE book_copies SET copies = copies - 1 WHERE book_id = ? AND branch_id = ?")
+        .bind(book_id)
+        .bind(row.get::<i64, _>("from_branch_id"))
+        .execute(&mut *tx)
+        .await?;
+    
+    sqlx::query(
+        "INSERT INTO book_copies (book_id, branch_id, copies) VALUES (?, ?, 1) 
+         ON CONFLICT(book_id, branch_id) DO UPDATE SET copies = copies + 1"
+    )
+    .bind(book_id)
+    .bind(row.get::<i64, _>("to_branch_id"))
+    .execute(&mut *tx)
+    .await?;
+    
+    sqlx::query("UPDATE books SET available_copies = available_copies - 1 WHERE id = ?")
+        .bind(book_id)
+        .execute(&mut *tx)
+        .await?;
+    
+    tx.commit().await?;
+    Ok(())
+}
+
+// Receives a copy at its destination and holds it there for the member who asked for it.
+// Returns the reservation now ready for pickup, or None if the member no longer needed it
+// and the copy went back on the shelf.
+pub async fn receive_transfer(pool: &SqlitePool, id: i64, handled_by: i64, hold_days: i64) -> Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let now = crate::clock::now();
+    
+    let row = sqlx::query(
+        "UPDATE transfers SET status = 'arrived', handled_by = ?, arrived_at = ? 
+         WHERE id = ? AND status = 'in_transit' 
+         RETURNING book_id, user_id"
+    )
+    .bind(handled_by)
+    .bind(now.to_rfc3339())
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)?;
+    let book_id: i64 = row.get("book_id");
+    let user_id: i64 = row.get("user_id");
+    
+    let existing = sqlx::query("SELECT id, status FROM reservations WHERE user_id = ? AND book_id = ? AND status IN ('waiting', 'ready')")
+        .bind(user_id)
+        .bind(book_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    
+    let hold = match existing {
+        // Another copy is already held for the member, so this one goes to the shelf
+        Some(r) if r.get::<String, _>("status") == "ready" => None,
+        Some(r) => {
+            let reservation_id: i64 = r.get("id");
+            sqlx::query("UPDATE reservations SET status = 'ready', ready_at = ?, expires_at = ? WHERE id = ?")
+                .bind(now.to_rfc3339())
+                .bind((now + Duration::days(hold_days)).to_rfc3339())
+                .bind(reservation_id)
+                .execute(&mut *tx)
+                .await?;
+            Some(reservation_id)
+        }
+        None => {
+            let result = sqlx::query(
+                "INSERT INTO reservations (user_id, book_id, status, created_at, ready_at, expires_at) 
+                 VALUES (?, ?, 'ready', ?, ?, ?)"
+            )
+            .bind(user_id)
+            .bind(book_id)
+            .bind(now.to_rfc3339())
+            .bind(now.to_rfc3339())
+            .bind((now + Duration::days(hold_days)).to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+            Some(result.last_insert_rowid())
+        }
+    };
+    
+    if hold.is_none() {
+        sqlx::query("UPDATE books SET available_copies = available_copies + 1 WHERE id = ?")
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    
+    tx.commit().await?;
+    Ok(hold)
+}
+
+// Secret operations
+
+// Returns the stored secret, saving `candidate` first if none exists yet
+pub async fn get_or_create_secret(pool: &SqlitePool, name: &str, candidate: &str) -> Result<String, sqlx::Error> {
+    sqlx::query("INSERT OR IGNORE INTO secrets (name, value) VALUES (?, ?)")
+        .bind(name)
+        .bind(candidate)
+        .execute(pool)
+        .await?;
+    
+    sqlx::query_scalar("SELECT value FROM secrets WHERE name = ?")
+        .bind(name)
+        .fetch_one(pool)
+        .await
+}
+
+// Announcement operations
+pub async fn create_announcement(pool: &SqlitePool, req: &CreateAnnouncementRequest, created_by: i64) -> Result<i64, sqlx::Error> {
+    let starts_at = req.starts_at.clone().unwrap_or_else(|| crate::clock::now().to_rfc3339());
+    
+    let result = sqlx::query(
+        "INSERT INTO announcements (title, message, level, starts_at, ends_at, created_by) 
+         VALUES (?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&req.title)
+    .bind(&req.message)
+    .bind(req.level.as_deref().unwrap_or("info"))
+    .bind(starts_at)
+    .bind(&req.ends_at)
+    .bind(created_by)
+    .execute(pool)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_announcement_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Announcement>, sqlx::Error> {
+    let announcement = sqlx::query_as::<_, Announcement>(
+        "SELECT id, title, message, level, starts_at, ends_at, created_by, created_at 
+         FROM announcements WHERE id = ?"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    
+    Ok(announcement)
+}
+
+pub async fn get_all_announcements(pool: &SqlitePool) -> Result<Vec<Announcement>, sqlx::Error> {
+    let announcements = sqlx::query_as::<_, Announcement>(
+        "SELECT id, title, message, level, starts_at, ends_at, created_by, created_at 
+         FROM announcements ORDER BY starts_at DESC"
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(announcements)
+}
+
+pub async fn get_active_announcements(pool: &SqlitePool) -> Result<Vec<Announcement>, sqlx::Error> {
+    let now = crate::clock::now().to_rfc3339();
+    
+    let announcements = sqlx::query_as::<_, Announcement>(
+        "SELECT id, title, message, level, starts_at, ends_at, created_by, created_at 
+         FROM announcements 
+         WHERE starts_at <= ? AND (ends_at IS NULL OR ends_at > ?)
+         ORDER BY starts_at DESC"
+    )
+    .bind(&now)
+    .bind(&now)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(announcements)
+}
+
+pub async fn update_announcement(pool: &SqlitePool, id: i64, title: &str, message: &str, level: &str, starts_at: &str, ends_at: Option<&str>) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE announcements SET title = ?, message = ?, level = ?, starts_at = ?, ends_at = ? WHERE id = ?"
+    )
+    .bind(title)
+    .bind(message)
+    .bind(level)
+    .bind(starts_at)
+    .bind(ends_at)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+pub async fn delete_announcement(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("DELETE FROM announcements WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    Ok(())
+}
+
+// Broadcast operations
+const BROADCAST_COLUMNS: &str = "b.id, b.subject, b.body, b.audience, b.status, b.total, 
+    (SELECT COUNT(*) FROM broadcast_recipients r WHERE r.broadcast_id = b.id AND r.status = 'sent') AS sent, 
+    (SELECT COUNT(*) FROM broadcast_recipients r WHERE r.broadcast_id = b.id AND r.status = 'failed') AS failed, 
+    (SELECT COUNT(*) FROM broadcast_recipients r WHERE r.broadcast_id = b.id AND r.status = 'skipped') AS skipped, 
+    (SELECT COUNT(*) FROM broadcast_recipients r WHERE r.broadcast_id = b.id AND r.status = 'pending') AS pending, 
+    b.created_by, b.created_at, b.started_at, b.completed_at";
+
+// Queues a broadcast and snapshots its recipients: active, approved, non-anonymized users in
+// the audience at the time of sending. Members who join the audience later are not added
+pub async fn create_broadcast(pool: &SqlitePool, req: &CreateBroadcastRequest, audience: &str, created_by: i64) -> Result<i64, sqlx::Error> {
+    let filter = match audience {
+        "overdue" => " AND id IN (SELECT user_id FROM lending_records WHERE status = 'overdue')",
+        "holds_ready" => " AND id IN (SELECT user_id FROM reservations WHERE status = 'ready')",
+        _ => "",
+    };
+    let mut tx = pool.begin().await?;
+    
+    let id = sqlx::query("INSERT INTO broadcasts (subject, body, audience, created_by) VALUES (?, ?, ?, ?)")
+        .bind(&req.subject)
+        .bind(&req.body)
+        .bind(audience)
+        .bind(created_by)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    
+    let total = sqlx::query(&format!(
+        "INSERT INTO broadcast_recipients (broadcast_id, user_id) 
+         SELECT ?, id FROM users 
+         WHERE is_active = 1 AND anonymized_at IS NULL AND approval_status = 'approved'{}",
+        filter
+    ))
+    .bind(id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    
+    sqlx::query(
+        "UPDATE broadcasts SET total = ?, 
+         status = CASE WHEN ? = 0 THEN 'completed' ELSE status END, 
+         completed_at = CASE WHEN ? = 0 THEN CURRENT_TIMESTAMP ELSE NULL END 
+         WHERE id = ?"
+    )
+    .bind(total as i64)
+    .bind(total as i64)
+    .bind(total as i64)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    Ok(id)
+}
+
+pub async fn get_broadcast_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Broadcast>, sqlx::Error> {
+    sqlx::query_as::<_, Broadcast>(&format!("SELECT {} FROM broadcasts b WHERE b.id = ?", BROADCAST_COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn get_broadcasts(pool: &SqlitePool) -> Result<Vec<Broadcast>, sqlx::Error> {
+    sqlx::query_as::<_, Broadcast>(&format!("SELECT {} FROM broadcasts b ORDER BY b.id DESC", BROADCAST_COLUMNS))
+        .fetch_all(pool)
+        .await
+}
+
+// Oldest broadcasts first, so a large send does not hold up one queued after it for long
+pub async fn get_pending_broadcast_recipients(pool: &SqlitePool, limit: i64) -> Result<Vec<(i64, i64, String, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (i64, i64, String, String)>(
+        "SELECT r.broadcast_id, r.user_id, b.subject, b.body 
+         FROM broadcast_recipients r 
+         JOIN broadcasts b ON b.id = r.broadcast_id 
+         WHERE r.status = 'pending' 
+         ORDER BY r.broadcast_id, r.user_id 
+         LIMIT ?"
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn start_broadcast(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE broadcasts SET status = 'sending', started_at = COALESCE(started_at, CURRENT_TIMESTAMP) 
+         WHERE id = ? AND status = 'queued'"
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+pub async fn set_broadcast_recipient_status(pool: &SqlitePool, broadcast_id: i64, user_id: i64, status: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE broadcast_recipients SET status = ?, sent_at = CASE WHEN ? = 'sent' THEN CURRENT_TIMESTAMP ELSE sent_at END 
+         WHERE broadcast_id = ? AND user_id = ?"
     )
+    .bind(status)
+    .bind(status)
+    .bind(broadcast_id)
     .bind(user_id)
-    .bind(book_id)
-    .bind(borrowed_at.to_rfc3339())
-    .bind(due_date.to_rfc3339())
     .execute(pool)
     .await?;
     
-    // Decrease available copies
-    sqlx::query("UPDATE books SET available_copies = available_copies - 1 WHERE id = ?")
-        .bind(book_id)
+    Ok(())
+}
+
+// Marks broadcasts with no pending recipients left as completed
+pub async fn finish_broadcasts(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE broadcasts SET status = 'completed', completed_at = CURRENT_TIMESTAMP 
+         WHERE status != 'completed' 
+         AND NOT EXISTS (SELECT 1 FROM broadcast_recipients r WHERE r.broadcast_id = broadcasts.id AND r.status = 'pending')"
+    )
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected())
+}
+
+// Retention operations
+
+// Returned loans past the retention cutoff. Loans that carried a fine stay with the member's
+// payment history; loans already detached from their borrower only match when deleting
+fn expired_loans_sql(action: &str) -> &'static str {
+    match action {
+        "delete" => "SELECT id FROM lending_records 
+                     WHERE status = 'returned' AND returned_at < ? AND id NOT IN (SELECT lending_record_id FROM fines)",
+        _ => "SELECT id FROM lending_records 
+              WHERE status = 'returned' AND returned_at < ? AND user_id IS NOT NULL AND id NOT IN (SELECT lending_record_id FROM fines)",
+    }
+}
+
+pub async fn count_expired_loans(pool: &SqlitePool, before: &str, action: &str) -> Result<u64, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM ({})", expired_loans_sql(action)))
+        .bind(before)
+        .fetch_one(pool)
+        .await?;
+    
+    Ok(count as u64)
+}
+
+// Loan events carry the record id, which ties them to the loan they describe
+const LOAN_EVENTS_SQL: &str = "event_type LIKE 'loan.%' AND json_extract(data, '$.record_id') IN ({})";
+
+// Detaches expired loans from their borrowers so they only count towards circulation
+// statistics. Extension requests name the member and are removed with the link, and the
+// loan's events in the feed lose their user_id
+pub async fn anonymize_expired_loans(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let expired = expired_loans_sql("anonymize");
+    let mut tx = pool.begin().await?;
+    
+    for statement in [
+        "DELETE FROM extension_requests WHERE lending_record_id IN ({})".to_string(),
+        format!("UPDATE events SET data = json_set(data, '$.user_id', NULL) WHERE {}", LOAN_EVENTS_SQL),
+    ] {
+        sqlx::query(&statement.replace("{}", expired))
+            .bind(before)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let result = sqlx::query(&format!("UPDATE lending_records SET user_id = NULL WHERE id IN ({})", expired))
+        .bind(before)
+        .execute(&mut *tx)
+        .await?;
+    
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_expired_loans(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let expired = expired_loans_sql("delete");
+    let mut tx = pool.begin().await?;
+    
+    for statement in [
+        "DELETE FROM extension_requests WHERE lending_record_id IN ({})".to_string(),
+        "DELETE FROM lending_notes WHERE lending_record_id IN ({})".to_string(),
+        format!("DELETE FROM events WHERE {}", LOAN_EVENTS_SQL),
+    ] {
+        sqlx::query(&statement.replace("{}", expired))
+            .bind(before)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let result = sqlx::query(&format!("DELETE FROM lending_records WHERE id IN ({})", expired))
+        .bind(before)
+        .execute(&mut *tx)
+        .await?;
+    
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn count_events_before(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events WHERE created_at < ?")
+        .bind(before)
+        .fetch_one(pool)
+        .await?;
+    
+    Ok(count as u64)
+}
+
+pub async fn delete_events_before(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM events WHERE created_at < ?")
+        .bind(before)
         .execute(pool)
         .await?;
     
+    Ok(result.rows_affected())
+}
+
+// Notifications still held for a digest are kept until it goes out
+pub async fn count_notifications_before(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM notifications WHERE created_at < ? AND (digest_status IS NULL OR digest_status != 'pending')"
+    )
+    .bind(before)
+    .fetch_one(pool)
+    .await?;
+    
+    Ok(count as u64)
+}
+
+pub async fn delete_notifications_before(pool: &SqlitePool, before: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM notifications WHERE created_at < ? AND (digest_status IS NULL OR digest_status != 'pending')"
+    )
+    .bind(before)
+    .execute(pool)
+    .await?;
+    
+    Ok(result.rows_affected())
+}
+
+// Closure operations
+pub async fn create_closure(pool: &SqlitePool, req: &CreateClosureRequest, created_by: i64) -> Result<i64, sqlx::Error> {
+    let ends_on = req.ends_on.as_ref().unwrap_or(&req.starts_on);
+    
+    let result = sqlx::query(
+        "INSERT INTO closures (starts_on, ends_on, reason, created_by) VALUES (?, ?, ?, ?)"
+    )
+    .bind(&req.starts_on)
+    .bind(ends_on)
+    .bind(&req.reason)
+    .bind(created_by)
+    .execute(pool)
+    .await?;
+    
     Ok(result.last_insert_rowid())
 }
 
-pub async fn return_book(pool: &SqlitePool, record_id: i64, user_id: i64) -> Result<(), sqlx::Error> {
-    // Get lending record
-    let record = sqlx::query(
-        "SELECT id, user_id, book_id, status FROM lending_records WHERE id = ?"
+pub async fn get_closure_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Closure>, sqlx::Error> {
+    let closure = sqlx::query_as::<_, Closure>(
+        "SELECT id, starts_on, ends_on, reason, created_by, created_at FROM closures WHERE id = ?"
     )
-    .bind(record_id)
+    .bind(id)
     .fetch_optional(pool)
     .await?;
     
-    if record.is_none() {
+    Ok(closure)
+}
+
+pub async fn get_all_closures(pool: &SqlitePool) -> Result<Vec<Closure>, sqlx::Error> {
+    let closures = sqlx::query_as::<_, Closure>(
+        "SELECT id, starts_on, ends_on, reason, created_by, created_at FROM closures ORDER BY starts_on DESC"
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(closures)
+}
+
+// Closures overlapping the inclusive date range `from`..=`to` (YYYY-MM-DD)
+pub async fn get_closures_between(pool: &SqlitePool, from: &str, to: &str) -> Result<Vec<Closure>, sqlx::Error> {
+    let closures = sqlx::query_as::<_, Closure>(
+        "SELECT id, starts_on, ends_on, reason, created_by, created_at FROM closures 
+         WHERE ends_on >= ? AND starts_on <= ?
+         ORDER BY starts_on"
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(closures)
+}
+
+pub async fn update_closure(pool: &SqlitePool, id: i64, starts_on: &str, ends_on: &str, reason: &str) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("UPDATE closures SET starts_on = ?, ends_on = ?, reason = ? WHERE id = ?")
+        .bind(starts_on)
+        .bind(ends_on)
+        .bind(reason)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    
+    if result.rows_affected() == 0 {
         return Err(sqlx::Error::RowNotFound);
     }
     
-    let record = record.unwrap();
-    let record_user_id: i64 = record.get("user_id");
-    let book_id: i64 = record.get("book_id");
-    let status: String = record.get("status");
+    Ok(())
+}
+
+pub async fn delete_closure(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    let result = sqlx::query("DELETE FROM closures WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
     
-    if record_user_id != user_id {
-        return Err(sqlx::Error::RowNotFound); // Not authorized
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
     }
     
-    if status != "borrowed" && status != "overdue" {
-        return Err(sqlx::Error::RowNotFound); // Already returned
+    Ok(())
+}
+
+// Integrity checks
+pub async fn schema_version(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(pool)
+        .await
+}
+
+// PRAGMA integrity_check answers a single "ok" row for a sound file, else one row per problem
+pub async fn integrity_problems(pool: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+    let rows: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().filter(|row| row != "ok").collect())
+}
+
+pub async fn foreign_key_violations(pool: &SqlitePool) -> Result<Vec<ForeignKeyViolation>, sqlx::Error> {
+    let rows = sqlx::query("PRAGMA foreign_key_check")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| ForeignKeyViolation {
+            table: row.get("table"),
+            rowid: row.get("rowid"),
+            parent: row.get("parent"),
+        })
+        .collect())
+}
+
+// Backup operations
+pub async fn backup_to(pool: &SqlitePool, path: &std::path::Path) -> Result<(), sqlx::Error> {
+    // VACUUM INTO writes a transactionally consistent copy without blocking writers for long
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+    
+    Ok(())
+}
+
+pub fn backup_file_name() -> String {
+    format!("library-{}.db", crate::clock::now().format("%Y%m%d-%H%M%S"))
+}
+
+// Only names backup_file_name could have produced, so a requested name can't reach other files
+pub fn is_backup_file_name(name: &str) -> bool {
+    name.strip_prefix("library-")
+        .and_then(|rest| rest.strip_suffix(".db"))
+        .is_some_and(|stamp| stamp.len() == 15 && stamp.chars().enumerate().all(|(i, c)| if i == 8 { c == '-' } else { c.is_ascii_digit() }))
+}
+
+// Implement FromRow for custom types
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for User {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(User {
+            id: row.try_get("id")?,
+            username: row.try_get("username")?,
+            email: row.try_get("email")?,
+            password_hash: row.try_get("password_hash")?,
+            role: row.try_get("role")?,
+            display_name: row.try_get("display_name")?,
+            email_verified: row.try_get("email_verified")?,
+            is_active: row.try_get("is_active")?,
+            anonymized_at: row.try_get("anonymized_at")?,
+            card_number: row.try_get("card_number")?,
+            member_type: row.try_get("member_type")?,
+            membership_expires_at: row.try_get("membership_expires_at")?,
+            suspended_at: row.try_get("suspended_at")?,
+            suspension_reason: row.try_get("suspension_reason")?,
+            home_branch_id: row.try_get("home_branch_id")?,
+            approval_status: row.try_get("approval_status")?,
+            two_factor_enabled: row.try_get("two_factor_enabled")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Book {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Book {
+            id: row.try_get("id")?,
+            title: row.try_get("title")?,
+            author: row.try_get("author")?,
+            isbn: row.try_get("isbn")?,
+            publication_year: row.try_get("publication_year")?,
+            genre: row.try_get("genre")?,
+            total_copies: row.try_get("total_copies")?,
+            available_copies: row.try_get("available_copies")?,
+            created_at: row.try_get("created_at")?,
+            on_order: row.try_get("on_order")?,
+            item_type: row.try_get("item_type")?,
+            issue_number: row.try_get("issue_number")?,
+            platform: row.try_get("platform")?,
+            loan_period_days: row.try_get("loan_period_days")?,
+            loanable: row.try_get("loanable")?,
+            series_id: row.try_get("series_id")?,
+            series_position: row.try_get("series_position")?,
+            version: row.try_get("version")?,
+            tags: row
+                .try_get::<Option<String>, _>("tags")?
+                .map(|tags| tags.split('\x1f').map(str::to_string).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for LendingRecordWithDetails {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(LendingRecordWithDetails {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            username: row.try_get("username")?,
+            book_id: row.try_get("book_id")?,
+            title: row.try_get("title")?,
+            author: row.try_get("author")?,
+            borrowed_at: row.try_get("borrowed_at")?,
+            due_date: row.try_get("due_date")?,
+            returned_at: row.try_get("returned_at")?,
+            status: row.try_get("status")?,
+            recalled_at: row.try_get("recalled_at")?,
+            branch_id: row.try_get("branch_id")?,
+            escalation_level: row.try_get("escalation_level")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Announcement {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Announcement {
+            id: row.try_get("id")?,
+            title: row.try_get("title")?,
+            message: row.try_get("message")?,
+            level: row.try_get("level")?,
+            starts_at: row.try_get("starts_at")?,
+            ends_at: row.try_get("ends_at")?,
+            created_by: row.try_get("created_by")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for ExtensionRequest {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(ExtensionRequest {
+            id: row.try_get("id")?,
+            lending_record_id: row.try_get("lending_record_id")?,
+            user_id: row.try_get("user_id")?,
+            username: row.try_get("username")?,
+            email: row.try_get("email")?,
+            title: row.try_get("title")?,
+            due_date: row.try_get("due_date")?,
+            reason: row.try_get("reason")?,
+            requested_days: row.try_get("requested_days")?,
+            status: row.try_get("status")?,
+            decision_note: row.try_get("decision_note")?,
+            decided_by: row.try_get("decided_by")?,
+            decided_at: row.try_get("decided_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Suggestion {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Suggestion {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            username: row.try_get("username")?,
+            email: row.try_get("email")?,
+            title: row.try_get("title")?,
+            author: row.try_get("author")?,
+            isbn: row.try_get("isbn")?,
+            reason: row.try_get("reason")?,
+            status: row.try_get("status")?,
+            decision_note: row.try_get("decision_note")?,
+            decided_by: row.try_get("decided_by")?,
+            decided_at: row.try_get("decided_at")?,
+            book_id: row.try_get("book_id")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Branch {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Branch {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            code: row.try_get("code")?,
+            address: row.try_get("address")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for BranchHolding {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(BranchHolding {
+            branch_id: row.try_get("branch_id")?,
+            branch_name: row.try_get("branch_name")?,
+            copies: row.try_get("copies")?,
+            available_copies: row.try_get("available_copies")?,
+            location_id: row.try_get("location_id")?,
+            location: row.try_get("location")?,
+            call_number_prefix: row.try_get("call_number_prefix")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Series {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Series {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            description: row.try_get("description")?,
+            volume_count: row.try_get("volume_count")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for WishlistEntry {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(WishlistEntry {
+            book_id: row.try_get("book_id")?,
+            title: row.try_get("title")?,
+            author: row.try_get("author")?,
+            available_copies: row.try_get("available_copies")?,
+            notify: row.try_get("notify")?,
+            notified_at: row.try_get("notified_at")?,
+            added_at: row.try_get("added_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for BookWatch {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(BookWatch {
+            book_id: row.try_get("book_id")?,
+            title: row.try_get("title")?,
+            author: row.try_get("author")?,
+            available_copies: row.try_get("available_copies")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for SearchSuggestion {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(SearchSuggestion {
+            text: row.try_get("text")?,
+            kind: row.try_get("kind")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for AuthorFollow {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(AuthorFollow {
+            author: row.try_get("author")?,
+            book_count: row.try_get("book_count")?,
+            created_at: row.try_get("created_at")?,
+        })
     }
-    
-    // Update lending record
-    let returned_at = Utc::now();
-    sqlx::query(
-        "UPDATE lending_records SET returned_at = ?, status = 'returned' WHERE id = ?"
-    )
-    .bind(returned_at.to_rfc3339())
-    .bind(record_id)
-    .execute(pool)
-    .await?;
-    
-    // Increase available copies
-    sqlx::query("UPDATE books SET available_copies = available_copies + 1 WHERE id = ?")
-        .bind(book_id)
-        .execute(pool)
-        .await?;
-    
-    Ok(())
 }
 
-pub async fn get_user_borrowed_books(pool: &SqlitePool, user_id: i64) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
-    let records = sqlx::query_as::<_, LendingRecordWithDetails>(
-        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
-                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status
-         FROM lending_records lr
-         INNER JOIN users u ON lr.user_id = u.id
-         INNER JOIN books b ON lr.book_id = b.id
-         WHERE lr.user_id = ? AND lr.status IN ('borrowed', 'overdue')
-         ORDER BY lr.borrowed_at DESC"
-    )
-    .bind(user_id)
-    .fetch_all(pool)
-    .await?;
-    
-    Ok(records)
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for ReadingList {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(ReadingList {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            description: row.try_get("description")?,
+            entry_count: row.try_get("entry_count")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
 }
 
-pub async fn get_all_active_lending(pool: &SqlitePool) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
-    let records = sqlx::query_as::<_, LendingRecordWithDetails>(
-        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
-                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status
-         FROM lending_records lr
-         INNER JOIN users u ON lr.user_id = u.id
-         INNER JOIN books b ON lr.book_id = b.id
-         WHERE lr.status IN ('borrowed', 'overdue')
-         ORDER BY lr.borrowed_at DESC"
-    )
-    .fetch_all(pool)
-    .await?;
-    
-    Ok(records)
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for PeriodicalIssue {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(PeriodicalIssue {
+            id: row.try_get("id")?,
+            title_id: row.try_get("title_id")?,
+            title: row.try_get("title")?,
+            volume: row.try_get("volume")?,
+            number: row.try_get("number")?,
+            issue_date: row.try_get("issue_date")?,
+            status: row.try_get("status")?,
+            received_at: row.try_get("received_at")?,
+            total_copies: row.try_get("total_copies")?,
+            available_copies: row.try_get("available_copies")?,
+            loan_period_days: row.try_get("loan_period_days")?,
+            loanable: row.try_get("loanable")?,
+        })
+    }
 }
 
-pub async fn get_overdue_books(pool: &SqlitePool) -> Result<Vec<LendingRecordWithDetails>, sqlx::Error> {
-    let now = Utc::now().to_rfc3339();
-    
-    // First update overdue status
-    sqlx::query(
-        "UPDATE lending_records SET status = 'overdue' 
-         WHERE status = 'borrowed' AND due_date < ?"
-    )
-    .bind(&now)
-    .execute(pool)
-    .await?;
-    
-    let records = sqlx::query_as::<_, LendingRecordWithDetails>(
-        "SELECT lr.id, lr.user_id, u.username, lr.book_id, b.title, b.author, 
-                lr.borrowed_at, lr.due_date, lr.returned_at, lr.status
-         FROM lending_records lr
-         INNER JOIN users u ON lr.user_id = u.id
-         INNER JOIN books b ON lr.book_id = b.id
-         WHERE lr.status = 'overdue'
-         ORDER BY lr.due_date ASC"
-    )
-    .fetch_all(pool)
-    .await?;
-    
-    Ok(records)
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Location {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Location {
+            id: row.try_get("id")?,
+            branch_id: row.try_get("branch_id")?,
+            branch_name: row.try_get("branch_name")?,
+            room: row.try_get("room")?,
+            shelf: row.try_get("shelf")?,
+            call_number_prefix: row.try_get("call_number_prefix")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
 }
 
-// Implement FromRow for custom types
-impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for User {
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for ShelfListEntry {
     fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
-        Ok(User {
+        Ok(ShelfListEntry {
+            book_id: row.try_get("book_id")?,
+            title: row.try_get("title")?,
+            author: row.try_get("author")?,
+            isbn: row.try_get("isbn")?,
+            copies: row.try_get("copies")?,
+            on_loan: row.try_get("on_loan")?,
+            in_transit: row.try_get("in_transit")?,
+            expected_on_shelf: row.try_get("expected_on_shelf")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Transfer {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Transfer {
             id: row.try_get("id")?,
+            book_id: row.try_get("book_id")?,
+            title: row.try_get("title")?,
+            author: row.try_get("author")?,
+            user_id: row.try_get("user_id")?,
             username: row.try_get("username")?,
             email: row.try_get("email")?,
-            password_hash: row.try_get("password_hash")?,
-            role: row.try_get("role")?,
+            from_branch_id: row.try_get("from_branch_id")?,
+            from_branch: row.try_get("from_branch")?,
+            to_branch_id: row.try_get("to_branch_id")?,
+            to_branch: row.try_get("to_branch")?,
+            status: row.try_get("status")?,
+            handled_by: row.try_get("handled_by")?,
             created_at: row.try_get("created_at")?,
+            shipped_at: row.try_get("shipped_at")?,
+            arrived_at: row.try_get("arrived_at")?,
+            cancelled_at: row.try_get("cancelled_at")?,
         })
     }
 }
 
-impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Book {
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Donation {
     fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
-        Ok(Book {
+        Ok(Donation {
             id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            username: row.try_get("username")?,
+            email: row.try_get("email")?,
             title: row.try_get("title")?,
             author: row.try_get("author")?,
             isbn: row.try_get("isbn")?,
             publication_year: row.try_get("publication_year")?,
             genre: row.try_get("genre")?,
-            total_copies: row.try_get("total_copies")?,
-            available_copies: row.try_get("available_copies")?,
+            copies: row.try_get("copies")?,
+            notes: row.try_get("notes")?,
+            status: row.try_get("status")?,
+            decision_note: row.try_get("decision_note")?,
+            decided_by: row.try_get("decided_by")?,
+            decided_at: row.try_get("decided_at")?,
+            book_id: row.try_get("book_id")?,
             created_at: row.try_get("created_at")?,
         })
     }
 }
 
-impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for LendingRecordWithDetails {
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Acquisition {
     fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
-        Ok(LendingRecordWithDetails {
+        Ok(Acquisition {
+            id: row.try_get("id")?,
+            book_id: row.try_get("book_id")?,
+            title: row.try_get("title")?,
+            copies: row.try_get("copies")?,
+            source: row.try_get("source")?,
+            donor_id: row.try_get("donor_id")?,
+            donor: row.try_get("donor")?,
+            donation_id: row.try_get("donation_id")?,
+            recorded_by: row.try_get("recorded_by")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for LendingNote {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(LendingNote {
+            id: row.try_get("id")?,
+            lending_record_id: row.try_get("lending_record_id")?,
+            author_id: row.try_get("author_id")?,
+            author: row.try_get("author")?,
+            note: row.try_get("note")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Closure {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Closure {
+            id: row.try_get("id")?,
+            starts_on: row.try_get("starts_on")?,
+            ends_on: row.try_get("ends_on")?,
+            reason: row.try_get("reason")?,
+            created_by: row.try_get("created_by")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for SessionInfo {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(SessionInfo {
+            id: row.try_get("id")?,
+            created_at: row.try_get("created_at")?,
+            expires_at: row.try_get("expires_at")?,
+            last_used_at: row.try_get("last_used_at")?,
+            user_agent: row.try_get("user_agent")?,
+            ip_address: row.try_get("ip_address")?,
+            impersonated: row.try_get("impersonated")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Notification {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Notification {
+            id: row.try_get("id")?,
+            channel: row.try_get("channel")?,
+            recipient: row.try_get("recipient")?,
+            subject: row.try_get("subject")?,
+            body: row.try_get("body")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for AuditEntry {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(AuditEntry {
+            id: row.try_get("id")?,
+            actor_id: row.try_get("actor_id")?,
+            action: row.try_get("action")?,
+            target_type: row.try_get("target_type")?,
+            target_id: row.try_get("target_id")?,
+            details: row.try_get("details")?,
+            ip: row.try_get("ip")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for ItemType {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(ItemType {
+            name: row.try_get("name")?,
+            loan_period_days: row.try_get("loan_period_days")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for MemberType {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(MemberType {
+            name: row.try_get("name")?,
+            loan_period_days: row.try_get("loan_period_days")?,
+            max_concurrent_loans: row.try_get("max_concurrent_loans")?,
+            fine_per_day: row.try_get("fine_per_day")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Fine {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Fine {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            lending_record_id: row.try_get("lending_record_id")?,
+            title: row.try_get("title")?,
+            amount: row.try_get("amount")?,
+            amount_paid: row.try_get("amount_paid")?,
+            status: row.try_get("status")?,
+            reason: row.try_get("reason")?,
+            created_at: row.try_get("created_at")?,
+            paid_at: row.try_get("paid_at")?,
+            accruing: row.try_get("accruing")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for FinePayment {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(FinePayment {
+            id: row.try_get("id")?,
+            fine_id: row.try_get("fine_id")?,
+            amount: row.try_get("amount")?,
+            method: row.try_get("method")?,
+            note: row.try_get("note")?,
+            recorded_by: row.try_get("recorded_by")?,
+            paid_at: row.try_get("paid_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for ReservationWithDetails {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(ReservationWithDetails {
             id: row.try_get("id")?,
             user_id: row.try_get("user_id")?,
             username: row.try_get("username")?,
+            email: row.try_get("email")?,
             book_id: row.try_get("book_id")?,
             title: row.try_get("title")?,
             author: row.try_get("author")?,
-            borrowed_at: row.try_get("borrowed_at")?,
-            due_date: row.try_get("due_date")?,
-            returned_at: row.try_get("returned_at")?,
             status: row.try_get("status")?,
+            queue_position: row.try_get("queue_position")?,
+            created_at: row.try_get("created_at")?,
+            ready_at: row.try_get("ready_at")?,
+            expires_at: row.try_get("expires_at")?,
+            fulfilled_at: row.try_get("fulfilled_at")?,
+        })
+    }
+}
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for AvailabilityCorrection {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(AvailabilityCorrection {
+            book_id: row.try_get("book_id")?,
+            title: row.try_get("title")?,
+            total_copies: row.try_get("total_copies")?,
+            recorded: row.try_get("recorded")?,
+            actual: row.try_get("actual")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for AccessLogEntry {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(AccessLogEntry {
+            id: row.try_get("id")?,
+            method: row.try_get("method")?,
+            path: row.try_get("path")?,
+            status: row.try_get("status")?,
+            user_id: row.try_get("user_id")?,
+            username: row.try_get("username")?,
+            ip: row.try_get("ip")?,
+            latency_ms: row.try_get("latency_ms")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for ApiKey {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        let scopes: String = row.try_get("scopes")?;
+        Ok(ApiKey {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            prefix: row.try_get("prefix")?,
+            scopes: scopes.split_whitespace().map(str::to_string).collect(),
+            created_at: row.try_get("created_at")?,
+            last_used_at: row.try_get("last_used_at")?,
+            revoked_at: row.try_get("revoked_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Invite {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Invite {
+            id: row.try_get("id")?,
+            role: row.try_get("role")?,
+            username: row.try_get("username")?,
+            email: row.try_get("email")?,
+            member_type: row.try_get("member_type")?,
+            card_number: row.try_get("card_number")?,
+            created_by: row.try_get("created_by")?,
+            created_at: row.try_get("created_at")?,
+            expires_at: row.try_get("expires_at")?,
+            used_at: row.try_get("used_at")?,
+            used_by: row.try_get("used_by")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for EmailTemplate {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(EmailTemplate {
+            key: row.try_get("key")?,
+            subject: row.try_get("subject")?,
+            body: row.try_get("body")?,
+            updated_by: row.try_get("updated_by")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for PushSubscription {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(PushSubscription {
+            id: row.try_get("id")?,
+            endpoint: row.try_get("endpoint")?,
+            p256dh: row.try_get("p256dh")?,
+            auth: row.try_get("auth")?,
+            user_agent: row.try_get("user_agent")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Webhook {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        let url: String = row.try_get("url")?;
+        let events: String = row.try_get("events")?;
+        let host = url.trim_start_matches("https://").split('/').next().unwrap_or_default();
+        let tail: String = url.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+        Ok(Webhook {
+            id: row.try_get("id")?,
+            service: row.try_get("service")?,
+            url_hint: format!("https://{}/...{}", host, tail),
+            url,
+            events: events.split_whitespace().map(str::to_string).collect(),
+            enabled: row.try_get("enabled")?,
+            created_by: row.try_get("created_by")?,
+            created_at: row.try_get("created_at")?,
+            last_delivery_at: row.try_get("last_delivery_at")?,
+            last_error: row.try_get("last_error")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Event {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        let data: String = row.try_get("data")?;
+        Ok(Event {
+            id: row.try_get("id")?,
+            event_type: row.try_get("event_type")?,
+            data: serde_json::from_str(&data).unwrap_or(serde_json::Value::Null),
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Broadcast {
+    fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Broadcast {
+            id: row.try_get("id")?,
+            subject: row.try_get("subject")?,
+            body: row.try_get("body")?,
+            audience: row.try_get("audience")?,
+            status: row.try_get("status")?,
+            total: row.try_get("total")?,
+            sent: row.try_get("sent")?,
+            failed: row.try_get("failed")?,
+            skipped: row.try_get("skipped")?,
+            pending: row.try_get("pending")?,
+            created_by: row.try_get("created_by")?,
+            created_at: row.try_get("created_at")?,
+            started_at: row.try_get("started_at")?,
+            completed_at: row.try_get("completed_at")?,
         })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,124 @@
+use std::sync::OnceLock;
+
+// Where panics and 5xx responses are sent for tracking. The default does nothing; building with
+// the `sentry` feature and setting LIBRARY_SENTRY_DSN sends them to Sentry (or anything speaking
+// its store API, such as GlitchTip)
+
+// Only the Sentry reporter reads the fields; without it every report is dropped
+#[cfg_attr(not(feature = "sentry"), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub struct Problem {
+    // "panic" for a handler that panicked, "server_error" for any other 5xx response
+    pub kind: &'static str,
+    pub message: String,
+    pub method: String,
+    // Without the query string, which can carry tokens
+    pub path: String,
+    pub status: u16,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+pub trait ErrorReporter: Send + Sync {
+    // Called on the request's task, so anything slow belongs on another thread
+    fn report(&self, problem: &Problem);
+}
+
+pub struct NoopReporter;
+
+impl ErrorReporter for NoopReporter {
+    fn report(&self, _problem: &Problem) {}
+}
+
+static REPORTER: OnceLock<Box<dyn ErrorReporter>> = OnceLock::new();
+
+pub fn reporter() -> &'static dyn ErrorReporter {
+    REPORTER.get_or_init(select).as_ref()
+}
+
+#[cfg(feature = "sentry")]
+fn select() -> Box<dyn ErrorReporter> {
+    match &crate::config::get().sentry_dsn {
+        Some(dsn) => Box::new(sentry::SentryReporter::from_dsn(dsn).unwrap_or_else(|| panic!("Invalid LIBRARY_SENTRY_DSN: {}", dsn))),
+        None => Box::new(NoopReporter),
+    }
+}
+
+#[cfg(not(feature = "sentry"))]
+fn select() -> Box<dyn ErrorReporter> {
+    Box::new(NoopReporter)
+}
+
+// The message a panic was raised with, when it was a string
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Handler panicked".to_string())
+}
+
+#[cfg(feature = "sentry")]
+mod sentry {
+    use serde_json::json;
+
+    use super::{ErrorReporter, Problem};
+    use crate::{config, http};
+
+    // Events go to the store endpoint named by the DSN, https://<key>@<host>/<project>
+    pub struct SentryReporter {
+        store_url: String,
+        auth: String,
+    }
+
+    impl SentryReporter {
+        pub fn from_dsn(dsn: &str) -> Option<SentryReporter> {
+            let rest = dsn.strip_prefix("https://")?;
+            let (key, rest) = rest.split_once('@')?;
+            let (host, project) = rest.rsplit_once('/')?;
+            let key = key.split(':').next()?;
+            if key.is_empty() || host.is_empty() || project.is_empty() || !project.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            Some(SentryReporter {
+                store_url: format!("https://{}/api/{}/store/", host, project),
+                auth: format!("Sentry sentry_version=7, sentry_key={}, sentry_client=book-lending-system/{}", key, env!("CARGO_PKG_VERSION")),
+            })
+        }
+    }
+
+    impl ErrorReporter for SentryReporter {
+        fn report(&self, problem: &Problem) {
+            let mut event = json!({
+                "event_id": uuid::Uuid::new_v4().simple().to_string(),
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "platform": "other",
+                "level": if problem.kind == "panic" { "fatal" } else { "error" },
+                "logger": "book-lending-system",
+                "release": env!("CARGO_PKG_VERSION"),
+                "message": { "formatted": problem.message },
+                "tags": { "kind": problem.kind, "status": problem.status.to_string() },
+                "request": {
+                    "method": problem.method,
+                    "url": format!("{}{}", config::get().public_url.trim_end_matches('/'), problem.path),
+                    "headers": { "User-Agent": problem.user_agent },
+                },
+                "user": { "ip_address": problem.client_ip },
+            });
+            if problem.kind == "panic" {
+                event["exception"] = json!({ "values": [{ "type": "panic", "value": problem.message }] });
+            }
+
+            let (url, auth) = (self.store_url.clone(), self.auth.clone());
+            tokio::task::spawn_blocking(move || {
+                let headers = [("Content-Type", "application/json"), ("X-Sentry-Auth", auth.as_str())];
+                // The DSN comes from configuration and may well name a server on the local network
+                match http::request("POST", &url, &headers, event.to_string().as_bytes(), http::Reach::Any) {
+                    Ok(response) if (200..300).contains(&response.status) => {}
+                    Ok(response) => eprintln!("Error report rejected with status {}", response.status),
+                    Err(e) => eprintln!("Failed to send error report: {}", e),
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::csv;
+
+// Reads personal-collection exports from Goodreads ("Bookshelves", "ISBN13", ...) and
+// LibraryThing ("Primary Author", "Tags", ...), matching columns by header name
+pub struct CollectionBook {
+    pub title: String,
+    pub author: String,
+    pub isbn: String,
+    pub publication_year: Option<i32>,
+    pub tags: Vec<String>,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Result<CollectionBook, String>>, String> {
+    let mut rows = csv::parse(text).into_iter();
+    let header = rows.next().ok_or("The file is empty")?;
+    let columns = Columns::new(&header)?;
+    Ok(rows.map(|row| columns.book(&row)).collect())
+}
+
+struct Columns {
+    title: usize,
+    author: Option<usize>,
+    isbn: Vec<usize>,
+    year: Vec<usize>,
+    shelves: Vec<usize>,
+}
+
+impl Columns {
+    fn new(header: &[String]) -> Result<Columns, String> {
+        let find = |names: &[&str]| names.iter().filter_map(|n| header.iter().position(|h| h.trim().eq_ignore_ascii_case(n))).collect::<Vec<_>>();
+
+        Ok(Columns {
+            title: *find(&["Title"]).first().ok_or("Missing Title column")?,
+            author: find(&["Author", "Primary Author", "Author (First, Last)"]).first().copied(),
+            isbn: find(&["ISBN13", "ISBN", "ISBNs"]),
+            year: find(&["Original Publication Year", "Year Published", "Date", "Publication Date"]),
+            shelves: find(&["Bookshelves", "Exclusive Shelf", "Tags", "Collections"]),
+        })
+    }
+
+    fn book(&self, row: &[String]) -> Result<CollectionBook, String> {
+        let get = |i: usize| row.get(i).map(|v| v.trim()).unwrap_or("");
+
+        let title = get(self.title).to_string();
+        if title.is_empty() {
+            return Err("Missing title".to_string());
+        }
+
+        let author = self.author.map(get).unwrap_or("").to_string();
+        if author.is_empty() {
+            return Err("Missing author".to_string());
+        }
+
+        // Goodreads writes ISBNs as ="9780140449136" so spreadsheets keep leading zeros
+        let isbn = self
+            .isbn
+            .iter()
+            .map(|i| clean_isbn(get(*i)))
+            .find(|isbn| isbn.len() == 13 || isbn.len() == 10)
+            .ok_or("Missing or invalid ISBN")?;
+
+        let publication_year = self.year.iter().find_map(|i| first_year(get(*i)));
+
+        let mut tags: Vec<String> = self
+            .shelves
+            .iter()
+            .flat_map(|i| get(*i).split(','))
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        Ok(CollectionBook { title, author, isbn, publication_year, tags })
+    }
+}
+
+fn clean_isbn(value: &str) -> String {
+    // LibraryThing's "ISBNs" column may list several; take the first
+    let first = value.trim_start_matches('=').trim_matches('"').split([',', ' ']).next().unwrap_or("");
+    first.chars().filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x').collect()
+}
+
+fn first_year(value: &str) -> Option<i32> {
+    let chars: Vec<char> = value.chars().collect();
+    chars
+        .windows(4)
+        .find(|w| w.iter().all(|c| c.is_ascii_digit()))
+        .and_then(|w| w.iter().collect::<String>().parse().ok())
+}
@@ -0,0 +1,210 @@
+// gzip response compression. The encoder is LZ77 over a 32 KiB window with deflate's fixed
+// Huffman codes: no dynamic tables, but JSON and CSV are repetitive enough that matches alone
+// bring them down to a fraction of their size
+
+use crate::config;
+
+// Responses smaller than this aren't worth the CPU or the gzip framing
+const MIN_BYTES: usize = 1024;
+
+// True when the Accept-Encoding header allows gzip; an explicit q=0 refuses it, and `*` stands
+// in for gzip when gzip isn't named
+pub fn accepted(request: &str) -> bool {
+    let Some(header) = crate::request_header(request, "accept-encoding") else { return false };
+    crate::accept_quality(header, "gzip")
+        .or_else(|| crate::accept_quality(header, "*"))
+        .is_some_and(|q| q > 0.0)
+}
+
+// Compresses the body of a successful text response (JSON, CSV, HTML, XML, calendars) and
+// marks it with Content-Encoding; anything else, or a body that doesn't shrink, goes out as is
+pub fn encode_response(response: Vec<u8>) -> Vec<u8> {
+    let level = config::get().compression_level;
+    if level == 0 || response.len() < MIN_BYTES || !response.starts_with(b"HTTP/1.1 200 ") {
+        return response;
+    }
+    let Some(head_end) = response.windows(4).position(|w| w == b"\r\n\r\n") else { return response };
+    let body = &response[head_end + 4..];
+    if body.len() < MIN_BYTES {
+        return response;
+    }
+
+    let head = String::from_utf8_lossy(&response[..head_end]).into_owned();
+    let mut content_type = None;
+    for line in head.lines().skip(1) {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-encoding" => return response,
+            "content-type" => content_type = Some(value.trim().to_ascii_lowercase()),
+            _ => {}
+        }
+    }
+    let compressible = content_type.is_some_and(|t| {
+        t.starts_with("text/") || t.contains("json") || t.contains("xml") || t.contains("javascript")
+    });
+    if !compressible {
+        return response;
+    }
+
+    let compressed = gzip(body, level);
+    if compressed.len() >= body.len() {
+        return response;
+    }
+
+    let mut out = Vec::with_capacity(head_end + compressed.len() + 64);
+    for (i, line) in head.lines().enumerate() {
+        if i > 0 {
+            out.extend_from_slice(b"\r\n");
+        }
+        match line.split_once(':') {
+            Some((name, _)) if name.trim().eq_ignore_ascii_case("content-length") => {
+                out.extend_from_slice(format!("Content-Length: {}", compressed.len()).as_bytes());
+            }
+            _ => out.extend_from_slice(line.as_bytes()),
+        }
+    }
+    out.extend_from_slice(b"\r\nContent-Encoding: gzip\r\nVary: Accept-Encoding\r\n\r\n");
+    out.extend_from_slice(&compressed);
+    out
+}
+
+// A complete gzip member: 10-byte header (no name, no timestamp), deflate data, CRC and length
+pub fn gzip(data: &[u8], level: u32) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    out.extend_from_slice(&deflate(data, level));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+
+// Base value and extra bit count of each length code (257..=285) and distance code (0..=29)
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u32; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u32; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Raw deflate as a single fixed-Huffman block. The level (1-9) sets how many earlier positions
+// with the same three-byte prefix are tried for each match
+fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+    let max_chain = 1usize << (level.clamp(1, 9) + 2);
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW];
+    let hash = |pos: usize| {
+        let key = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+        (key.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+    };
+
+    let mut bits = BitWriter::default();
+    bits.put(1, 1);
+    bits.put(1, 2);
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(pos);
+            let mut candidate = head[h];
+            let mut chain = max_chain;
+            let limit = (data.len() - pos).min(MAX_MATCH);
+            while candidate != usize::MAX && pos - candidate <= WINDOW && chain > 0 {
+                let length = data[candidate..].iter().zip(&data[pos..pos + limit]).take_while(|(a, b)| a == b).count();
+                if length > best.0 {
+                    best = (length, pos - candidate);
+                    if length == limit {
+                        break;
+                    }
+                }
+                candidate = prev[candidate % WINDOW];
+                chain -= 1;
+            }
+        }
+
+        let step = if best.0 >= MIN_MATCH {
+            write_match(&mut bits, best.0, best.1);
+            best.0
+        } else {
+            write_literal(&mut bits, data[pos] as u16);
+            1
+        };
+        for p in pos..pos + step {
+            if p + MIN_MATCH <= data.len() {
+                let h = hash(p);
+                prev[p % WINDOW] = head[h];
+                head[h] = p;
+            }
+        }
+        pos += step;
+    }
+
+    write_literal(&mut bits, 256);
+    bits.finish()
+}
+
+// Fixed Huffman literal/length code for a symbol (RFC 1951 section 3.2.6)
+fn write_literal(bits: &mut BitWriter, symbol: u16) {
+    let (code, length) = match symbol {
+        0..=143 => (0x30 + symbol as u32, 8),
+        144..=255 => (0x190 + (symbol - 144) as u32, 9),
+        256..=279 => ((symbol - 256) as u32, 7),
+        _ => (0xc0 + (symbol - 280) as u32, 8),
+    };
+    bits.put_code(code, length);
+}
+
+fn write_match(bits: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1;
+    write_literal(bits, 257 + index as u16);
+    bits.put((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index]);
+
+    let index = DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    bits.put_code(index as u32, 5);
+    bits.put((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index]);
+}
+
+// Deflate packs bits from the least significant end, but Huffman codes go most significant bit
+// first, hence put_code reversing them
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, length: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += length;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn put_code(&mut self, code: u32, length: u32) {
+        self.put(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
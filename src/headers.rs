@@ -0,0 +1,41 @@
+use crate::{config, settings};
+
+// Security headers added to every response on its way out, whichever helper built it.
+// A header the response already carries is left as it is, and an empty setting turns that
+// header off
+pub fn apply(response: Vec<u8>) -> Vec<u8> {
+    let Some(head_end) = response.windows(4).position(|w| w == b"\r\n\r\n") else { return response };
+    let Some(status_end) = response.windows(2).position(|w| w == b"\r\n") else { return response };
+    let head = String::from_utf8_lossy(&response[..head_end]).to_ascii_lowercase();
+
+    let mut extra = String::new();
+    for (name, value) in security_headers() {
+        if !value.is_empty() && !head.contains(&format!("\r\n{}:", name.to_ascii_lowercase())) {
+            extra.push_str(&format!("\r\n{}: {}", name, value));
+        }
+    }
+
+    let mut out = Vec::with_capacity(response.len() + extra.len());
+    out.extend_from_slice(&response[..status_end]);
+    out.extend_from_slice(extra.as_bytes());
+    out.extend_from_slice(&response[status_end..]);
+    out
+}
+
+// HSTS only makes sense when clients reach the server over HTTPS, which is the case when the
+// public URL (served through a TLS-terminating proxy) is an https one
+fn security_headers() -> [(&'static str, String); 4] {
+    let hsts_max_age = settings::hsts_max_age();
+    let hsts = if hsts_max_age > 0 && config::get().public_url.starts_with("https://") {
+        format!("max-age={}", hsts_max_age)
+    } else {
+        String::new()
+    };
+
+    [
+        ("X-Content-Type-Options", "nosniff".to_string()),
+        ("Content-Security-Policy", settings::content_security_policy()),
+        ("Referrer-Policy", settings::referrer_policy()),
+        ("Strict-Transport-Security", hsts),
+    ]
+}
@@ -0,0 +1,188 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// Outgoing HTTPS calls: to services that receive our messages (push services, chat webhooks,
+// error reporting) and to the OAuth providers
+
+// Host names that only mean something inside a network
+const INTERNAL_SUFFIXES: &[&str] = &["localhost", "local", "internal", "home.arpa", "lan", "intranet"];
+
+// Connecting gives up after this long, and so does each read or write once connected
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Responses are only read up to this size
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+// Where a request may go. URLs members and staff type in (push endpoints, webhooks) must lead
+// to a public address; ones from the server's own configuration (the Sentry DSN) may point at a
+// host on the local network
+#[derive(Clone, Copy, PartialEq)]
+pub enum Reach {
+    Public,
+    // Only the Sentry reporter sends to a configured host
+    #[cfg_attr(not(feature = "sentry"), allow(dead_code))]
+    Any,
+}
+
+// For URLs supplied by users (push endpoints, webhook URLs): only an https URL on a public
+// host name is accepted. Names that resolve to an internal address are caught when connecting
+pub fn check_public_url(url: &str) -> Result<(), String> {
+    let (host, _) = split_url(url)?;
+    let name = host.to_ascii_lowercase();
+    if name.parse::<IpAddr>().is_ok() || !name.contains('.') || INTERNAL_SUFFIXES.iter().any(|suffix| name == *suffix || name.ends_with(&format!(".{}", suffix))) {
+        return Err("URL must be on a public host".to_string());
+    }
+    Ok(())
+}
+
+// The host and the path with its query. Only characters RFC 3986 allows in a URL get through, and
+// `%` only as a percent-escape, so nothing can break out of the request line or add headers
+fn split_url(url: &str) -> Result<(&str, String), String> {
+    let invalid = || "URL is not valid".to_string();
+    let rest = url.strip_prefix("https://").ok_or("URL must start with https://")?;
+    let allowed = |c: char| c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=%".contains(c);
+    let escapes_valid = url.match_indices('%').all(|(i, _)| url.get(i + 1..i + 3).is_some_and(|hex| hex.chars().all(|c| c.is_ascii_hexdigit())));
+    if url.len() > 2048 || !url.chars().all(allowed) || !escapes_valid {
+        return Err(invalid());
+    }
+
+    // The fragment stays with the client
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (host, path) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+    if host.is_empty() || !host.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+        return Err(invalid());
+    }
+    let path = if path.starts_with('/') { path.to_string() } else { format!("/{}", path) };
+    Ok((host, path))
+}
+
+// Loopback, private, link-local, carrier-grade NAT and other ranges that aren't the internet
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            !(v4.is_unspecified() || v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_broadcast()
+                || v4.is_documentation() || v4.is_multicast()
+                || a == 0 || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b)))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_unspecified() || v6.is_loopback() || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && v6.segments()[1] == 0x0db8))
+        }
+    }
+}
+
+// Every address the name resolves to is checked, not just the first, so a name can't pass with
+// one public address and then be reached at an internal one
+fn connect(host: &str, reach: Reach) -> Result<TcpStream, String> {
+    let addresses: Vec<SocketAddr> = (host, 443).to_socket_addrs().map_err(|e| format!("Cannot resolve {}: {}", host, e))?.collect();
+    if reach == Reach::Public && addresses.iter().any(|address| !is_public(address.ip())) {
+        return Err(format!("{} resolves to an address that isn't public", host));
+    }
+    let mut last_error = format!("{} has no addresses", host);
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = format!("Cannot reach {}: {}", host, e),
+        }
+    }
+    Err(last_error)
+}
+
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+// One request per connection, read to the end; chunked bodies are put back together
+pub fn request(method: &str, url: &str, headers: &[(&str, &str)], body: &[u8], reach: Reach) -> Result<Response, String> {
+    let (host, path) = split_url(url)?;
+    if headers.iter().any(|(name, value)| name.contains([':', '\r', '\n']) || value.contains(['\r', '\n'])) {
+        return Err("Header is not valid".to_string());
+    }
+
+    let tcp = connect(host, reach)?;
+    tcp.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    tcp.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    let connector = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+    let mut stream = connector.connect(host, tcp).map_err(|e| format!("TLS handshake with {} failed: {}", host, e))?;
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: book-lending-system\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method, path, host, body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).map_err(|e| e.to_string())?;
+    stream.write_all(body).map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    stream.take(MAX_RESPONSE_BYTES).read_to_end(&mut response).map_err(|e| e.to_string())?;
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(|| format!("Malformed response from {}", host))?;
+    let head = String::from_utf8_lossy(&response[..split]).to_lowercase();
+    let content = &response[split + 4..];
+
+    let status = head.split_whitespace().nth(1).and_then(|s| s.parse().ok()).ok_or_else(|| format!("Malformed response from {}", host))?;
+    let chunked = head.lines().any(|l| l.starts_with("transfer-encoding:") && l.contains("chunked"));
+    let body = if chunked { dechunk(content) } else { content.to_vec() };
+    Ok(Response { status, body })
+}
+
+// A POST to a user-supplied URL where only the status matters
+pub fn post(url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<u16, String> {
+    request("POST", url, headers, body, Reach::Public).map(|response| response.status)
+}
+
+fn dechunk(mut content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(line_end) = content.windows(2).position(|w| w == b"\r\n") {
+        let size_line = String::from_utf8_lossy(&content[..line_end]);
+        let size = usize::from_str_radix(size_line.split(';').next().unwrap_or("").trim(), 16).unwrap_or(0);
+        let rest = &content[line_end + 2..];
+        if size == 0 || rest.len() < size {
+            break;
+        }
+        out.extend_from_slice(&rest[..size]);
+        content = rest[size..].strip_prefix(b"\r\n").unwrap_or(&rest[size..]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_urls_that_could_reach_inside() {
+        assert!(check_public_url("https://push.example.com/send/abc?x=1%2F").is_ok());
+        assert!(check_public_url("https://push.example.com/x HTTP/1.1\r\nHost: internal.example.com\r\n\r\nGET /admin").is_err());
+        assert!(check_public_url("https://push.example.com/x%zz").is_err());
+        assert!(check_public_url("https://metadata.google.internal/computeMetadata/v1/").is_err());
+        assert!(check_public_url("https://user@push.example.com/").is_err());
+        assert!(check_public_url("https://push.example.com:8443/").is_err());
+        assert!(check_public_url("https://10.0.0.1/").is_err());
+        assert!(check_public_url("http://push.example.com/").is_err());
+    }
+
+    #[test]
+    fn only_internet_addresses_are_public() {
+        for ip in ["8.8.8.8", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::config;
+
+// Translations of member-facing text: error messages in API responses and notification emails
+// and pushes. The code writes every message in English, and a catalog maps English text to
+// another language. A catalog is a JSON object of English message to translation; `{1}`, `{2}`
+// and so on in a message stand for the parts that vary (a title, a date) and are put back in
+// the translation wherever it places them. Catalogs shipped here are compiled in; more can be
+// dropped into LIBRARY_LOCALE_DIR as `<language>.json` without rebuilding, and a file there for
+// a shipped language adds to it and overrides its entries
+
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+const BUILT_IN: &[(&str, &str)] = &[("es", include_str!("../locales/es.json"))];
+
+#[derive(Default)]
+struct Catalog {
+    exact: HashMap<String, String>,
+    patterns: Vec<(Pattern, String)>,
+}
+
+// A message with placeholders, split into the literal text around them
+struct Pattern {
+    literals: Vec<String>,
+    slots: Vec<usize>,
+}
+
+fn catalogs() -> &'static HashMap<String, Catalog> {
+    static CATALOGS: OnceLock<HashMap<String, Catalog>> = OnceLock::new();
+    CATALOGS.get_or_init(|| load().unwrap_or_else(|e| panic!("{}", e)))
+}
+
+// Reads the catalogs up front, so a broken file in LIBRARY_LOCALE_DIR stops startup
+pub fn init() {
+    catalogs();
+}
+
+fn load() -> Result<HashMap<String, Catalog>, String> {
+    let mut catalogs: HashMap<String, Catalog> = HashMap::new();
+    for (language, source) in BUILT_IN {
+        add(catalogs.entry(language.to_string()).or_default(), source).map_err(|e| format!("Invalid built-in catalog {}: {}", language, e))?;
+    }
+
+    let Some(dir) = &config::get().locale_dir else { return Ok(catalogs) };
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read LIBRARY_LOCALE_DIR {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(language) = path.file_stem().and_then(|s| s.to_str()).map(str::to_ascii_lowercase) else { continue };
+        if language == DEFAULT_LANGUAGE || !is_language_tag(&language) {
+            return Err(format!("Catalog {} must be named after a language other than {}, e.g. fr.json", path.display(), DEFAULT_LANGUAGE));
+        }
+        let source = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        add(catalogs.entry(language).or_default(), &source).map_err(|e| format!("Invalid catalog {}: {}", path.display(), e))?;
+    }
+    Ok(catalogs)
+}
+
+fn add(catalog: &mut Catalog, source: &str) -> Result<(), String> {
+    let messages: HashMap<String, String> = serde_json::from_str(source).map_err(|e| e.to_string())?;
+    for (message, translation) in messages {
+        let pattern = parse(&message);
+        if let Some(slot) = placeholders(&translation).into_iter().find(|slot| !pattern.slots.contains(slot)) {
+            return Err(format!("translation of {:?} uses {{{}}}, which the message doesn't have", message, slot));
+        }
+        if pattern.slots.is_empty() {
+            catalog.exact.insert(message, translation);
+        } else if pattern.literals.iter().skip(1).take(pattern.slots.len() - 1).any(String::is_empty) {
+            return Err(format!("{:?} has two placeholders with nothing between them", message));
+        } else {
+            catalog.patterns.retain(|(existing, _)| existing.literals != pattern.literals);
+            catalog.patterns.push((pattern, translation));
+        }
+    }
+    Ok(())
+}
+
+fn parse(message: &str) -> Pattern {
+    let mut literals = vec![String::new()];
+    let mut slots = Vec::new();
+    let mut rest = message;
+    while let Some(start) = rest.find('{') {
+        let slot = rest[start + 1..].find('}').and_then(|end| rest[start + 1..start + 1 + end].parse::<usize>().ok().map(|n| (n, end)));
+        match slot {
+            Some((n, end)) => {
+                literals.last_mut().unwrap().push_str(&rest[..start]);
+                slots.push(n);
+                literals.push(String::new());
+                rest = &rest[start + end + 2..];
+            }
+            None => {
+                literals.last_mut().unwrap().push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    literals.last_mut().unwrap().push_str(rest);
+    Pattern { literals, slots }
+}
+
+fn placeholders(text: &str) -> Vec<usize> {
+    parse(text).slots
+}
+
+// The parts of `text` standing in for each placeholder, by number, if it has this shape. Each
+// placeholder takes the shortest text up to the literal after it, except the last, which runs
+// to the final literal at the end
+fn captures(pattern: &Pattern, text: &str) -> Option<HashMap<usize, String>> {
+    let mut rest = text.strip_prefix(pattern.literals[0].as_str())?;
+    let mut values = HashMap::new();
+    for (i, &slot) in pattern.slots.iter().enumerate() {
+        let literal = &pattern.literals[i + 1];
+        let end = if i + 1 == pattern.slots.len() {
+            rest.strip_suffix(literal.as_str())?.len()
+        } else {
+            rest.find(literal.as_str())?
+        };
+        values.insert(slot, rest[..end].to_string());
+        rest = &rest[end + literal.len()..];
+    }
+    Some(values)
+}
+
+// The text in the given language, or unchanged when that language has no translation for it
+pub fn translate(language: &str, text: &str) -> String {
+    let Some(catalog) = catalogs().get(language) else { return text.to_string() };
+    if let Some(translation) = catalog.exact.get(text) {
+        return translation.clone();
+    }
+    for (pattern, translation) in &catalog.patterns {
+        if let Some(values) = captures(pattern, text) {
+            let target = parse(translation);
+            let mut out = target.literals[0].clone();
+            for (slot, literal) in target.slots.iter().zip(&target.literals[1..]) {
+                out.push_str(&values[slot]);
+                out.push_str(literal);
+            }
+            return out;
+        }
+    }
+    text.to_string()
+}
+
+// Every language messages can be shown in, English first
+pub fn languages() -> Vec<String> {
+    let mut languages: Vec<String> = catalogs().keys().cloned().collect();
+    languages.sort();
+    languages.insert(0, DEFAULT_LANGUAGE.to_string());
+    languages
+}
+
+pub fn is_supported(language: &str) -> bool {
+    language == DEFAULT_LANGUAGE || catalogs().contains_key(language)
+}
+
+fn is_language_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 35
+        && tag.split('-').all(|part| !part.is_empty() && part.len() <= 8 && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+// The best supported language in an Accept-Language header, by quality and then order. A tag
+// the catalogs don't have exactly falls back to its primary language, so es-MX gets es
+pub fn negotiate(accept_language: &str) -> Option<String> {
+    let mut ranges: Vec<(f32, String)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q=").map(|q| q.trim().parse::<f32>().unwrap_or(0.0)))
+                .unwrap_or(1.0);
+            (quality > 0.0 && tag != "*").then_some((quality, tag))
+        })
+        .collect();
+    ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    ranges.into_iter().find_map(|(_, tag)| {
+        let primary = tag.split('-').next().unwrap_or(&tag).to_string();
+        [tag, primary].into_iter().find(|candidate| is_supported(candidate))
+    })
+}
+
+// Rewrites the `error` message of an error response into the language, leaving the rest of the
+// response alone, and marks it with Content-Language
+pub fn localize_response(response: Vec<u8>, language: &str) -> Vec<u8> {
+    if language == DEFAULT_LANGUAGE || crate::response_status(&response) < 400 {
+        return response;
+    }
+    let Some(head_end) = response.windows(4).position(|w| w == b"\r\n\r\n") else { return response };
+    let Ok(mut body) = serde_json::from_slice::<serde_json::Value>(&response[head_end + 4..]) else { return response };
+    let Some(message) = body.get("error").and_then(|e| e.as_str()) else { return response };
+    let translated = translate(language, message);
+    if translated == message {
+        return response;
+    }
+    body["error"] = serde_json::Value::String(translated);
+    let body = crate::json_body(&body);
+
+    let head = String::from_utf8_lossy(&response[..head_end]).into_owned();
+    let mut out = Vec::with_capacity(head_end + body.len() + 64);
+    for (i, line) in head.lines().enumerate() {
+        if i > 0 {
+            out.extend_from_slice(b"\r\n");
+        }
+        match line.split_once(':') {
+            Some((name, _)) if name.trim().eq_ignore_ascii_case("content-length") => {
+                out.extend_from_slice(format!("Content-Length: {}", body.len()).as_bytes());
+            }
+            _ => out.extend_from_slice(line.as_bytes()),
+        }
+    }
+    out.extend_from_slice(format!("\r\nContent-Language: {}\r\n\r\n", language).as_bytes());
+    out.extend_from_slice(body.as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{call, pool, UserFactory};
+
+    #[test]
+    fn translates_exact_and_patterned_messages() {
+        assert_eq!(translate("es", "Book not found"), "Libro no encontrado");
+        assert_eq!(translate("es", "Maximum of 5 concurrent loans reached"), "Has alcanzado el máximo de 5 préstamos simultáneos");
+        assert_eq!(translate("es", "Something nobody translated"), "Something nobody translated");
+        assert_eq!(translate("fr", "Book not found"), "Book not found");
+    }
+
+    #[test]
+    fn negotiates_by_quality_and_primary_language() {
+        assert_eq!(negotiate("fr;q=0.9, es-MX;q=0.8, en;q=0.5").as_deref(), Some("es"));
+        assert_eq!(negotiate("en, es;q=0.9").as_deref(), Some("en"));
+        assert_eq!(negotiate("es;q=0, de").as_deref(), None);
+    }
+
+    #[tokio::test]
+    async fn error_responses_follow_the_members_language() {
+        let pool = pool().await;
+        let (_, token) = UserFactory::default().signed_in(&pool).await;
+
+        let (status, body) = call(&pool, "PUT", "/api/auth/me/language", Some(&token), Some(r#"{"language":"es"}"#)).await;
+        assert_eq!((status, body["language"].as_str()), (200, Some("es")));
+
+        let request = crate::test_support::request("GET", "/api/books/999999", Some(&token), None);
+        let response = crate::route_request(&request, &pool, None).await;
+        let response = String::from_utf8(crate::localize(&pool, &request, response).await).unwrap();
+        assert!(response.contains("Content-Language: es"));
+        assert!(response.ends_with(r#"{"error":"Libro no encontrado"}"#));
+    }
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+
+use crate::models::{LendingRecordWithDetails, ReservationWithDetails};
+
+// iCalendar (RFC 5545) feed of a member's due dates, for subscribing from calendar apps
+pub const CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+pub fn due_dates_calendar(host: &str, loans: &[LendingRecordWithDetails], holds: &[ReservationWithDetails]) -> String {
+    let now = ical_time(&Utc::now());
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Book Lending System//Due Dates//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        "X-WR-CALNAME:Library due dates".to_string(),
+    ];
+
+    for loan in loans {
+        let Some(due) = parse_time(&loan.due_date) else { continue };
+        let summary = if loan.recalled_at.is_some() {
+            format!("Recalled: return \"{}\"", loan.title)
+        } else {
+            format!("Return \"{}\"", loan.title)
+        };
+        lines.extend(event(
+            &format!("loan-{}@{}", loan.id, host),
+            &now,
+            &due,
+            &summary,
+            &format!("{} by {} is due back at the library.", loan.title, loan.author),
+        ));
+    }
+
+    for hold in holds {
+        let Some(expires) = hold.expires_at.as_deref().and_then(parse_time) else { continue };
+        lines.extend(event(
+            &format!("hold-{}@{}", hold.id, host),
+            &now,
+            &expires,
+            &format!("Pick up \"{}\"", hold.title),
+            &format!("{} by {} is on hold for you until this time.", hold.title, hold.author),
+        ));
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line)).collect::<Vec<_>>().join("")
+}
+
+fn event(uid: &str, stamp: &str, at: &DateTime<Utc>, summary: &str, description: &str) -> Vec<String> {
+    vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}", uid),
+        format!("DTSTAMP:{}", stamp),
+        format!("DTSTART:{}", ical_time(at)),
+        format!("SUMMARY:{}", escape(summary)),
+        format!("DESCRIPTION:{}", escape(description)),
+        "TRANSP:TRANSPARENT".to_string(),
+        "END:VEVENT".to_string(),
+    ]
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|t| t.with_timezone(&Utc))
+}
+
+fn ical_time(t: &DateTime<Utc>) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Content lines are limited to 75 octets; longer ones continue on lines starting with a space
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
@@ -0,0 +1,68 @@
+use std::time::Instant;
+
+use sqlx::SqlitePool;
+
+use crate::db;
+use crate::models::IntegrityReport;
+
+// Checks that library.db is sound: SQLite's own integrity check over every page and index, the
+// foreign key check, and the schema version migrate leaves behind. It runs at startup, unless
+// LIBRARY_STARTUP_INTEGRITY_CHECK is off, and on demand from the admin API. A full check reads
+// the whole file, so on a large database it takes a while
+
+pub async fn check(pool: &SqlitePool) -> Result<IntegrityReport, sqlx::Error> {
+    let started = Instant::now();
+    let schema_version = db::schema_version(pool).await?;
+    let problems = db::integrity_problems(pool).await?;
+    let foreign_key_violations = db::foreign_key_violations(pool).await?;
+
+    Ok(IntegrityReport {
+        ok: problems.is_empty() && foreign_key_violations.is_empty() && schema_version == db::SCHEMA_VERSION,
+        schema_version,
+        expected_schema_version: db::SCHEMA_VERSION,
+        problems,
+        foreign_key_violations,
+        checked_at: chrono::Utc::now().to_rfc3339(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+// The startup check. A damaged file or an unexpected schema stops the server before it can make
+// things worse; dangling references only warn, since the rows around them are still usable
+pub async fn verify(pool: &SqlitePool) -> Result<(), String> {
+    let report = check(pool).await.map_err(|e| format!("Integrity check failed to run: {}", e))?;
+    if report.schema_version != report.expected_schema_version {
+        return Err(format!("Database schema version is {} after migration, expected {}", report.schema_version, report.expected_schema_version));
+    }
+    if !report.problems.is_empty() {
+        return Err(format!("Database integrity check failed; restore a backup (see Backup and Restore):\n  {}", report.problems.join("\n  ")));
+    }
+    for violation in &report.foreign_key_violations {
+        eprintln!(
+            "Warning: {} row {} refers to a missing {} row",
+            violation.table,
+            violation.rowid.map(|id| id.to_string()).unwrap_or_else(|| "?".to_string()),
+            violation.parent
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, UserFactory};
+
+    #[tokio::test]
+    async fn fresh_database_passes_the_check() {
+        let pool = test_support::pool().await;
+        super::verify(&pool).await.unwrap();
+
+        let (_, token) = UserFactory::admin().signed_in(&pool).await;
+        let (status, body) = test_support::call(&pool, "POST", "/api/admin/maintenance/integrity-check", Some(&token), None).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["ok"], true);
+        assert_eq!(body["schema_version"], crate::db::SCHEMA_VERSION);
+        assert_eq!(body["problems"].as_array().unwrap().len(), 0);
+    }
+}
+
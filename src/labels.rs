@@ -0,0 +1,186 @@
+use crate::pdf;
+
+// Spine and barcode labels for a book's copies, laid out on standard sheets of adhesive labels.
+// Each label carries the call number on the left for the spine and a Code 128 barcode of the
+// copy's item number on the right
+
+pub const PDF_TYPE: &str = "application/pdf";
+
+pub struct Label {
+    // Item number encoded in the barcode, e.g. B42-3 for the third copy of book 42
+    pub barcode: String,
+    // Call number, one part per line: shelf prefix, author cutter, year
+    pub call_number: Vec<String>,
+    pub title: String,
+    pub branch: String,
+}
+
+// A sheet of equal labels in a grid; all measurements in points
+pub struct Sheet {
+    pub name: &'static str,
+    page_width: f64,
+    page_height: f64,
+    columns: usize,
+    rows: usize,
+    top_margin: f64,
+    left_margin: f64,
+    label_width: f64,
+    label_height: f64,
+    horizontal_pitch: f64,
+    vertical_pitch: f64,
+}
+
+pub const SHEETS: &[Sheet] = &[
+    // Avery 5160 and compatibles: US Letter, 3 x 10 labels of 2.625" x 1"
+    Sheet {
+        name: "letter",
+        page_width: 612.0,
+        page_height: 792.0,
+        columns: 3,
+        rows: 10,
+        top_margin: 36.0,
+        left_margin: 13.5,
+        label_width: 189.0,
+        label_height: 72.0,
+        horizontal_pitch: 198.0,
+        vertical_pitch: 72.0,
+    },
+    // Avery L7160 and compatibles: A4, 3 x 7 labels of 63.5 x 38.1 mm
+    Sheet {
+        name: "a4",
+        page_width: 595.28,
+        page_height: 841.89,
+        columns: 3,
+        rows: 7,
+        top_margin: 42.9,
+        left_margin: 20.4,
+        label_width: 180.0,
+        label_height: 108.0,
+        horizontal_pitch: 187.2,
+        vertical_pitch: 108.0,
+    },
+];
+
+pub fn sheet(name: &str) -> Option<&'static Sheet> {
+    SHEETS.iter().find(|s| s.name == name)
+}
+
+// Shelf prefix, the first three letters of the author's surname and the year,
+// e.g. ["FIC", "TOL", "1954"]
+pub fn call_number(prefix: Option<&str>, author: &str, year: Option<i32>) -> Vec<String> {
+    let mut parts = Vec::new();
+    if let Some(prefix) = prefix.map(str::trim).filter(|p| !p.is_empty()) {
+        parts.push(prefix.to_string());
+    }
+    let surname = author.split(',').next().unwrap_or("");
+    // "Last, First" names keep the part before the comma; otherwise the last word is the surname
+    let surname = if author.contains(',') { surname } else { author.split_whitespace().last().unwrap_or("") };
+    let cutter: String = surname.chars().filter(|c| c.is_alphabetic()).take(3).collect::<String>().to_uppercase();
+    if !cutter.is_empty() {
+        parts.push(cutter);
+    }
+    if let Some(year) = year {
+        parts.push(year.to_string());
+    }
+    parts
+}
+
+pub fn pdf(sheet: &Sheet, labels: &[Label]) -> String {
+    let per_page = sheet.columns * sheet.rows;
+    let pages: Vec<pdf::Page> = labels.chunks(per_page).map(|page_labels| {
+        let mut content = String::new();
+        for (i, label) in page_labels.iter().enumerate() {
+            let x = sheet.left_margin + (i % sheet.columns) as f64 * sheet.horizontal_pitch;
+            let top = sheet.page_height - sheet.top_margin - (i / sheet.columns) as f64 * sheet.vertical_pitch;
+            draw_label(&mut content, sheet, label, x, top - sheet.label_height);
+        }
+        pdf::Page { width: sheet.page_width, height: sheet.page_height, content }
+    }).collect();
+    pdf::document(&pages)
+}
+
+// Padding inside each label, and the share of its width given to the call number
+const PADDING: f64 = 6.0;
+const SPINE_WIDTH: f64 = 48.0;
+
+fn draw_label(content: &mut String, sheet: &Sheet, label: &Label, x: f64, y: f64) {
+    let (width, height) = (sheet.label_width, sheet.label_height);
+
+    // Call number, stacked as it reads on a spine
+    let mut line_y = y + height - PADDING - 10.0;
+    for part in &label.call_number {
+        content.push_str(&pdf::text(2, 10.0, x + PADDING, line_y, &truncate(part, 7)));
+        line_y -= 12.0;
+    }
+
+    // Title and branch above the barcode, barcode with its number printed underneath
+    let area_x = x + PADDING + SPINE_WIDTH;
+    let area_width = width - PADDING * 2.0 - SPINE_WIDTH;
+    let chars = (area_width / 3.6) as usize;
+    content.push_str(&pdf::text(3, 6.5, area_x, y + height - PADDING - 6.5, &truncate(&label.title, chars)));
+    content.push_str(&pdf::text(3, 6.5, area_x, y + height - PADDING - 14.0, &truncate(&label.branch, chars)));
+
+    let text_height = 10.0;
+    let bar_bottom = y + PADDING + text_height;
+    let bar_height = (height - PADDING * 2.0 - text_height - 18.0).min(40.0);
+    if let Some(widths) = code128(&label.barcode) {
+        let modules: u32 = widths.iter().map(|&w| w as u32).sum::<u32>() + QUIET_ZONE * 2;
+        let module = area_width / modules as f64;
+        let mut bar_x = area_x + QUIET_ZONE as f64 * module;
+        for (i, &w) in widths.iter().enumerate() {
+            let w = w as f64 * module;
+            // Elements alternate bar, space, bar, ... starting with a bar
+            if i % 2 == 0 {
+                content.push_str(&pdf::rect(bar_x, bar_bottom, w, bar_height));
+            }
+            bar_x += w;
+        }
+    }
+    let text_x = area_x + (area_width - label.barcode.len() as f64 * 4.8) / 2.0;
+    content.push_str(&pdf::text(1, 8.0, text_x.max(area_x), y + PADDING + 2.0, &label.barcode));
+}
+
+fn truncate(text: &str, chars: usize) -> String {
+    if text.chars().count() <= chars {
+        text.to_string()
+    } else {
+        text.chars().take(chars.saturating_sub(1)).collect::<String>() + "."
+    }
+}
+
+// Blank modules required either side of the symbol
+const QUIET_ZONE: u32 = 10;
+
+// Bar and space widths, in modules, of Code 128 symbol values 0 to 106 (106 is the stop pattern)
+const PATTERNS: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212", "221213",
+    "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221", "223211", "221132",
+    "221231", "213212", "223112", "312131", "311222", "321122", "321221", "312212", "322112", "322211",
+    "212123", "212321", "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+    "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121", "313121", "211331",
+    "231131", "213113", "213311", "213131", "311123", "311321", "331121", "312113", "312311", "332111",
+    "314111", "221411", "431111", "111224", "111422", "121124", "121421", "141122", "141221", "112214",
+    "112412", "122114", "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+    "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311", "113141",
+    "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+
+const START_B: usize = 104;
+const STOP: usize = 106;
+
+// Element widths of `text` encoded in code set B: start, data, check symbol, stop.
+// None if the text has characters outside printable ASCII
+pub fn code128(text: &str) -> Option<Vec<u8>> {
+    let mut values = vec![START_B];
+    for c in text.chars() {
+        if !(' '..='~').contains(&c) {
+            return None;
+        }
+        values.push(c as usize - 32);
+    }
+    let checksum = values.iter().enumerate().map(|(i, &v)| v * i.max(1)).sum::<usize>() % 103;
+    values.push(checksum);
+    values.push(STOP);
+    Some(values.iter().flat_map(|&v| PATTERNS[v].bytes().map(|b| b - b'0')).collect())
+}
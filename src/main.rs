@@ -1,31 +1,134 @@
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
 use sqlx::SqlitePool;
 use serde_json::json;
+use tokio::sync::Semaphore;
 
 mod models;
 mod db;
 mod auth;
+mod config;
+mod scheduler;
+mod settings;
+mod notify;
+mod privacy;
+mod csv;
+mod opds;
+mod marc;
+mod goodreads;
+mod ical;
+mod search;
+mod cache;
+mod maintenance;
+mod permissions;
+mod scopes;
+mod oauth;
+mod password;
+mod throttle;
+mod roster;
+mod push;
+mod http;
+mod alerts;
+mod receipt;
+mod labels;
+mod pdf;
+mod money;
+mod qr;
+mod png;
+mod retention;
+mod headers;
+mod cidr;
+mod proxy;
+mod gzip;
+mod range;
+mod metrics;
+mod error_reporter;
+mod seed;
+mod clock;
+mod i18n;
+mod bootstrap;
+mod integrity;
+#[cfg(feature = "sqlcipher")]
+mod cipher;
+#[cfg(test)]
+mod test_support;
 
 use models::*;
+use permissions::Permission;
+
+// The route table is written once and expanded twice: as the match that dispatches a request,
+// and, when nothing matches, as a probe of which methods do have a route for the path, which
+// answers OPTIONS and turns a wrong verb into 405. The probe runs every guard for each method,
+// so guards must only look at the path
+macro_rules! routes {
+    (($method:expr, $path:expr) { $($pattern:pat $(if $guard:expr)? => $handler:expr),* $(,)? } unmatched($has_route:ident) => $fallback:expr) => {
+        match ($method, $path) {
+            $($pattern $(if $guard)? => $handler,)*
+            _ => {
+                #[allow(unused_variables)]
+                let $has_route = |method: &str| match (method, $path) {
+                    $($pattern $(if $guard)? => true,)*
+                    _ => false,
+                };
+                $fallback
+            }
+        }
+    };
+}
 
 #[tokio::main]
 async fn main() {
+    if std::env::args().skip(1).any(|arg| arg == "--rekey") {
+        rekey().await;
+        return;
+    }
+    
     println!("Initializing database...");
-    let pool = db::init_db().await.expect("Failed to initialize database");
+    let pool = db::init_db().await.unwrap_or_else(|e| panic!("Failed to initialize database: {}", e));
     println!("Database initialized successfully");
     
+    settings::load(&pool).await.expect("Failed to load settings");
+    throttle::restore(db::get_login_failures(&pool).await.expect("Failed to load login failures"));
+    
+    if std::env::args().skip(1).any(|arg| arg == "--seed-demo") {
+        match seed::demo(&pool).await.expect("Failed to seed demo data") {
+            Some(seeded) => println!(
+                "Seeded demo data: {} books, {} users, {} loans ({} overdue), {} reservations; demo accounts use password '{}'",
+                seeded.books, seeded.users, seeded.loans, seeded.overdue, seeded.reservations, seed::DEMO_PASSWORD
+            ),
+            None => println!("Demo data already present, not seeding again"),
+        }
+    }
+    
+    // After seeding, whose demo admin makes a setup token unnecessary
+    bootstrap::run(&pool).await.unwrap_or_else(|e| panic!("{}", e));
+    
+    start_jobs(&pool);
+    // Chosen up front, so a bad LIBRARY_SENTRY_DSN stops startup rather than the first report
+    error_reporter::reporter();
+    i18n::init();
+    
     let listener = TcpListener::bind("127.0.0.1:8080").expect("Failed to bind to port 8080");
     println!("Server running on http://127.0.0.1:8080");
     
+    // Each connection holds a permit until it is done; when none are left the connection is
+    // answered straight away instead of queueing behind the others
+    let connections = Arc::new(Semaphore::new(config::get().max_connections));
+    
     for stream in listener.incoming() {
         match stream {
-            Ok(stream) => {
-                let pool_clone = pool.clone();
-                tokio::spawn(async move {
-                    handle_connection(stream, pool_clone).await;
-                });
-            }
+            Ok(stream) => match connections.clone().try_acquire_owned() {
+                Ok(permit) => {
+                    let pool_clone = pool.clone();
+                    tokio::spawn(async move {
+                        handle_connection(stream, pool_clone).await;
+                        drop(permit);
+                    });
+                }
+                Err(_) => reject_connection(stream),
+            },
             Err(e) => {
                 eprintln!("Connection failed: {}", e);
             }
@@ -33,33 +136,282 @@ async fn main() {
     }
 }
 
+// Re-encrypts library.db under LIBRARY_DB_NEW_KEY and exits; the server must be stopped first
+#[cfg(feature = "sqlcipher")]
+async fn rekey() {
+    let new_key = config::secret("LIBRARY_DB_NEW_KEY").expect("Set LIBRARY_DB_NEW_KEY or LIBRARY_DB_NEW_KEY_FILE to the new key");
+    cipher::rekey(db::DB_PATH, config::get().db_key.as_deref(), &new_key).await.unwrap_or_else(|e| panic!("{}", e));
+    println!("Re-encrypted {}; start the server with LIBRARY_DB_KEY set to the new key", db::DB_PATH);
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+async fn rekey() {
+    panic!("--rekey needs a build with `cargo build --features sqlcipher`");
+}
+
+// Upper bound for a request including its body (bulk imports send whole catalog files)
+const MAX_REQUEST_BYTES: usize = 16 * 1024 * 1024;
+
 async fn handle_connection(mut stream: TcpStream, pool: SqlitePool) {
-    let mut buffer = [0; 8192];
+    let started = std::time::Instant::now();
+    let config = config::get();
+    if let Err(e) = stream.set_write_timeout(Some(Duration::from_secs(config.write_timeout_secs))) {
+        eprintln!("Failed to set write timeout: {}", e);
+        return;
+    }
+    
+    // Socket reads and writes block, so they run on the blocking pool where a slow client holds
+    // a pool thread rather than one of the runtime's workers
+    let read_timeout = Duration::from_secs(config.read_timeout_secs);
+    let (mut stream, read) = match tokio::task::spawn_blocking(move || {
+        let read = read_request(&mut stream, read_timeout);
+        (stream, read)
+    }).await {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Failed to read from stream: {}", e);
+            return;
+        }
+    };
     
-    match stream.read(&mut buffer) {
-        Ok(size) => {
-            let request = String::from_utf8_lossy(&buffer[..size]);
-            let response = route_request(&request, &pool).await;
+    match read {
+        Ok(data) => {
+            let request = data.map(|data| String::from_utf8_lossy(&data).into_owned());
+            let ip = match (&request, stream.peer_addr()) {
+                (Some(request), Ok(peer)) => Some(proxy::client_ip(request, peer)),
+                _ => None,
+            };
+            
+            // Resolved before routing, since logging out ends the session the request was made with
+            let user_id = match &request {
+                Some(request) if config.access_log => match session_token(request).0 {
+                    Some(token) => lookup_user(&pool, &token).await.ok().flatten().map(|u| u.id),
+                    None => None,
+                },
+                _ => None,
+            };
+            
+            // The handler runs as a task of its own, so a panic in it becomes a 500 and an error
+            // report instead of a dropped connection. One that runs past the deadline is aborted,
+            // which rolls back any open transaction
+            let mut panic = None;
+            let response = match &request {
+                Some(request) => {
+                    let (handler_request, handler_pool, handler_ip) = (request.clone(), pool.clone(), ip.clone());
+                    let handler = tokio::spawn(proxy::scope(ip.clone(), async move {
+                        route_request(&handler_request, &handler_pool, handler_ip.as_deref()).await
+                    }));
+                    let abort = handler.abort_handle();
+                    match tokio::time::timeout(Duration::from_secs(config.handler_timeout_secs), handler).await {
+                        Ok(Ok(response)) => response,
+                        Ok(Err(e)) => {
+                            panic = Some(if e.is_panic() { error_reporter::panic_message(&*e.into_panic()) } else { e.to_string() });
+                            error_response(500, "Internal Server Error").into_bytes()
+                        }
+                        Err(_) => {
+                            abort.abort();
+                            eprintln!("Request timed out: {}", request.lines().next().unwrap_or(""));
+                            error_response(504, "Gateway Timeout").into_bytes()
+                        }
+                    }
+                }
+                None => error_response(413, "Payload Too Large").into_bytes(),
+            };
+            let response = match &request {
+                Some(request) => localize(&pool, request, response).await,
+                None => response,
+            };
+            let response = headers::apply(response);
+            if let Some(request) = &request {
+                report_problem(request, &response, ip.as_deref(), panic);
+            }
+            let compress = request.as_deref().is_some_and(gzip::accepted);
+            let range = request.as_deref().and_then(range::requested);
+            
+            // Compression happens here rather than in route_request so it stays off the runtime's workers
+            let response = match tokio::task::spawn_blocking(move || {
+                let response = if compress { gzip::encode_response(response) } else { response };
+                let response = match &range {
+                    Some(range) => range::apply(range, response),
+                    None => response,
+                };
+                let written = stream.write_all(&response);
+                (response, written)
+            }).await {
+                Ok((response, written)) => {
+                    if let Err(e) = written {
+                        eprintln!("Failed to write response: {}", e);
+                    }
+                    response
+                }
+                Err(e) => {
+                    eprintln!("Failed to write response: {}", e);
+                    return;
+                }
+            };
+            
+            if let Some(request) = &request {
+                record_metrics(request, &response, started.elapsed());
+            }
             
-            if let Err(e) = stream.write_all(response.as_bytes()) {
-                eprintln!("Failed to write response: {}", e);
+            if let Some(request) = request.filter(|_| config.access_log) {
+                log_access(&pool, &request, &response, user_id, ip.as_deref(), started.elapsed()).await;
             }
         }
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            // Best effort; the socket is closed either way when the stream is dropped
+            let _ = stream.write_all(&headers::apply(error_response(408, "Request Timeout").into_bytes()));
+        }
         Err(e) => {
             eprintln!("Failed to read from stream: {}", e);
         }
     }
 }
 
-async fn route_request(request: &str, pool: &SqlitePool) -> String {
+// Seconds a client turned away for lack of capacity is told to wait before retrying
+const BUSY_RETRY_AFTER_SECS: u64 = 5;
+
+// Answers without reading the request. The reply fits in the socket buffer, so writing it from
+// the accept loop doesn't hold up accepting; the timeout covers a client whose buffer is full
+fn reject_connection(mut stream: TcpStream) {
+    if stream.set_write_timeout(Some(Duration::from_secs(1))).is_ok() {
+        let _ = stream.write_all(&headers::apply(busy_response().into_bytes()));
+    }
+}
+
+// Records an API call in the access log. Query strings are left out because some carry
+// secrets (verification tokens, signed feed links)
+async fn log_access(pool: &SqlitePool, request: &str, response: &[u8], user_id: Option<i64>, ip: Option<&str>, elapsed: std::time::Duration) {
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else { return };
+    let path = target.split('?').next().unwrap_or(target);
+    if !path.starts_with("/api/") {
+        return;
+    }
+    
+    let status = response_status(response) as i64;
+    if let Err(e) = db::record_access(pool, method, path, status, user_id, ip, elapsed.as_millis() as i64).await {
+        eprintln!("Failed to record access log entry: {:?}", e);
+    }
+}
+
+// Requests that fell through every route are answered by unmatched_route with this exact status line
+fn record_metrics(request: &str, response: &[u8], elapsed: Duration) {
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else { return };
+    let unmatched = response.starts_with(b"HTTP/1.1 404 Not Found\r\n");
+    metrics::record_request(method, target, response_status(response), unmatched, elapsed);
+}
+
+// Error messages go out in the language the member chose, or else the best one the request's
+// Accept-Language asks for
+async fn localize(pool: &SqlitePool, request: &str, response: Vec<u8>) -> Vec<u8> {
+    if response_status(&response) < 400 {
+        return response;
+    }
+    let chosen = match session_token(request).0 {
+        Some(token) if !auth::is_api_key(&token) => db::get_session_language(pool, &token).await.ok().flatten(),
+        _ => None,
+    };
+    let language = chosen
+        .filter(|language| i18n::is_supported(language))
+        .or_else(|| request_header(request, "accept-language").and_then(i18n::negotiate));
+    match language {
+        Some(language) => i18n::localize_response(response, &language),
+        None => response,
+    }
+}
+
+// Panics and 5xx responses go to the error reporter. 503 is left out, since maintenance mode
+// and a full server answer with it on purpose
+fn report_problem(request: &str, response: &[u8], client_ip: Option<&str>, panic: Option<String>) {
+    let status = response_status(response);
+    if status < 500 || status == 503 {
+        return;
+    }
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else { return };
+    let body_error = || {
+        let body_start = response.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+        let body: serde_json::Value = serde_json::from_slice(&response[body_start..]).ok()?;
+        body.get("error")?.as_str().map(str::to_string)
+    };
+    let problem = error_reporter::Problem {
+        kind: if panic.is_some() { "panic" } else { "server_error" },
+        message: panic.or_else(body_error).unwrap_or_else(|| format!("HTTP {}", status)),
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or(target).to_string(),
+        status,
+        client_ip: client_ip.map(str::to_string),
+        user_agent: request_header(request, "user-agent").map(str::to_string),
+    };
+    error_reporter::reporter().report(&problem);
+}
+
+fn response_status(response: &[u8]) -> u16 {
+    String::from_utf8_lossy(&response[..response.len().min(16)])
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or(0)
+}
+
+// Reads the headers, then keeps reading until the whole Content-Length body has arrived.
+// Returns None when the request exceeds MAX_REQUEST_BYTES. The timeout covers the whole request
+// rather than each read, so a client trickling in a byte at a time still runs out of time
+fn read_request(stream: &mut TcpStream, timeout: Duration) -> std::io::Result<Option<Vec<u8>>> {
+    let deadline = std::time::Instant::now() + timeout;
+    let mut data = Vec::new();
+    let mut buffer = [0; 8192];
+    
+    loop {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Err(ErrorKind::TimedOut.into());
+        }
+        stream.set_read_timeout(Some(remaining))?;
+        let size = stream.read(&mut buffer)?;
+        if size == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..size]);
+        
+        if let Some(header_end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&data[..header_end]);
+            let content_length = headers
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    if name.trim().eq_ignore_ascii_case("content-length") { value.trim().parse::<usize>().ok() } else { None }
+                })
+                .unwrap_or(0);
+            // A Content-Length too large to add up is as much too large as one that does
+            let total = match (header_end + 4).checked_add(content_length) {
+                Some(total) if total <= MAX_REQUEST_BYTES => total,
+                _ => return Ok(None),
+            };
+            if data.len() >= total {
+                break;
+            }
+        }
+        
+        if data.len() > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+    }
+    
+    Ok(Some(data))
+}
+
+async fn route_request(request: &str, pool: &SqlitePool, client_ip: Option<&str>) -> Vec<u8> {
     let lines: Vec<&str> = request.lines().collect();
     if lines.is_empty() {
-        return error_response(400, "Bad Request");
+        return error_response(400, "Bad Request").into_bytes();
     }
     
     let request_line: Vec<&str> = lines[0].split_whitespace().collect();
     if request_line.len() < 2 {
-        return error_response(400, "Bad Request");
+        return error_response(400, "Bad Request").into_bytes();
     }
     
     let method = request_line[0];
@@ -72,27 +424,204 @@ async fn route_request(request: &str, pool: &SqlitePool) -> String {
         ""
     };
     
-    // Extract token from Authorization header
-    let token = extract_token(request);
+    // Extract token from the Authorization header, or else the session cookie
+    let (token, from_cookie) = session_token(request);
+    let user_agent = request_header(request, "user-agent");
+    
+    // HEAD is answered as GET with the body left off; Content-Length still gives the full size
+    if method == "HEAD" {
+        let request = request.replacen("HEAD", "GET", 1);
+        let response = Box::pin(route_request(&request, pool, client_ip)).await;
+        let head_end = response.windows(4).position(|w| w == b"\r\n\r\n").map_or(response.len(), |end| end + 4);
+        return response[..head_end].to_vec();
+    }
     
     println!("{} {}", method, path);
     
+    // Administration can be kept to the library's own network while the catalog stays public
+    let allowlist = &config::get().admin_allowlist;
+    if path.starts_with("/api/admin/") && !allowlist.is_empty() && !client_ip.is_some_and(|ip| cidr::any_contains(allowlist, ip)) {
+        return error_response(403, "Admin endpoints are not available from this network").into_bytes();
+    }
+    
+    // A cookie rides along on any request the browser makes, so changes made with one must
+    // also echo the CSRF cookie in a header, which other sites cannot read
+    if from_cookie && !matches!(method, "GET" | "HEAD" | "OPTIONS") && !csrf_exempt(path) && !csrf_valid(request) {
+        return error_response(403, "Missing or invalid CSRF token").into_bytes();
+    }
+    
+    // Staff acting as a member can look around but not change the member's sign-in or account
+    if method != "GET" && path.starts_with("/api/auth/") && path != "/api/auth/logout" {
+        if let Some(token) = token.as_deref() {
+            if let Ok(Some(_)) = db::get_session_impersonator(pool, token).await {
+                return error_response(403, "Not allowed while impersonating a member").into_bytes();
+            }
+        }
+    }
+    
+    // API keys only reach the endpoints their scopes cover
+    if let Some(key) = token.as_deref().filter(|t| auth::is_api_key(t)) {
+        if let Err(response) = check_api_key_scope(pool, key, method, path).await {
+            return response.into_bytes();
+        }
+    }
+    
+    // While staff run maintenance only admins get through to the API
+    if maintenance::is_enabled() && maintenance::applies_to(method, path) && authenticate_for(pool, token.as_deref(), Permission::ManageSystem).await.is_err() {
+        return maintenance_response(&maintenance::status()).into_bytes();
+    }
+    
+    // A members-only library keeps its catalog behind sign-in as well. Changes to the catalog
+    // need staff whatever this is set to
+    if !config::get().public_catalog && method == "GET" && scopes::is_catalog(path) && authenticate(pool, token.as_deref()).await.is_err() {
+        return error_response(401, "Unauthorized").into_bytes();
+    }
+    
+    // Retried borrows and returns get the original response back instead of running again
+    let idempotency = match claim_idempotency_key(pool, method, path, request, token.as_deref()).await {
+        Ok(claim) => claim.map(|(user_id, key)| IdempotencyClaim { pool: pool.clone(), user_id, key, settled: false }),
+        Err(response) => return response.into_bytes(),
+    };
+    
+    // Catalog reads are answered from the cache when possible
+    let cacheable = method == "GET" && cache::is_cacheable(path) && !prefers_csv(request);
+    let cache_key = cache::key(path);
+    if cacheable {
+        if let Some(entry) = cache::get(&cache_key) {
+            return cached_response(&entry, request_header(request, "if-none-match")).into_bytes();
+        }
+    }
+    let cache_generation = cache::generation();
+    
     // Route matching
-    match (method, path) {
+    let response = routes! { (method, path) {
+        // Binary downloads
+        ("POST", "/api/admin/backup") => return handle_backup(pool, token.as_deref()).await,
+        ("GET", path) if path.starts_with("/api/admin/backups/") => {
+            return handle_download_backup(pool, token.as_deref(), path.trim_start_matches("/api/admin/backups/")).await;
+        },
+        ("GET", "/api/admin/backups") => handle_get_backups(pool, token.as_deref()).await,
+        ("GET", "/metrics") => handle_get_metrics(pool, token.as_deref()).await,
+        ("GET", "/api/admin/metrics-summary") => handle_get_metrics_summary(pool, token.as_deref()).await,
+        ("GET", path) if path.starts_with("/api/books/") && path.split('?').next().unwrap_or(path).ends_with("/qr.png") => {
+            let id = path.split('?').next().unwrap_or(path).trim_start_matches("/api/books/").trim_end_matches("/qr.png").parse::<i64>().unwrap_or(0);
+            return handle_get_book_qr(pool, id, query_param(path, "scale").as_deref()).await;
+        },
+        
         // Serve frontend files
         ("GET", "/") => serve_file("frontend/index.html", "text/html"),
         ("GET", "/lender.html") => serve_file("frontend/lender.html", "text/html"),
         ("GET", "/admin.html") => serve_file("frontend/admin.html", "text/html"),
         ("GET", "/app.js") => serve_file("frontend/app.js", "application/javascript"),
+        ("GET", "/sw.js") => serve_file("frontend/sw.js", "application/javascript"),
+        
+        // OPDS catalog
+        ("GET", "/opds") => text_response(opds::NAVIGATION_TYPE, &opds::root_feed(&config::get().public_url)),
+        ("GET", "/opds/opensearch.xml") => text_response(opds::OPENSEARCH_TYPE, &opds::opensearch_description(&config::get().public_url)),
+        ("GET", "/opds/new") => handle_opds_new(pool).await,
+        ("GET", "/opds/all") => handle_opds_all(pool).await,
+        ("GET", path) if path.starts_with("/opds/search") => {
+            let query = query_param(path, "q").unwrap_or_default();
+            handle_opds_search(pool, &query).await
+        },
         
         // Auth endpoints
         ("POST", "/api/auth/register") => handle_register(pool, body).await,
-        ("POST", "/api/auth/login") => handle_login(pool, body).await,
+        ("GET", "/api/auth/api-keys") => handle_get_api_keys(pool, token.as_deref()).await,
+        ("POST", "/api/auth/api-keys") => handle_create_api_key(pool, token.as_deref(), body).await,
+        ("DELETE", path) if path.starts_with("/api/auth/api-keys/") => {
+            let id = path.trim_start_matches("/api/auth/api-keys/").parse::<i64>().unwrap_or(0);
+            handle_revoke_api_key(pool, token.as_deref(), id).await
+        },
+        ("POST", "/api/auth/login") => handle_login(pool, body, client_ip, user_agent).await,
         ("POST", "/api/auth/logout") => handle_logout(pool, token.as_deref()).await,
+        ("POST", "/api/auth/2fa/setup") => handle_two_factor_setup(pool, token.as_deref()).await,
+        ("POST", "/api/auth/2fa/enable") => handle_two_factor_enable(pool, token.as_deref(), body).await,
+        ("POST", "/api/auth/2fa/disable") => handle_two_factor_disable(pool, token.as_deref(), body).await,
+        ("POST", "/api/auth/2fa/verify") => handle_two_factor_verify(pool, body, client_ip, user_agent).await,
+        ("GET", "/api/auth/sessions") => handle_get_sessions(pool, token.as_deref()).await,
+        ("DELETE", path) if path.starts_with("/api/auth/sessions/") => {
+            let id = path.trim_start_matches("/api/auth/sessions/").parse::<i64>().unwrap_or(0);
+            handle_revoke_session(pool, token.as_deref(), id).await
+        },
+        ("GET", "/api/auth/oauth/providers") => success_response(json!(oauth::enabled_providers())),
+        ("GET", path) if path.starts_with("/api/auth/oauth/") => {
+            let rest = path.trim_start_matches("/api/auth/oauth/");
+            let route = rest.split('?').next().unwrap_or("");
+            match route.split_once('/') {
+                Some((provider, "start")) => handle_oauth_start(pool, provider).await,
+                Some((provider, "callback")) => {
+                    let code = query_param(path, "code").unwrap_or_default();
+                    let state = query_param(path, "state").unwrap_or_default();
+                    handle_oauth_callback(pool, provider, &code, &state, client_ip, user_agent).await
+                },
+                _ => error_response(404, "Not found"),
+            }
+        },
         ("GET", "/api/auth/me") => handle_get_current_user(pool, token.as_deref()).await,
+        ("GET", "/api/auth/me/export") => handle_export_my_data(pool, token.as_deref()).await,
+        ("DELETE", "/api/auth/me") => handle_delete_my_account(pool, token.as_deref()).await,
+        ("PUT", "/api/auth/me") => handle_update_profile(pool, token.as_deref(), body).await,
+        ("PUT", "/api/auth/me/home-branch") => handle_set_own_home_branch(pool, token.as_deref(), body).await,
+        ("POST", "/api/setup") => handle_setup(pool, body).await,
+        ("GET", "/api/auth/me/language") => handle_get_language_preference(pool, token.as_deref()).await,
+        ("PUT", "/api/auth/me/language") => handle_update_language_preference(pool, token.as_deref(), body).await,
+        ("GET", "/api/auth/me/notifications") => handle_get_notification_preferences(pool, token.as_deref()).await,
+        ("PUT", "/api/auth/me/notifications") => handle_update_notification_preferences(pool, token.as_deref(), body).await,
+        ("GET", "/api/push/public-key") => handle_get_push_public_key(pool).await,
+        ("GET", "/api/auth/me/push-subscriptions") => handle_get_push_subscriptions(pool, token.as_deref()).await,
+        ("POST", "/api/auth/me/push-subscriptions") => handle_create_push_subscription(pool, token.as_deref(), body, user_agent).await,
+        ("DELETE", path) if path.starts_with("/api/auth/me/push-subscriptions/") => {
+            let id = path.trim_start_matches("/api/auth/me/push-subscriptions/").parse::<i64>().unwrap_or(0);
+            handle_delete_push_subscription(pool, token.as_deref(), id).await
+        },
+        ("GET", path) if path.starts_with("/api/auth/verify-email?") => {
+            let verify_token = query_param(path, "token").unwrap_or_default();
+            handle_verify_email(pool, &verify_token).await
+        },
+        ("GET", path) if path.starts_with("/api/auth/confirm-email-change?") => {
+            let change_token = query_param(path, "token").unwrap_or_default();
+            handle_confirm_email_change(pool, &change_token).await
+        },
         
         // Book endpoints
-        ("GET", "/api/books") => handle_get_books(pool).await,
+        ("GET", "/api/books") => handle_get_books(pool, listing_format(request, path).as_deref()).await,
+        ("GET", path) if path.starts_with("/api/books?") => {
+            let branch_id = query_param(path, "branch").map(|b| b.parse::<i64>().unwrap_or(0));
+            handle_get_filtered_books(pool, branch_id, query_param(path, "type").as_deref(), listing_format(request, path).as_deref()).await
+        },
+        ("GET", path) if path.strip_prefix("/api/books/").is_some_and(|id| id.parse::<i64>().is_ok()) => {
+            let id = path.trim_start_matches("/api/books/").parse::<i64>().unwrap_or(0);
+            handle_get_book(pool, id).await
+        },
+        ("GET", path) if path.starts_with("/api/books/") && path.ends_with("/branches") => {
+            let id = path.trim_start_matches("/api/books/").trim_end_matches("/branches").parse::<i64>().unwrap_or(0);
+            handle_get_book_holdings(pool, id).await
+        },
+        ("GET", "/api/languages") => success_response(json!({ "languages": i18n::languages() })),
+        ("GET", "/api/branches") => handle_get_branches(pool).await,
+        ("GET", "/api/series") => handle_get_all_series(pool).await,
+        ("GET", "/api/lists") => handle_get_reading_lists(pool).await,
+        ("GET", path) if path.starts_with("/api/lists/") => {
+            let id = path.trim_start_matches("/api/lists/").parse::<i64>().unwrap_or(0);
+            handle_get_reading_list(pool, id).await
+        },
+        ("GET", path) if path.starts_with("/api/series/") => {
+            let id = path.trim_start_matches("/api/series/").parse::<i64>().unwrap_or(0);
+            handle_get_series(pool, id).await
+        },
+        ("GET", path) if path.starts_with("/api/periodicals/") && path.ends_with("/issues") => {
+            let id = path.trim_start_matches("/api/periodicals/").trim_end_matches("/issues").parse::<i64>().unwrap_or(0);
+            handle_get_issues(pool, id).await
+        },
+        ("POST", path) if path.starts_with("/api/books/") && path.ends_with("/watch") => {
+            let book_id = path.trim_start_matches("/api/books/").trim_end_matches("/watch").parse::<i64>().unwrap_or(0);
+            handle_watch_book(pool, token.as_deref(), book_id).await
+        },
+        ("DELETE", path) if path.starts_with("/api/books/") && path.ends_with("/watch") => {
+            let book_id = path.trim_start_matches("/api/books/").trim_end_matches("/watch").parse::<i64>().unwrap_or(0);
+            handle_unwatch_book(pool, token.as_deref(), book_id).await
+        },
         ("POST", "/api/books") => handle_create_book(pool, token.as_deref(), body).await,
         ("PUT", path) if path.starts_with("/api/books/") => {
             let id = path.trim_start_matches("/api/books/").parse::<i64>().unwrap_or(0);
@@ -103,31 +632,544 @@ async fn route_request(request: &str, pool: &SqlitePool) -> String {
             handle_delete_book(pool, token.as_deref(), id).await
         },
         ("GET", path) if path.starts_with("/api/books/search?") => {
-            let query = path.split("q=").nth(1).unwrap_or("");
-            let decoded = urlencoding::decode(query).unwrap_or_default();
-            handle_search_books(pool, &decoded).await
+            let query = query_param(path, "q").unwrap_or_default();
+            let with_facets = query_param(path, "facets").as_deref() == Some("true");
+            handle_search_books(pool, &query, query_param(path, "type").as_deref(), with_facets).await
+        },
+        ("GET", path) if path.starts_with("/api/books/suggest?") => {
+            let prefix = query_param(path, "q").unwrap_or_default();
+            handle_suggest_completions(pool, &prefix).await
         },
         
         // Lending endpoints
         ("POST", path) if path.starts_with("/api/lending/borrow/") => {
-            let book_id = path.trim_start_matches("/api/lending/borrow/").parse::<i64>().unwrap_or(0);
-            handle_borrow_book(pool, token.as_deref(), book_id).await
+            let rest = path.trim_start_matches("/api/lending/borrow/");
+            let book_id = rest.split('?').next().unwrap_or("").parse::<i64>().unwrap_or(0);
+            let branch_id = query_param(path, "branch").and_then(|b| b.parse::<i64>().ok());
+            handle_borrow_book(pool, token.as_deref(), book_id, branch_id).await
         },
         ("POST", path) if path.starts_with("/api/lending/return/") => {
             let record_id = path.trim_start_matches("/api/lending/return/").parse::<i64>().unwrap_or(0);
             handle_return_book(pool, token.as_deref(), record_id).await
         },
-        ("GET", "/api/lending/my-books") => handle_get_my_books(pool, token.as_deref()).await,
+        ("GET", "/api/lending/my-books") => handle_get_my_books(pool, token.as_deref(), listing_format(request, path).as_deref()).await,
+        ("POST", path) if path.starts_with("/api/lending/reserve/") => {
+            let book_id = path.trim_start_matches("/api/lending/reserve/").parse::<i64>().unwrap_or(0);
+            handle_reserve_book(pool, token.as_deref(), book_id).await
+        },
+        ("GET", "/api/lending/my-watches") => handle_get_my_watches(pool, token.as_deref()).await,
+        ("DELETE", path) if path.starts_with("/api/lending/reservations/") => {
+            let id = path.trim_start_matches("/api/lending/reservations/").parse::<i64>().unwrap_or(0);
+            handle_cancel_reservation(pool, token.as_deref(), id).await
+        },
+        ("GET", "/api/lending/my-reservations") => handle_get_my_reservations(pool, token.as_deref()).await,
+        ("POST", path) if path.starts_with("/api/lending/") && path.ends_with("/extension-request") => {
+            let id = path.trim_start_matches("/api/lending/").trim_end_matches("/extension-request").parse::<i64>().unwrap_or(0);
+            handle_request_extension(pool, token.as_deref(), id, body).await
+        },
+        ("GET", "/api/lending/my-stats") => handle_get_my_stats(pool, token.as_deref()).await,
+        ("GET", path) if path.starts_with("/api/lending/") && path.split('?').next().unwrap_or(path).ends_with("/receipt") => {
+            let id = path.split('?').next().unwrap_or(path).trim_start_matches("/api/lending/").trim_end_matches("/receipt").parse::<i64>().unwrap_or(0);
+            handle_get_receipt(pool, token.as_deref(), id, query_param(path, "format").as_deref()).await
+        },
+        ("GET", path) if path.starts_with("/api/lending/my-due-dates.ics") => {
+            let feed_token = query_param(path, "token").unwrap_or_default();
+            handle_due_dates_feed(pool, &feed_token).await
+        },
+        ("GET", "/api/lending/calendar-feed") => handle_get_calendar_feed(pool, token.as_deref(), false).await,
+        ("POST", "/api/lending/calendar-feed/reset") => handle_get_calendar_feed(pool, token.as_deref(), true).await,
+        ("GET", "/api/lending/my-fines") => handle_get_my_fines(pool, token.as_deref()).await,
         
-        // Admin endpoints
-        ("GET", "/api/admin/users") => handle_get_all_users(pool, token.as_deref()).await,
-        ("GET", "/api/admin/lending/active") => handle_get_active_lending(pool, token.as_deref()).await,
-        ("GET", "/api/admin/lending/overdue") => handle_get_overdue_books(pool, token.as_deref()).await,
+        // Favorite and author follow endpoints
+        ("GET", "/api/favorites") => handle_get_favorites(pool, token.as_deref()).await,
+        ("POST", path) if path.starts_with("/api/favorites/") => {
+            let book_id = path.trim_start_matches("/api/favorites/").parse::<i64>().unwrap_or(0);
+            handle_add_favorite(pool, token.as_deref(), book_id).await
+        },
+        ("DELETE", path) if path.starts_with("/api/favorites/") => {
+            let book_id = path.trim_start_matches("/api/favorites/").parse::<i64>().unwrap_or(0);
+            handle_remove_favorite(pool, token.as_deref(), book_id).await
+        },
+        ("GET", "/api/authors/following") => handle_get_followed_authors(pool, token.as_deref()).await,
+        ("POST", path) if path.starts_with("/api/authors/") && path.ends_with("/follow") => {
+            let author = urlencoding::decode(path.trim_start_matches("/api/authors/").trim_end_matches("/follow")).unwrap_or_default().into_owned();
+            handle_follow_author(pool, token.as_deref(), &author, true).await
+        },
+        ("DELETE", path) if path.starts_with("/api/authors/") && path.ends_with("/follow") => {
+            let author = urlencoding::decode(path.trim_start_matches("/api/authors/").trim_end_matches("/follow")).unwrap_or_default().into_owned();
+            handle_follow_author(pool, token.as_deref(), &author, false).await
+        },
+        
+        // Wishlist endpoints
+        ("GET", "/api/wishlist") => handle_get_wishlist(pool, token.as_deref()).await,
+        ("POST", path) if path.starts_with("/api/wishlist/") => {
+            let book_id = path.trim_start_matches("/api/wishlist/").parse::<i64>().unwrap_or(0);
+            handle_save_to_wishlist(pool, token.as_deref(), book_id, body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/wishlist/") => {
+            let book_id = path.trim_start_matches("/api/wishlist/").parse::<i64>().unwrap_or(0);
+            handle_remove_from_wishlist(pool, token.as_deref(), book_id).await
+        },
+        
+        // Suggestion endpoints
+        ("POST", "/api/suggestions") => handle_create_suggestion(pool, token.as_deref(), body).await,
+        ("GET", "/api/suggestions/mine") => handle_get_my_suggestions(pool, token.as_deref()).await,
+        
+        // Donation endpoints
+        ("POST", "/api/donations") => handle_create_donation(pool, token.as_deref(), body).await,
+        ("GET", "/api/donations/mine") => handle_get_my_donations(pool, token.as_deref()).await,
+        ("POST", "/api/transfers") => handle_create_transfer(pool, token.as_deref(), body).await,
+        ("GET", "/api/transfers/mine") => handle_get_my_transfers(pool, token.as_deref()).await,
+        ("POST", path) if path.starts_with("/api/transfers/") && path.ends_with("/cancel") => {
+            let id = path.trim_start_matches("/api/transfers/").trim_end_matches("/cancel").parse::<i64>().unwrap_or(0);
+            handle_cancel_my_transfer(pool, token.as_deref(), id).await
+        },
         
-        _ => error_response(404, "Not Found"),
+        // Announcement endpoints
+        ("GET", "/api/announcements/active") => handle_get_active_announcements(pool).await,
+        
+        // Admin endpoints
+        ("POST", path) if path == "/api/admin/books/import-marc" || path.starts_with("/api/admin/books/import-marc?") => {
+            let copies = query_param(path, "copies").and_then(|c| c.parse::<i32>().ok()).unwrap_or(1);
+            handle_import_marc(pool, token.as_deref(), body, copies).await
+        },
+        ("POST", path) if path == "/api/admin/books/import-goodreads" || path.starts_with("/api/admin/books/import-goodreads?") => {
+            let copies = query_param(path, "copies").and_then(|c| c.parse::<i32>().ok()).unwrap_or(1);
+            handle_import_goodreads(pool, token.as_deref(), body, copies).await
+        },
+        ("GET", path) if path == "/api/admin/users" || path.starts_with("/api/admin/users?") => {
+            handle_get_all_users(pool, token.as_deref(), user_query(path), listing_format(request, path).as_deref()).await
+        },
+        ("POST", path) if path == "/api/admin/users/import" || path.starts_with("/api/admin/users/import?") => {
+            let send_invites = query_param(path, "credentials").as_deref() == Some("invite");
+            let days = query_param(path, "expires_in_days").and_then(|d| d.parse::<i64>().ok()).unwrap_or(INVITE_DEFAULT_DAYS);
+            handle_import_users(pool, token.as_deref(), body, send_invites, days).await
+        },
+        ("POST", "/api/admin/users/merge") => handle_merge_users(pool, token.as_deref(), body).await,
+        ("GET", "/api/admin/users/pending") => handle_get_pending_users(pool, token.as_deref()).await,
+        ("POST", path) if path.starts_with("/api/admin/users/") && path.ends_with("/approve") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/approve").parse::<i64>().unwrap_or(0);
+            handle_decide_registration(pool, token.as_deref(), id, true, body).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/users/") && path.ends_with("/reject") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/reject").parse::<i64>().unwrap_or(0);
+            handle_decide_registration(pool, token.as_deref(), id, false, body).await
+        },
+        ("GET", path) if path.starts_with("/api/admin/users/by-card/") => {
+            let number = path.trim_start_matches("/api/admin/users/by-card/");
+            handle_get_user_by_card(pool, token.as_deref(), number).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/users/") && path.ends_with("/home-branch") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/home-branch").parse::<i64>().unwrap_or(0);
+            handle_set_home_branch(pool, token.as_deref(), id, body).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/users/") && path.ends_with("/role") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/role").parse::<i64>().unwrap_or(0);
+            handle_set_role(pool, token.as_deref(), id, body).await
+        },
+        ("GET", "/api/admin/invites") => handle_get_invites(pool, token.as_deref()).await,
+        ("POST", "/api/admin/invites") => handle_create_invite(pool, token.as_deref(), body).await,
+        ("DELETE", path) if path.starts_with("/api/admin/invites/") => {
+            let id = path.trim_start_matches("/api/admin/invites/").parse::<i64>().unwrap_or(0);
+            handle_delete_invite(pool, token.as_deref(), id).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/users/") && path.ends_with("/impersonate") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/impersonate").parse::<i64>().unwrap_or(0);
+            handle_impersonate_user(pool, token.as_deref(), id).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/users/") && path.ends_with("/member-type") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/member-type").parse::<i64>().unwrap_or(0);
+            handle_set_member_type(pool, token.as_deref(), id, body).await
+        },
+        ("GET", "/api/admin/member-types") => handle_get_member_types(pool, token.as_deref()).await,
+        ("POST", path) if path.starts_with("/api/admin/periodicals/") && path.ends_with("/issues") => {
+            let id = path.trim_start_matches("/api/admin/periodicals/").trim_end_matches("/issues").parse::<i64>().unwrap_or(0);
+            handle_create_issue(pool, token.as_deref(), id, body).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/issues/") && path.ends_with("/receive") => {
+            let id = path.trim_start_matches("/api/admin/issues/").trim_end_matches("/receive").parse::<i64>().unwrap_or(0);
+            handle_receive_issue(pool, token.as_deref(), id, body).await
+        },
+        ("GET", "/api/admin/item-types") => handle_get_item_types(pool, token.as_deref()).await,
+        ("PUT", path) if path.starts_with("/api/admin/item-types/") => {
+            let name = urlencoding::decode(path.trim_start_matches("/api/admin/item-types/")).unwrap_or_default().into_owned();
+            handle_upsert_item_type(pool, token.as_deref(), &name, body).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/member-types/") => {
+            let name = urlencoding::decode(path.trim_start_matches("/api/admin/member-types/")).unwrap_or_default().into_owned();
+            handle_upsert_member_type(pool, token.as_deref(), &name, body).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/users/") && path.ends_with("/renew-membership") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/renew-membership").parse::<i64>().unwrap_or(0);
+            handle_renew_membership(pool, token.as_deref(), id, body).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/users/") && path.ends_with("/lift-suspension") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/lift-suspension").parse::<i64>().unwrap_or(0);
+            handle_lift_suspension(pool, token.as_deref(), id).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/users/") && path.ends_with("/deactivate") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/deactivate").parse::<i64>().unwrap_or(0);
+            handle_set_user_active(pool, token.as_deref(), id, false).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/users/") && path.ends_with("/reactivate") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/reactivate").parse::<i64>().unwrap_or(0);
+            handle_set_user_active(pool, token.as_deref(), id, true).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/users/") => {
+            let id = path.trim_start_matches("/api/admin/users/").parse::<i64>().unwrap_or(0);
+            handle_admin_delete_user(pool, token.as_deref(), id).await
+        },
+        ("GET", path) if path.starts_with("/api/admin/users/") && path.ends_with("/summary") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/summary").parse::<i64>().unwrap_or(0);
+            handle_get_user_summary(pool, token.as_deref(), id).await
+        },
+        ("GET", path) if path.starts_with("/api/admin/users/") && path.ends_with("/fines") => {
+            let id = path.trim_start_matches("/api/admin/users/").trim_end_matches("/fines").parse::<i64>().unwrap_or(0);
+            handle_get_user_fines(pool, token.as_deref(), id).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/fines/") && path.ends_with("/pay") => {
+            let id = path.trim_start_matches("/api/admin/fines/").trim_end_matches("/pay").parse::<i64>().unwrap_or(0);
+            handle_pay_fine(pool, token.as_deref(), id, body).await
+        },
+        ("GET", path) if path == "/api/admin/extension-requests" || path.starts_with("/api/admin/extension-requests?") => {
+            let status = query_param(path, "status").unwrap_or_else(|| "pending".to_string());
+            handle_get_extension_requests(pool, token.as_deref(), &status).await
+        },
+        ("GET", path) if path == "/api/admin/donations" || path.starts_with("/api/admin/donations?") => {
+            let status = query_param(path, "status").unwrap_or_else(|| "pending".to_string());
+            handle_get_donations(pool, token.as_deref(), &status).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/donations/") && path.ends_with("/accept") => {
+            let id = path.trim_start_matches("/api/admin/donations/").trim_end_matches("/accept").parse::<i64>().unwrap_or(0);
+            handle_decide_donation(pool, token.as_deref(), id, body, true).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/donations/") && path.ends_with("/decline") => {
+            let id = path.trim_start_matches("/api/admin/donations/").trim_end_matches("/decline").parse::<i64>().unwrap_or(0);
+            handle_decide_donation(pool, token.as_deref(), id, body, false).await
+        },
+        ("GET", path) if path == "/api/admin/transfers" || path.starts_with("/api/admin/transfers?") => {
+            let status = query_param(path, "status");
+            let branch_id = query_param(path, "branch").and_then(|b| b.parse::<i64>().ok());
+            handle_get_transfers(pool, token.as_deref(), status.as_deref(), branch_id).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/transfers/") && path.ends_with("/ship") => {
+            let id = path.trim_start_matches("/api/admin/transfers/").trim_end_matches("/ship").parse::<i64>().unwrap_or(0);
+            handle_advance_transfer(pool, token.as_deref(), id, "in_transit").await
+        },
+        ("POST", path) if path.starts_with("/api/admin/transfers/") && path.ends_with("/receive") => {
+            let id = path.trim_start_matches("/api/admin/transfers/").trim_end_matches("/receive").parse::<i64>().unwrap_or(0);
+            handle_advance_transfer(pool, token.as_deref(), id, "arrived").await
+        },
+        ("POST", path) if path.starts_with("/api/admin/transfers/") && path.ends_with("/cancel") => {
+            let id = path.trim_start_matches("/api/admin/transfers/").trim_end_matches("/cancel").parse::<i64>().unwrap_or(0);
+            handle_advance_transfer(pool, token.as_deref(), id, "cancelled").await
+        },
+        ("GET", "/api/admin/acquisitions") => handle_get_acquisitions(pool, token.as_deref()).await,
+        ("GET", path) if path == "/api/admin/suggestions" || path.starts_with("/api/admin/suggestions?") => {
+            let status = query_param(path, "status").unwrap_or_else(|| "pending".to_string());
+            handle_get_suggestions(pool, token.as_deref(), &status).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/suggestions/") && path.ends_with("/approve") => {
+            let id = path.trim_start_matches("/api/admin/suggestions/").trim_end_matches("/approve").parse::<i64>().unwrap_or(0);
+            handle_decide_suggestion(pool, token.as_deref(), id, body, true).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/suggestions/") && path.ends_with("/decline") => {
+            let id = path.trim_start_matches("/api/admin/suggestions/").trim_end_matches("/decline").parse::<i64>().unwrap_or(0);
+            handle_decide_suggestion(pool, token.as_deref(), id, body, false).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/extension-requests/") && path.ends_with("/approve") => {
+            let id = path.trim_start_matches("/api/admin/extension-requests/").trim_end_matches("/approve").parse::<i64>().unwrap_or(0);
+            handle_decide_extension(pool, token.as_deref(), id, body, true).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/extension-requests/") && path.ends_with("/deny") => {
+            let id = path.trim_start_matches("/api/admin/extension-requests/").trim_end_matches("/deny").parse::<i64>().unwrap_or(0);
+            handle_decide_extension(pool, token.as_deref(), id, body, false).await
+        },
+        ("GET", "/api/admin/reservations") => handle_get_active_reservations(pool, token.as_deref()).await,
+        ("POST", path) if path.starts_with("/api/admin/lending/") && path.ends_with("/recall") => {
+            let id = path.trim_start_matches("/api/admin/lending/").trim_end_matches("/recall").parse::<i64>().unwrap_or(0);
+            handle_recall_loan(pool, token.as_deref(), id, body).await
+        },
+        ("GET", path) if path == "/api/admin/lending/active" || path.starts_with("/api/admin/lending/active?") => {
+            handle_get_active_lending(pool, token.as_deref(), lending_query(path), listing_format(request, path).as_deref()).await
+        },
+        ("GET", path) if path == "/api/admin/lending/overdue" || path.starts_with("/api/admin/lending/overdue?") => {
+            let branch_id = query_param(path, "branch").and_then(|b| b.parse::<i64>().ok());
+            handle_get_overdue_books(pool, token.as_deref(), branch_id, query_param(path, "level").as_deref(), listing_format(request, path).as_deref()).await
+        },
+        ("POST", path) if path.starts_with("/api/admin/lending/") && path.ends_with("/notes") => {
+            let id = path.trim_start_matches("/api/admin/lending/").trim_end_matches("/notes").parse::<i64>().unwrap_or(0);
+            handle_add_lending_note(pool, token.as_deref(), id, body).await
+        },
+        ("GET", path) if path.starts_with("/api/admin/lending/") => {
+            let id = path.trim_start_matches("/api/admin/lending/").parse::<i64>().unwrap_or(0);
+            handle_get_lending_record(pool, token.as_deref(), id).await
+        },
+        ("GET", "/api/admin/announcements") => handle_get_announcements(pool, token.as_deref()).await,
+        ("POST", "/api/admin/announcements") => handle_create_announcement(pool, token.as_deref(), body).await,
+        ("PUT", path) if path.starts_with("/api/admin/announcements/") => {
+            let id = path.trim_start_matches("/api/admin/announcements/").parse::<i64>().unwrap_or(0);
+            handle_update_announcement(pool, token.as_deref(), id, body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/announcements/") => {
+            let id = path.trim_start_matches("/api/admin/announcements/").parse::<i64>().unwrap_or(0);
+            handle_delete_announcement(pool, token.as_deref(), id).await
+        },
+        ("POST", "/api/admin/messages/broadcast") => handle_create_broadcast(pool, token.as_deref(), body).await,
+        ("GET", "/api/admin/messages/broadcasts") => handle_get_broadcasts(pool, token.as_deref()).await,
+        ("GET", path) if path.starts_with("/api/admin/messages/broadcasts/") => {
+            let id = path.trim_start_matches("/api/admin/messages/broadcasts/").parse::<i64>().unwrap_or(0);
+            handle_get_broadcast(pool, token.as_deref(), id).await
+        },
+        ("GET", "/api/admin/lists") => handle_get_admin_reading_lists(pool, token.as_deref()).await,
+        ("POST", "/api/admin/lists") => handle_create_reading_list(pool, token.as_deref(), body).await,
+        ("GET", path) if path.starts_with("/api/admin/lists/") => {
+            let id = path.trim_start_matches("/api/admin/lists/").parse::<i64>().unwrap_or(0);
+            handle_get_admin_reading_list(pool, token.as_deref(), id).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/lists/") => {
+            let id = path.trim_start_matches("/api/admin/lists/").parse::<i64>().unwrap_or(0);
+            handle_update_reading_list(pool, token.as_deref(), id, body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/lists/") => {
+            let id = path.trim_start_matches("/api/admin/lists/").parse::<i64>().unwrap_or(0);
+            handle_delete_reading_list(pool, token.as_deref(), id).await
+        },
+        ("POST", "/api/admin/series") => handle_create_series(pool, token.as_deref(), body).await,
+        ("PUT", path) if path.starts_with("/api/admin/series/") => {
+            let id = path.trim_start_matches("/api/admin/series/").parse::<i64>().unwrap_or(0);
+            handle_update_series(pool, token.as_deref(), id, body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/series/") => {
+            let id = path.trim_start_matches("/api/admin/series/").parse::<i64>().unwrap_or(0);
+            handle_delete_series(pool, token.as_deref(), id).await
+        },
+        ("GET", "/api/admin/branches") => handle_get_admin_branches(pool, token.as_deref()).await,
+        ("POST", "/api/admin/branches") => handle_create_branch(pool, token.as_deref(), body).await,
+        ("PUT", path) if path.starts_with("/api/admin/branches/") => {
+            let id = path.trim_start_matches("/api/admin/branches/").parse::<i64>().unwrap_or(0);
+            handle_update_branch(pool, token.as_deref(), id, body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/branches/") => {
+            let id = path.trim_start_matches("/api/admin/branches/").parse::<i64>().unwrap_or(0);
+            handle_delete_branch(pool, token.as_deref(), id).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/books/") && path.contains("/branches/") && path.ends_with("/location") => {
+            let rest = path.trim_start_matches("/api/admin/books/").trim_end_matches("/location");
+            let (book, branch) = rest.split_once("/branches/").unwrap_or(("", ""));
+            handle_set_copies_location(pool, token.as_deref(), book.parse::<i64>().unwrap_or(0), branch.parse::<i64>().unwrap_or(0), body).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/books/") && path.contains("/branches/") => {
+            let (book, branch) = path.trim_start_matches("/api/admin/books/").split_once("/branches/").unwrap_or(("", ""));
+            handle_set_branch_copies(pool, token.as_deref(), book.parse::<i64>().unwrap_or(0), branch.parse::<i64>().unwrap_or(0), body).await
+        },
+        ("GET", path) if path.starts_with("/api/admin/books/") && path.split('?').next().unwrap_or(path).ends_with("/labels") => {
+            let id = path.split('?').next().unwrap_or(path).trim_start_matches("/api/admin/books/").trim_end_matches("/labels").parse::<i64>().unwrap_or(0);
+            handle_get_book_labels(pool, token.as_deref(), id, path).await
+        },
+        ("GET", path) if path.starts_with("/api/admin/locations/") && path.contains("/shelf-list") => {
+            let id = path.trim_start_matches("/api/admin/locations/").split('/').next().unwrap_or("").parse::<i64>().unwrap_or(0);
+            handle_get_shelf_list(pool, token.as_deref(), id, listing_format(request, path).as_deref()).await
+        },
+        ("GET", path) if path == "/api/admin/locations" || path.starts_with("/api/admin/locations?") => {
+            let branch_id = query_param(path, "branch").and_then(|b| b.parse::<i64>().ok());
+            handle_get_locations(pool, token.as_deref(), branch_id).await
+        },
+        ("POST", "/api/admin/locations") => handle_create_location(pool, token.as_deref(), body).await,
+        ("PUT", path) if path.starts_with("/api/admin/locations/") => {
+            let id = path.trim_start_matches("/api/admin/locations/").parse::<i64>().unwrap_or(0);
+            handle_update_location(pool, token.as_deref(), id, body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/locations/") => {
+            let id = path.trim_start_matches("/api/admin/locations/").parse::<i64>().unwrap_or(0);
+            handle_delete_location(pool, token.as_deref(), id).await
+        },
+        ("GET", "/api/admin/closures") => handle_get_closures(pool, token.as_deref()).await,
+        ("POST", "/api/admin/closures") => handle_create_closure(pool, token.as_deref(), body).await,
+        ("PUT", path) if path.starts_with("/api/admin/closures/") => {
+            let id = path.trim_start_matches("/api/admin/closures/").parse::<i64>().unwrap_or(0);
+            handle_update_closure(pool, token.as_deref(), id, body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/closures/") => {
+            let id = path.trim_start_matches("/api/admin/closures/").parse::<i64>().unwrap_or(0);
+            handle_delete_closure(pool, token.as_deref(), id).await
+        },
+        ("GET", "/api/admin/jobs") => handle_get_jobs(pool, token.as_deref()).await,
+        ("GET", "/api/admin/retention/preview") => handle_get_retention_preview(pool, token.as_deref()).await,
+        ("GET", path) if path == "/api/admin/events" || path.starts_with("/api/admin/events?") => {
+            let since_id = query_param(path, "since_id").and_then(|v| v.parse().ok()).unwrap_or(0);
+            let limit = query_param(path, "limit").and_then(|v| v.parse().ok()).unwrap_or(EVENT_PAGE_SIZE).clamp(1, EVENT_MAX_PAGE_SIZE);
+            handle_get_events(pool, token.as_deref(), since_id, query_param(path, "type").as_deref(), limit).await
+        },
+        ("GET", path) if path == "/api/admin/access-log" || path.starts_with("/api/admin/access-log?") => {
+            let query = AccessLogQuery {
+                user_id: query_param(path, "user_id").and_then(|v| v.parse().ok()),
+                method: query_param(path, "method").map(|v| v.to_uppercase()),
+                path: query_param(path, "path"),
+                status: query_param(path, "status").and_then(|v| v.parse().ok()),
+                ip: query_param(path, "ip"),
+                from: query_param(path, "from"),
+                to: query_param(path, "to"),
+                limit: query_param(path, "limit").and_then(|v| v.parse().ok()).unwrap_or(ACCESS_LOG_PAGE_SIZE).clamp(1, ACCESS_LOG_MAX_PAGE_SIZE),
+            };
+            handle_get_access_log(pool, token.as_deref(), &query, listing_format(request, path).as_deref()).await
+        },
+        ("GET", "/api/admin/maintenance") => handle_get_maintenance(pool, token.as_deref()).await,
+        ("POST", "/api/admin/maintenance") => handle_set_maintenance(pool, token.as_deref(), body).await,
+        ("POST", "/api/admin/maintenance/integrity-check") => handle_integrity_check(pool, token.as_deref()).await,
+        ("POST", "/api/admin/reconcile-availability") => handle_reconcile_availability(pool, token.as_deref()).await,
+        ("GET", "/api/admin/settings") => handle_get_settings(pool, token.as_deref()).await,
+        ("GET", "/api/admin/webhooks") => handle_get_webhooks(pool, token.as_deref()).await,
+        ("GET", "/api/admin/webhooks/events") => handle_get_webhook_events(pool, token.as_deref()).await,
+        ("POST", "/api/admin/webhooks") => handle_create_webhook(pool, token.as_deref(), body).await,
+        ("POST", path) if path.starts_with("/api/admin/webhooks/") && path.ends_with("/test") => {
+            let id = path.trim_start_matches("/api/admin/webhooks/").trim_end_matches("/test").parse::<i64>().unwrap_or(0);
+            handle_test_webhook(pool, token.as_deref(), id).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/webhooks/") => {
+            let id = path.trim_start_matches("/api/admin/webhooks/").parse::<i64>().unwrap_or(0);
+            handle_update_webhook(pool, token.as_deref(), id, body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/webhooks/") => {
+            let id = path.trim_start_matches("/api/admin/webhooks/").parse::<i64>().unwrap_or(0);
+            handle_delete_webhook(pool, token.as_deref(), id).await
+        },
+        ("GET", "/api/admin/templates") => handle_get_email_templates(pool, token.as_deref()).await,
+        ("GET", path) if path.starts_with("/api/admin/templates/") => {
+            handle_get_email_template(pool, token.as_deref(), path.trim_start_matches("/api/admin/templates/")).await
+        },
+        ("PUT", path) if path.starts_with("/api/admin/templates/") => {
+            handle_update_email_template(pool, token.as_deref(), path.trim_start_matches("/api/admin/templates/"), body).await
+        },
+        ("DELETE", path) if path.starts_with("/api/admin/templates/") => {
+            handle_reset_email_template(pool, token.as_deref(), path.trim_start_matches("/api/admin/templates/")).await
+        },
+        ("PUT", "/api/admin/settings") => handle_update_settings(pool, token.as_deref(), body).await,
+    } unmatched(has_route) => unmatched_route(method, has_route) };
+    
+    // Any write that went through may have changed the catalog
+    if !matches!(method, "GET" | "OPTIONS") && (200..300).contains(&response_status(response.as_bytes())) {
+        cache::invalidate();
+    }
+    
+    if let Some(claim) = idempotency {
+        claim.settle(&response).await;
+    }
+    
+    if cacheable && response.starts_with("HTTP/1.1 200") && response.contains("Content-Type: application/json") {
+        if let Some((_, body)) = response.split_once("\r\n\r\n") {
+            let entry = cache::put(&cache_key, cache_generation, body);
+            return cached_response(&entry, request_header(request, "if-none-match")).into_bytes();
+        }
+    }
+    
+    response.into_bytes()
+}
+
+const IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
+
+// Returns the (user, key) claimed for this request, or the response to send instead: the stored
+// original for a retry, or an error when the key is misused or its first request is still running
+async fn claim_idempotency_key(
+    pool: &SqlitePool,
+    method: &str,
+    path: &str,
+    request: &str,
+    token: Option<&str>,
+) -> Result<Option<(i64, String)>, String> {
+    if method != "POST" || !(path.starts_with("/api/lending/borrow/") || path.starts_with("/api/lending/return/")) {
+        return Ok(None);
+    }
+    let Some(key) = request_header(request, "idempotency-key") else { return Ok(None) };
+    if key.is_empty() || key.len() > 255 {
+        return Err(error_response(400, "Idempotency-Key must be 1 to 255 characters"));
+    }
+    
+    // Requests authenticate would turn away fall through to the handler, which rejects them
+    let Some(user) = (match token {
+        Some(token) => lookup_user(pool, token).await.ok().flatten().filter(|user| user.is_active),
+        None => None,
+    }) else {
+        return Ok(None);
+    };
+    
+    let signature = format!("{} {}", method, path);
+    match db::claim_idempotency_key(pool, user.id, key, &signature).await {
+        Ok(true) => return Ok(Some((user.id, key.to_string()))),
+        Ok(false) => {}
+        Err(_) => return Err(error_response(500, "Failed to check idempotency key")),
+    }
+    
+    match db::get_idempotency_key(pool, user.id, key).await {
+        Ok(Some((original, _))) if original != signature => {
+            Err(error_response(422, "Idempotency-Key was already used for a different request"))
+        }
+        Ok(Some((_, Some(response)))) => Err(response.replacen("\r\n", "\r\nIdempotent-Replayed: true\r\n", 1)),
+        Ok(Some((_, None))) => Err(error_response(409, "A request with this Idempotency-Key is still in progress")),
+        // Released between the claim and the lookup; the client can simply retry
+        Ok(None) => Err(error_response(409, "A request with this Idempotency-Key is still in progress")),
+        Err(_) => Err(error_response(500, "Failed to check idempotency key")),
+    }
+}
+
+// A claimed key, released again when the request never gets as far as storing its response. A
+// handler that panics or runs past the deadline is dropped midway, and a key left claimed would
+// answer every retry with 409 until the nightly cleanup
+struct IdempotencyClaim {
+    pool: SqlitePool,
+    user_id: i64,
+    key: String,
+    settled: bool,
+}
+
+impl IdempotencyClaim {
+    async fn settle(mut self, response: &str) {
+        store_idempotent_response(&self.pool, self.user_id, &self.key, response).await;
+        self.settled = true;
+    }
+}
+
+impl Drop for IdempotencyClaim {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        // Dropped by the aborted or panicking task, which can't await anything any more
+        let (pool, user_id, key) = (self.pool.clone(), self.user_id, std::mem::take(&mut self.key));
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(e) = db::release_idempotency_key(&pool, user_id, &key).await {
+                    eprintln!("Failed to release idempotency key: {:?}", e);
+                }
+            });
+        }
     }
 }
 
+// Server errors are not stored, so the client can retry them with the same key
+async fn store_idempotent_response(pool: &SqlitePool, user_id: i64, key: &str, response: &str) {
+    let result = if response.starts_with("HTTP/1.1 5") {
+        db::release_idempotency_key(pool, user_id, key).await
+    } else {
+        db::save_idempotent_response(pool, user_id, key, response).await
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to store idempotent response: {:?}", e);
+    }
+}
+
+fn request_header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request
+        .split("\r\n\r\n")
+        .next()?
+        .lines()
+        .skip(1)
+        .find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+}
+
 fn extract_token(request: &str) -> Option<String> {
     for line in request.lines() {
         let lower_line = line.to_lowercase();
@@ -149,31 +1191,226 @@ fn extract_token(request: &str) -> Option<String> {
     None
 }
 
-fn serve_file(path: &str, content_type: &str) -> String {
-    match std::fs::read_to_string(path) {
-        Ok(content) => {
-            format!(
-                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
-                content_type,
-                content.len(),
-                content
-            )
-        }
-        Err(_) => error_response(404, "File not found"),
+const SESSION_COOKIE: &str = "library_session";
+const CSRF_COOKIE: &str = "library_csrf";
+const CSRF_HEADER: &str = "x-csrf-token";
+
+// The Authorization header wins; browsers signed in through the frontend send the cookie instead.
+// The flag says whether the token came from the cookie
+fn session_token(request: &str) -> (Option<String>, bool) {
+    match extract_token(request) {
+        Some(token) => (Some(token), false),
+        None => match request_cookie(request, SESSION_COOKIE) {
+            Some(token) => (Some(token.to_string()), true),
+            None => (None, false),
+        },
     }
 }
 
-fn success_response(data: serde_json::Value) -> String {
-    let body = data.to_string();
-    format!(
-        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
-        body.len(),
-        body
+fn request_cookie<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request_header(request, "cookie")?
+        .split(';')
+        .find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key.trim() == name).then(|| value.trim())
+        })
+        .filter(|value| !value.is_empty())
+}
+
+fn csrf_valid(request: &str) -> bool {
+    match (request_cookie(request, CSRF_COOKIE), request_header(request, CSRF_HEADER)) {
+        (Some(cookie), Some(header)) => auth::constant_time_eq(cookie, header),
+        _ => false,
+    }
+}
+
+// Signing in doesn't act on an existing session, and a stale cookie must not block it
+fn csrf_exempt(path: &str) -> bool {
+    matches!(path, "/api/auth/login" | "/api/auth/register" | "/api/auth/2fa/verify")
+}
+
+// Sets the HttpOnly session cookie and the script-readable CSRF cookie that must be echoed back
+fn with_session_cookies(response: String, token: &str, max_age: i64) -> String {
+    let secure = if config::get().public_url.starts_with("https://") { "; Secure" } else { "" };
+    with_headers(response, &[
+        format!("Set-Cookie: {}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict{}", SESSION_COOKIE, token, max_age, secure),
+        format!("Set-Cookie: {}={}; Path=/; Max-Age={}; SameSite=Strict{}", CSRF_COOKIE, auth::generate_token(), max_age, secure),
+    ])
+}
+
+fn clear_session_cookies(response: String) -> String {
+    with_headers(response, &[
+        format!("Set-Cookie: {}=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict", SESSION_COOKIE),
+        format!("Set-Cookie: {}=; Path=/; Max-Age=0; SameSite=Strict", CSRF_COOKIE),
+    ])
+}
+
+// Adds headers right after the status line of a built response
+fn with_headers(response: String, headers: &[String]) -> String {
+    match response.split_once("\r\n") {
+        Some((status, rest)) => format!("{}\r\n{}\r\n{}", status, headers.join("\r\n"), rest),
+        None => response,
+    }
+}
+
+fn query_param(path: &str, name: &str) -> Option<String> {
+    let query = path.split_once('?')?.1;
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if key == name {
+            urlencoding::decode(&value.replace('+', " ")).ok().map(|v| v.into_owned())
+        } else {
+            None
+        }
+    })
+}
+
+fn serve_file(path: &str, content_type: &str) -> String {
+    match std::fs::read_to_string(path) {
+        Ok(content) => {
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n\r\n{}",
+                content_type,
+                content.len(),
+                content
+            )
+        }
+        Err(_) => error_response(404, "File not found"),
+    }
+}
+
+fn download_response(content_type: &str, filename: &str, data: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Disposition: attachment; filename=\"{}\"\r\nAccept-Ranges: bytes\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n",
+        content_type,
+        filename,
+        data.len()
+    ).into_bytes();
+    response.extend_from_slice(data);
+    response
+}
+
+fn image_response(content_type: &str, data: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nCache-Control: public, max-age=86400\r\nAccept-Ranges: bytes\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n",
+        content_type,
+        data.len()
+    ).into_bytes();
+    response.extend_from_slice(data);
+    response
+}
+
+fn redirect_response(location: &str) -> String {
+    format!(
+        "HTTP/1.1 302 Found\r\nLocation: {}\r\nCache-Control: no-store\r\nContent-Length: 0\r\n\r\n",
+        location
+    )
+}
+
+fn text_response(content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+fn csv_response(filename: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/csv; charset=utf-8\r\nContent-Disposition: attachment; filename=\"{}\"\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+        filename,
+        body.len(),
+        body
+    )
+}
+
+// Listings return JSON by default and CSV when requested with ?format=csv or an Accept header
+// preferring text/csv; the query parameter wins when both are given
+fn listing_format(request: &str, path: &str) -> Option<String> {
+    query_param(path, "format").or_else(|| prefers_csv(request).then(|| "csv".to_string()))
+}
+
+// text/csv has to be listed and ranked above application/json, so browsers sending */* get JSON
+fn prefers_csv(request: &str) -> bool {
+    let Some(accept) = request_header(request, "accept") else { return false };
+    let csv = accept_quality(accept, "text/csv").unwrap_or(0.0);
+    csv > 0.0 && csv > accept_quality(accept, "application/json").unwrap_or(0.0)
+}
+
+// The q-value an Accept-style header gives a token, 1 when it has none; None when not listed
+fn accept_quality(header: &str, token: &str) -> Option<f32> {
+    header.split(',').find_map(|entry| {
+        let mut parts = entry.split(';');
+        if !parts.next()?.trim().eq_ignore_ascii_case(token) {
+            return None;
+        }
+        let q = parts
+            .find_map(|param| param.trim().strip_prefix("q=").and_then(|q| q.trim().parse::<f32>().ok()))
+            .unwrap_or(1.0);
+        Some(q)
+    })
+}
+
+fn listing_response<T: serde::Serialize>(rows: &[T], format: Option<&str>, name: &str, columns: &[&str]) -> String {
+    match format {
+        None | Some("json") => success_response(serde_json::to_value(rows).unwrap()),
+        Some("csv") => {
+            let filename = format!("{}-{}.csv", name, chrono::Utc::now().format("%Y%m%d"));
+            csv_response(&filename, &csv::to_csv(rows, columns))
+        }
+        Some(_) => error_response(400, "Format must be json or csv"),
+    }
+}
+
+const SHELF_LIST_CSV_COLUMNS: &[&str] = &[
+    "book_id", "title", "author", "isbn", "copies", "on_loan", "in_transit", "expected_on_shelf",
+];
+
+const BOOK_CSV_COLUMNS: &[&str] = &[
+    "id", "title", "author", "isbn", "publication_year", "genre", "item_type", "total_copies", "available_copies", "tags", "on_order", "loanable", "created_at",
+];
+
+const USER_CSV_COLUMNS: &[&str] = &[
+    "id", "username", "email", "display_name", "role", "member_type", "card_number", "is_active", "approval_status", "membership_expires_at", "suspended_at", "home_branch_id", "created_at",
+];
+
+const LENDING_CSV_COLUMNS: &[&str] = &[
+    "id", "user_id", "username", "book_id", "title", "author", "borrowed_at", "due_date", "status", "recalled_at", "branch_id", "escalation_level",
+];
+
+// Clients must revalidate, which costs them a 304 with no body while the catalog is unchanged
+fn cached_response(entry: &cache::Entry, if_none_match: Option<&str>) -> String {
+    if if_none_match.is_some_and(|tags| cache::matches(tags, &entry.etag)) {
+        return format!(
+            "HTTP/1.1 304 Not Modified\r\nETag: {}\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\n\r\n",
+            entry.etag
+        );
+    }
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nETag: {}\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+        entry.etag,
+        entry.body.len(),
+        entry.body
+    )
+}
+
+// JSON with <, > and & written as \u escapes, so a body can never be mistaken for HTML
+fn json_body(data: &serde_json::Value) -> String {
+    data.to_string().replace('<', "\\u003c").replace('>', "\\u003e").replace('&', "\\u0026")
+}
+
+fn success_response(data: serde_json::Value) -> String {
+    let body = json_body(&data);
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
     )
 }
 
 fn created_response(data: serde_json::Value) -> String {
-    let body = data.to_string();
+    let body = json_body(&data);
     format!(
         "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
         body.len(),
@@ -181,12 +1418,78 @@ fn created_response(data: serde_json::Value) -> String {
     )
 }
 
+// A 409 that hands back the current state of the record the caller tried to change
+fn conflict_response(message: &str, current: serde_json::Value) -> String {
+    let body = json_body(&json!({ "error": message, "current": current }));
+    format!(
+        "HTTP/1.1 409 Conflict\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+// A 503 telling members the library is in maintenance mode and since when
+fn maintenance_response(status: &maintenance::Status) -> String {
+    let message = status.message.as_deref().unwrap_or(maintenance::DEFAULT_MESSAGE);
+    let body = json_body(&json!({ "error": message, "maintenance": true, "since": status.since }));
+    format!(
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nRetry-After: 300\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn busy_response() -> String {
+    let body = json_body(&json!({ "error": "Server is busy, please retry shortly" }));
+    format!(
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nRetry-After: {}\r\nContent-Length: {}\r\n\r\n{}",
+        BUSY_RETRY_AFTER_SECS,
+        body.len(),
+        body
+    )
+}
+
+// A 429 for a login tried while its address or account is still backing off
+fn too_many_attempts_response(wait: std::time::Duration) -> String {
+    let seconds = wait.as_millis().div_ceil(1000).max(1);
+    with_headers(error_response(429, "Too many failed attempts, try again later"), &[format!("Retry-After: {}", seconds)])
+}
+
+// Headers a cross-origin caller may send, for CORS preflight requests
+const ALLOWED_REQUEST_HEADERS: &str = "Authorization, Content-Type, Idempotency-Key, If-None-Match, X-CSRF-Token";
+
+// OPTIONS lists the methods the path supports, and any other method the path has no route for
+// gets 405 with the same list; a path with no routes at all is 404
+fn unmatched_route(method: &str, has_route: impl Fn(&str) -> bool) -> String {
+    let mut allowed: Vec<&str> = ["GET", "POST", "PUT", "DELETE", "PATCH"].into_iter().filter(|m| has_route(m)).collect();
+    if allowed.is_empty() {
+        return error_response(404, "Not Found");
+    }
+    if allowed.contains(&"GET") {
+        allowed.insert(1, "HEAD");
+    }
+    allowed.push("OPTIONS");
+    let allow = allowed.join(", ");
+    
+    if method == "OPTIONS" {
+        return format!(
+            "HTTP/1.1 204 No Content\r\nAllow: {}\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: {}\r\nAccess-Control-Allow-Headers: {}\r\nAccess-Control-Max-Age: 86400\r\nContent-Length: 0\r\n\r\n",
+            allow, allow, ALLOWED_REQUEST_HEADERS
+        );
+    }
+    let mut response = error_response(405, "Method Not Allowed");
+    let status_end = response.find("\r\n").unwrap_or(response.len());
+    response.insert_str(status_end, &format!("\r\nAllow: {}", allow));
+    response
+}
+
+// The message doubles as the reason phrase, so line breaks in it must not reach the status line
 fn error_response(code: u16, message: &str) -> String {
-    let body = json!({ "error": message }).to_string();
+    let body = json_body(&json!({ "error": message }));
     format!(
         "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\r\n{}",
         code,
-        message,
+        message.replace(char::is_control, " "),
         body.len(),
         body
     )
@@ -201,7 +1504,11 @@ async fn authenticate(pool: &SqlitePool, token: Option<&str>) -> Result<User, St
     let token = token.unwrap();
     println!("Authenticating with token: {}...", &token[..token.len().min(10)]);
     
-    match db::get_user_by_token(pool, token).await {
+    match lookup_user(pool, token).await {
+        Ok(Some(user)) if !user.is_active => {
+            println!("Authentication failed: Account {} is deactivated", user.username);
+            Err("Unauthorized".to_string())
+        },
         Ok(Some(user)) => {
             println!("Authentication successful for user: {}", user.username);
             Ok(user)
@@ -217,11 +1524,28 @@ async fn authenticate(pool: &SqlitePool, token: Option<&str>) -> Result<User, St
     }
 }
 
-async fn authenticate_admin(pool: &SqlitePool, token: Option<&str>) -> Result<User, String> {
+// Borrowing and holds are closed to members whose registration still awaits approval
+async fn authenticate_member(pool: &SqlitePool, token: Option<&str>) -> Result<User, String> {
     let user = authenticate(pool, token).await?;
-    if user.role != "admin" {
-        return Err("Forbidden".to_string());
+    if user.approval_status == "pending" {
+        return Err("Your registration is awaiting approval".to_string());
+    }
+    Ok(user)
+}
+
+// Bearer tokens are either login sessions or API keys
+async fn lookup_user(pool: &SqlitePool, token: &str) -> Result<Option<User>, sqlx::Error> {
+    if auth::is_api_key(token) {
+        db::get_user_by_api_key(pool, &auth::hash_api_key(token)).await
+    } else {
+        db::get_user_by_token(pool, token).await
     }
+}
+
+// Authenticates the caller and checks their role grants `permission`
+async fn authenticate_for(pool: &SqlitePool, token: Option<&str>, permission: Permission) -> Result<User, String> {
+    let user = authenticate(pool, token).await?;
+    permissions::authorize(&user, permission)?;
     Ok(user)
 }
 
@@ -236,33 +1560,100 @@ async fn handle_register(pool: &SqlitePool, body: &str) -> String {
         return error_response(400, "Missing required fields");
     }
     
+    if let Err(msg) = password::check(&req.password, &req.username) {
+        return error_response(422, &msg);
+    }
+    
     let password_hash = match auth::hash_password(&req.password) {
         Ok(hash) => hash,
         Err(_) => return error_response(500, "Failed to hash password"),
     };
     
-    match db::create_user(pool, &req.username, &req.email, &password_hash, "lender").await {
+    // Invitation-only libraries need a code; any valid code also brings the role it was issued for
+    let invite = match req.invite_code.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        Some(code) => match db::claim_invite(pool, &auth::hash_invite_code(code)).await {
+            Ok(Some(invite)) => Some(invite),
+            Ok(None) => return error_response(403, "Invite code is invalid, expired or already used"),
+            Err(_) => return error_response(500, "Failed to check invite code"),
+        },
+        None if config::get().invite_only => return error_response(403, "Registration requires an invite code"),
+        None => None,
+    };
+    // Invites from a bulk import are for one account and only work with its username and email
+    if let Some(invite) = &invite {
+        let differs = |pinned: &Option<String>, given: &str| pinned.as_deref().is_some_and(|p| !p.eq_ignore_ascii_case(given.trim()));
+        if differs(&invite.username, &req.username) || differs(&invite.email, &req.email) {
+            if let Err(e) = db::release_invite(pool, invite.id).await {
+                eprintln!("Failed to release invite: {:?}", e);
+            }
+            return error_response(403, "This invite is for a different username or email");
+        }
+    }
+    let role = invite.as_ref().map(|invite| invite.role.as_str()).unwrap_or("lender");
+    // An invite already carries an admin's approval
+    let approval_status = if invite.is_none() && config::get().registration_approval { "pending" } else { "approved" };
+    
+    match db::create_user(pool, &req.username, &req.email, &password_hash, role, approval_status).await {
         Ok(user_id) => {
+            if let Some(invite) = &invite {
+                if let Err(e) = db::set_invite_user(pool, invite.id, user_id).await {
+                    eprintln!("Failed to record invite use: {:?}", e);
+                }
+                if let Some(member_type) = &invite.member_type {
+                    if let Err(e) = db::set_member_type(pool, user_id, member_type).await {
+                        eprintln!("Failed to apply invited member type: {:?}", e);
+                    }
+                }
+                if let Some(card_number) = &invite.card_number {
+                    if let Err(e) = db::set_card_number(pool, user_id, card_number).await {
+                        eprintln!("Failed to apply invited card number: {:?}", e);
+                    }
+                }
+            }
             let user = db::get_user_by_id(pool, user_id).await.ok().flatten();
             if let Some(user) = user {
+                if let Err(e) = send_email_verification(pool, &user).await {
+                    eprintln!("Failed to send verification email: {:?}", e);
+                }
                 created_response(serde_json::to_value(user).unwrap())
             } else {
                 error_response(500, "Failed to retrieve user")
             }
         }
-        Err(_) => error_response(409, "Username or email already exists"),
+        Err(_) => {
+            if let Some(invite) = &invite {
+                if let Err(e) = db::release_invite(pool, invite.id).await {
+                    eprintln!("Failed to release invite: {:?}", e);
+                }
+            }
+            error_response(409, "Username or email already exists")
+        }
     }
 }
 
-async fn handle_login(pool: &SqlitePool, body: &str) -> String {
+async fn handle_login(pool: &SqlitePool, body: &str, client_ip: Option<&str>, user_agent: Option<&str>) -> String {
     let req: LoginRequest = match serde_json::from_str(body) {
         Ok(req) => req,
         Err(_) => return error_response(400, "Invalid request body"),
     };
     
+    // Repeated failures from this address or against this account must wait ever longer before
+    // the next try. Each attempt counts as a failure until the password checks out, so parallel
+    // guesses can't all slip in before the first one fails
+    let account_key = throttle::account_key(&req.username);
+    let ip_key = client_ip.map(throttle::ip_key);
+    let throttle_keys: Vec<String> = std::iter::once(account_key.clone()).chain(ip_key.clone()).collect();
+    if let Some(wait) = throttle::retry_after(&throttle_keys) {
+        return too_many_attempts_response(wait);
+    }
+    throttle::record_failure(&throttle_keys);
+    
     let user = match db::get_user_by_username(pool, &req.username).await {
         Ok(Some(user)) => user,
-        _ => return error_response(401, "Invalid credentials"),
+        _ => {
+            metrics::increment(metrics::Counter::FailedLogins);
+            return error_response(401, "Invalid credentials");
+        }
     };
     
     let valid = match auth::verify_password(&req.password, &user.password_hash) {
@@ -271,181 +1662,5384 @@ async fn handle_login(pool: &SqlitePool, body: &str) -> String {
     };
     
     if !valid {
+        metrics::increment(metrics::Counter::FailedLogins);
         return error_response(401, "Invalid credentials");
     }
+    throttle::clear(&account_key);
+    if let Some(ip_key) = &ip_key {
+        throttle::forgive(ip_key);
+    }
     
-    let token = auth::generate_token();
-    if let Err(_) = db::create_session(pool, user.id, &token).await {
-        return error_response(500, "Failed to create session");
+    if user.approval_status == "rejected" {
+        return error_response(403, "Your registration was not approved");
+    }
+    
+    // The plain password is only in hand now, so older hashes are upgraded here
+    if auth::needs_rehash(&user.password_hash) {
+        match auth::hash_password(&req.password) {
+            Ok(hash) => if let Err(e) = db::set_password_hash(pool, user.id, &hash).await {
+                eprintln!("Failed to upgrade password hash: {:?}", e);
+            },
+            Err(e) => eprintln!("Failed to upgrade password hash: {}", e),
+        }
+    }
+    
+    if !user.is_active {
+        return error_response(403, "Account is deactivated");
+    }
+    
+    // Enrolled users finish signing in with a code from their authenticator
+    if user.two_factor_enabled {
+        let challenge = auth::generate_token();
+        if db::create_login_challenge(pool, user.id, &challenge, LOGIN_CHALLENGE_MINUTES).await.is_err() {
+            return error_response(500, "Failed to start two-factor sign-in");
+        }
+        return success_response(json!({
+            "two_factor_required": true,
+            "challenge": challenge,
+            "expires_in": LOGIN_CHALLENGE_MINUTES * 60,
+        }));
     }
     
+    start_session(pool, user, client_ip, user_agent).await
+}
+
+async fn start_session(pool: &SqlitePool, user: User, client_ip: Option<&str>, user_agent: Option<&str>) -> String {
+    let token = match open_session(pool, &user, client_ip, user_agent).await {
+        Ok(token) => token,
+        Err(_) => return error_response(500, "Failed to create session"),
+    };
+    
     let response = LoginResponse {
-        token,
+        token: token.clone(),
         user,
     };
     
-    success_response(serde_json::to_value(response).unwrap())
+    with_session_cookies(success_response(serde_json::to_value(response).unwrap()), &token, config::get().session_max_hours * 3600)
 }
 
-async fn handle_logout(pool: &SqlitePool, token: Option<&str>) -> String {
-    if let Some(token) = token {
-        let _ = db::delete_session(pool, token).await;
+const MAX_USER_AGENT_CHARS: usize = 255;
+
+// Creates a login session noting where it came from, and warns the user by email when the
+// sign-in is from a device or network they haven't used before
+async fn open_session(pool: &SqlitePool, user: &User, client_ip: Option<&str>, user_agent: Option<&str>) -> Result<String, sqlx::Error> {
+    let token = auth::generate_token();
+    let user_agent: Option<String> = user_agent.map(|ua| ua.chars().take(MAX_USER_AGENT_CHARS).collect());
+    db::create_session(pool, user.id, &token, user_agent.as_deref(), client_ip).await?;
+    
+    if let Err(e) = alert_on_new_device(pool, user, user_agent.as_deref(), client_ip).await {
+        eprintln!("Failed to check sign-in device: {:?}", e);
     }
-    success_response(json!({ "message": "Logged out successfully" }))
+    
+    Ok(token)
 }
 
-async fn handle_get_current_user(pool: &SqlitePool, token: Option<&str>) -> String {
-    match authenticate(pool, token).await {
-        Ok(user) => success_response(serde_json::to_value(user).unwrap()),
-        Err(msg) => error_response(401, &msg),
+async fn alert_on_new_device(pool: &SqlitePool, user: &User, user_agent: Option<&str>, client_ip: Option<&str>) -> Result<(), sqlx::Error> {
+    let user_agent = user_agent.unwrap_or("unknown");
+    let network = client_ip.map(ip_network).unwrap_or_else(|| "unknown".to_string());
+    if !db::record_login_device(pool, user.id, user_agent, &network).await? {
+        return Ok(());
     }
+    
+    let body = format!(
+        "Hello {},\n\nYour library account was just signed in to from a device or location you haven't used before:\n\nDevice: {}\nIP address: {}\nTime: {}\n\nIf this was you, there is nothing to do. If not, sign out that session from your account's session list and contact the library.\n",
+        user.username,
+        user_agent,
+        client_ip.unwrap_or("unknown"),
+        chrono::Utc::now().format("%Y-%m-%d %H:%M UTC")
+    );
+    notify::send_email(pool, Some(user.id), &user.email, "New sign-in to your library account", &body).await
 }
 
-// Book handlers
-async fn handle_get_books(pool: &SqlitePool) -> String {
-    match db::get_all_books(pool).await {
-        Ok(books) => success_response(serde_json::to_value(books).unwrap()),
-        Err(_) => error_response(500, "Failed to fetch books"),
+// The network an address belongs to, so a new address from the same ISP block doesn't count
+// as a new location: /24 for IPv4, /48 for IPv6
+fn ip_network(ip: &str) -> String {
+    match ip.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(v4)) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        Ok(std::net::IpAddr::V6(v6)) => {
+            let s = v6.segments();
+            format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+        }
+        Err(_) => ip.to_string(),
     }
 }
 
-async fn handle_create_book(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
-    if let Err(msg) = authenticate_admin(pool, token).await {
-        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
-    }
-    
-    let req: CreateBookRequest = match serde_json::from_str(body) {
-        Ok(req) => req,
-        Err(_) => return error_response(400, "Invalid request body"),
+async fn handle_get_sessions(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
     };
     
-    if req.title.is_empty() || req.author.is_empty() || req.isbn.is_empty() || req.total_copies < 0 {
-        return error_response(400, "Invalid book data");
-    }
-    
-    match db::create_book(pool, &req).await {
-        Ok(book_id) => {
-            let book = db::get_book_by_id(pool, book_id).await.ok().flatten();
-            if let Some(book) = book {
-                created_response(serde_json::to_value(book).unwrap())
-            } else {
-                error_response(500, "Failed to retrieve book")
-            }
+    let current = db::get_session_id(pool, token.unwrap_or_default()).await.ok().flatten();
+    match db::get_user_sessions(pool, user.id).await {
+        Ok(sessions) => {
+            // Staff impersonation sessions are not the member's own devices
+            let sessions: Vec<_> = sessions
+                .into_iter()
+                .filter(|s| !s.impersonated)
+                .map(|s| {
+                    let is_current = Some(s.id) == current;
+                    let mut data = serde_json::to_value(s).unwrap();
+                    data["current"] = json!(is_current);
+                    data
+                })
+                .collect();
+            success_response(json!(sessions))
         }
-        Err(_) => error_response(409, "ISBN already exists"),
+        Err(_) => error_response(500, "Failed to fetch sessions"),
     }
 }
 
-async fn handle_update_book(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
-    if let Err(msg) = authenticate_admin(pool, token).await {
-        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
-    }
-    
-    let req: UpdateBookRequest = match serde_json::from_str(body) {
-        Ok(req) => req,
-        Err(_) => return error_response(400, "Invalid request body"),
+async fn handle_revoke_session(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
     };
     
-    match db::update_book(pool, id, &req).await {
-        Ok(_) => {
-            let book = db::get_book_by_id(pool, id).await.ok().flatten();
-            if let Some(book) = book {
-                success_response(serde_json::to_value(book).unwrap())
-            } else {
-                error_response(500, "Failed to retrieve updated book")
+    match db::delete_user_session(pool, user.id, id).await {
+        Ok(true) => {
+            let details = json!({ "session_id": id });
+            if let Err(e) = db::record_audit(pool, Some(user.id), "session.revoke", "user", Some(user.id), &details).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
             }
+            success_response(json!({ "message": "Session revoked" }))
         }
-        Err(_) => error_response(404, "Book not found"),
+        Ok(false) => error_response(404, "Session not found"),
+        Err(_) => error_response(500, "Failed to revoke session"),
     }
 }
 
-async fn handle_delete_book(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
-    if let Err(msg) = authenticate_admin(pool, token).await {
-        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
-    }
-    
-    match db::delete_book(pool, id).await {
-        Ok(_) => success_response(json!({ "message": "Book deleted successfully" })),
-        Err(_) => error_response(404, "Book not found"),
-    }
-}
+// Two-factor handlers
+const LOGIN_CHALLENGE_MINUTES: i64 = 5;
+const LOGIN_CHALLENGE_ATTEMPTS: i64 = 5;
+const RECOVERY_CODE_COUNT: usize = 10;
 
-async fn handle_search_books(pool: &SqlitePool, query: &str) -> String {
-    match db::search_books(pool, query).await {
-        Ok(books) => success_response(serde_json::to_value(books).unwrap()),
-        Err(_) => error_response(500, "Failed to search books"),
+// Accepts either a current authenticator code or an unused recovery code
+async fn check_second_factor(pool: &SqlitePool, user_id: i64, code: Option<&str>, recovery_code: Option<&str>) -> Result<bool, sqlx::Error> {
+    if let Some(code) = code {
+        let (secret, _, last_step) = db::get_totp_state(pool, user_id).await?;
+        let step = secret.and_then(|secret| auth::verify_totp(&secret, code, clock::now().timestamp(), last_step));
+        return match step {
+            Some(step) => db::record_totp_step(pool, user_id, step).await,
+            None => Ok(false),
+        };
+    }
+    if let Some(recovery_code) = recovery_code {
+        return db::use_recovery_code(pool, user_id, &auth::hash_recovery_code(recovery_code)).await;
     }
+    Ok(false)
 }
 
-// Lending handlers
-async fn handle_borrow_book(pool: &SqlitePool, token: Option<&str>, book_id: i64) -> String {
+async fn handle_two_factor_setup(pool: &SqlitePool, token: Option<&str>) -> String {
     let user = match authenticate(pool, token).await {
         Ok(user) => user,
         Err(msg) => return error_response(401, &msg),
     };
     
-    match db::borrow_book(pool, user.id, book_id).await {
-        Ok(record_id) => {
-            created_response(json!({ "message": "Book borrowed successfully", "record_id": record_id }))
-        }
-        Err(_) => error_response(409, "Book not available"),
+    if user.two_factor_enabled {
+        return error_response(409, "Two-factor sign-in is already enabled");
+    }
+    
+    let secret = auth::generate_totp_secret();
+    match db::set_pending_totp_secret(pool, user.id, &secret).await {
+        Ok(true) => success_response(json!({
+            "secret": secret,
+            "otpauth_uri": auth::totp_uri(&secret, &user.username),
+        })),
+        Ok(false) => error_response(409, "Two-factor sign-in is already enabled"),
+        Err(_) => error_response(500, "Failed to start two-factor setup"),
     }
 }
 
-async fn handle_return_book(pool: &SqlitePool, token: Option<&str>, record_id: i64) -> String {
+async fn handle_two_factor_enable(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
     let user = match authenticate(pool, token).await {
         Ok(user) => user,
         Err(msg) => return error_response(401, &msg),
     };
     
-    match db::return_book(pool, record_id, user.id).await {
-        Ok(_) => success_response(json!({ "message": "Book returned successfully" })),
-        Err(_) => error_response(404, "Lending record not found or already returned"),
+    let req: TwoFactorCodeRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let (secret, enabled, last_step) = match db::get_totp_state(pool, user.id).await {
+        Ok(state) => state,
+        Err(_) => return error_response(500, "Failed to enable two-factor sign-in"),
+    };
+    if enabled {
+        return error_response(409, "Two-factor sign-in is already enabled");
+    }
+    let Some(secret) = secret else {
+        return error_response(400, "Start two-factor setup first");
+    };
+    
+    let code = req.code.as_deref().unwrap_or("");
+    let Some(step) = auth::verify_totp(&secret, code, clock::now().timestamp(), last_step) else {
+        return error_response(400, "Invalid code");
+    };
+    
+    let recovery_codes = auth::generate_recovery_codes(RECOVERY_CODE_COUNT);
+    let hashes: Vec<String> = recovery_codes.iter().map(|c| auth::hash_recovery_code(c)).collect();
+    if db::enable_totp(pool, user.id, step, &hashes).await.is_err() {
+        return error_response(500, "Failed to enable two-factor sign-in");
+    }
+    
+    if let Err(e) = db::record_audit(pool, Some(user.id), "two_factor.enable", "user", Some(user.id), &json!({})).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
     }
+    
+    success_response(json!({ "recovery_codes": recovery_codes }))
 }
 
-async fn handle_get_my_books(pool: &SqlitePool, token: Option<&str>) -> String {
+async fn handle_two_factor_disable(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
     let user = match authenticate(pool, token).await {
         Ok(user) => user,
         Err(msg) => return error_response(401, &msg),
     };
     
-    match db::get_user_borrowed_books(pool, user.id).await {
-        Ok(records) => success_response(serde_json::to_value(records).unwrap()),
-        Err(_) => error_response(500, "Failed to fetch borrowed books"),
+    let req: TwoFactorDisableRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if !user.two_factor_enabled {
+        return error_response(409, "Two-factor sign-in is not enabled");
     }
-}
-
-// Admin handlers
-async fn handle_get_all_users(pool: &SqlitePool, token: Option<&str>) -> String {
-    if let Err(msg) = authenticate_admin(pool, token).await {
-        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    
+    // Guesses count against the account exactly as failed logins do
+    let throttle_keys = [throttle::account_key(&user.username)];
+    if let Some(wait) = throttle::retry_after(&throttle_keys) {
+        return too_many_attempts_response(wait);
     }
+    throttle::record_failure(&throttle_keys);
     
-    match db::get_all_users(pool).await {
-        Ok(users) => success_response(serde_json::to_value(users).unwrap()),
-        Err(_) => error_response(500, "Failed to fetch users"),
+    match auth::verify_password(&req.password, &user.password_hash) {
+        Ok(true) => {}
+        Ok(false) => return error_response(401, "Invalid password"),
+        Err(_) => return error_response(500, "Authentication error"),
     }
-}
-
-async fn handle_get_active_lending(pool: &SqlitePool, token: Option<&str>) -> String {
-    if let Err(msg) = authenticate_admin(pool, token).await {
-        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    match check_second_factor(pool, user.id, req.code.as_deref(), req.recovery_code.as_deref()).await {
+        Ok(true) => {}
+        Ok(false) => return error_response(400, "Invalid code"),
+        Err(_) => return error_response(500, "Failed to disable two-factor sign-in"),
     }
+    throttle::clear(&throttle_keys[0]);
     
-    match db::get_all_active_lending(pool).await {
-        Ok(records) => success_response(serde_json::to_value(records).unwrap()),
-        Err(_) => error_response(500, "Failed to fetch lending records"),
+    if db::disable_totp(pool, user.id).await.is_err() {
+        return error_response(500, "Failed to disable two-factor sign-in");
+    }
+    
+    if let Err(e) = db::record_audit(pool, Some(user.id), "two_factor.disable", "user", Some(user.id), &json!({})).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
     }
+    
+    success_response(json!({ "message": "Two-factor sign-in disabled" }))
 }
 
-async fn handle_get_overdue_books(pool: &SqlitePool, token: Option<&str>) -> String {
-    if let Err(msg) = authenticate_admin(pool, token).await {
-        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
-    }
+// Second step of a two-factor login: trades the challenge from /api/auth/login and a code for a session
+async fn handle_two_factor_verify(pool: &SqlitePool, body: &str, client_ip: Option<&str>, user_agent: Option<&str>) -> String {
+    let req: TwoFactorVerifyRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
     
-    match db::get_overdue_books(pool).await {
-        Ok(records) => success_response(serde_json::to_value(records).unwrap()),
-        Err(_) => error_response(500, "Failed to fetch overdue books"),
+    let user_id = match db::get_login_challenge_user(pool, &req.challenge).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return error_response(401, "Sign-in expired; log in again"),
+        Err(_) => return error_response(500, "Failed to verify code"),
+    };
+    let user = match db::get_user_by_id(pool, user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return error_response(401, "Invalid credentials"),
+        Err(_) => return error_response(500, "Failed to verify code"),
+    };
+    
+    // Wrong codes also count against the account, so they slow down its logins as well
+    let throttle_keys = [throttle::account_key(&user.username)];
+    if let Some(wait) = throttle::retry_after(&throttle_keys) {
+        return too_many_attempts_response(wait);
+    }
+    match check_second_factor(pool, user_id, req.code.as_deref(), req.recovery_code.as_deref()).await {
+        Ok(true) => {}
+        Ok(false) => {
+            throttle::record_failure(&throttle_keys);
+            metrics::increment(metrics::Counter::FailedLogins);
+            if let Err(e) = db::fail_login_challenge(pool, &req.challenge, LOGIN_CHALLENGE_ATTEMPTS).await {
+                eprintln!("Failed to record failed two-factor attempt: {:?}", e);
+            }
+            return error_response(401, "Invalid code");
+        }
+        Err(_) => return error_response(500, "Failed to verify code"),
+    }
+    
+    if db::delete_login_challenge(pool, &req.challenge).await.is_err() {
+        return error_response(500, "Failed to verify code");
+    }
+    
+    if !user.is_active {
+        return error_response(403, "Account is deactivated");
+    }
+    
+    start_session(pool, user, client_ip, user_agent).await
+}
+
+// OAuth handlers
+const OAUTH_STATE_MINUTES: i64 = 10;
+
+async fn handle_oauth_start(pool: &SqlitePool, provider: &str) -> String {
+    let Some(provider) = oauth::provider(provider) else {
+        return error_response(404, "Sign-in provider is not available");
+    };
+    
+    let state = auth::generate_token();
+    if db::create_oauth_state(pool, &state, provider.name, OAUTH_STATE_MINUTES).await.is_err() {
+        return error_response(500, "Failed to start sign-in");
+    }
+    
+    redirect_response(&oauth::authorization_url(provider, &state))
+}
+
+// The browser lands here from the provider, so every outcome goes back to the frontend
+// in the URL fragment rather than as a JSON body
+async fn handle_oauth_callback(pool: &SqlitePool, provider: &str, code: &str, state: &str, client_ip: Option<&str>, user_agent: Option<&str>) -> String {
+    match complete_oauth_sign_in(pool, provider, code, state, client_ip, user_agent).await {
+        Ok(OAuthSignIn::Session(token)) => with_session_cookies(redirect_response("/#signed_in"), &token, config::get().session_max_hours * 3600),
+        Ok(OAuthSignIn::Challenge(challenge)) => redirect_response(&format!("/#challenge={}", challenge)),
+        Err(msg) => redirect_response(&format!("/#oauth_error={}", urlencoding::encode(&msg))),
+    }
+}
+
+enum OAuthSignIn {
+    Session(String),
+    Challenge(String),
+}
+
+async fn complete_oauth_sign_in(pool: &SqlitePool, provider: &str, code: &str, state: &str, client_ip: Option<&str>, user_agent: Option<&str>) -> Result<OAuthSignIn, String> {
+    let provider = oauth::provider(provider).ok_or("Sign-in provider is not available")?;
+    
+    match db::take_oauth_state(pool, state, provider.name).await {
+        Ok(true) => {}
+        Ok(false) => return Err("Sign-in request expired, please try again".to_string()),
+        Err(_) => return Err("Failed to complete sign-in".to_string()),
+    }
+    if code.is_empty() {
+        return Err("Sign-in was cancelled".to_string());
+    }
+    
+    let code = code.to_string();
+    let identity = tokio::task::spawn_blocking(move || oauth::identify(provider, &code))
+        .await
+        .map_err(|_| "Failed to complete sign-in".to_string())?
+        .map_err(|e| {
+            eprintln!("OAuth sign-in with {} failed: {}", provider.name, e);
+            "The sign-in provider could not confirm who you are".to_string()
+        })?;
+    
+    let user = oauth_user(pool, provider.name, &identity).await?;
+    if !user.is_active {
+        return Err("Account is deactivated".to_string());
+    }
+    
+    if user.two_factor_enabled {
+        let challenge = auth::generate_token();
+        if db::create_login_challenge(pool, user.id, &challenge, LOGIN_CHALLENGE_MINUTES).await.is_err() {
+            return Err("Failed to start two-factor sign-in".to_string());
+        }
+        return Ok(OAuthSignIn::Challenge(challenge));
+    }
+    
+    let token = open_session(pool, &user, client_ip, user_agent).await.map_err(|_| "Failed to create session".to_string())?;
+    Ok(OAuthSignIn::Session(token))
+}
+
+// Finds the local account for a provider identity: one linked earlier, else the account with
+// the same verified email (which is then linked), else a new lender account
+async fn oauth_user(pool: &SqlitePool, provider: &str, identity: &oauth::Identity) -> Result<User, String> {
+    let failed = |_| "Failed to complete sign-in".to_string();
+    
+    if let Some(user) = db::get_user_by_oauth_identity(pool, provider, &identity.subject).await.map_err(failed)? {
+        return Ok(user);
+    }
+    
+    let email = identity.email.as_deref().ok_or("Your account with the sign-in provider has no verified email address")?;
+    
+    let user = match db::get_user_by_email(pool, email).await.map_err(failed)? {
+        Some(user) if !user.email_verified => {
+            return Err("An account with this email exists but its email is not verified; sign in with your password instead".to_string());
+        }
+        Some(user) => user,
+        None if config::get().invite_only => {
+            return Err("Registration is by invitation only; register with your invite code first".to_string());
+        }
+        None => {
+            let user_id = provision_oauth_user(pool, email, identity.name.as_deref()).await.map_err(failed)?;
+            db::get_user_by_id(pool, user_id).await.map_err(failed)?.ok_or("Failed to complete sign-in")?
+        }
+    };
+    
+    db::link_oauth_identity(pool, user.id, provider, &identity.subject, email).await.map_err(failed)?;
+    Ok(user)
+}
+
+// New accounts get a username from the email's local part and a random password nobody
+// knows, so they can only sign in through the provider
+async fn provision_oauth_user(pool: &SqlitePool, email: &str, name: Option<&str>) -> Result<i64, sqlx::Error> {
+    let base: String = email.split('@').next().unwrap_or("")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .collect();
+    let base = if base.is_empty() { "member".to_string() } else { base };
+    
+    let mut username = base.clone();
+    let mut suffix = 1;
+    while db::get_user_by_username(pool, &username).await?.is_some() {
+        suffix += 1;
+        username = format!("{}{}", base, suffix);
+    }
+    
+    let password_hash = auth::hash_password(&auth::generate_token()).map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+    let approval_status = if config::get().registration_approval { "pending" } else { "approved" };
+    let user_id = db::create_user(pool, &username, email, &password_hash, "lender", approval_status).await?;
+    db::update_profile(pool, user_id, email, true, name).await?;
+    Ok(user_id)
+}
+
+async fn handle_logout(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Some(token) = token {
+        let _ = db::delete_session(pool, token).await;
+    }
+    clear_session_cookies(success_response(json!({ "message": "Logged out successfully" })))
+}
+
+async fn handle_get_current_user(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let pending_email = db::get_pending_email_change(pool, user.id).await.ok().flatten();
+    let mut data = serde_json::to_value(user).unwrap();
+    data["pending_email"] = json!(pending_email);
+    if let Some((id, username)) = db::get_session_impersonator(pool, token.unwrap_or_default()).await.ok().flatten() {
+        data["impersonated_by"] = json!({ "id": id, "username": username });
+    }
+    success_response(data)
+}
+
+async fn handle_update_profile(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let req: UpdateProfileRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let email = req.email.as_deref().map(str::trim).unwrap_or(&user.email).to_string();
+    if email.is_empty() || !email.contains('@') {
+        return error_response(400, "Invalid email address");
+    }
+    
+    // An empty display name clears it
+    let display_name = match req.display_name.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(name) if name.chars().count() > 100 => return error_response(400, "Display name is too long"),
+        Some(name) => Some(name.to_string()),
+        None => user.display_name.clone(),
+    };
+    
+    let email_changed = email != user.email;
+    if email_changed {
+        match db::get_user_by_email(pool, &email).await {
+            Ok(Some(_)) => return error_response(409, "Email already in use"),
+            Ok(None) => {}
+            Err(_) => return error_response(500, "Failed to update profile"),
+        }
+    }
+    
+    // The current address stays in use until the new one is confirmed from its own inbox
+    if db::update_profile(pool, user.id, &user.email, user.email_verified, display_name.as_deref()).await.is_err() {
+        return error_response(500, "Failed to update profile");
+    }
+    
+    let details = json!({
+        "display_name": { "from": user.display_name, "to": display_name },
+        "email_change_requested": if email_changed { Some(&email) } else { None },
+    });
+    if let Err(e) = db::record_audit(pool, Some(user.id), "profile.update", "user", Some(user.id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    if email_changed {
+        if let Err(e) = send_email_change_confirmation(pool, &user, &email).await {
+            eprintln!("Failed to send email change confirmation: {:?}", e);
+            return error_response(500, "Failed to request email change");
+        }
+    }
+    
+    let updated = match db::get_user_by_id(pool, user.id).await {
+        Ok(Some(updated)) => updated,
+        _ => return error_response(500, "Failed to retrieve user"),
+    };
+    
+    let pending_email = db::get_pending_email_change(pool, user.id).await.ok().flatten();
+    let mut data = serde_json::to_value(updated).unwrap();
+    data["pending_email"] = json!(pending_email);
+    success_response(data)
+}
+
+async fn handle_export_my_data(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match privacy::export_user_data(pool, &user).await {
+        Ok(archive) => success_response(archive),
+        Err(_) => error_response(500, "Failed to export user data"),
+    }
+}
+
+async fn check_api_key_scope(pool: &SqlitePool, key: &str, method: &str, path: &str) -> Result<(), String> {
+    let granted = match db::get_api_key_scopes(pool, &auth::hash_api_key(key)).await {
+        Ok(Some(granted)) => granted,
+        Ok(None) => return Err(error_response(401, "Invalid or revoked API key")),
+        Err(_) => return Err(error_response(500, "Failed to check API key")),
+    };
+    
+    match scopes::required(method, path) {
+        Some(scope) if granted.iter().any(|g| g == scope) => Ok(()),
+        Some(scope) => Err(error_response(403, &format!("API key lacks the {} scope", scope))),
+        None => Err(error_response(403, "This endpoint requires a login session")),
+    }
+}
+
+// API key handlers
+async fn handle_get_api_keys(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_api_keys(pool, user.id).await {
+        Ok(keys) => success_response(serde_json::to_value(keys).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch API keys"),
+    }
+}
+
+async fn handle_create_api_key(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let req: CreateApiKeyRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let name = req.name.trim();
+    if name.is_empty() {
+        return error_response(400, "Name is required");
+    }
+    if req.scopes.is_empty() {
+        return error_response(400, "At least one scope is required");
+    }
+    if let Some(unknown) = req.scopes.iter().find(|s| !scopes::is_scope(s)) {
+        return error_response(400, &format!("Unknown scope: {}", unknown));
+    }
+    let mut granted = req.scopes.clone();
+    granted.sort();
+    granted.dedup();
+    
+    let key = auth::generate_api_key();
+    let prefix = &key[..12];
+    let id = match db::create_api_key(pool, user.id, name, &auth::hash_api_key(&key), prefix, &granted).await {
+        Ok(id) => id,
+        Err(_) => return error_response(500, "Failed to create API key"),
+    };
+    
+    match db::get_api_key(pool, user.id, id).await {
+        Ok(Some(created)) => {
+            let mut body = serde_json::to_value(created).unwrap();
+            body["key"] = json!(key);
+            created_response(body)
+        }
+        _ => error_response(500, "Failed to retrieve API key"),
+    }
+}
+
+async fn handle_revoke_api_key(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::revoke_api_key(pool, user.id, id).await {
+        Ok(true) => success_response(json!({ "message": "API key revoked" })),
+        Ok(false) => error_response(404, "API key not found"),
+        Err(_) => error_response(500, "Failed to revoke API key"),
+    }
+}
+
+async fn handle_delete_my_account(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    if permissions::has_permission(&user.role, Permission::ManageMembers) {
+        return error_response(400, "Admin accounts must be removed by another admin");
+    }
+    
+    erase_account(pool, user.id, user.id).await
+}
+
+async fn erase_account(pool: &SqlitePool, actor_id: i64, user_id: i64) -> String {
+    match privacy::erasure_blocker(pool, user_id).await {
+        Ok(Some(reason)) => return error_response(409, &reason),
+        Ok(None) => {}
+        Err(_) => return error_response(500, "Failed to delete account"),
+    }
+    
+    if privacy::erase_user(pool, user_id).await.is_err() {
+        return error_response(404, "User not found");
+    }
+    
+    if let Err(e) = db::record_audit(pool, Some(actor_id), "user.erase", "user", Some(user_id), &json!({})).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    success_response(json!({ "message": "Account deleted successfully" }))
+}
+
+async fn handle_verify_email(pool: &SqlitePool, verify_token: &str) -> String {
+    match db::confirm_email_verification(pool, verify_token).await {
+        Ok(_) => success_response(json!({ "message": "Email verified successfully" })),
+        Err(_) => error_response(400, "Invalid or expired verification link"),
+    }
+}
+
+async fn handle_confirm_email_change(pool: &SqlitePool, change_token: &str) -> String {
+    let (user_id, old_email, new_email) = match db::confirm_email_change(pool, change_token).await {
+        Ok(Some(change)) => change,
+        Ok(None) => return error_response(400, "Invalid or expired confirmation link"),
+        // Someone else registered the address while the link was waiting
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => return error_response(409, "Email already in use"),
+        Err(_) => return error_response(500, "Failed to change email"),
+    };
+    
+    let details = json!({ "email": { "from": old_email, "to": new_email } });
+    if let Err(e) = db::record_audit(pool, Some(user_id), "profile.email_change", "user", Some(user_id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    // Tell the old address, so a change the owner didn't make doesn't go unnoticed
+    let body = format!(
+        "Hello,\n\nThe email address on your library account has been changed to {}. If you did not make this change, contact the library.\n",
+        new_email
+    );
+    if let Err(e) = notify::send_email(pool, Some(user_id), &old_email, "Your email address was changed", &body).await {
+        eprintln!("Failed to notify previous email address: {:?}", e);
+    }
+    
+    success_response(json!({ "message": "Email address changed", "email": new_email }))
+}
+
+async fn send_email_change_confirmation(pool: &SqlitePool, user: &User, new_email: &str) -> Result<(), sqlx::Error> {
+    let change_token = auth::generate_token();
+    db::create_email_change(pool, user.id, new_email, &change_token).await?;
+    
+    let link = format!("{}/api/auth/confirm-email-change?token={}", config::get().public_url, change_token);
+    let body = format!(
+        "Hello {},\n\nPlease confirm the new email address for your library account by opening this link within 48 hours:\n{}\n\nUntil then, {} remains the address on your account.\n",
+        user.username, link, user.email
+    );
+    notify::send_email(pool, Some(user.id), new_email, "Confirm your new email address", &body).await
+}
+
+async fn send_email_verification(pool: &SqlitePool, user: &User) -> Result<(), sqlx::Error> {
+    let verify_token = auth::generate_token();
+    db::create_email_verification(pool, user.id, &user.email, &verify_token).await?;
+    
+    let link = format!("{}/api/auth/verify-email?token={}", config::get().public_url, verify_token);
+    let body = format!(
+        "Hello {},\n\nPlease confirm your email address by opening this link within 48 hours:\n{}\n",
+        user.username, link
+    );
+    notify::send_email(pool, Some(user.id), &user.email, "Confirm your email address", &body).await
+}
+
+// Book handlers
+async fn handle_get_books(pool: &SqlitePool, format: Option<&str>) -> String {
+    match db::get_all_books(pool).await {
+        Ok(books) => listing_response(&books, format, "books", BOOK_CSV_COLUMNS),
+        Err(_) => error_response(500, "Failed to fetch books"),
+    }
+}
+
+async fn handle_get_book(pool: &SqlitePool, id: i64) -> String {
+    match db::get_book_by_id(pool, id).await {
+        Ok(Some(book)) => success_response(serde_json::to_value(book).unwrap()),
+        Ok(None) => error_response(404, "Book not found"),
+        Err(_) => error_response(500, "Failed to fetch book"),
+    }
+}
+
+// Books, or only the copies held at one branch, optionally limited to one item type
+async fn handle_get_filtered_books(pool: &SqlitePool, branch_id: Option<i64>, item_type: Option<&str>, format: Option<&str>) -> String {
+    let books = match branch_id {
+        Some(branch_id) => match db::get_branch_by_id(pool, branch_id).await {
+            Ok(Some(_)) => db::get_books_at_branch(pool, branch_id).await,
+            Ok(None) => return error_response(404, "Branch not found"),
+            Err(_) => return error_response(500, "Failed to fetch books"),
+        },
+        None => db::get_all_books(pool).await,
+    };
+    
+    match books {
+        Ok(mut books) => {
+            if let Some(item_type) = item_type {
+                books.retain(|b| b.item_type == item_type);
+            }
+            listing_response(&books, format, "books", BOOK_CSV_COLUMNS)
+        }
+        Err(_) => error_response(500, "Failed to fetch books"),
+    }
+}
+
+// Issue numbers only describe periodicals and platforms only describe media
+async fn check_item_fields(pool: &SqlitePool, item_type: &str, issue_number: Option<&str>, platform: Option<&str>) -> Result<(), String> {
+    match db::get_item_type(pool, item_type).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(format!("Unknown item type: {}", item_type)),
+        Err(_) => return Err("Failed to check item type".to_string()),
+    }
+    if issue_number.is_some_and(|v| !v.is_empty()) && item_type != "periodical" {
+        return Err("issue_number applies to periodicals only".to_string());
+    }
+    if platform.is_some_and(|v| !v.is_empty()) && item_type != "media" {
+        return Err("platform applies to media only".to_string());
+    }
+    Ok(())
+}
+
+async fn handle_create_book(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageCatalog).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let mut req: CreateBookRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if req.title.is_empty() || req.author.is_empty() || req.isbn.is_empty() || req.total_copies < 0 {
+        return error_response(400, "Invalid book data");
+    }
+    
+    req.item_type = req.item_type.map(|t| t.trim().to_lowercase());
+    req.issue_number = req.issue_number.filter(|v| !v.trim().is_empty());
+    req.platform = req.platform.filter(|v| !v.trim().is_empty());
+    let item_type = req.item_type.as_deref().unwrap_or("book");
+    if let Err(msg) = check_item_fields(pool, item_type, req.issue_number.as_deref(), req.platform.as_deref()).await {
+        return error_response(400, &msg);
+    }
+    
+    match db::create_book(pool, &req).await {
+        Ok(book_id) => {
+            let book = db::get_book_by_id(pool, book_id).await.ok().flatten();
+            if let Some(book) = book {
+                publish_event(pool, "book.created", json!({ "book": book })).await;
+                notify_author_followers(pool, &book).await;
+                created_response(serde_json::to_value(book).unwrap())
+            } else {
+                error_response(500, "Failed to retrieve book")
+            }
+        }
+        Err(_) => error_response(409, "ISBN already exists"),
+    }
+}
+
+// Bulk-creates books from a MARC21 or MARCXML file, reporting the outcome of every record
+async fn handle_import_marc(pool: &SqlitePool, token: Option<&str>, body: &str, copies: i32) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    if copies < 1 {
+        return error_response(400, "Copies must be at least 1");
+    }
+    
+    let records = marc::parse(body);
+    if records.is_empty() {
+        return error_response(400, "No MARC records found");
+    }
+    
+    let books = records
+        .into_iter()
+        .map(|record| record.map(|book| (
+            CreateBookRequest {
+                title: book.title,
+                author: book.author,
+                isbn: book.isbn,
+                publication_year: book.publication_year,
+                genre: book.genre,
+                total_copies: copies,
+                item_type: None,
+                issue_number: None,
+                platform: None,
+            },
+            Vec::new(),
+        )))
+        .collect();
+    
+    import_books(pool, admin.id, "marc", books).await
+}
+
+// Loads a Goodreads or LibraryThing CSV export; shelves become book tags
+async fn handle_import_goodreads(pool: &SqlitePool, token: Option<&str>, body: &str, copies: i32) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    if copies < 1 {
+        return error_response(400, "Copies must be at least 1");
+    }
+    
+    let rows = match goodreads::parse(body) {
+        Ok(rows) => rows,
+        Err(msg) => return error_response(400, &msg),
+    };
+    
+    let books = rows
+        .into_iter()
+        .map(|row| row.map(|book| (
+            CreateBookRequest {
+                title: book.title,
+                author: book.author,
+                isbn: book.isbn,
+                publication_year: book.publication_year,
+                genre: None,
+                total_copies: copies,
+                item_type: None,
+                issue_number: None,
+                platform: None,
+            },
+            book.tags,
+        )))
+        .collect();
+    
+    import_books(pool, admin.id, "goodreads", books).await
+}
+
+// Creates each parsed book unless its ISBN is already catalogued (tags are still merged
+// into the existing book) and reports the outcome of every record. The books go in with one
+// transaction; followers of their authors are emailed after the response has gone
+async fn import_books(pool: &SqlitePool, admin_id: i64, format: &str, books: Vec<Result<(CreateBookRequest, Vec<String>), String>>) -> String {
+    let mut report = Vec::new();
+    let (mut created, mut skipped, mut failed) = (0, 0, 0);
+    
+    let mut parsed = Vec::new();
+    for (index, book) in books.into_iter().enumerate() {
+        match book {
+            Ok(book) => parsed.push((index, book)),
+            Err(message) => report.push((index, json!({ "index": index, "status": "failed", "message": message }))),
+        }
+    }
+    failed += report.len();
+    
+    let (indexes, parsed): (Vec<usize>, Vec<(CreateBookRequest, Vec<String>)>) = parsed.into_iter().unzip();
+    let outcomes = match db::import_books(pool, &parsed).await {
+        Ok(outcomes) => outcomes,
+        Err(_) => return error_response(500, "Failed to import books"),
+    };
+    
+    let mut created_ids = Vec::new();
+    for ((index, (req, _)), outcome) in indexes.into_iter().zip(&parsed).zip(outcomes) {
+        let entry = match outcome {
+            ImportOutcome::Created(book_id) => {
+                created += 1;
+                created_ids.push(book_id);
+                json!({ "index": index, "status": "created", "title": req.title, "isbn": req.isbn, "book_id": book_id })
+            }
+            ImportOutcome::Skipped(book_id) => {
+                skipped += 1;
+                json!({
+                    "index": index, "status": "skipped", "title": req.title, "isbn": req.isbn,
+                    "book_id": book_id, "message": "ISBN already exists",
+                })
+            }
+            ImportOutcome::Failed(message) => {
+                failed += 1;
+                json!({ "index": index, "status": "failed", "title": req.title, "isbn": req.isbn, "message": message })
+            }
+        };
+        report.push((index, entry));
+    }
+    report.sort_by_key(|(index, _)| *index);
+    let report: Vec<serde_json::Value> = report.into_iter().map(|(_, entry)| entry).collect();
+    
+    let details = json!({ "format": format, "created": created, "skipped": skipped, "failed": failed });
+    if let Err(e) = db::record_audit(pool, Some(admin_id), "books.import", "book", None, &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let background = pool.clone();
+    tokio::spawn(async move {
+        for book_id in created_ids {
+            if let Ok(Some(book)) = db::get_book_by_id(&background, book_id).await {
+                notify_author_followers(&background, &book).await;
+            }
+        }
+    });
+    
+    success_response(json!({ "created": created, "skipped": skipped, "failed": failed, "records": report }))
+}
+
+// Tells members following the book's author that it has joined the catalog
+async fn notify_author_followers(pool: &SqlitePool, book: &Book) {
+    let followers = match db::get_author_followers(pool, &book.author).await {
+        Ok(followers) => followers,
+        Err(e) => return eprintln!("Failed to fetch followers of {}: {:?}", book.author, e),
+    };
+    
+    for user_id in followers {
+        let Ok(Some(user)) = db::get_user_by_id(pool, user_id).await else { continue };
+        if let Err(e) = notify::send_new_book_by_author(pool, &user, book).await {
+            eprintln!("Failed to send new book notification: {:?}", e);
+        }
+    }
+}
+
+// Longest loan period an item, item type or member type may set, matching the loan_period_days setting
+const MAX_LOAN_PERIOD_DAYS: i64 = 3650;
+
+async fn handle_update_book(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageCatalog).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let mut req: UpdateBookRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let book = match db::get_book_by_id(pool, id).await {
+        Ok(Some(book)) => book,
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    };
+    
+    // 0 clears the item's own loan period
+    if req.loan_period_days.is_some_and(|days| days != 0 && !(1..=MAX_LOAN_PERIOD_DAYS).contains(&days)) {
+        return error_response(400, &format!("Loan period must be between 1 and {} days, or 0 to clear it", MAX_LOAN_PERIOD_DAYS));
+    }
+    
+    if req.series_position.is_some_and(|position| position < 1) {
+        return error_response(400, "Series position must be at least 1");
+    }
+    if let Some(series_id) = req.series_id.filter(|id| *id > 0) {
+        match db::get_series_by_id(pool, series_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return error_response(400, "Unknown series"),
+            Err(_) => return error_response(500, "Failed to fetch series"),
+        }
+    }
+    
+    req.item_type = req.item_type.map(|t| t.trim().to_lowercase());
+    let item_type = req.item_type.as_deref().unwrap_or(&book.item_type);
+    if let Err(msg) = check_item_fields(pool, item_type, req.issue_number.as_deref(), req.platform.as_deref()).await {
+        return error_response(400, &msg);
+    }
+    
+    // Changes to total_copies land on the main branch, so it must hold the copies being removed
+    if let Some(total) = req.total_copies {
+        let out = book.total_copies - book.available_copies;
+        if total < out {
+            return error_response(409, &format!("{} copies are on loan, held or in transit", out));
+        }
+        if total < book.total_copies {
+            let at_main = match db::get_book_holdings(pool, id).await {
+                Ok(holdings) => holdings.iter().find(|h| h.branch_id == db::MAIN_BRANCH_ID).map(|h| h.copies).unwrap_or(0),
+                Err(_) => return error_response(500, "Failed to check branch copies"),
+            };
+            if at_main < (book.total_copies - total) as i64 {
+                return error_response(409, "Not enough copies at the main branch; remove copies from the branch that holds them");
+            }
+        }
+    }
+    
+    match db::update_book(pool, id, &req).await {
+        Ok(false) => match db::get_book_by_id(pool, id).await {
+            Ok(Some(current)) => conflict_response("Book was changed by someone else; review the current record and try again", serde_json::to_value(current).unwrap()),
+            _ => error_response(404, "Book not found"),
+        },
+        Ok(true) => {
+            release_to_queue(pool, id).await;
+            let book = db::get_book_by_id(pool, id).await.ok().flatten();
+            if let Some(book) = book {
+                publish_event(pool, "book.updated", json!({ "book": book })).await;
+                success_response(serde_json::to_value(book).unwrap())
+            } else {
+                error_response(500, "Failed to retrieve updated book")
+            }
+        }
+        Err(_) => error_response(404, "Book not found"),
+    }
+}
+
+async fn handle_delete_book(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageCatalog).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::delete_book(pool, id).await {
+        Ok(_) => {
+            publish_event(pool, "book.deleted", json!({ "book_id": id })).await;
+            success_response(json!({ "message": "Book deleted successfully" }))
+        }
+        Err(_) => error_response(404, "Book not found"),
+    }
+}
+
+async fn handle_search_books(pool: &SqlitePool, query: &str, item_type: Option<&str>, with_facets: bool) -> String {
+    if query.chars().count() > search::MAX_QUERY_CHARS {
+        return error_response(400, "Search query is too long");
+    }
+    
+    match search::search(pool, query, item_type).await {
+        Ok(results) if with_facets => {
+            let facets = search::facets(&results);
+            success_response(json!({ "results": results, "facets": facets }))
+        }
+        Ok(results) => success_response(serde_json::to_value(results).unwrap()),
+        Err(_) => error_response(500, "Failed to search books"),
+    }
+}
+
+// Completions for the search box, called on every keystroke
+async fn handle_suggest_completions(pool: &SqlitePool, prefix: &str) -> String {
+    let prefix = prefix.trim();
+    if prefix.is_empty() {
+        return success_response(json!([]));
+    }
+    
+    match db::suggest_completions(pool, prefix, 10).await {
+        Ok(suggestions) => success_response(serde_json::to_value(suggestions).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch suggestions"),
+    }
+}
+
+// Periodical handlers
+async fn handle_get_issues(pool: &SqlitePool, title_id: i64) -> String {
+    match db::get_book_by_id(pool, title_id).await {
+        Ok(Some(book)) if book.item_type == "periodical" => {}
+        Ok(_) => return error_response(404, "Periodical not found"),
+        Err(_) => return error_response(500, "Failed to fetch periodical"),
+    }
+    
+    match db::get_issues(pool, title_id).await {
+        Ok(issues) => success_response(serde_json::to_value(issues).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch issues"),
+    }
+}
+
+async fn handle_create_issue(pool: &SqlitePool, token: Option<&str>, title_id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: CreateIssueRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    req.number = req.number.trim().to_string();
+    req.volume = req.volume.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    if req.number.is_empty() {
+        return error_response(400, "Issue number is required");
+    }
+    let Ok(issue_date) = chrono::NaiveDate::parse_from_str(req.issue_date.trim(), "%Y-%m-%d") else {
+        return error_response(400, "Issue date must be YYYY-MM-DD");
+    };
+    req.issue_date = issue_date.format("%Y-%m-%d").to_string();
+    
+    // Issues hang off the title record, never off another issue
+    let title = match db::get_book_by_id(pool, title_id).await {
+        Ok(Some(book)) if book.item_type == "periodical" => book,
+        Ok(_) => return error_response(404, "Periodical not found"),
+        Err(_) => return error_response(500, "Failed to fetch periodical"),
+    };
+    match db::get_issue(pool, title_id).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return error_response(400, "That item is an issue, not a periodical title"),
+        Err(_) => return error_response(500, "Failed to fetch periodical"),
+    }
+    
+    match db::create_issue(pool, &title, &req).await {
+        Ok(id) => {
+            let details = json!({ "title_id": title_id, "volume": req.volume, "number": req.number });
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "issue.create", "book", Some(id), &details).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            match db::get_issue(pool, id).await {
+                Ok(Some(issue)) => {
+                    publish_event(pool, "book.created", json!({ "book": issue })).await;
+                    created_response(serde_json::to_value(issue).unwrap())
+                }
+                _ => error_response(500, "Failed to retrieve issue"),
+            }
+        }
+        Err(_) => error_response(409, "That issue is already catalogued"),
+    }
+}
+
+async fn handle_receive_issue(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    // The body is optional; an empty body receives one copy at the main branch
+    let req: ReceiveIssueRequest = if body.trim().is_empty() {
+        ReceiveIssueRequest { copies: None, branch_id: None }
+    } else {
+        match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(_) => return error_response(400, "Invalid request body"),
+        }
+    };
+    
+    let copies = req.copies.unwrap_or(1);
+    if copies < 1 {
+        return error_response(400, "Copies must be at least 1");
+    }
+    let branch_id = req.branch_id.unwrap_or(db::MAIN_BRANCH_ID);
+    match db::get_branch_by_id(pool, branch_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(400, "Unknown branch"),
+        Err(_) => return error_response(500, "Failed to receive issue"),
+    }
+    
+    match db::get_issue(pool, id).await {
+        Ok(Some(issue)) if issue.received_at.is_none() => {}
+        Ok(Some(_)) => return error_response(409, "Issue has already been received"),
+        Ok(None) => return error_response(404, "Issue not found"),
+        Err(_) => return error_response(500, "Failed to fetch issue"),
+    }
+    
+    match db::receive_issue(pool, id, copies, branch_id).await {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => return error_response(409, "Issue has already been received"),
+        Err(_) => return error_response(500, "Failed to receive issue"),
+    }
+    
+    let details = json!({ "copies": copies, "branch_id": branch_id });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "issue.receive", "book", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    release_to_queue(pool, id).await;
+    
+    match db::get_issue(pool, id).await {
+        Ok(Some(issue)) => success_response(serde_json::to_value(issue).unwrap()),
+        _ => error_response(500, "Failed to retrieve issue"),
+    }
+}
+
+// Series handlers
+async fn handle_get_all_series(pool: &SqlitePool) -> String {
+    match db::get_all_series(pool).await {
+        Ok(series) => success_response(serde_json::to_value(series).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch series"),
+    }
+}
+
+async fn handle_get_series(pool: &SqlitePool, id: i64) -> String {
+    let series = match db::get_series_by_id(pool, id).await {
+        Ok(Some(series)) => series,
+        Ok(None) => return error_response(404, "Series not found"),
+        Err(_) => return error_response(500, "Failed to fetch series"),
+    };
+    
+    match db::get_series_volumes(pool, id).await {
+        Ok(volumes) => success_response(serde_json::to_value(SeriesDetails { series, volumes }).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch series volumes"),
+    }
+}
+
+async fn handle_create_series(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: CreateSeriesRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    req.name = req.name.trim().to_string();
+    req.description = req.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    if req.name.is_empty() {
+        return error_response(400, "Name is required");
+    }
+    
+    match db::create_series(pool, &req).await {
+        Ok(id) => {
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "series.create", "series", Some(id), &json!({ "name": req.name })).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            match db::get_series_by_id(pool, id).await {
+                Ok(Some(series)) => created_response(serde_json::to_value(series).unwrap()),
+                _ => error_response(500, "Failed to retrieve series"),
+            }
+        }
+        Err(_) => error_response(500, "Failed to create series"),
+    }
+}
+
+async fn handle_update_series(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageCatalog).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let req: UpdateSeriesRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let series = match db::get_series_by_id(pool, id).await {
+        Ok(Some(series)) => series,
+        Ok(None) => return error_response(404, "Series not found"),
+        Err(_) => return error_response(500, "Failed to fetch series"),
+    };
+    
+    let name = req.name.map(|n| n.trim().to_string()).unwrap_or(series.name);
+    if name.is_empty() {
+        return error_response(400, "Name is required");
+    }
+    
+    // An empty description clears it
+    let description = match req.description.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(description) => Some(description.to_string()),
+        None => series.description,
+    };
+    
+    match db::update_series(pool, id, &name, description.as_deref()).await {
+        Ok(_) => match db::get_series_by_id(pool, id).await {
+            Ok(Some(series)) => success_response(serde_json::to_value(series).unwrap()),
+            _ => error_response(500, "Failed to retrieve updated series"),
+        },
+        Err(_) => error_response(404, "Series not found"),
+    }
+}
+
+async fn handle_delete_series(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    match db::delete_series(pool, id).await {
+        Ok(_) => {
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "series.delete", "series", Some(id), &json!({})).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            success_response(json!({ "message": "Series deleted successfully" }))
+        }
+        Err(_) => error_response(404, "Series not found"),
+    }
+}
+
+// Reading list handlers
+async fn handle_get_reading_lists(pool: &SqlitePool) -> String {
+    match db::get_reading_lists(pool).await {
+        Ok(lists) => success_response(serde_json::to_value(lists).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch reading lists"),
+    }
+}
+
+// The staff views of reading lists are the public ones, but need a staff sign-in whatever
+// LIBRARY_PUBLIC_CATALOG says, like every other /api/admin/ endpoint
+async fn handle_get_admin_reading_lists(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    handle_get_reading_lists(pool).await
+}
+
+async fn handle_get_admin_reading_list(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    handle_get_reading_list(pool, id).await
+}
+
+async fn handle_get_reading_list(pool: &SqlitePool, id: i64) -> String {
+    let list = match db::get_reading_list_by_id(pool, id).await {
+        Ok(Some(list)) => list,
+        Ok(None) => return error_response(404, "Reading list not found"),
+        Err(_) => return error_response(500, "Failed to fetch reading list"),
+    };
+    
+    match db::get_reading_list_entries(pool, id).await {
+        Ok(entries) => success_response(serde_json::to_value(ReadingListDetails { list, entries }).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch reading list entries"),
+    }
+}
+
+// A list names each book once, and only books in the catalog
+async fn check_reading_list_books(pool: &SqlitePool, book_ids: &[i64]) -> Result<(), String> {
+    for (i, book_id) in book_ids.iter().enumerate() {
+        if book_ids[..i].contains(book_id) {
+            return Err(format!("Book {} appears more than once", book_id));
+        }
+        match db::get_book_by_id(pool, *book_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(format!("Book {} not found", book_id)),
+            Err(_) => return Err("Failed to fetch book".to_string()),
+        }
+    }
+    
+    Ok(())
+}
+
+async fn handle_create_reading_list(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: CreateReadingListRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    req.name = req.name.trim().to_string();
+    req.description = req.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    if req.name.is_empty() {
+        return error_response(400, "Name is required");
+    }
+    if let Err(msg) = check_reading_list_books(pool, &req.book_ids).await {
+        return error_response(400, &msg);
+    }
+    
+    match db::create_reading_list(pool, &req).await {
+        Ok(id) => {
+            let details = json!({ "name": req.name, "book_ids": req.book_ids });
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "list.create", "reading_list", Some(id), &details).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            match db::get_reading_list_by_id(pool, id).await {
+                Ok(Some(list)) => created_response(serde_json::to_value(list).unwrap()),
+                _ => error_response(500, "Failed to retrieve reading list"),
+            }
+        }
+        Err(_) => error_response(500, "Failed to create reading list"),
+    }
+}
+
+async fn handle_update_reading_list(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: UpdateReadingListRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let list = match db::get_reading_list_by_id(pool, id).await {
+        Ok(Some(list)) => list,
+        Ok(None) => return error_response(404, "Reading list not found"),
+        Err(_) => return error_response(500, "Failed to fetch reading list"),
+    };
+    
+    let name = req.name.map(|n| n.trim().to_string()).unwrap_or(list.name);
+    if name.is_empty() {
+        return error_response(400, "Name is required");
+    }
+    
+    // An empty description clears it
+    let description = match req.description.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(description) => Some(description.to_string()),
+        None => list.description,
+    };
+    
+    if let Some(book_ids) = &req.book_ids {
+        if let Err(msg) = check_reading_list_books(pool, book_ids).await {
+            return error_response(400, &msg);
+        }
+    }
+    
+    match db::update_reading_list(pool, id, &name, description.as_deref(), req.book_ids.as_deref()).await {
+        Ok(_) => {
+            let details = json!({ "name": name, "book_ids": req.book_ids });
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "list.update", "reading_list", Some(id), &details).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            match db::get_reading_list_by_id(pool, id).await {
+                Ok(Some(list)) => success_response(serde_json::to_value(list).unwrap()),
+                _ => error_response(500, "Failed to retrieve updated reading list"),
+            }
+        }
+        Err(sqlx::Error::RowNotFound) => error_response(404, "Reading list not found"),
+        Err(_) => error_response(500, "Failed to update reading list"),
+    }
+}
+
+async fn handle_delete_reading_list(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    match db::delete_reading_list(pool, id).await {
+        Ok(_) => {
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "list.delete", "reading_list", Some(id), &json!({})).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            success_response(json!({ "message": "Reading list deleted successfully" }))
+        }
+        Err(_) => error_response(404, "Reading list not found"),
+    }
+}
+
+// Lending handlers
+async fn handle_borrow_book(pool: &SqlitePool, token: Option<&str>, book_id: i64, branch_id: Option<i64>) -> String {
+    let user = match authenticate_member(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let book = match db::get_book_by_id(pool, book_id).await {
+        Ok(Some(book)) => book,
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    };
+    if !book.loanable {
+        return error_response(409, "This item is for in-library use only");
+    }
+    
+    if let Some(expires_at) = &user.membership_expires_at {
+        if expires_at.as_str() < clock::now().to_rfc3339().as_str() {
+            return error_response(403, &format!("Membership expired on {}; please renew to borrow", &expires_at[..10]));
+        }
+    }
+    
+    let suspension = match db::apply_automatic_suspension(pool, user.id, settings::suspension_overdue_threshold(), settings::suspension_fine_threshold()).await {
+        Ok(_) => db::get_user_by_id(pool, user.id).await.ok().flatten().and_then(|u| u.suspension_reason),
+        Err(_) => return error_response(500, "Failed to check borrowing privileges"),
+    };
+    if let Some(reason) = suspension {
+        return error_response(403, &format!("Borrowing suspended: {}", reason));
+    }
+    
+    let policy = match settings::loan_policy(pool, &user.member_type).await {
+        Ok(policy) => policy,
+        Err(_) => return error_response(500, "Failed to load loan policy"),
+    };
+    
+    match db::count_active_loans(pool, user.id).await {
+        Ok(count) if count >= policy.max_concurrent_loans => {
+            return error_response(409, &format!("Maximum of {} concurrent loans reached", policy.max_concurrent_loans));
+        }
+        Ok(_) => {}
+        Err(_) => return error_response(500, "Failed to check current loans"),
+    }
+    
+    // Without an explicit branch, prefer the member's home branch, then whichever has the most copies free
+    let chosen = match branch_id {
+        Some(id) => id,
+        None => match db::get_book_holdings(pool, book_id).await {
+            Ok(holdings) => holdings
+                .iter()
+                .filter(|h| h.available_copies > 0)
+                .max_by_key(|h| (Some(h.branch_id) == user.home_branch_id, h.available_copies))
+                .map(|h| h.branch_id)
+                .unwrap_or(db::MAIN_BRANCH_ID),
+            Err(_) => return error_response(500, "Failed to check availability"),
+        },
+    };
+    
+    // An item's own loan period wins, then its type's (a week for DVDs, say), then the member's
+    let loan_period_days = match book.loan_period_days {
+        Some(days) => days,
+        None => match db::get_item_type(pool, &book.item_type).await {
+            Ok(item_type) => item_type.and_then(|t| t.loan_period_days).unwrap_or(policy.loan_period_days),
+            Err(_) => return error_response(500, "Failed to load loan policy"),
+        },
+    };
+    
+    match db::borrow_book(pool, user.id, book_id, chosen, loan_period_days).await {
+        Ok(record_id) => {
+            metrics::increment(metrics::Counter::Borrows);
+            created_response(json!({ "message": "Book borrowed successfully", "record_id": record_id, "branch_id": chosen }))
+        }
+        Err(_) if branch_id.is_some() => error_response(409, "Book not available at this branch"),
+        Err(_) => error_response(409, "Book not available"),
+    }
+}
+
+async fn handle_return_book(pool: &SqlitePool, token: Option<&str>, record_id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let policy = match settings::loan_policy(pool, &user.member_type).await {
+        Ok(policy) => policy,
+        Err(_) => return error_response(500, "Failed to load loan policy"),
+    };
+    
+    match db::return_book(pool, record_id, user.id, policy.fine_per_day, settings::max_fine_per_item()).await {
+        Ok(outcome) => {
+            metrics::increment(metrics::Counter::Returns);
+            release_to_queue(pool, outcome.book_id).await;
+            success_response(json!({ "message": "Book returned successfully", "fine": outcome.fine }))
+        }
+        Err(_) => error_response(404, "Lending record not found or already returned"),
+    }
+}
+
+// Printable slip for a loan; members can print their own, circulation staff anyone's
+async fn handle_get_receipt(pool: &SqlitePool, token: Option<&str>, id: i64, format: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let record = match db::get_lending_record_by_id(pool, id).await {
+        Ok(Some(record)) if record.user_id == user.id || permissions::has_permission(&user.role, Permission::ManageCirculation) => record,
+        Ok(_) => return error_response(404, "Lending record not found"),
+        Err(_) => return error_response(500, "Failed to fetch lending record"),
+    };
+    
+    let member = match db::get_user_by_id(pool, record.user_id).await {
+        Ok(Some(member)) => member,
+        _ => return error_response(500, "Failed to fetch member"),
+    };
+    let branch = match db::get_branch_by_id(pool, record.branch_id).await {
+        Ok(branch) => branch.map(|b| b.name).unwrap_or_default(),
+        Err(_) => return error_response(500, "Failed to fetch branch"),
+    };
+    let fines: Vec<f64> = match db::get_user_fines(pool, record.user_id).await {
+        Ok(fines) => fines.iter().filter(|f| f.lending_record_id == record.id).map(|f| f.amount).collect(),
+        Err(_) => return error_response(500, "Failed to fetch fines"),
+    };
+    
+    let slip = receipt::Receipt {
+        record_id: record.id,
+        member: match &member.display_name {
+            Some(name) => format!("{} ({})", name, member.username),
+            None => member.username.clone(),
+        },
+        card_number: member.card_number.clone(),
+        title: record.title,
+        author: record.author,
+        branch,
+        borrowed_at: record.borrowed_at,
+        due_date: record.due_date,
+        returned_at: record.returned_at,
+        fine: if fines.is_empty() { None } else { Some(fines.iter().sum()) },
+    };
+    
+    match format.unwrap_or("html") {
+        "html" => text_response(receipt::HTML_TYPE, &receipt::html(&slip)),
+        "pdf" => text_response(receipt::PDF_TYPE, &receipt::pdf(&slip)),
+        _ => error_response(400, "Format must be 'html' or 'pdf'"),
+    }
+}
+
+async fn handle_get_my_books(pool: &SqlitePool, token: Option<&str>, format: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_user_borrowed_books(pool, user.id).await {
+        Ok(records) => listing_response(&records, format, "my-loans", LENDING_CSV_COLUMNS),
+        Err(_) => error_response(500, "Failed to fetch borrowed books"),
+    }
+}
+
+async fn handle_get_my_stats(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let year_start = clock::now().format("%Y-01-01").to_string();
+    match db::get_reading_stats(pool, user.id, &year_start).await {
+        Ok(stats) => success_response(serde_json::to_value(stats).unwrap()),
+        Err(_) => error_response(500, "Failed to compute reading statistics"),
+    }
+}
+
+// Calendar feed handlers
+async fn feed_secret(pool: &SqlitePool) -> Result<String, sqlx::Error> {
+    match &config::get().feed_secret {
+        Some(secret) => Ok(secret.clone()),
+        None => db::get_or_create_secret(pool, "calendar_feed", &auth::generate_token()).await,
+    }
+}
+
+// Returns the member's subscription URL; `reset` revokes previously issued URLs first
+async fn handle_get_calendar_feed(pool: &SqlitePool, token: Option<&str>, reset: bool) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let version = if reset {
+        db::rotate_calendar_feed(pool, user.id).await
+    } else {
+        db::get_calendar_feed_version(pool, user.id).await
+    };
+    let (version, secret) = match (version, feed_secret(pool).await) {
+        (Ok(version), Ok(secret)) => (version, secret),
+        _ => return error_response(500, "Failed to prepare calendar feed"),
+    };
+    
+    let feed_token = auth::sign_feed_token(&secret, user.id, version);
+    let url = format!("{}/api/lending/my-due-dates.ics?token={}", config::get().public_url, feed_token);
+    success_response(json!({ "url": url }))
+}
+
+// Authenticated by the signed feed token alone, since calendar apps can't send headers
+async fn handle_due_dates_feed(pool: &SqlitePool, feed_token: &str) -> String {
+    let secret = match feed_secret(pool).await {
+        Ok(secret) => secret,
+        Err(_) => return error_response(500, "Failed to load calendar feed"),
+    };
+    
+    let Some((user_id, version)) = auth::verify_feed_token(&secret, feed_token) else {
+        return error_response(401, "Invalid feed token");
+    };
+    
+    let user = match db::get_user_by_id(pool, user_id).await {
+        Ok(Some(user)) if user.is_active => user,
+        Ok(_) => return error_response(401, "Invalid feed token"),
+        Err(_) => return error_response(500, "Failed to load calendar feed"),
+    };
+    
+    match db::get_calendar_feed_version(pool, user.id).await {
+        Ok(current) if current == version => {}
+        Ok(_) => return error_response(401, "Invalid feed token"),
+        Err(_) => return error_response(500, "Failed to load calendar feed"),
+    }
+    
+    let loans = match db::get_user_borrowed_books(pool, user.id).await {
+        Ok(loans) => loans,
+        Err(_) => return error_response(500, "Failed to load calendar feed"),
+    };
+    let holds: Vec<ReservationWithDetails> = match db::get_user_reservations(pool, user.id, true).await {
+        Ok(reservations) => reservations.into_iter().filter(|r| r.status == "ready").collect(),
+        Err(_) => return error_response(500, "Failed to load calendar feed"),
+    };
+    
+    let base = &config::get().public_url;
+    let host = base.split("://").nth(1).unwrap_or(base).trim_end_matches('/');
+    text_response(ical::CONTENT_TYPE, &ical::due_dates_calendar(host, &loans, &holds))
+}
+
+// Extension request handlers
+async fn handle_request_extension(pool: &SqlitePool, token: Option<&str>, record_id: i64, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let req: CreateExtensionRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if req.reason.trim().is_empty() {
+        return error_response(400, "A reason is required");
+    }
+    
+    let days = req.days.unwrap_or_else(settings::extension_days);
+    if days <= 0 {
+        return error_response(400, "Days must be positive");
+    }
+    
+    let record = match db::get_lending_record_by_id(pool, record_id).await {
+        Ok(Some(record)) if record.user_id == user.id && record.status != "returned" => record,
+        Ok(_) => return error_response(404, "Active loan not found"),
+        Err(_) => return error_response(500, "Failed to fetch loan"),
+    };
+    
+    if record.recalled_at.is_some() {
+        return error_response(409, "Recalled loans cannot be extended");
+    }
+    
+    match db::has_pending_extension_request(pool, record_id).await {
+        Ok(true) => return error_response(409, "An extension request for this loan is already pending"),
+        Ok(false) => {}
+        Err(_) => return error_response(500, "Failed to create extension request"),
+    }
+    
+    match db::create_extension_request(pool, record_id, user.id, req.reason.trim(), days).await {
+        Ok(id) => match db::get_extension_request_by_id(pool, id).await {
+            Ok(Some(request)) => created_response(serde_json::to_value(request).unwrap()),
+            _ => error_response(500, "Failed to retrieve extension request"),
+        },
+        Err(_) => error_response(500, "Failed to create extension request"),
+    }
+}
+
+async fn handle_get_extension_requests(pool: &SqlitePool, token: Option<&str>, status: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    if !["pending", "approved", "denied"].contains(&status) {
+        return error_response(400, "Status must be pending, approved, or denied");
+    }
+    
+    match db::get_extension_requests(pool, status).await {
+        Ok(requests) => success_response(serde_json::to_value(requests).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch extension requests"),
+    }
+}
+
+async fn handle_decide_extension(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str, approve: bool) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCirculation).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    // The body is optional; approving without one grants the days that were requested
+    let req: DecideExtensionRequest = if body.trim().is_empty() {
+        DecideExtensionRequest { days: None, note: None }
+    } else {
+        match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(_) => return error_response(400, "Invalid request body"),
+        }
+    };
+    
+    let request = match db::get_extension_request_by_id(pool, id).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return error_response(404, "Extension request not found"),
+        Err(_) => return error_response(500, "Failed to fetch extension request"),
+    };
+    
+    if request.status != "pending" {
+        return error_response(409, "Extension request has already been decided");
+    }
+    
+    let new_due_date = if approve {
+        let days = req.days.unwrap_or(request.requested_days);
+        if days <= 0 {
+            return error_response(400, "Days must be positive");
+        }
+        let current = chrono::DateTime::parse_from_rfc3339(&request.due_date)
+            .map(|d| d.with_timezone(&chrono::Utc))
+            .unwrap_or_else(|_| clock::now());
+        match db::roll_past_closures(pool, current + chrono::Duration::days(days)).await {
+            Ok(due) => Some(due.to_rfc3339()),
+            Err(_) => return error_response(500, "Failed to compute new due date"),
+        }
+    } else {
+        None
+    };
+    
+    if db::decide_extension_request(pool, id, admin.id, req.note.as_deref(), new_due_date.as_deref()).await.is_err() {
+        return error_response(409, "Extension request has already been decided");
+    }
+    
+    let action = if approve { "extension.approve" } else { "extension.deny" };
+    let details = json!({ "extension_request_id": id, "previous_due_date": request.due_date, "due_date": new_due_date });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), action, "lending_record", Some(request.lending_record_id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let request = match db::get_extension_request_by_id(pool, id).await {
+        Ok(Some(request)) => request,
+        _ => return error_response(500, "Failed to retrieve extension request"),
+    };
+    
+    if let Err(e) = notify::send_extension_decision(pool, &request).await {
+        eprintln!("Failed to send extension decision: {:?}", e);
+    }
+    
+    success_response(serde_json::to_value(request).unwrap())
+}
+
+// Favorite and author follow handlers
+async fn handle_get_favorites(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_favorites(pool, user.id).await {
+        Ok(books) => success_response(serde_json::to_value(books).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch favorites"),
+    }
+}
+
+async fn handle_add_favorite(pool: &SqlitePool, token: Option<&str>, book_id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_book_by_id(pool, book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    }
+    
+    match db::add_favorite(pool, user.id, book_id).await {
+        Ok(true) => created_response(json!({ "message": "Added to favorites", "book_id": book_id })),
+        Ok(false) => success_response(json!({ "message": "Already a favorite", "book_id": book_id })),
+        Err(_) => error_response(500, "Failed to add favorite"),
+    }
+}
+
+async fn handle_remove_favorite(pool: &SqlitePool, token: Option<&str>, book_id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::remove_favorite(pool, user.id, book_id).await {
+        Ok(_) => success_response(json!({ "message": "Removed from favorites" })),
+        Err(_) => error_response(404, "Book is not a favorite"),
+    }
+}
+
+async fn handle_get_followed_authors(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_followed_authors(pool, user.id).await {
+        Ok(follows) => success_response(serde_json::to_value(follows).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch followed authors"),
+    }
+}
+
+async fn handle_follow_author(pool: &SqlitePool, token: Option<&str>, author: &str, follow: bool) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let author = author.trim();
+    if author.is_empty() {
+        return error_response(400, "Author is required");
+    }
+    
+    if !follow {
+        return match db::unfollow_author(pool, user.id, author).await {
+            Ok(_) => success_response(json!({ "message": "Stopped following author", "author": author })),
+            Err(_) => error_response(404, "You are not following this author"),
+        };
+    }
+    
+    match db::follow_author(pool, user.id, author).await {
+        Ok(true) => created_response(json!({ "message": "Following author", "author": author })),
+        Ok(false) => success_response(json!({ "message": "Already following author", "author": author })),
+        Err(_) => error_response(500, "Failed to follow author"),
+    }
+}
+
+// Wishlist handlers
+async fn handle_get_wishlist(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_wishlist(pool, user.id).await {
+        Ok(entries) => success_response(serde_json::to_value(entries).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch wishlist"),
+    }
+}
+
+async fn handle_save_to_wishlist(pool: &SqlitePool, token: Option<&str>, book_id: i64, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    // The body is optional; an empty body saves the title without notifications
+    let req: AddToWishlistRequest = if body.trim().is_empty() {
+        AddToWishlistRequest { notify: None }
+    } else {
+        match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(_) => return error_response(400, "Invalid request body"),
+        }
+    };
+    
+    match db::get_book_by_id(pool, book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    }
+    
+    let existing = match db::get_wishlist_entry(pool, user.id, book_id).await {
+        Ok(entry) => entry,
+        Err(_) => return error_response(500, "Failed to save to wishlist"),
+    };
+    
+    if db::save_to_wishlist(pool, user.id, book_id, req.notify.unwrap_or(false)).await.is_err() {
+        return error_response(500, "Failed to save to wishlist");
+    }
+    
+    match db::get_wishlist_entry(pool, user.id, book_id).await {
+        Ok(Some(entry)) if existing.is_some() => success_response(serde_json::to_value(entry).unwrap()),
+        Ok(Some(entry)) => created_response(serde_json::to_value(entry).unwrap()),
+        _ => error_response(500, "Failed to retrieve wishlist entry"),
+    }
+}
+
+async fn handle_remove_from_wishlist(pool: &SqlitePool, token: Option<&str>, book_id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::remove_from_wishlist(pool, user.id, book_id).await {
+        Ok(_) => success_response(json!({ "message": "Removed from wishlist" })),
+        Err(_) => error_response(404, "Book is not on your wishlist"),
+    }
+}
+
+// Suggestion handlers
+async fn handle_create_suggestion(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let mut req: CreateSuggestionRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    req.title = req.title.trim().to_string();
+    req.author = req.author.trim().to_string();
+    req.isbn = req.isbn.map(|isbn| isbn.trim().to_string()).filter(|isbn| !isbn.is_empty());
+    req.reason = req.reason.map(|reason| reason.trim().to_string()).filter(|reason| !reason.is_empty());
+    
+    if req.title.is_empty() || req.author.is_empty() {
+        return error_response(400, "Title and author are required");
+    }
+    
+    if let Some(isbn) = &req.isbn {
+        match db::get_book_by_isbn(pool, isbn).await {
+            Ok(Some(_)) => return error_response(409, "This book is already in the catalog"),
+            Ok(None) => {}
+            Err(_) => return error_response(500, "Failed to create suggestion"),
+        }
+    }
+    
+    match db::create_suggestion(pool, user.id, &req).await {
+        Ok(id) => match db::get_suggestion_by_id(pool, id).await {
+            Ok(Some(suggestion)) => {
+                let reason = suggestion.reason.as_deref().map(|r| format!("\n> {}", r)).unwrap_or_default();
+                let text = format!("{} suggested \"{}\" by {}{}", suggestion.username, suggestion.title, suggestion.author, reason);
+                alerts::raise(pool, "suggestion_created", "New purchase suggestion", &text).await;
+                created_response(serde_json::to_value(suggestion).unwrap())
+            }
+            _ => error_response(500, "Failed to retrieve suggestion"),
+        },
+        Err(_) => error_response(500, "Failed to create suggestion"),
+    }
+}
+
+async fn handle_get_my_suggestions(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_user_suggestions(pool, user.id).await {
+        Ok(suggestions) => success_response(serde_json::to_value(suggestions).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch suggestions"),
+    }
+}
+
+async fn handle_get_suggestions(pool: &SqlitePool, token: Option<&str>, status: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    if !["pending", "approved", "declined"].contains(&status) {
+        return error_response(400, "Status must be pending, approved, or declined");
+    }
+    
+    match db::get_suggestions(pool, status).await {
+        Ok(suggestions) => success_response(serde_json::to_value(suggestions).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch suggestions"),
+    }
+}
+
+async fn handle_decide_suggestion(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str, approve: bool) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: DecideSuggestionRequest = if body.trim().is_empty() {
+        DecideSuggestionRequest { note: None, add_to_catalog: None, genre: None }
+    } else {
+        match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(_) => return error_response(400, "Invalid request body"),
+        }
+    };
+    
+    let suggestion = match db::get_suggestion_by_id(pool, id).await {
+        Ok(Some(suggestion)) => suggestion,
+        Ok(None) => return error_response(404, "Suggestion not found"),
+        Err(_) => return error_response(500, "Failed to fetch suggestion"),
+    };
+    
+    if suggestion.status != "pending" {
+        return error_response(409, "Suggestion has already been decided");
+    }
+    
+    let order = if approve && req.add_to_catalog.unwrap_or(false) {
+        let Some(isbn) = suggestion.isbn.clone() else {
+            return error_response(400, "An ISBN is required to add the book to the catalog");
+        };
+        Some(CreateBookRequest {
+            title: suggestion.title.clone(),
+            author: suggestion.author.clone(),
+            isbn,
+            publication_year: None,
+            genre: req.genre.clone(),
+            total_copies: 0,
+            item_type: None,
+            issue_number: None,
+            platform: None,
+        })
+    } else {
+        None
+    };
+    
+    let status = if approve { "approved" } else { "declined" };
+    let book_id = match db::decide_suggestion(pool, id, admin.id, status, req.note.as_deref(), order.as_ref()).await {
+        Ok(book_id) => book_id,
+        Err(sqlx::Error::RowNotFound) => return error_response(409, "Suggestion has already been decided"),
+        Err(_) => return error_response(500, "Failed to record decision"),
+    };
+    
+    let action = if approve { "suggestion.approve" } else { "suggestion.decline" };
+    let details = json!({ "title": suggestion.title, "isbn": suggestion.isbn, "book_id": book_id });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), action, "suggestion", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let suggestion = match db::get_suggestion_by_id(pool, id).await {
+        Ok(Some(suggestion)) => suggestion,
+        _ => return error_response(500, "Failed to retrieve suggestion"),
+    };
+    
+    if let Err(e) = notify::send_suggestion_decision(pool, &suggestion).await {
+        eprintln!("Failed to send suggestion decision: {:?}", e);
+    }
+    
+    success_response(serde_json::to_value(suggestion).unwrap())
+}
+
+// Donation handlers
+async fn handle_create_donation(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let mut req: CreateDonationRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    req.title = req.title.trim().to_string();
+    req.author = req.author.trim().to_string();
+    req.isbn = req.isbn.trim().to_string();
+    req.notes = req.notes.map(|notes| notes.trim().to_string()).filter(|notes| !notes.is_empty());
+    
+    if req.title.is_empty() || req.author.is_empty() || req.isbn.is_empty() {
+        return error_response(400, "Title, author, and ISBN are required");
+    }
+    
+    let copies = req.copies.unwrap_or(1);
+    if copies < 1 {
+        return error_response(400, "Copies must be at least 1");
+    }
+    
+    match db::create_donation(pool, user.id, &req, copies).await {
+        Ok(id) => match db::get_donation_by_id(pool, id).await {
+            Ok(Some(donation)) => created_response(serde_json::to_value(donation).unwrap()),
+            _ => error_response(500, "Failed to retrieve donation"),
+        },
+        Err(_) => error_response(500, "Failed to create donation"),
+    }
+}
+
+async fn handle_get_my_donations(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_user_donations(pool, user.id).await {
+        Ok(donations) => success_response(serde_json::to_value(donations).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch donations"),
+    }
+}
+
+async fn handle_get_donations(pool: &SqlitePool, token: Option<&str>, status: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    if !["pending", "accepted", "declined"].contains(&status) {
+        return error_response(400, "Status must be pending, accepted, or declined");
+    }
+    
+    match db::get_donations(pool, status).await {
+        Ok(donations) => success_response(serde_json::to_value(donations).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch donations"),
+    }
+}
+
+async fn handle_decide_donation(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str, accept: bool) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    // The body is optional; accepting without one adds the number of copies offered
+    let req: DecideDonationRequest = if body.trim().is_empty() {
+        DecideDonationRequest { copies: None, branch_id: None, note: None }
+    } else {
+        match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(_) => return error_response(400, "Invalid request body"),
+        }
+    };
+    
+    let donation = match db::get_donation_by_id(pool, id).await {
+        Ok(Some(donation)) => donation,
+        Ok(None) => return error_response(404, "Donation not found"),
+        Err(_) => return error_response(500, "Failed to fetch donation"),
+    };
+    
+    if donation.status != "pending" {
+        return error_response(409, "Donation has already been decided");
+    }
+    
+    let result = if accept {
+        let copies = req.copies.unwrap_or(donation.copies);
+        if copies < 1 {
+            return error_response(400, "Copies must be at least 1");
+        }
+        let branch_id = req.branch_id.unwrap_or(db::MAIN_BRANCH_ID);
+        match db::get_branch_by_id(pool, branch_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return error_response(400, "Unknown branch"),
+            Err(_) => return error_response(500, "Failed to record decision"),
+        }
+        db::accept_donation(pool, id, admin.id, copies, branch_id, req.note.as_deref()).await.map(Some)
+    } else {
+        db::decline_donation(pool, id, admin.id, req.note.as_deref()).await.map(|_| None)
+    };
+    
+    let book_id = match result {
+        Ok(book_id) => book_id,
+        Err(sqlx::Error::RowNotFound) => return error_response(409, "Donation has already been decided"),
+        Err(_) => return error_response(500, "Failed to record decision"),
+    };
+    
+    if let Some(book_id) = book_id {
+        release_to_queue(pool, book_id).await;
+    }
+    
+    let action = if accept { "donation.accept" } else { "donation.decline" };
+    let details = json!({ "title": donation.title, "isbn": donation.isbn, "book_id": book_id });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), action, "donation", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let donation = match db::get_donation_by_id(pool, id).await {
+        Ok(Some(donation)) => donation,
+        _ => return error_response(500, "Failed to retrieve donation"),
+    };
+    
+    if let Err(e) = notify::send_donation_decision(pool, &donation).await {
+        eprintln!("Failed to send donation decision: {:?}", e);
+    }
+    
+    success_response(serde_json::to_value(donation).unwrap())
+}
+
+async fn handle_get_acquisitions(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_acquisitions(pool).await {
+        Ok(acquisitions) => success_response(serde_json::to_value(acquisitions).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch acquisitions"),
+    }
+}
+
+// Reservation handlers
+async fn handle_reserve_book(pool: &SqlitePool, token: Option<&str>, book_id: i64) -> String {
+    let user = match authenticate_member(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let book = match db::get_book_by_id(pool, book_id).await {
+        Ok(Some(book)) => book,
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    };
+    
+    if !book.loanable {
+        return error_response(409, "This item is for in-library use only");
+    }
+    
+    if book.available_copies > 0 {
+        return error_response(409, "Copies are available; borrow the book directly");
+    }
+    
+    match db::has_active_reservation(pool, user.id, book_id).await {
+        Ok(true) => return error_response(409, "You already have a reservation for this book"),
+        Ok(false) => {}
+        Err(_) => return error_response(500, "Failed to create reservation"),
+    }
+    
+    match db::create_reservation(pool, user.id, book_id).await {
+        Ok(id) => match db::get_reservation_by_id(pool, id).await {
+            Ok(Some(reservation)) => created_response(serde_json::to_value(reservation).unwrap()),
+            _ => error_response(500, "Failed to retrieve reservation"),
+        },
+        Err(_) => error_response(500, "Failed to create reservation"),
+    }
+}
+
+async fn handle_watch_book(pool: &SqlitePool, token: Option<&str>, book_id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let book = match db::get_book_by_id(pool, book_id).await {
+        Ok(Some(book)) => book,
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    };
+    
+    if !book.loanable {
+        return error_response(409, "This item is for in-library use only");
+    }
+    
+    if book.available_copies > 0 {
+        return error_response(409, "Copies are available; borrow the book directly");
+    }
+    
+    match db::get_watch(pool, user.id, book_id).await {
+        Ok(Some(_)) => return error_response(409, "You are already watching this book"),
+        Ok(None) => {}
+        Err(_) => return error_response(500, "Failed to watch book"),
+    }
+    
+    if db::create_watch(pool, user.id, book_id).await.is_err() {
+        return error_response(500, "Failed to watch book");
+    }
+    
+    match db::get_watch(pool, user.id, book_id).await {
+        Ok(Some(watch)) => created_response(serde_json::to_value(watch).unwrap()),
+        _ => error_response(500, "Failed to retrieve watch"),
+    }
+}
+
+async fn handle_unwatch_book(pool: &SqlitePool, token: Option<&str>, book_id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::delete_watch(pool, user.id, book_id).await {
+        Ok(_) => success_response(json!({ "message": "Stopped watching book" })),
+        Err(_) => error_response(404, "You are not watching this book"),
+    }
+}
+
+async fn handle_get_my_watches(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_user_watches(pool, user.id).await {
+        Ok(watches) => success_response(serde_json::to_value(watches).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch watches"),
+    }
+}
+
+async fn handle_cancel_reservation(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let reservation = match db::get_reservation_by_id(pool, id).await {
+        Ok(Some(reservation)) if reservation.user_id == user.id => reservation,
+        _ => return error_response(404, "Reservation not found"),
+    };
+    
+    if db::close_reservation(pool, id, "cancelled").await.is_err() {
+        return error_response(409, "Reservation is no longer active");
+    }
+    
+    release_to_queue(pool, reservation.book_id).await;
+    success_response(json!({ "message": "Reservation cancelled successfully" }))
+}
+
+async fn handle_get_my_reservations(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_user_reservations(pool, user.id, true).await {
+        Ok(reservations) => success_response(serde_json::to_value(reservations).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch reservations"),
+    }
+}
+
+// Corrects drifted availability counts; copies found to be free go to the reservation queue
+async fn reconcile_availability(pool: &SqlitePool) -> Result<Vec<AvailabilityCorrection>, sqlx::Error> {
+    let corrections = db::reconcile_availability(pool).await?;
+    cache::invalidate();
+    for correction in corrections.iter().filter(|c| c.actual > c.recorded) {
+        release_to_queue(pool, correction.book_id).await;
+    }
+    Ok(corrections)
+}
+
+// Offers any free copies of a book to the reservation queue and tells the lucky readers
+async fn release_to_queue(pool: &SqlitePool, book_id: i64) {
+    // Also reached from background jobs, outside any request that would clear the cache
+    cache::invalidate();
+    
+    match db::allocate_holds(pool, book_id, settings::reservation_hold_days()).await {
+        Ok(promoted) => {
+            for reservation in promoted {
+                if let Err(e) = notify::send_hold_ready(pool, &reservation).await {
+                    eprintln!("Failed to send hold notification: {:?}", e);
+                }
+            }
+        }
+        Err(e) => eprintln!("Failed to allocate holds for book {}: {:?}", book_id, e),
+    }
+    
+    notify_availability_watchers(pool, book_id).await;
+}
+
+// Copies left on the shelf once the holds are served are news to members watching the book
+// or waiting on it from their wishlist; each member hears about it once
+async fn notify_availability_watchers(pool: &SqlitePool, book_id: i64) {
+    let book = match db::get_book_by_id(pool, book_id).await {
+        Ok(Some(book)) if book.available_copies > 0 && book.loanable => book,
+        Ok(_) => return,
+        Err(e) => return eprintln!("Failed to check availability for book {}: {:?}", book_id, e),
+    };
+    
+    let watchers = match db::get_book_watchers(pool, book_id).await {
+        Ok(watchers) => watchers,
+        Err(e) => return eprintln!("Failed to fetch watchers for book {}: {:?}", book_id, e),
+    };
+    
+    // Watches are one-shot and end once the notice is sent
+    for &user_id in &watchers {
+        let Ok(Some(user)) = db::get_user_by_id(pool, user_id).await else { continue };
+        if let Err(e) = notify::send_watch_available(pool, &user, &book).await {
+            eprintln!("Failed to send watch notification: {:?}", e);
+            continue;
+        }
+        if let Err(e) = db::delete_watch(pool, user_id, book_id).await {
+            eprintln!("Failed to close watch: {:?}", e);
+        }
+    }
+    
+    let wishers = match db::get_wishlist_watchers(pool, book_id).await {
+        Ok(wishers) => wishers,
+        Err(e) => return eprintln!("Failed to fetch wishlist watchers for book {}: {:?}", book_id, e),
+    };
+    
+    for user_id in wishers {
+        if watchers.contains(&user_id) {
+            if let Err(e) = db::mark_wishlist_notified(pool, user_id, book_id).await {
+                eprintln!("Failed to mark wishlist entry notified: {:?}", e);
+            }
+            continue;
+        }
+        let Ok(Some(user)) = db::get_user_by_id(pool, user_id).await else { continue };
+        if let Err(e) = notify::send_wishlist_available(pool, &user, &book).await {
+            eprintln!("Failed to send wishlist notification: {:?}", e);
+            continue;
+        }
+        if let Err(e) = db::mark_wishlist_notified(pool, user_id, book_id).await {
+            eprintln!("Failed to mark wishlist entry notified: {:?}", e);
+        }
+    }
+}
+
+async fn handle_get_my_fines(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match fine_balance(pool, user.id).await {
+        Ok(balance) => success_response(serde_json::to_value(balance).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch fines"),
+    }
+}
+
+async fn fine_balance(pool: &SqlitePool, user_id: i64) -> Result<FineBalance, sqlx::Error> {
+    let fines = db::get_user_fines(pool, user_id).await?;
+    let total_fined = db::round_cents(fines.iter().fold(0.0, |sum, f| sum + f.amount));
+    let total_paid = db::round_cents(fines.iter().fold(0.0, |sum, f| sum + f.amount_paid));
+    
+    Ok(FineBalance {
+        user_id,
+        currency: settings::currency_code(),
+        total_fined,
+        total_paid,
+        balance: db::round_cents(total_fined - total_paid),
+        fines,
+    })
+}
+
+// Admin handlers
+// A member's standing at a glance, for staff deciding on special requests
+async fn handle_get_user_summary(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let user = match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return error_response(404, "User not found"),
+        Err(_) => return error_response(500, "Failed to fetch user"),
+    };
+    
+    let counts = db::get_loan_counts(pool, id).await;
+    let fines = fine_balance(pool, id).await;
+    let top_genres = db::get_top_genres(pool, id, 5).await;
+    
+    match (counts, fines, top_genres) {
+        (Ok((total_loans, current_loans, overdue_count)), Ok(fines), Ok(top_genres)) => {
+            let summary = UserSummary {
+                user,
+                total_loans,
+                current_loans,
+                overdue_count,
+                total_fined: fines.total_fined,
+                unpaid_fines: fines.balance,
+                top_genres,
+            };
+            success_response(serde_json::to_value(summary).unwrap())
+        }
+        _ => error_response(500, "Failed to compute user summary"),
+    }
+}
+
+const USER_MAX_PAGE_SIZE: i64 = 1000;
+
+// Reads the user list's search, filters, sorting and paging from the query string. Without
+// `sort` the newest accounts come first; an explicit sort is ascending unless `order=desc`
+fn user_query(path: &str) -> Result<UserQuery, String> {
+    let role = query_param(path, "role").filter(|r| !r.is_empty());
+    if let Some(role) = role.as_deref().filter(|r| !permissions::is_role(r)) {
+        return Err(format!("Unknown role '{}'", role));
+    }
+    let active = match query_param(path, "active").as_deref() {
+        None | Some("") => None,
+        Some("true" | "1") => Some(true),
+        Some("false" | "0") => Some(false),
+        Some(_) => return Err("Active must be true or false".to_string()),
+    };
+    let sort = query_param(path, "sort");
+    if let Some(sort) = sort.as_deref().filter(|s| !db::USER_SORTS.iter().any(|(key, _)| key == s)) {
+        let keys: Vec<&str> = db::USER_SORTS.iter().map(|(key, _)| *key).collect();
+        return Err(format!("Unknown sort '{}'; expected one of: {}", sort, keys.join(", ")));
+    }
+    let descending = match query_param(path, "order").as_deref() {
+        None => sort.is_none(),
+        Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => return Err("Order must be asc or desc".to_string()),
+    };
+    
+    Ok(UserQuery {
+        text: query_param(path, "q").map(|q| q.trim().to_string()).filter(|q| !q.is_empty()),
+        role,
+        active,
+        sort: sort.unwrap_or_else(|| "created_at".to_string()),
+        descending,
+        limit: query_param(path, "limit").and_then(|v| v.parse::<i64>().ok()).map(|v| v.clamp(1, USER_MAX_PAGE_SIZE)),
+        offset: query_param(path, "offset").and_then(|v| v.parse::<i64>().ok()).unwrap_or(0).max(0),
+    })
+}
+
+async fn handle_get_all_users(pool: &SqlitePool, token: Option<&str>, query: Result<UserQuery, String>, format: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let query = match query {
+        Ok(query) => query,
+        Err(msg) => return error_response(400, &msg),
+    };
+    
+    match db::get_all_users(pool, &query).await {
+        Ok(users) => listing_response(&users, format, "users", USER_CSV_COLUMNS),
+        Err(_) => error_response(500, "Failed to fetch users"),
+    }
+}
+
+async fn handle_get_user_by_card(pool: &SqlitePool, token: Option<&str>, number: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_user_by_card_number(pool, number.trim()).await {
+        Ok(Some(user)) => success_response(serde_json::to_value(user).unwrap()),
+        Ok(None) => error_response(404, "User not found"),
+        Err(_) => error_response(500, "Failed to fetch user"),
+    }
+}
+
+async fn handle_set_member_type(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: SetMemberTypeRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    match db::get_member_type(pool, &req.member_type).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(400, "Unknown member type"),
+        Err(_) => return error_response(500, "Failed to update member type"),
+    }
+    
+    if db::set_member_type(pool, id, &req.member_type).await.is_err() {
+        return error_response(404, "User not found");
+    }
+    
+    let details = json!({ "member_type": req.member_type });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "user.member_type", "user", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => success_response(serde_json::to_value(user).unwrap()),
+        _ => error_response(500, "Failed to retrieve user"),
+    }
+}
+
+async fn handle_set_role(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: SetRoleRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if !permissions::is_role(&req.role) {
+        return error_response(400, "Unknown role");
+    }
+    // Otherwise the last admin could lock everyone out of staff tools
+    if id == admin.id {
+        return error_response(400, "You cannot change your own role");
+    }
+    
+    if db::set_user_role(pool, id, &req.role).await.is_err() {
+        return error_response(404, "User not found");
+    }
+    
+    let details = json!({ "role": req.role });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "user.role", "user", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => success_response(serde_json::to_value(user).unwrap()),
+        _ => error_response(500, "Failed to retrieve user"),
+    }
+}
+
+// Invite handlers
+const INVITE_DEFAULT_DAYS: i64 = 7;
+const INVITE_MAX_DAYS: i64 = 365;
+
+async fn handle_get_invites(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageMembers).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_invites(pool).await {
+        Ok(invites) => success_response(serde_json::to_value(invites).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch invites"),
+    }
+}
+
+async fn handle_create_invite(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: CreateInviteRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let role = req.role.as_deref().unwrap_or("lender");
+    if !permissions::is_role(role) {
+        return error_response(400, "Unknown role");
+    }
+    let days = req.expires_in_days.unwrap_or(INVITE_DEFAULT_DAYS);
+    if !(1..=INVITE_MAX_DAYS).contains(&days) {
+        return error_response(400, &format!("expires_in_days must be between 1 and {}", INVITE_MAX_DAYS));
+    }
+    
+    let code = auth::generate_invite_code();
+    let id = match db::create_invite(pool, &auth::hash_invite_code(&code), role, admin.id, days).await {
+        Ok(id) => id,
+        Err(_) => return error_response(500, "Failed to create invite"),
+    };
+    
+    let details = json!({ "role": role, "expires_in_days": days });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "invite.create", "invite", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_invite(pool, id).await {
+        Ok(Some(invite)) => {
+            let mut body = serde_json::to_value(invite).unwrap();
+            body["code"] = json!(code);
+            created_response(body)
+        }
+        _ => error_response(500, "Failed to retrieve invite"),
+    }
+}
+
+async fn handle_delete_invite(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    match db::delete_invite(pool, id).await {
+        Ok(true) => {}
+        Ok(false) => return error_response(404, "Invite not found or already used"),
+        Err(_) => return error_response(500, "Failed to delete invite"),
+    }
+    
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "invite.delete", "invite", Some(id), &json!({})).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    success_response(json!({ "message": "Invite deleted" }))
+}
+
+const IMPERSONATION_MINUTES: i64 = 30;
+
+// Lets staff see the app exactly as a member does, without the member's password
+async fn handle_impersonate_user(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    if id == admin.id {
+        return error_response(400, "You cannot impersonate yourself");
+    }
+    
+    let user = match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return error_response(404, "User not found"),
+        Err(_) => return error_response(500, "Failed to retrieve user"),
+    };
+    
+    // A session as another staff member would hand out their permissions
+    if permissions::is_staff(&user.role) {
+        return error_response(403, "Staff accounts cannot be impersonated");
+    }
+    if !user.is_active || user.anonymized_at.is_some() {
+        return error_response(409, "Deactivated accounts cannot be impersonated");
+    }
+    
+    let session_token = auth::generate_token();
+    let expires_at = match db::create_impersonation_session(pool, user.id, &session_token, admin.id, IMPERSONATION_MINUTES).await {
+        Ok(expires_at) => expires_at,
+        Err(_) => return error_response(500, "Failed to create session"),
+    };
+    
+    let details = json!({ "expires_at": expires_at });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "user.impersonate", "user", Some(user.id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let response = success_response(json!({
+        "token": session_token,
+        "user": user,
+        "expires_at": expires_at,
+        "impersonated_by": { "id": admin.id, "username": admin.username },
+    }));
+    with_session_cookies(response, &session_token, IMPERSONATION_MINUTES * 60)
+}
+
+// Folds an accidental second registration into the member's main account
+async fn handle_merge_users(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: MergeUsersRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if req.primary_id == req.duplicate_id {
+        return error_response(400, "Cannot merge an account into itself");
+    }
+    
+    let (primary, duplicate) = match (db::get_user_by_id(pool, req.primary_id).await, db::get_user_by_id(pool, req.duplicate_id).await) {
+        (Ok(Some(primary)), Ok(Some(duplicate))) => (primary, duplicate),
+        (Ok(_), Ok(_)) => return error_response(404, "User not found"),
+        _ => return error_response(500, "Failed to retrieve user"),
+    };
+    
+    if primary.anonymized_at.is_some() || duplicate.anonymized_at.is_some() {
+        return error_response(409, "Deleted accounts cannot be merged");
+    }
+    // Staff accounts carry permissions, so folding one away needs a deliberate role change first
+    if permissions::is_staff(&duplicate.role) {
+        return error_response(403, "Staff accounts cannot be merged away");
+    }
+    
+    let (merged, mut released_books) = match db::merge_users(pool, primary.id, duplicate.id).await {
+        Ok(merged) => merged,
+        Err(_) => return error_response(500, "Failed to merge accounts"),
+    };
+    released_books.sort_unstable();
+    released_books.dedup();
+    for book_id in released_books {
+        release_to_queue(pool, book_id).await;
+    }
+    
+    let details = json!({
+        "duplicate_id": duplicate.id,
+        "duplicate_username": duplicate.username,
+        "moved": merged,
+    });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "user.merge", "user", Some(primary.id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_user_by_id(pool, primary.id).await {
+        Ok(Some(user)) => success_response(json!({ "user": user, "duplicate_id": duplicate.id, "moved": merged })),
+        _ => error_response(500, "Failed to retrieve user"),
+    }
+}
+
+async fn handle_get_member_types(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_all_member_types(pool).await {
+        Ok(member_types) => success_response(serde_json::to_value(member_types).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch member types"),
+    }
+}
+
+async fn handle_get_item_types(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_all_item_types(pool).await {
+        Ok(item_types) => success_response(serde_json::to_value(item_types).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch item types"),
+    }
+}
+
+async fn handle_upsert_item_type(pool: &SqlitePool, token: Option<&str>, name: &str, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageCatalog).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let req: ItemTypePolicyRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let item_type = ItemType {
+        name: name.trim().to_lowercase(),
+        loan_period_days: req.loan_period_days,
+    };
+    
+    if item_type.name.is_empty() || item_type.loan_period_days.is_some_and(|v| !(1..=MAX_LOAN_PERIOD_DAYS).contains(&v)) {
+        return error_response(400, "Invalid item type data");
+    }
+    
+    match db::upsert_item_type(pool, &item_type).await {
+        Ok(_) => success_response(serde_json::to_value(item_type).unwrap()),
+        Err(_) => error_response(500, "Failed to save item type"),
+    }
+}
+
+async fn handle_upsert_member_type(pool: &SqlitePool, token: Option<&str>, name: &str, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageMembers).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let req: MemberTypePolicyRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let member_type = MemberType {
+        name: name.trim().to_lowercase(),
+        loan_period_days: req.loan_period_days,
+        max_concurrent_loans: req.max_concurrent_loans,
+        fine_per_day: req.fine_per_day,
+    };
+    
+    if member_type.name.is_empty()
+        || member_type.loan_period_days.is_some_and(|v| !(1..=MAX_LOAN_PERIOD_DAYS).contains(&v))
+        || member_type.max_concurrent_loans.is_some_and(|v| v < 0)
+        || member_type.fine_per_day.is_some_and(|v| v < 0.0)
+    {
+        return error_response(400, "Invalid member type data");
+    }
+    
+    match db::upsert_member_type(pool, &member_type).await {
+        Ok(_) => success_response(serde_json::to_value(member_type).unwrap()),
+        Err(_) => error_response(500, "Failed to save member type"),
+    }
+}
+
+const MEMBERSHIP_MAX_DAYS: i64 = 3650;
+
+async fn handle_renew_membership(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    // The body is optional; an empty body renews for the default period
+    let req: RenewMembershipRequest = if body.trim().is_empty() {
+        RenewMembershipRequest { days: None }
+    } else {
+        match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(_) => return error_response(400, "Invalid request body"),
+        }
+    };
+    
+    let days = req.days.unwrap_or_else(settings::membership_period_days);
+    if !(1..=MEMBERSHIP_MAX_DAYS).contains(&days) {
+        return error_response(400, &format!("Renewal period must be between 1 and {} days", MEMBERSHIP_MAX_DAYS));
+    }
+    
+    let user = match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => user,
+        _ => return error_response(404, "User not found"),
+    };
+    
+    // Renewing early extends from the current expiry rather than from today
+    let now = clock::now();
+    let start = user.membership_expires_at
+        .as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .filter(|t| *t > now)
+        .unwrap_or(now);
+    let expires_at = (start + chrono::Duration::days(days)).to_rfc3339();
+    
+    if db::renew_membership(pool, id, &expires_at).await.is_err() {
+        return error_response(404, "User not found");
+    }
+    
+    let details = json!({ "from": user.membership_expires_at, "to": expires_at });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "user.renew_membership", "user", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => success_response(serde_json::to_value(user).unwrap()),
+        _ => error_response(500, "Failed to retrieve user"),
+    }
+}
+
+async fn handle_lift_suspension(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let user = match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => user,
+        _ => return error_response(404, "User not found"),
+    };
+    
+    if user.suspended_at.is_none() {
+        return error_response(409, "User is not suspended");
+    }
+    
+    if db::lift_suspension(pool, id).await.is_err() {
+        return error_response(404, "User not found");
+    }
+    
+    let details = json!({ "reason": user.suspension_reason });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "user.lift_suspension", "user", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => success_response(serde_json::to_value(user).unwrap()),
+        _ => error_response(500, "Failed to retrieve user"),
+    }
+}
+
+// Signs up a list of members from CSV. Each new member gets either an account with a temporary
+// password, returned in the report, or an emailed invite link for that account. Rows matching
+// an existing user, an open invite or an earlier row are skipped
+async fn handle_import_users(pool: &SqlitePool, token: Option<&str>, body: &str, send_invites: bool, days: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    if send_invites && !(1..=INVITE_MAX_DAYS).contains(&days) {
+        return error_response(400, &format!("expires_in_days must be between 1 and {}", INVITE_MAX_DAYS));
+    }
+    
+    let rows = match roster::parse(body) {
+        Ok(rows) => rows,
+        Err(msg) => return error_response(400, &msg),
+    };
+    
+    let member_types: Vec<String> = match db::get_all_member_types(pool).await {
+        Ok(types) => types.into_iter().map(|t| t.name).collect(),
+        Err(_) => return error_response(500, "Failed to fetch member types"),
+    };
+    
+    let mut seen = std::collections::HashSet::new();
+    let mut report = Vec::new();
+    let (mut created, mut invited, mut skipped, mut failed) = (0, 0, 0, 0);
+    
+    for (index, row) in rows.into_iter().enumerate() {
+        let row = match row {
+            Ok(row) => row,
+            Err(message) => {
+                failed += 1;
+                report.push(json!({ "index": index, "status": "failed", "message": message }));
+                continue;
+            }
+        };
+        let mut skip = |message: String| {
+            skipped += 1;
+            report.push(json!({ "index": index, "status": "skipped", "username": row.username, "email": row.email, "message": message }));
+        };
+        
+        if let Some(member_type) = row.member_type.as_ref().filter(|t| !member_types.contains(t)) {
+            failed += 1;
+            report.push(json!({ "index": index, "status": "failed", "username": row.username, "message": format!("Unknown member type {}", member_type) }));
+            continue;
+        }
+        
+        let keys = [Some(row.username.to_lowercase()), Some(row.email.to_lowercase()), row.card_number.clone()];
+        if keys.iter().flatten().any(|key| seen.contains(key)) {
+            skip("Same username, email or card number as an earlier row".to_string());
+            continue;
+        }
+        seen.extend(keys.into_iter().flatten());
+        
+        let existing = match existing_member(pool, &row).await {
+            Ok(existing) => existing,
+            Err(_) => return error_response(500, "Failed to check existing users"),
+        };
+        if let Some(user) = existing {
+            skipped += 1;
+            report.push(json!({
+                "index": index, "status": "skipped", "username": row.username, "email": row.email,
+                "user_id": user.id, "message": "Matches an existing user",
+            }));
+            continue;
+        }
+        
+        if send_invites {
+            match db::has_open_account_invite(pool, &row.username, &row.email, row.card_number.as_deref()).await {
+                Ok(true) => {
+                    skip("An open invite was already sent".to_string());
+                    continue;
+                }
+                Ok(false) => {}
+                Err(_) => return error_response(500, "Failed to check existing invites"),
+            }
+            
+            let code = auth::generate_invite_code();
+            match db::create_account_invite(pool, &auth::hash_invite_code(&code), admin.id, days, &row).await {
+                Ok(invite_id) => {
+                    if let Err(e) = send_account_invite(pool, &row, &code, days).await {
+                        eprintln!("Failed to send invite email: {:?}", e);
+                    }
+                    invited += 1;
+                    report.push(json!({ "index": index, "status": "invited", "username": row.username, "email": row.email, "invite_id": invite_id, "code": code }));
+                }
+                Err(e) => {
+                    failed += 1;
+                    report.push(json!({ "index": index, "status": "failed", "username": row.username, "message": e.to_string() }));
+                }
+            }
+        } else {
+            let password = auth::generate_temporary_password();
+            let password_hash = match auth::hash_password(&password) {
+                Ok(hash) => hash,
+                Err(_) => return error_response(500, "Failed to hash password"),
+            };
+            
+            match db::create_imported_user(pool, &row, &password_hash).await {
+                Ok(user_id) => {
+                    if let Ok(Some(user)) = db::get_user_by_id(pool, user_id).await {
+                        if let Err(e) = send_email_verification(pool, &user).await {
+                            eprintln!("Failed to send verification email: {:?}", e);
+                        }
+                    }
+                    created += 1;
+                    report.push(json!({
+                        "index": index, "status": "created", "username": row.username, "email": row.email,
+                        "user_id": user_id, "temporary_password": password,
+                    }));
+                }
+                Err(e) => {
+                    failed += 1;
+                    report.push(json!({ "index": index, "status": "failed", "username": row.username, "message": e.to_string() }));
+                }
+            }
+        }
+    }
+    
+    let details = json!({ "credentials": if send_invites { "invite" } else { "password" }, "created": created, "invited": invited, "skipped": skipped, "failed": failed });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "users.import", "user", None, &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    success_response(json!({ "created": created, "invited": invited, "skipped": skipped, "failed": failed, "records": report }))
+}
+
+// The user an import row would duplicate, matched by username, email or card number
+async fn existing_member(pool: &SqlitePool, row: &ImportedMember) -> Result<Option<User>, sqlx::Error> {
+    if let Some(user) = db::get_user_by_username(pool, &row.username).await? {
+        return Ok(Some(user));
+    }
+    if let Some(user) = db::get_user_by_email(pool, &row.email).await? {
+        return Ok(Some(user));
+    }
+    match &row.card_number {
+        Some(card_number) => db::get_user_by_card_number(pool, card_number).await,
+        None => Ok(None),
+    }
+}
+
+async fn send_account_invite(pool: &SqlitePool, row: &ImportedMember, code: &str, days: i64) -> Result<(), sqlx::Error> {
+    let link = format!(
+        "{}/#invite={}&username={}&email={}",
+        config::get().public_url,
+        code,
+        urlencoding::encode(&row.username),
+        urlencoding::encode(&row.email)
+    );
+    let body = format!(
+        "Hello {},\n\nAn account has been set up for you at the library. Choose a password and finish signing up by opening this link within {} days:\n{}\n",
+        row.username, days, link
+    );
+    notify::send_email(pool, None, &row.email, "You're invited to the library", &body).await
+}
+
+async fn handle_get_pending_users(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_pending_users(pool).await {
+        Ok(users) => success_response(serde_json::to_value(users).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch pending registrations"),
+    }
+}
+
+async fn handle_decide_registration(pool: &SqlitePool, token: Option<&str>, id: i64, approve: bool, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    // The body is optional; a reason, when given, is passed on to the member
+    let req: RegistrationDecisionRequest = if body.trim().is_empty() {
+        RegistrationDecisionRequest { reason: None }
+    } else {
+        match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(_) => return error_response(400, "Invalid request body"),
+        }
+    };
+    let reason = req.reason.as_deref().map(str::trim).filter(|r| !r.is_empty());
+    
+    match db::decide_registration(pool, id, approve).await {
+        Ok(true) => {}
+        Ok(false) => return match db::get_user_by_id(pool, id).await {
+            Ok(Some(_)) => error_response(409, "Registration is not pending"),
+            _ => error_response(404, "User not found"),
+        },
+        Err(_) => return error_response(500, "Failed to record decision"),
+    }
+    
+    let action = if approve { "user.approve" } else { "user.reject" };
+    if let Err(e) = db::record_audit(pool, Some(admin.id), action, "user", Some(id), &json!({ "reason": reason })).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let user = match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => user,
+        _ => return error_response(500, "Failed to retrieve user"),
+    };
+    if let Err(e) = notify::send_registration_decision(pool, &user, approve, reason).await {
+        eprintln!("Failed to send registration decision: {:?}", e);
+    }
+    
+    success_response(serde_json::to_value(user).unwrap())
+}
+
+async fn handle_set_user_active(pool: &SqlitePool, token: Option<&str>, id: i64, active: bool) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    if admin.id == id && !active {
+        return error_response(400, "You cannot deactivate your own account");
+    }
+    
+    match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) if user.anonymized_at.is_some() => return error_response(409, "User account has been deleted"),
+        Ok(Some(user)) if active && user.approval_status == "rejected" => return error_response(409, "This registration was rejected"),
+        Ok(Some(_)) => {}
+        _ => return error_response(404, "User not found"),
+    }
+    
+    if db::set_user_active(pool, id, active).await.is_err() {
+        return error_response(404, "User not found");
+    }
+    
+    let action = if active { "user.reactivate" } else { "user.deactivate" };
+    if let Err(e) = db::record_audit(pool, Some(admin.id), action, "user", Some(id), &json!({})).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => success_response(serde_json::to_value(user).unwrap()),
+        _ => error_response(500, "Failed to retrieve user"),
+    }
+}
+
+async fn handle_admin_delete_user(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    if admin.id == id {
+        return error_response(400, "You cannot delete your own account");
+    }
+    
+    erase_account(pool, admin.id, id).await
+}
+
+async fn handle_get_user_fines(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_user_by_id(pool, id).await {
+        Ok(Some(_)) => {}
+        _ => return error_response(404, "User not found"),
+    }
+    
+    match fine_balance(pool, id).await {
+        Ok(balance) => success_response(serde_json::to_value(balance).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch fines"),
+    }
+}
+
+async fn handle_pay_fine(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCirculation).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: PayFineRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    req.amount = db::round_cents(req.amount);
+    
+    if !["cash", "card", "other"].contains(&req.method.as_str()) {
+        return error_response(400, "Method must be cash, card, or other");
+    }
+    
+    let fine = match db::get_fine_by_id(pool, id).await {
+        Ok(Some(fine)) => fine,
+        Ok(None) => return error_response(404, "Fine not found"),
+        Err(_) => return error_response(500, "Failed to fetch fine"),
+    };
+    
+    let outstanding = db::round_cents(fine.amount - fine.amount_paid);
+    if fine.status == "paid" || outstanding <= 0.0 {
+        return error_response(409, "Fine is already paid");
+    }
+    if req.amount <= 0.0 || req.amount > outstanding {
+        return error_response(400, &format!("Amount must be between {} and {}", money::format(0.01), money::format(outstanding)));
+    }
+    
+    // Another payment may have landed since the fine was read
+    let payment_id = match db::record_fine_payment(pool, id, &req, admin.id).await {
+        Ok(payment_id) => payment_id,
+        Err(sqlx::Error::RowNotFound) => return error_response(409, "Fine balance has changed; check the fine and try again"),
+        Err(_) => return error_response(500, "Failed to record payment"),
+    };
+    
+    let details = json!({ "fine_id": id, "amount": req.amount, "method": req.method });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "fine.payment", "user", Some(fine.user_id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let payment = db::get_fine_payment_by_id(pool, payment_id).await.ok().flatten();
+    let fine = db::get_fine_by_id(pool, id).await.ok().flatten();
+    match (payment, fine) {
+        (Some(payment), Some(fine)) => created_response(json!({
+            "receipt": payment,
+            "fine": fine,
+            "remaining_balance": db::round_cents(fine.amount - fine.amount_paid),
+        })),
+        _ => error_response(500, "Failed to retrieve payment"),
+    }
+}
+
+async fn handle_get_active_reservations(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_all_active_reservations(pool).await {
+        Ok(reservations) => success_response(serde_json::to_value(reservations).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch reservations"),
+    }
+}
+
+async fn handle_get_lending_record(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let record = match db::get_lending_record_by_id(pool, id).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return error_response(404, "Lending record not found"),
+        Err(_) => return error_response(500, "Failed to fetch lending record"),
+    };
+    
+    match db::get_lending_notes(pool, id).await {
+        Ok(notes) => {
+            let mut value = serde_json::to_value(record).unwrap();
+            value["notes"] = serde_json::to_value(notes).unwrap();
+            success_response(value)
+        }
+        Err(_) => error_response(500, "Failed to fetch lending notes"),
+    }
+}
+
+async fn handle_add_lending_note(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCirculation).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: CreateLendingNoteRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let note = req.note.trim();
+    if note.is_empty() {
+        return error_response(400, "Note cannot be empty");
+    }
+    
+    match db::get_lending_record_by_id(pool, id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(404, "Lending record not found"),
+        Err(_) => return error_response(500, "Failed to fetch lending record"),
+    }
+    
+    let note_id = match db::create_lending_note(pool, id, admin.id, note).await {
+        Ok(note_id) => note_id,
+        Err(_) => return error_response(500, "Failed to save note"),
+    };
+    
+    match db::get_lending_notes(pool, id).await {
+        Ok(notes) => match notes.into_iter().find(|n| n.id == note_id) {
+            Some(note) => created_response(serde_json::to_value(note).unwrap()),
+            None => error_response(500, "Failed to retrieve note"),
+        },
+        Err(_) => error_response(500, "Failed to fetch lending notes"),
+    }
+}
+
+async fn handle_recall_loan(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCirculation).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    // The body is optional; without a due date the loan is recalled with the default notice period
+    let mut req: RecallRequest = if body.trim().is_empty() {
+        RecallRequest { due_date: None, reason: None }
+    } else {
+        match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(_) => return error_response(400, "Invalid request body"),
+        }
+    };
+    
+    if let Err(msg) = normalize_timestamp(&mut req.due_date) {
+        return error_response(400, msg);
+    }
+    
+    let record = match db::get_lending_record_by_id(pool, id).await {
+        Ok(Some(record)) if record.status != "returned" => record,
+        Ok(_) => return error_response(404, "Active loan not found"),
+        Err(_) => return error_response(500, "Failed to fetch loan"),
+    };
+    
+    if record.recalled_at.is_some() {
+        return error_response(409, "Loan has already been recalled");
+    }
+    
+    let due_date = match req.due_date.clone() {
+        Some(due_date) => due_date,
+        None => {
+            let due = clock::now() + chrono::Duration::days(settings::recall_notice_days());
+            match db::roll_past_closures(pool, due).await {
+                Ok(due) => due.to_rfc3339(),
+                Err(_) => return error_response(500, "Failed to compute recall date"),
+            }
+        }
+    };
+    if due_date >= record.due_date {
+        return error_response(409, "Recall date must be earlier than the current due date");
+    }
+    
+    if db::recall_loan(pool, id, &due_date).await.is_err() {
+        return error_response(409, "Loan could not be recalled");
+    }
+    
+    let details = json!({ "original_due_date": record.due_date, "due_date": due_date, "reason": req.reason });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "loan.recall", "lending_record", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let record = match db::get_lending_record_by_id(pool, id).await {
+        Ok(Some(record)) => record,
+        _ => return error_response(500, "Failed to retrieve loan"),
+    };
+    
+    if let Ok(Some(borrower)) = db::get_user_by_id(pool, record.user_id).await {
+        if let Err(e) = notify::send_recall_notice(pool, &record, &borrower.email, req.reason.as_deref()).await {
+            eprintln!("Failed to send recall notice: {:?}", e);
+        }
+    }
+    
+    success_response(serde_json::to_value(record).unwrap())
+}
+
+const LENDING_MAX_PAGE_SIZE: i64 = 1000;
+
+// Reads the active loan listing's filters, sorting and paging from the query string. Without
+// `sort` the newest loans come first; an explicit sort is ascending unless `order=desc`
+fn lending_query(path: &str) -> Result<LendingQuery, String> {
+    let date = |name: &str| match query_param(path, name) {
+        Some(value) if chrono::NaiveDate::parse_from_str(&value, "%Y-%m-%d").is_err() => Err(format!("{} must be YYYY-MM-DD", name)),
+        value => Ok(value),
+    };
+    let sort = query_param(path, "sort");
+    if let Some(sort) = sort.as_deref().filter(|s| !db::LENDING_SORTS.iter().any(|(key, _)| key == s)) {
+        let keys: Vec<&str> = db::LENDING_SORTS.iter().map(|(key, _)| *key).collect();
+        return Err(format!("Unknown sort '{}'; expected one of: {}", sort, keys.join(", ")));
+    }
+    let descending = match query_param(path, "order").as_deref() {
+        None => sort.is_none(),
+        Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => return Err("Order must be asc or desc".to_string()),
+    };
+    
+    Ok(LendingQuery {
+        username: query_param(path, "username").filter(|v| !v.is_empty()),
+        title: query_param(path, "title").filter(|v| !v.is_empty()),
+        branch_id: query_param(path, "branch").and_then(|b| b.parse::<i64>().ok()),
+        borrowed_from: date("borrowed_from")?,
+        borrowed_to: date("borrowed_to")?,
+        overdue_only: matches!(query_param(path, "overdue").as_deref(), Some("true" | "1")),
+        sort: sort.unwrap_or_else(|| "borrowed_at".to_string()),
+        descending,
+        limit: query_param(path, "limit").and_then(|v| v.parse::<i64>().ok()).map(|v| v.clamp(1, LENDING_MAX_PAGE_SIZE)),
+        offset: query_param(path, "offset").and_then(|v| v.parse::<i64>().ok()).unwrap_or(0).max(0),
+    })
+}
+
+async fn handle_get_active_lending(pool: &SqlitePool, token: Option<&str>, query: Result<LendingQuery, String>, format: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let query = match query {
+        Ok(query) => query,
+        Err(msg) => return error_response(400, &msg),
+    };
+    
+    match db::get_all_active_lending(pool, &query).await {
+        Ok(records) => listing_response(&records, format, "active-loans", LENDING_CSV_COLUMNS),
+        Err(_) => error_response(500, "Failed to fetch lending records"),
+    }
+}
+
+async fn handle_get_overdue_books(pool: &SqlitePool, token: Option<&str>, branch_id: Option<i64>, level: Option<&str>, format: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    if let Some(level) = level.filter(|l| *l != "none" && !notify::ESCALATION_LEVELS.contains(l)) {
+        return error_response(400, &format!("Unknown escalation level '{}'; expected none, {}", level, notify::ESCALATION_LEVELS.join(", ")));
+    }
+    
+    match db::get_overdue_books(pool, branch_id, level).await {
+        Ok(records) => listing_response(&records, format, "overdue-loans", LENDING_CSV_COLUMNS),
+        Err(_) => error_response(500, "Failed to fetch overdue books"),
+    }
+}
+
+async fn handle_get_jobs(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    success_response(serde_json::to_value(scheduler::statuses()).unwrap())
+}
+
+// Dry run of the retention purge: what the next run would anonymize or delete
+async fn handle_get_retention_preview(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match retention::purge(pool, true).await {
+        Ok(report) => success_response(serde_json::to_value(report).unwrap()),
+        Err(_) => error_response(500, "Failed to preview retention purge"),
+    }
+}
+
+async fn handle_get_maintenance(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    success_response(serde_json::to_value(maintenance::status()).unwrap())
+}
+
+async fn handle_set_maintenance(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: MaintenanceRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let status = if req.enabled {
+        maintenance::enable(req.message)
+    } else {
+        maintenance::disable()
+    };
+    
+    let details = json!({ "enabled": status.enabled, "message": status.message });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "maintenance.set", "system", None, &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    success_response(serde_json::to_value(status).unwrap())
+}
+
+async fn handle_integrity_check(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match integrity::check(pool).await {
+        Ok(report) => success_response(serde_json::to_value(report).unwrap()),
+        Err(_) => error_response(500, "Failed to run integrity check"),
+    }
+}
+
+async fn handle_reconcile_availability(pool: &SqlitePool, token: Option<&str>) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let corrections = match reconcile_availability(pool).await {
+        Ok(corrections) => corrections,
+        Err(_) => return error_response(500, "Failed to reconcile availability"),
+    };
+    
+    let details = json!({ "corrected": corrections.len() });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "books.reconcile", "book", None, &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    success_response(serde_json::to_value(corrections).unwrap())
+}
+
+const ACCESS_LOG_PAGE_SIZE: i64 = 100;
+const ACCESS_LOG_MAX_PAGE_SIZE: i64 = 1000;
+
+const ACCESS_LOG_CSV_COLUMNS: &[&str] = &[
+    "id", "created_at", "method", "path", "status", "user_id", "username", "ip", "latency_ms",
+];
+
+// Event feed for integrations that poll instead of receiving webhooks
+const EVENT_PAGE_SIZE: i64 = 100;
+const EVENT_MAX_PAGE_SIZE: i64 = 1000;
+
+async fn publish_event(pool: &SqlitePool, event_type: &str, data: serde_json::Value) {
+    if let Err(e) = db::record_event(pool, event_type, &data).await {
+        eprintln!("Failed to record {} event: {:?}", event_type, e);
+    }
+}
+
+async fn handle_get_events(pool: &SqlitePool, token: Option<&str>, since_id: i64, event_type: Option<&str>, limit: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    // One extra row tells whether another page is waiting
+    let mut events = match db::get_events_after(pool, since_id, event_type, limit + 1).await {
+        Ok(events) => events,
+        Err(_) => return error_response(500, "Failed to fetch events"),
+    };
+    let has_more = events.len() as i64 > limit;
+    events.truncate(limit as usize);
+    let next_since_id = events.last().map(|e| e.id).unwrap_or(since_id);
+    
+    success_response(json!({ "events": events, "next_since_id": next_since_id, "has_more": has_more }))
+}
+
+async fn handle_get_access_log(pool: &SqlitePool, token: Option<&str>, query: &AccessLogQuery, format: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_access_log(pool, query).await {
+        Ok(entries) => listing_response(&entries, format, "access-log", ACCESS_LOG_CSV_COLUMNS),
+        Err(_) => error_response(500, "Failed to fetch access log"),
+    }
+}
+
+async fn handle_get_settings(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    success_response(settings::all())
+}
+
+async fn handle_update_settings(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let values: serde_json::Map<String, serde_json::Value> = match serde_json::from_str(body) {
+        Ok(values) => values,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    match settings::update(pool, &values).await {
+        Ok(_) => success_response(settings::all()),
+        Err(msg) => error_response(400, &msg),
+    }
+}
+
+// Email template handlers
+const TEMPLATE_SUBJECT_MAX_CHARS: usize = 200;
+const TEMPLATE_BODY_MAX_CHARS: usize = 10_000;
+
+// The wording in use for a template, with its placeholders and whether the library edited it
+async fn email_template_view(pool: &SqlitePool, template: &notify::Template) -> Result<serde_json::Value, sqlx::Error> {
+    let custom = db::get_email_template(pool, template.key).await?;
+    Ok(json!({
+        "key": template.key,
+        "description": template.description,
+        "variables": template.variables,
+        "subject": custom.as_ref().map(|c| c.subject.as_str()).unwrap_or(template.subject),
+        "body": custom.as_ref().map(|c| c.body.as_str()).unwrap_or(template.body),
+        "customized": custom.is_some(),
+        "updated_by": custom.as_ref().and_then(|c| c.updated_by),
+        "updated_at": custom.as_ref().map(|c| c.updated_at.as_str()),
+        "default_subject": template.subject,
+        "default_body": template.body,
+    }))
+}
+
+// Staff alert webhook handlers
+fn validate_webhook_events(events: &[String]) -> Result<Vec<String>, String> {
+    if let Some(unknown) = events.iter().find(|e| !alerts::is_event(e)) {
+        return Err(format!("Unknown event: {}", unknown));
+    }
+    let mut events = events.to_vec();
+    events.sort();
+    events.dedup();
+    Ok(events)
+}
+
+async fn handle_get_webhooks(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_webhooks(pool).await {
+        Ok(webhooks) => success_response(serde_json::to_value(webhooks).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch webhooks"),
+    }
+}
+
+async fn handle_get_webhook_events(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let events: Vec<_> = alerts::EVENTS.iter().map(|e| json!({ "key": e.key, "description": e.description })).collect();
+    success_response(json!(events))
+}
+
+async fn handle_create_webhook(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: CreateWebhookRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    req.service = req.service.trim().to_lowercase();
+    req.url = req.url.trim().to_string();
+    if !alerts::SERVICES.contains(&req.service.as_str()) {
+        return error_response(400, "Service must be 'slack' or 'discord'");
+    }
+    if let Err(msg) = http::check_public_url(&req.url) {
+        return error_response(400, &format!("Invalid webhook URL: {}", msg));
+    }
+    req.events = match validate_webhook_events(&req.events) {
+        Ok(events) => events,
+        Err(msg) => return error_response(400, &msg),
+    };
+    
+    let id = match db::create_webhook(pool, &req, admin.id).await {
+        Ok(id) => id,
+        Err(_) => return error_response(500, "Failed to create webhook"),
+    };
+    
+    let details = json!({ "service": req.service, "events": req.events });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "webhook.create", "webhook", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_webhook(pool, id).await {
+        Ok(Some(webhook)) => created_response(serde_json::to_value(webhook).unwrap()),
+        _ => error_response(500, "Failed to retrieve webhook"),
+    }
+}
+
+async fn handle_update_webhook(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: UpdateWebhookRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let webhook = match db::get_webhook(pool, id).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return error_response(404, "Webhook not found"),
+        Err(_) => return error_response(500, "Failed to update webhook"),
+    };
+    
+    let events = match validate_webhook_events(req.events.as_ref().unwrap_or(&webhook.events)) {
+        Ok(events) => events,
+        Err(msg) => return error_response(400, &msg),
+    };
+    let enabled = req.enabled.unwrap_or(webhook.enabled);
+    
+    match db::update_webhook(pool, id, &events, enabled).await {
+        Ok(true) => {}
+        Ok(false) => return error_response(404, "Webhook not found"),
+        Err(_) => return error_response(500, "Failed to update webhook"),
+    }
+    
+    let details = json!({ "events": events, "enabled": enabled });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "webhook.update", "webhook", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_webhook(pool, id).await {
+        Ok(Some(webhook)) => success_response(serde_json::to_value(webhook).unwrap()),
+        _ => error_response(500, "Failed to retrieve webhook"),
+    }
+}
+
+async fn handle_delete_webhook(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    match db::delete_webhook(pool, id).await {
+        Ok(true) => {
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "webhook.delete", "webhook", Some(id), &json!({})).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            success_response(json!({ "message": "Webhook deleted" }))
+        }
+        Ok(false) => error_response(404, "Webhook not found"),
+        Err(_) => error_response(500, "Failed to delete webhook"),
+    }
+}
+
+// Sends a sample message straight away so admins can check the URL before relying on it
+async fn handle_test_webhook(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let webhook = match db::get_webhook(pool, id).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return error_response(404, "Webhook not found"),
+        Err(_) => return error_response(500, "Failed to fetch webhook"),
+    };
+    
+    match alerts::deliver(pool, &webhook, "Test alert", "Library alerts will be posted here.").await {
+        Ok(_) => success_response(json!({ "message": "Test message delivered" })),
+        Err(e) => error_response(502, &format!("Delivery failed: {}", e)),
+    }
+}
+
+async fn handle_get_email_templates(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let mut templates = Vec::new();
+    for template in notify::TEMPLATES {
+        match email_template_view(pool, template).await {
+            Ok(view) => templates.push(view),
+            Err(_) => return error_response(500, "Failed to fetch templates"),
+        }
+    }
+    success_response(json!(templates))
+}
+
+async fn handle_get_email_template(pool: &SqlitePool, token: Option<&str>, key: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let Some(template) = notify::template(key) else {
+        return error_response(404, "Template not found");
+    };
+    match email_template_view(pool, template).await {
+        Ok(view) => success_response(view),
+        Err(_) => error_response(500, "Failed to fetch template"),
+    }
+}
+
+async fn handle_update_email_template(pool: &SqlitePool, token: Option<&str>, key: &str, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let Some(template) = notify::template(key) else {
+        return error_response(404, "Template not found");
+    };
+    
+    let req: UpdateEmailTemplateRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let subject = req.subject.trim();
+    if subject.is_empty() || subject.contains('\n') || subject.chars().count() > TEMPLATE_SUBJECT_MAX_CHARS {
+        return error_response(400, &format!("Subject must be a single line of 1 to {} characters", TEMPLATE_SUBJECT_MAX_CHARS));
+    }
+    if req.body.trim().is_empty() || req.body.chars().count() > TEMPLATE_BODY_MAX_CHARS {
+        return error_response(400, &format!("Body must be 1 to {} characters", TEMPLATE_BODY_MAX_CHARS));
+    }
+    if let Err(msg) = notify::validate(template, subject).and_then(|_| notify::validate(template, &req.body)) {
+        return error_response(400, &msg);
+    }
+    
+    if db::upsert_email_template(pool, template.key, subject, &req.body, admin.id).await.is_err() {
+        return error_response(500, "Failed to save template");
+    }
+    
+    let details = json!({ "key": template.key, "subject": subject, "body": req.body });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "template.update", "email_template", None, &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match email_template_view(pool, template).await {
+        Ok(view) => success_response(view),
+        Err(_) => error_response(500, "Failed to fetch template"),
+    }
+}
+
+// Drops the library's wording so the built-in default applies again
+async fn handle_reset_email_template(pool: &SqlitePool, token: Option<&str>, key: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let Some(template) = notify::template(key) else {
+        return error_response(404, "Template not found");
+    };
+    
+    match db::delete_email_template(pool, template.key).await {
+        Ok(true) => {
+            let details = json!({ "key": template.key });
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "template.reset", "email_template", None, &details).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+        }
+        Ok(false) => {}
+        Err(_) => return error_response(500, "Failed to reset template"),
+    }
+    
+    match email_template_view(pool, template).await {
+        Ok(view) => success_response(view),
+        Err(_) => error_response(500, "Failed to fetch template"),
+    }
+}
+
+// Announcement handlers
+// Timestamps are stored as UTC RFC 3339 so they compare correctly as strings
+fn normalize_timestamp(value: &mut Option<String>) -> Result<(), &'static str> {
+    if let Some(text) = value.as_ref() {
+        let parsed = chrono::DateTime::parse_from_rfc3339(text).map_err(|_| "Timestamps must be RFC 3339")?;
+        *value = Some(parsed.with_timezone(&chrono::Utc).to_rfc3339());
+    }
+    Ok(())
+}
+
+fn validate_announcement_level(level: Option<&String>) -> Result<(), &'static str> {
+    match level {
+        Some(level) if !["info", "warning", "closure"].contains(&level.as_str()) => {
+            Err("Level must be info, warning, or closure")
+        }
+        _ => Ok(()),
+    }
+}
+
+// An announcement that ends before it starts would never be shown
+fn check_announcement_window(starts_at: &str, ends_at: Option<&str>) -> Result<(), &'static str> {
+    let parse = |text: &str| chrono::DateTime::parse_from_rfc3339(text).ok();
+    match (parse(starts_at), ends_at.map(parse)) {
+        (Some(start), Some(Some(end))) if end <= start => Err("ends_at must be after starts_at"),
+        _ => Ok(()),
+    }
+}
+
+async fn handle_get_active_announcements(pool: &SqlitePool) -> String {
+    match db::get_active_announcements(pool).await {
+        Ok(announcements) => success_response(serde_json::to_value(announcements).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch announcements"),
+    }
+}
+
+async fn handle_get_announcements(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_all_announcements(pool).await {
+        Ok(announcements) => success_response(serde_json::to_value(announcements).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch announcements"),
+    }
+}
+
+async fn handle_create_announcement(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: CreateAnnouncementRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if req.title.is_empty() || req.message.is_empty() {
+        return error_response(400, "Missing required fields");
+    }
+    
+    if let Err(msg) = validate_announcement_level(req.level.as_ref())
+        .and_then(|_| normalize_timestamp(&mut req.starts_at))
+        .and_then(|_| normalize_timestamp(&mut req.ends_at))
+    {
+        return error_response(400, msg);
+    }
+    
+    let starts_at = req.starts_at.clone().unwrap_or_else(|| clock::now().to_rfc3339());
+    if let Err(msg) = check_announcement_window(&starts_at, req.ends_at.as_deref()) {
+        return error_response(400, msg);
+    }
+    
+    match db::create_announcement(pool, &req, admin.id).await {
+        Ok(id) => {
+            let announcement = db::get_announcement_by_id(pool, id).await.ok().flatten();
+            if let Some(announcement) = announcement {
+                created_response(serde_json::to_value(announcement).unwrap())
+            } else {
+                error_response(500, "Failed to retrieve announcement")
+            }
+        }
+        Err(_) => error_response(500, "Failed to create announcement"),
+    }
+}
+
+async fn handle_update_announcement(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let req: UpdateAnnouncementRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let announcement = match db::get_announcement_by_id(pool, id).await {
+        Ok(Some(announcement)) => announcement,
+        Ok(None) => return error_response(404, "Announcement not found"),
+        Err(_) => return error_response(500, "Failed to fetch announcement"),
+    };
+    
+    // An empty or null ends_at clears it, leaving the announcement up until it is removed
+    let mut starts_at = req.starts_at;
+    let mut ends_at = match req.ends_at {
+        Some(ends_at) => ends_at.filter(|e| !e.trim().is_empty()),
+        None => announcement.ends_at,
+    };
+    if let Err(msg) = validate_announcement_level(req.level.as_ref())
+        .and_then(|_| normalize_timestamp(&mut starts_at))
+        .and_then(|_| normalize_timestamp(&mut ends_at))
+    {
+        return error_response(400, msg);
+    }
+    
+    let starts_at = starts_at.unwrap_or(announcement.starts_at);
+    if let Err(msg) = check_announcement_window(&starts_at, ends_at.as_deref()) {
+        return error_response(400, msg);
+    }
+    
+    let title = req.title.unwrap_or(announcement.title);
+    let message = req.message.unwrap_or(announcement.message);
+    let level = req.level.unwrap_or(announcement.level);
+    match db::update_announcement(pool, id, &title, &message, &level, &starts_at, ends_at.as_deref()).await {
+        Ok(_) => {
+            let announcement = db::get_announcement_by_id(pool, id).await.ok().flatten();
+            if let Some(announcement) = announcement {
+                success_response(serde_json::to_value(announcement).unwrap())
+            } else {
+                error_response(500, "Failed to retrieve updated announcement")
+            }
+        }
+        Err(_) => error_response(404, "Announcement not found"),
+    }
+}
+
+async fn handle_delete_announcement(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::delete_announcement(pool, id).await {
+        Ok(_) => success_response(json!({ "message": "Announcement deleted successfully" })),
+        Err(_) => error_response(404, "Announcement not found"),
+    }
+}
+
+// Broadcast handlers
+async fn handle_create_broadcast(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: CreateBroadcastRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if req.subject.trim().is_empty() || req.body.trim().is_empty() {
+        return error_response(400, "Missing required fields");
+    }
+    
+    let audience = req.audience.as_deref().unwrap_or("all");
+    if !notify::BROADCAST_AUDIENCES.contains(&audience) {
+        return error_response(400, "Audience must be all, overdue, or holds_ready");
+    }
+    
+    // Loans that fell due since the last sweep count as overdue for targeting
+    if audience == "overdue" {
+        if let Err(e) = db::mark_overdue_records(pool).await {
+            eprintln!("Failed to mark overdue records: {:?}", e);
+        }
+    }
+    
+    let id = match db::create_broadcast(pool, &req, audience, admin.id).await {
+        Ok(id) => id,
+        Err(_) => return error_response(500, "Failed to queue broadcast"),
+    };
+    
+    match db::get_broadcast_by_id(pool, id).await {
+        Ok(Some(broadcast)) => {
+            let details = json!({ "audience": audience, "subject": req.subject, "recipients": broadcast.total });
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "message.broadcast", "broadcast", Some(id), &details).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            created_response(serde_json::to_value(broadcast).unwrap())
+        }
+        _ => error_response(500, "Failed to retrieve broadcast"),
+    }
+}
+
+async fn handle_get_broadcasts(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_broadcasts(pool).await {
+        Ok(broadcasts) => success_response(serde_json::to_value(broadcasts).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch broadcasts"),
+    }
+}
+
+async fn handle_get_broadcast(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_broadcast_by_id(pool, id).await {
+        Ok(Some(broadcast)) => success_response(serde_json::to_value(broadcast).unwrap()),
+        Ok(None) => error_response(404, "Broadcast not found"),
+        Err(_) => error_response(500, "Failed to fetch broadcast"),
+    }
+}
+
+// Branch handlers
+async fn handle_get_branches(pool: &SqlitePool) -> String {
+    match db::get_all_branches(pool).await {
+        Ok(branches) => success_response(serde_json::to_value(branches).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch branches"),
+    }
+}
+
+// Same list as /api/branches, but behind a staff sign-in like the rest of /api/admin/
+async fn handle_get_admin_branches(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    handle_get_branches(pool).await
+}
+
+async fn handle_create_branch(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: CreateBranchRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    req.name = req.name.trim().to_string();
+    req.code = req.code.trim().to_uppercase();
+    if req.name.is_empty() || req.code.is_empty() {
+        return error_response(400, "Name and code are required");
+    }
+    
+    match db::create_branch(pool, &req).await {
+        Ok(id) => {
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "branch.create", "branch", Some(id), &json!({ "name": req.name, "code": req.code })).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            match db::get_branch_by_id(pool, id).await {
+                Ok(Some(branch)) => created_response(serde_json::to_value(branch).unwrap()),
+                _ => error_response(500, "Failed to retrieve branch"),
+            }
+        }
+        Err(_) => error_response(409, "Branch code already exists"),
+    }
+}
+
+async fn handle_update_branch(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let req: UpdateBranchRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let branch = match db::get_branch_by_id(pool, id).await {
+        Ok(Some(branch)) => branch,
+        _ => return error_response(404, "Branch not found"),
+    };
+    
+    let name = req.name.map(|n| n.trim().to_string()).unwrap_or(branch.name);
+    let code = req.code.map(|c| c.trim().to_uppercase()).unwrap_or(branch.code);
+    if name.is_empty() || code.is_empty() {
+        return error_response(400, "Name and code are required");
+    }
+    
+    // An empty address clears it
+    let address = match req.address.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(address) => Some(address.to_string()),
+        None => branch.address,
+    };
+    
+    match db::update_branch(pool, id, &name, &code, address.as_deref()).await {
+        Ok(_) => match db::get_branch_by_id(pool, id).await {
+            Ok(Some(branch)) => success_response(serde_json::to_value(branch).unwrap()),
+            _ => error_response(500, "Failed to retrieve updated branch"),
+        },
+        Err(_) => error_response(409, "Branch code already exists"),
+    }
+}
+
+async fn handle_delete_branch(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    if id == db::MAIN_BRANCH_ID {
+        return error_response(409, "The main branch cannot be deleted");
+    }
+    
+    match db::count_branch_references(pool, id).await {
+        Ok((0, 0, 0)) => {}
+        Ok((copies, members, loans)) => {
+            return error_response(409, &format!(
+                "Branch still has {} copies, {} members, and {} loans on record",
+                copies, members, loans
+            ));
+        }
+        Err(_) => return error_response(500, "Failed to delete branch"),
+    }
+    
+    match db::delete_branch(pool, id).await {
+        Ok(_) => {
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "branch.delete", "branch", Some(id), &json!({})).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            success_response(json!({ "message": "Branch deleted successfully" }))
+        }
+        Err(_) => error_response(404, "Branch not found"),
+    }
+}
+
+async fn handle_get_book_holdings(pool: &SqlitePool, book_id: i64) -> String {
+    match db::get_book_by_id(pool, book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    }
+    
+    match db::get_book_holdings(pool, book_id).await {
+        Ok(holdings) => success_response(serde_json::to_value(holdings).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch branch availability"),
+    }
+}
+
+async fn handle_set_branch_copies(pool: &SqlitePool, token: Option<&str>, book_id: i64, branch_id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let req: SetBranchCopiesRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if req.copies < 0 {
+        return error_response(400, "Copies must not be negative");
+    }
+    
+    match (db::get_book_by_id(pool, book_id).await, db::get_branch_by_id(pool, branch_id).await) {
+        (Ok(Some(_)), Ok(Some(_))) => {}
+        (Ok(None), _) => return error_response(404, "Book not found"),
+        (_, Ok(None)) => return error_response(404, "Branch not found"),
+        _ => return error_response(500, "Failed to update branch copies"),
+    }
+    
+    let on_loan = match db::get_book_holdings(pool, book_id).await {
+        Ok(holdings) => holdings.iter().find(|h| h.branch_id == branch_id).map(|h| h.copies - h.available_copies).unwrap_or(0),
+        Err(_) => return error_response(500, "Failed to update branch copies"),
+    };
+    if req.copies < on_loan {
+        return error_response(409, &format!("{} copies from this branch are on loan", on_loan));
+    }
+    
+    if db::set_branch_copies(pool, book_id, branch_id, req.copies).await.is_err() {
+        return error_response(500, "Failed to update branch copies");
+    }
+    
+    let details = json!({ "branch_id": branch_id, "copies": req.copies });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), "book.branch_copies", "book", Some(book_id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    release_to_queue(pool, book_id).await;
+    
+    match db::get_book_holdings(pool, book_id).await {
+        Ok(holdings) => success_response(serde_json::to_value(holdings).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch branch availability"),
+    }
+}
+
+async fn handle_set_home_branch(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageMembers).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    match update_home_branch(pool, id, body).await {
+        Ok(user) => {
+            let details = json!({ "home_branch_id": user.home_branch_id });
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "user.home_branch", "user", Some(id), &details).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            success_response(serde_json::to_value(user).unwrap())
+        }
+        Err((code, msg)) => error_response(code, msg),
+    }
+}
+
+async fn handle_set_own_home_branch(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match update_home_branch(pool, user.id, body).await {
+        Ok(user) => success_response(serde_json::to_value(user).unwrap()),
+        Err((code, msg)) => error_response(code, msg),
+    }
+}
+
+async fn update_home_branch(pool: &SqlitePool, user_id: i64, body: &str) -> Result<User, (u16, &'static str)> {
+    let req: SetHomeBranchRequest = serde_json::from_str(body).map_err(|_| (400, "Invalid request body"))?;
+    
+    if let Some(branch_id) = req.branch_id {
+        match db::get_branch_by_id(pool, branch_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err((400, "Unknown branch")),
+            Err(_) => return Err((500, "Failed to update home branch")),
+        }
+    }
+    
+    db::set_home_branch(pool, user_id, req.branch_id).await.map_err(|_| (404, "User not found"))?;
+    
+    match db::get_user_by_id(pool, user_id).await {
+        Ok(Some(user)) => Ok(user),
+        _ => Err((500, "Failed to retrieve user")),
+    }
+}
+
+async fn handle_get_notification_preferences(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::wants_digest(pool, user.id).await {
+        Ok(digest) => success_response(json!({ "digest": digest })),
+        Err(_) => error_response(500, "Failed to fetch notification preferences"),
+    }
+}
+
+async fn handle_update_notification_preferences(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let req: NotificationPreferencesRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    match db::set_digest_preference(pool, user.id, req.digest).await {
+        Ok(_) => success_response(json!({ "digest": req.digest })),
+        Err(_) => error_response(500, "Failed to update notification preferences"),
+    }
+}
+
+// Creates the first admin with the setup token from the log; gone once any admin exists
+async fn handle_setup(pool: &SqlitePool, body: &str) -> String {
+    let req: SetupRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    match db::count_active_admins(pool).await {
+        Ok(0) => {}
+        Ok(_) => return error_response(404, "Not Found"),
+        Err(_) => return error_response(500, "Failed to check for an admin account"),
+    }
+    let Some(token) = bootstrap::claim(&req.token) else {
+        return error_response(403, "Invalid setup token");
+    };
+    
+    let id = match bootstrap::create_admin(pool, &req.username, req.email.trim(), &req.password).await {
+        Ok(id) => id,
+        Err(msg) => {
+            bootstrap::restore(token);
+            return error_response(422, &msg);
+        }
+    };
+    if let Err(e) = db::record_audit(pool, Some(id), "setup.admin_created", "user", Some(id), &json!({ "username": req.username.trim() })).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    match db::get_user_by_id(pool, id).await {
+        Ok(Some(user)) => created_response(serde_json::to_value(user).unwrap()),
+        _ => error_response(500, "Failed to retrieve user"),
+    }
+}
+
+async fn handle_get_language_preference(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_user_language(pool, user.id).await {
+        Ok(language) => success_response(json!({ "language": language })),
+        Err(_) => error_response(500, "Failed to fetch language preference"),
+    }
+}
+
+async fn handle_update_language_preference(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let req: LanguagePreferenceRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    let language = req.language.map(|l| l.trim().to_ascii_lowercase());
+    if language.as_deref().is_some_and(|l| !i18n::is_supported(l)) {
+        return error_response(400, &format!("Language must be one of: {}", i18n::languages().join(", ")));
+    }
+    
+    match db::set_user_language(pool, user.id, language.as_deref()).await {
+        Ok(_) => success_response(json!({ "language": language })),
+        Err(_) => error_response(500, "Failed to update language preference"),
+    }
+}
+
+// Push subscription handlers
+async fn handle_get_push_public_key(pool: &SqlitePool) -> String {
+    let key = match notify::vapid_private_key(pool).await {
+        Ok(key) => key,
+        Err(_) => return error_response(500, "Failed to load push key"),
+    };
+    
+    match push::public_key(&key) {
+        Ok(public_key) => success_response(json!({ "public_key": public_key })),
+        Err(e) => {
+            eprintln!("Web Push key is unusable: {}", e);
+            error_response(500, "Push notifications are not available")
+        }
+    }
+}
+
+async fn handle_get_push_subscriptions(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_push_subscriptions(pool, user.id).await {
+        Ok(subscriptions) => success_response(serde_json::to_value(subscriptions).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch push subscriptions"),
+    }
+}
+
+async fn handle_create_push_subscription(pool: &SqlitePool, token: Option<&str>, body: &str, user_agent: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let req: PushSubscriptionRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    let checked = http::check_public_url(&req.endpoint)
+        .map_err(|e| format!("Invalid push endpoint: {}", e))
+        .and_then(|_| push::check_subscription_keys(&req.keys.p256dh, &req.keys.auth));
+    if let Err(msg) = checked {
+        return error_response(400, &msg);
+    }
+    
+    let user_agent: Option<String> = user_agent.map(|ua| ua.chars().take(MAX_USER_AGENT_CHARS).collect());
+    match db::upsert_push_subscription(pool, user.id, &req, user_agent.as_deref()).await {
+        Ok(subscription) => created_response(serde_json::to_value(subscription).unwrap()),
+        Err(_) => error_response(500, "Failed to save push subscription"),
+    }
+}
+
+async fn handle_delete_push_subscription(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::delete_push_subscription(pool, user.id, id).await {
+        Ok(true) => success_response(json!({ "message": "Push subscription removed" })),
+        Ok(false) => error_response(404, "Push subscription not found"),
+        Err(_) => error_response(500, "Failed to remove push subscription"),
+    }
+}
+
+// Location handlers
+async fn handle_get_locations(pool: &SqlitePool, token: Option<&str>, branch_id: Option<i64>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_locations(pool, branch_id).await {
+        Ok(locations) => success_response(serde_json::to_value(locations).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch locations"),
+    }
+}
+
+async fn handle_create_location(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: CreateLocationRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    req.room = req.room.trim().to_string();
+    req.shelf = req.shelf.trim().to_string();
+    req.call_number_prefix = req.call_number_prefix.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+    if req.room.is_empty() || req.shelf.is_empty() {
+        return error_response(400, "Room and shelf are required");
+    }
+    
+    match db::get_branch_by_id(pool, req.branch_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(400, "Unknown branch"),
+        Err(_) => return error_response(500, "Failed to create location"),
+    }
+    
+    match db::create_location(pool, &req).await {
+        Ok(id) => {
+            let details = json!({ "branch_id": req.branch_id, "room": req.room, "shelf": req.shelf });
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "location.create", "location", Some(id), &details).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            match db::get_location_by_id(pool, id).await {
+                Ok(Some(location)) => created_response(serde_json::to_value(location).unwrap()),
+                _ => error_response(500, "Failed to retrieve location"),
+            }
+        }
+        Err(_) => error_response(409, "That shelf already exists at this branch"),
+    }
+}
+
+async fn handle_update_location(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageCatalog).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let req: UpdateLocationRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let location = match db::get_location_by_id(pool, id).await {
+        Ok(Some(location)) => location,
+        _ => return error_response(404, "Location not found"),
+    };
+    
+    let room = req.room.map(|r| r.trim().to_string()).unwrap_or(location.room);
+    let shelf = req.shelf.map(|s| s.trim().to_string()).unwrap_or(location.shelf);
+    if room.is_empty() || shelf.is_empty() {
+        return error_response(400, "Room and shelf are required");
+    }
+    
+    // An empty prefix clears it
+    let call_number_prefix = match req.call_number_prefix.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(prefix) => Some(prefix.to_string()),
+        None => location.call_number_prefix,
+    };
+    
+    match db::update_location(pool, id, &room, &shelf, call_number_prefix.as_deref()).await {
+        Ok(_) => match db::get_location_by_id(pool, id).await {
+            Ok(Some(location)) => success_response(serde_json::to_value(location).unwrap()),
+            _ => error_response(500, "Failed to retrieve updated location"),
+        },
+        Err(_) => error_response(409, "That shelf already exists at this branch"),
+    }
+}
+
+async fn handle_delete_location(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCatalog).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    match db::count_location_titles(pool, id).await {
+        Ok(0) => {}
+        Ok(titles) => return error_response(409, &format!("{} titles are still shelved here; move them first", titles)),
+        Err(_) => return error_response(500, "Failed to delete location"),
+    }
+    
+    match db::delete_location(pool, id).await {
+        Ok(_) => {
+            if let Err(e) = db::record_audit(pool, Some(admin.id), "location.delete", "location", Some(id), &json!({})).await {
+                eprintln!("Failed to record audit entry: {:?}", e);
+            }
+            success_response(json!({ "message": "Location deleted successfully" }))
+        }
+        Err(_) => error_response(404, "Location not found"),
+    }
+}
+
+async fn handle_set_copies_location(pool: &SqlitePool, token: Option<&str>, book_id: i64, branch_id: i64, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageCatalog).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let req: SetLocationRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if let Some(location_id) = req.location_id {
+        match db::get_location_by_id(pool, location_id).await {
+            Ok(Some(location)) if location.branch_id == branch_id => {}
+            Ok(Some(_)) => return error_response(400, "Location belongs to another branch"),
+            Ok(None) => return error_response(400, "Unknown location"),
+            Err(_) => return error_response(500, "Failed to update location"),
+        }
+    }
+    
+    if db::set_copies_location(pool, book_id, branch_id, req.location_id).await.is_err() {
+        return error_response(404, "Branch holds no copies of this book");
+    }
+    
+    match db::get_book_holdings(pool, book_id).await {
+        Ok(holdings) => success_response(serde_json::to_value(holdings).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch branch availability"),
+    }
+}
+
+async fn handle_get_shelf_list(pool: &SqlitePool, token: Option<&str>, id: i64, format: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let location = match db::get_location_by_id(pool, id).await {
+        Ok(Some(location)) => location,
+        Ok(None) => return error_response(404, "Location not found"),
+        Err(_) => return error_response(500, "Failed to fetch location"),
+    };
+    
+    match db::get_shelf_list(pool, id).await {
+        Ok(entries) => {
+            let name = format!("shelf-list-{}-{}", location.room, location.shelf)
+                .to_lowercase()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect::<String>();
+            listing_response(&entries, format, &name, SHELF_LIST_CSV_COLUMNS)
+        }
+        Err(_) => error_response(500, "Failed to fetch shelf list"),
+    }
+}
+
+async fn handle_get_book_labels(pool: &SqlitePool, token: Option<&str>, id: i64, path: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageCatalog).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    if query_param(path, "format").is_some_and(|f| f != "pdf") {
+        return error_response(400, "Format must be 'pdf'");
+    }
+    let sheet_name = query_param(path, "sheet").unwrap_or_else(|| "letter".to_string());
+    let sheet = match labels::sheet(&sheet_name) {
+        Some(sheet) => sheet,
+        None => {
+            let names: Vec<&str> = labels::SHEETS.iter().map(|s| s.name).collect();
+            return error_response(400, &format!("Sheet must be one of: {}", names.join(", ")));
+        }
+    };
+    
+    let book = match db::get_book_by_id(pool, id).await {
+        Ok(Some(book)) => book,
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    };
+    let holdings = match db::get_book_holdings(pool, id).await {
+        Ok(holdings) => holdings,
+        Err(_) => return error_response(500, "Failed to fetch holdings"),
+    };
+    
+    // Copies are numbered through the branches in order, so each has a stable item number
+    let mut all = Vec::new();
+    for holding in &holdings {
+        for _ in 0..holding.copies {
+            all.push(labels::Label {
+                barcode: format!("B{}-{}", book.id, all.len() + 1),
+                call_number: labels::call_number(holding.call_number_prefix.as_deref(), &book.author, book.publication_year),
+                title: book.title.clone(),
+                branch: holding.branch_name.clone(),
+            });
+        }
+    }
+    if all.is_empty() {
+        return error_response(400, "Book has no copies to label");
+    }
+    
+    // A count labels the newest copies only, e.g. the ones just added
+    let copies = query_param(path, "copies").unwrap_or_else(|| "all".to_string());
+    let skip = match copies.as_str() {
+        "all" => 0,
+        n => match n.parse::<usize>() {
+            Ok(n) if n > 0 => all.len().saturating_sub(n),
+            _ => return error_response(400, "Copies must be 'all' or a positive number"),
+        },
+    };
+    
+    text_response(labels::PDF_TYPE, &labels::pdf(sheet, &all[skip..]))
+}
+
+// Transfer handlers
+async fn handle_create_transfer(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    let req: CreateTransferRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    match db::get_book_by_id(pool, req.book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(404, "Book not found"),
+        Err(_) => return error_response(500, "Failed to fetch book"),
+    }
+    
+    let Some(to_branch_id) = req.to_branch_id.or(user.home_branch_id) else {
+        return error_response(400, "Choose a destination branch or set a home branch");
+    };
+    match db::get_branch_by_id(pool, to_branch_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(400, "Unknown branch"),
+        Err(_) => return error_response(500, "Failed to create transfer"),
+    }
+    
+    let holdings = match db::get_book_holdings(pool, req.book_id).await {
+        Ok(holdings) => holdings,
+        Err(_) => return error_response(500, "Failed to check availability"),
+    };
+    if holdings.iter().any(|h| h.branch_id == to_branch_id && h.available_copies > 0) {
+        return error_response(409, "A copy is already available at that branch");
+    }
+    
+    // Without an explicit source, ask the branch with the most copies free
+    let from_branch_id = match req.from_branch_id {
+        Some(id) if id == to_branch_id => return error_response(400, "Source and destination must differ"),
+        Some(id) if holdings.iter().any(|h| h.branch_id == id && h.available_copies > 0) => id,
+        Some(_) => return error_response(409, "No copy is available at that branch"),
+        None => match holdings.iter().filter(|h| h.available_copies > 0).max_by_key(|h| h.available_copies) {
+            Some(h) => h.branch_id,
+            None => return error_response(409, "No branch has a copy available; reserve the book instead"),
+        },
+    };
+    
+    match db::has_open_transfer(pool, user.id, req.book_id).await {
+        Ok(true) => return error_response(409, "You already have a transfer under way for this book"),
+        Ok(false) => {}
+        Err(_) => return error_response(500, "Failed to create transfer"),
+    }
+    
+    match db::create_transfer(pool, user.id, req.book_id, from_branch_id, to_branch_id).await {
+        Ok(id) => match db::get_transfer_by_id(pool, id).await {
+            Ok(Some(transfer)) => created_response(serde_json::to_value(transfer).unwrap()),
+            _ => error_response(500, "Failed to retrieve transfer"),
+        },
+        Err(_) => error_response(500, "Failed to create transfer"),
+    }
+}
+
+async fn handle_get_my_transfers(pool: &SqlitePool, token: Option<&str>) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_user_transfers(pool, user.id).await {
+        Ok(transfers) => success_response(serde_json::to_value(transfers).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch transfers"),
+    }
+}
+
+async fn handle_cancel_my_transfer(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    let user = match authenticate(pool, token).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(401, &msg),
+    };
+    
+    match db::get_transfer_by_id(pool, id).await {
+        Ok(Some(transfer)) if transfer.user_id == user.id => {}
+        _ => return error_response(404, "Transfer not found"),
+    }
+    
+    if db::cancel_transfer(pool, id, None).await.is_err() {
+        return error_response(409, "Transfer has already been sent");
+    }
+    
+    match db::get_transfer_by_id(pool, id).await {
+        Ok(Some(transfer)) => success_response(serde_json::to_value(transfer).unwrap()),
+        _ => error_response(500, "Failed to retrieve transfer"),
+    }
+}
+
+async fn handle_get_transfers(pool: &SqlitePool, token: Option<&str>, status: Option<&str>, branch_id: Option<i64>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    if status.is_some_and(|s| !["requested", "in_transit", "arrived", "cancelled"].contains(&s)) {
+        return error_response(400, "Status must be requested, in_transit, arrived, or cancelled");
+    }
+    
+    match db::get_transfers(pool, status, branch_id).await {
+        Ok(transfers) => success_response(serde_json::to_value(transfers).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch transfers"),
+    }
+}
+
+// Moves a transfer along requested -> in_transit -> arrived, or cancels it before it is sent
+async fn handle_advance_transfer(pool: &SqlitePool, token: Option<&str>, id: i64, status: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageCirculation).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let transfer = match db::get_transfer_by_id(pool, id).await {
+        Ok(Some(transfer)) => transfer,
+        Ok(None) => return error_response(404, "Transfer not found"),
+        Err(_) => return error_response(500, "Failed to fetch transfer"),
+    };
+    
+    let expected = if status == "arrived" { "in_transit" } else { "requested" };
+    if transfer.status != expected {
+        return error_response(409, &format!("Transfer is {}", transfer.status.replace('_', " ")));
+    }
+    
+    let mut hold = None;
+    let result = match status {
+        "in_transit" => {
+            // The copy must still be on the shelf at the source branch
+            let on_shelf = match (db::get_book_holdings(pool, transfer.book_id).await, db::get_book_by_id(pool, transfer.book_id).await) {
+                (Ok(holdings), Ok(Some(book))) => {
+                    book.available_copies > 0 && holdings.iter().any(|h| h.branch_id == transfer.from_branch_id && h.available_copies > 0)
+                }
+                _ => return error_response(500, "Failed to check availability"),
+            };
+            if !on_shelf {
+                return error_response(409, "No copy is available at the source branch");
+            }
+            db::ship_transfer(pool, id, admin.id).await
+        }
+        "arrived" => db::receive_transfer(pool, id, admin.id, settings::reservation_hold_days()).await.map(|r| hold = r),
+        _ => db::cancel_transfer(pool, id, Some(admin.id)).await,
+    };
+    
+    match result {
+        Ok(()) => {}
+        Err(sqlx::Error::RowNotFound) => return error_response(409, "Transfer has already been handled"),
+        Err(_) => return error_response(500, "Failed to update transfer"),
+    }
+    
+    let action = match status {
+        "in_transit" => "transfer.ship",
+        "arrived" => "transfer.receive",
+        _ => "transfer.cancel",
+    };
+    let details = json!({ "book_id": transfer.book_id, "from_branch_id": transfer.from_branch_id, "to_branch_id": transfer.to_branch_id });
+    if let Err(e) = db::record_audit(pool, Some(admin.id), action, "transfer", Some(id), &details).await {
+        eprintln!("Failed to record audit entry: {:?}", e);
+    }
+    
+    let transfer = match db::get_transfer_by_id(pool, id).await {
+        Ok(Some(transfer)) => transfer,
+        _ => return error_response(500, "Failed to retrieve transfer"),
+    };
+    
+    // On arrival the copy is held for the member; if they no longer need it, it goes to the queue
+    let notified = match hold {
+        Some(reservation_id) => match db::get_reservation_by_id(pool, reservation_id).await {
+            Ok(Some(reservation)) => notify::send_hold_ready(pool, &reservation).await,
+            _ => Ok(()),
+        },
+        None if status == "arrived" => {
+            release_to_queue(pool, transfer.book_id).await;
+            notify::send_transfer_update(pool, &transfer).await
+        }
+        None => notify::send_transfer_update(pool, &transfer).await,
+    };
+    if let Err(e) = notified {
+        eprintln!("Failed to send transfer notification: {:?}", e);
+    }
+    
+    success_response(serde_json::to_value(transfer).unwrap())
+}
+
+// Closure handlers
+async fn handle_get_closures(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::get_all_closures(pool).await {
+        Ok(closures) => success_response(serde_json::to_value(closures).unwrap()),
+        Err(_) => error_response(500, "Failed to fetch closures"),
+    }
+}
+
+async fn handle_create_closure(pool: &SqlitePool, token: Option<&str>, body: &str) -> String {
+    let admin = match authenticate_for(pool, token, Permission::ManageSystem).await {
+        Ok(user) => user,
+        Err(msg) => return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg),
+    };
+    
+    let mut req: CreateClosureRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    if req.reason.is_empty() {
+        return error_response(400, "Missing required fields");
+    }
+    
+    let ends_on = req.ends_on.take().unwrap_or_else(|| req.starts_on.clone());
+    match normalize_closure_dates(&req.starts_on, &ends_on) {
+        Ok((starts_on, ends_on)) => {
+            req.starts_on = starts_on;
+            req.ends_on = Some(ends_on);
+        }
+        Err(msg) => return error_response(400, msg),
+    }
+    
+    match db::create_closure(pool, &req, admin.id).await {
+        Ok(id) => match db::get_closure_by_id(pool, id).await {
+            Ok(Some(closure)) => created_response(serde_json::to_value(closure).unwrap()),
+            _ => error_response(500, "Failed to retrieve closure"),
+        },
+        Err(_) => error_response(500, "Failed to create closure"),
+    }
+}
+
+async fn handle_update_closure(pool: &SqlitePool, token: Option<&str>, id: i64, body: &str) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let req: UpdateClosureRequest = match serde_json::from_str(body) {
+        Ok(req) => req,
+        Err(_) => return error_response(400, "Invalid request body"),
+    };
+    
+    let closure = match db::get_closure_by_id(pool, id).await {
+        Ok(Some(closure)) => closure,
+        _ => return error_response(404, "Closure not found"),
+    };
+    
+    let reason = req.reason.unwrap_or(closure.reason);
+    let (starts_on, ends_on) = match normalize_closure_dates(
+        req.starts_on.as_ref().unwrap_or(&closure.starts_on),
+        req.ends_on.as_ref().unwrap_or(&closure.ends_on),
+    ) {
+        Ok(dates) => dates,
+        Err(msg) => return error_response(400, msg),
+    };
+    
+    match db::update_closure(pool, id, &starts_on, &ends_on, &reason).await {
+        Ok(_) => match db::get_closure_by_id(pool, id).await {
+            Ok(Some(closure)) => success_response(serde_json::to_value(closure).unwrap()),
+            _ => error_response(500, "Failed to retrieve updated closure"),
+        },
+        Err(_) => error_response(404, "Closure not found"),
+    }
+}
+
+async fn handle_delete_closure(pool: &SqlitePool, token: Option<&str>, id: i64) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    match db::delete_closure(pool, id).await {
+        Ok(_) => success_response(json!({ "message": "Closure deleted successfully" })),
+        Err(_) => error_response(404, "Closure not found"),
+    }
+}
+
+// Closure dates are compared as strings, so store them zero-padded
+fn normalize_closure_dates(starts_on: &str, ends_on: &str) -> Result<(String, String), &'static str> {
+    let start = chrono::NaiveDate::parse_from_str(starts_on, "%Y-%m-%d").map_err(|_| "Dates must be YYYY-MM-DD")?;
+    let end = chrono::NaiveDate::parse_from_str(ends_on, "%Y-%m-%d").map_err(|_| "Dates must be YYYY-MM-DD")?;
+    if end < start {
+        return Err("ends_on must not be before starts_on");
+    }
+    Ok((start.format("%Y-%m-%d").to_string(), end.format("%Y-%m-%d").to_string()))
+}
+
+// OPDS handlers
+async fn handle_opds_new(pool: &SqlitePool) -> String {
+    let base = &config::get().public_url;
+    match db::get_recent_books(pool, 50).await {
+        Ok(books) => text_response(opds::ACQUISITION_TYPE, &opds::acquisition_feed(base, &format!("{}/opds/new", base), "New Arrivals", &books)),
+        Err(_) => error_response(500, "Failed to fetch books"),
+    }
+}
+
+async fn handle_opds_all(pool: &SqlitePool) -> String {
+    let base = &config::get().public_url;
+    match db::get_all_books(pool).await {
+        Ok(books) => text_response(opds::ACQUISITION_TYPE, &opds::acquisition_feed(base, &format!("{}/opds/all", base), "All Books", &books)),
+        Err(_) => error_response(500, "Failed to fetch books"),
+    }
+}
+
+async fn handle_opds_search(pool: &SqlitePool, query: &str) -> String {
+    if query.chars().count() > search::MAX_QUERY_CHARS {
+        return error_response(400, "Search query is too long");
+    }
+    let base = &config::get().public_url;
+    let self_href = format!("{}/opds/search?q={}", base, urlencoding::encode(query));
+    match db::search_books(pool, &search::parse_query(query)).await {
+        Ok(books) => text_response(opds::ACQUISITION_TYPE, &opds::acquisition_feed(base, &self_href, &format!("Search: {}", query), &books)),
+        Err(_) => error_response(500, "Search failed"),
+    }
+}
+
+// Backup handlers
+async fn handle_backup(pool: &SqlitePool, token: Option<&str>) -> Vec<u8> {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg).into_bytes();
+    }
+    
+    let filename = db::backup_file_name();
+    let path = std::env::temp_dir().join(format!("{}-{}", auth::generate_token(), filename));
+    
+    if let Err(e) = db::backup_to(pool, &path).await {
+        eprintln!("Backup failed: {:?}", e);
+        return error_response(500, "Failed to create backup").into_bytes();
+    }
+    
+    let data = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    
+    match data {
+        Ok(data) => download_response("application/vnd.sqlite3", &filename, &data),
+        Err(_) => error_response(500, "Failed to read backup").into_bytes(),
+    }
+}
+
+// Prometheus scrape endpoint; a scraper authenticates with an API key scoped to admin:read
+async fn handle_get_metrics(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let body = metrics::prometheus();
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nCache-Control: no-store\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+async fn handle_get_metrics_summary(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ViewReports).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    success_response(serde_json::to_value(metrics::summary()).unwrap())
+}
+
+// Scheduled backups kept in LIBRARY_BACKUP_DIR, newest first
+async fn handle_get_backups(pool: &SqlitePool, token: Option<&str>) -> String {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg);
+    }
+    
+    let Some(dir) = &config::get().backup_dir else { return error_response(404, "Backup directory not configured") };
+    match stored_backups(dir) {
+        Ok(backups) => success_response(serde_json::to_value(backups).unwrap()),
+        Err(e) => {
+            eprintln!("Failed to list backups: {}", e);
+            error_response(500, "Failed to list backups")
+        }
+    }
+}
+
+fn stored_backups(dir: &std::path::Path) -> std::io::Result<Vec<StoredBackup>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !db::is_backup_file_name(&name) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        let created_at = metadata.modified().ok().map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339());
+        backups.push(StoredBackup { name, size: metadata.len(), created_at });
+    }
+    // The timestamp in the name sorts chronologically
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+// Stored backups never change, so unlike POST /api/admin/backup an interrupted download can be
+// resumed with a Range request
+async fn handle_download_backup(pool: &SqlitePool, token: Option<&str>, name: &str) -> Vec<u8> {
+    if let Err(msg) = authenticate_for(pool, token, Permission::ManageSystem).await {
+        return error_response(if msg == "Unauthorized" { 401 } else { 403 }, &msg).into_bytes();
+    }
+    
+    let Some(dir) = &config::get().backup_dir else { return error_response(404, "Backup directory not configured").into_bytes() };
+    if !db::is_backup_file_name(name) {
+        return error_response(404, "Backup not found").into_bytes();
+    }
+    
+    match std::fs::read(dir.join(name)) {
+        Ok(data) => download_response("application/vnd.sqlite3", name, &data),
+        Err(e) if e.kind() == ErrorKind::NotFound => error_response(404, "Backup not found").into_bytes(),
+        Err(e) => {
+            eprintln!("Failed to read backup {}: {}", name, e);
+            error_response(500, "Failed to read backup").into_bytes()
+        }
+    }
+}
+
+// A QR code of the book's catalog link, for shelf labels patrons can scan with their phone
+async fn handle_get_book_qr(pool: &SqlitePool, id: i64, scale: Option<&str>) -> Vec<u8> {
+    let scale = match scale.map(|s| s.parse::<usize>()) {
+        None => 8,
+        Some(Ok(scale)) if (1..=20).contains(&scale) => scale,
+        Some(_) => return error_response(400, "Scale must be between 1 and 20").into_bytes(),
+    };
+    
+    match db::get_book_by_id(pool, id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(404, "Book not found").into_bytes(),
+        Err(_) => return error_response(500, "Failed to fetch book").into_bytes(),
+    }
+    
+    let url = format!("{}/lender.html#book={}", config::get().public_url.trim_end_matches('/'), id);
+    match qr::encode(url.as_bytes()) {
+        Some(code) => image_response(png::CONTENT_TYPE, &qr::png(&code, scale)),
+        None => error_response(500, "Catalog link is too long for a QR code").into_bytes(),
+    }
+}
+
+// Scheduled jobs
+fn start_jobs(pool: &SqlitePool) {
+    scheduler::register("overdue_sweep", scheduler::Schedule::Every(chrono::Duration::hours(1)), pool.clone(), overdue_sweep_job);
+    scheduler::register(
+        "session_cleanup",
+        scheduler::Schedule::cron("0 3 * * *").expect("valid cron expression"),
+        pool.clone(),
+        session_cleanup_job,
+    );
+    scheduler::register("login_throttle_save", scheduler::Schedule::Every(chrono::Duration::minutes(1)), pool.clone(), login_throttle_save_job);
+    scheduler::register("hold_expiry", scheduler::Schedule::Every(chrono::Duration::minutes(15)), pool.clone(), hold_expiry_job);
+    scheduler::register(
+        "availability_reconciliation",
+        scheduler::Schedule::cron("30 3 * * *").expect("valid cron expression"),
+        pool.clone(),
+        availability_reconciliation_job,
+    );
+    scheduler::register(
+        "retention_purge",
+        scheduler::Schedule::cron("45 3 * * *").expect("valid cron expression"),
+        pool.clone(),
+        retention_purge_job,
+    );
+    scheduler::register(
+        "fine_accrual",
+        scheduler::Schedule::cron("15 0 * * *").expect("valid cron expression"),
+        pool.clone(),
+        fine_accrual_job,
+    );
+    scheduler::register(
+        "reminders",
+        scheduler::Schedule::cron("0 8 * * *").expect("valid cron expression"),
+        pool.clone(),
+        reminders_job,
+    );
+    scheduler::register("broadcast_delivery", scheduler::Schedule::Every(chrono::Duration::minutes(1)), pool.clone(), broadcast_delivery_job);
+    scheduler::register(
+        "notification_digest",
+        scheduler::Schedule::cron("0 9 * * *").expect("valid cron expression"),
+        pool.clone(),
+        notification_digest_job,
+    );
+    
+    if config::get().backup_dir.is_some() {
+        let hours = config::get().backup_interval_hours.max(1) as i64;
+        scheduler::register("backup", scheduler::Schedule::Every(chrono::Duration::hours(hours)), pool.clone(), backup_job);
+    }
+}
+
+async fn overdue_sweep_job(pool: SqlitePool) -> Result<(), String> {
+    let count = db::mark_overdue_records(&pool).await.map_err(|e| e.to_string())?;
+    if count > 0 {
+        println!("Overdue sweep marked {} lending records overdue", count);
+    }
+    let spike_threshold = settings::overdue_spike_threshold();
+    if spike_threshold > 0 && count as i64 >= spike_threshold {
+        let text = format!("{} loans became overdue in the last hour (alert threshold {})", count, spike_threshold);
+        alerts::raise(&pool, "overdue_spike", "Overdue spike", &text).await;
+    }
+    
+    let overdue_threshold = settings::suspension_overdue_threshold();
+    let fine_threshold = settings::suspension_fine_threshold();
+    for user_id in db::get_users_with_overdue_items_or_fines(&pool).await.map_err(|e| e.to_string())? {
+        if db::apply_automatic_suspension(&pool, user_id, overdue_threshold, fine_threshold).await.map_err(|e| e.to_string())? {
+            println!("Suspended borrowing for user {}", user_id);
+        }
+    }
+    
+    let escalated = notify::escalate_overdue_loans(&pool).await.map_err(|e| e.to_string())?;
+    if escalated > 0 {
+        println!("Overdue loans escalated: {}", escalated);
+    }
+    Ok(())
+}
+
+// Brings each overdue loan's running fine up to date, so balances and suspensions don't wait
+// for the item to come back
+async fn fine_accrual_job(pool: SqlitePool) -> Result<(), String> {
+    db::mark_overdue_records(&pool).await.map_err(|e| e.to_string())?;
+    let now = clock::now();
+    let cap = settings::max_fine_per_item();
+    let mut policies = std::collections::HashMap::new();
+    let mut users = std::collections::BTreeSet::new();
+    
+    for (record_id, user_id, due_date, member_type) in db::get_loans_for_fine_accrual(&pool).await.map_err(|e| e.to_string())? {
+        let Ok(due) = chrono::DateTime::parse_from_rfc3339(&due_date) else { continue };
+        if !policies.contains_key(&member_type) {
+            let policy = settings::loan_policy(&pool, &member_type).await.map_err(|e| e.to_string())?;
+            policies.insert(member_type.clone(), policy.fine_per_day);
+        }
+        let (days_late, amount) = db::late_fine(&pool, due.with_timezone(&chrono::Utc), now, policies[&member_type], cap).await.map_err(|e| e.to_string())?;
+        if amount > 0.0 {
+            db::accrue_fine(&pool, record_id, user_id, amount, days_late).await.map_err(|e| e.to_string())?;
+            users.insert(user_id);
+        }
+    }
+    if !users.is_empty() {
+        println!("Fines accrued for {} members", users.len());
+    }
+    
+    let overdue_threshold = settings::suspension_overdue_threshold();
+    let fine_threshold = settings::suspension_fine_threshold();
+    for user_id in users {
+        if db::apply_automatic_suspension(&pool, user_id, overdue_threshold, fine_threshold).await.map_err(|e| e.to_string())? {
+            println!("Suspended borrowing for user {}", user_id);
+        }
+    }
+    Ok(())
+}
+
+async fn login_throttle_save_job(pool: SqlitePool) -> Result<(), String> {
+    db::replace_login_failures(&pool, &throttle::snapshot()).await.map_err(|e| e.to_string())
+}
+
+// Expires holds that weren't picked up in time and passes the copy to the next reader
+async fn hold_expiry_job(pool: SqlitePool) -> Result<(), String> {
+    let expired = db::get_expired_holds(&pool).await.map_err(|e| e.to_string())?;
+    
+    for reservation in expired {
+        db::close_reservation(&pool, reservation.id, "expired").await.map_err(|e| e.to_string())?;
+        if let Err(e) = notify::send_hold_expired(&pool, &reservation).await {
+            eprintln!("Failed to send hold expiry notification: {:?}", e);
+        }
+        release_to_queue(&pool, reservation.book_id).await;
+    }
+    Ok(())
+}
+
+async fn availability_reconciliation_job(pool: SqlitePool) -> Result<(), String> {
+    let corrections = reconcile_availability(&pool).await.map_err(|e| e.to_string())?;
+    for c in &corrections {
+        println!("Corrected available copies of book {} from {} to {}", c.book_id, c.recorded, c.actual);
+    }
+    Ok(())
+}
+
+async fn reminders_job(pool: SqlitePool) -> Result<(), String> {
+    let due = notify::send_due_reminders(&pool).await.map_err(|e| e.to_string())?;
+    let expiring = notify::send_membership_expiry_warnings(&pool).await.map_err(|e| e.to_string())?;
+    println!("Reminders sent: {} due soon, {} membership expiry warnings", due, expiring);
+    Ok(())
+}
+
+// Runs after the morning reminders so they make the same day's digest
+async fn notification_digest_job(pool: SqlitePool) -> Result<(), String> {
+    let count = notify::send_digests(&pool).await.map_err(|e| e.to_string())?;
+    if count > 0 {
+        println!("Notification digests sent: {}", count);
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+async fn broadcast_delivery_job(pool: SqlitePool) -> Result<(), String> {
+    let count = notify::deliver_broadcasts(&pool).await.map_err(|e| e.to_string())?;
+    if count > 0 {
+        println!("Broadcast messages delivered: {}", count);
+    }
+    Ok(())
+}
+
+async fn session_cleanup_job(pool: SqlitePool) -> Result<(), String> {
+    let count = db::delete_expired_sessions(&pool).await.map_err(|e| e.to_string())?;
+    println!("Session cleanup removed {} expired sessions", count);
+    
+    let before = (clock::now() - chrono::Duration::hours(IDEMPOTENCY_KEY_TTL_HOURS)).to_rfc3339();
+    let count = db::delete_expired_idempotency_keys(&pool, &before).await.map_err(|e| e.to_string())?;
+    println!("Session cleanup removed {} expired idempotency keys", count);
+    Ok(())
+}
+
+async fn retention_purge_job(pool: SqlitePool) -> Result<(), String> {
+    let report = retention::purge(&pool, false).await.map_err(|e| e.to_string())?;
+    println!(
+        "Retention purge: {} lending records ({}), {} loans detached from readers, {} notifications, {} access log entries, {} events",
+        report.lending_records.records,
+        report.lending_records.action,
+        report.reader_history.records,
+        report.notifications.records,
+        report.access_log.records,
+        report.events.records
+    );
+    Ok(())
+}
+
+async fn backup_job(pool: SqlitePool) -> Result<(), String> {
+    let dir = config::get().backup_dir.clone().ok_or("Backup directory not configured")?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    
+    let path = dir.join(db::backup_file_name());
+    db::backup_to(&pool, &path).await.map_err(|e| e.to_string())?;
+    println!("Scheduled backup written to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::sync::{OnceLock, RwLock};
+use serde::Serialize;
+
+// Maintenance mode turns members away while staff work on the catalog. It lives in memory
+// only, so a restart always brings the library back online.
+
+pub const DEFAULT_MESSAGE: &str = "The library is undergoing maintenance. Please try again shortly.";
+
+#[derive(Clone, Serialize)]
+pub struct Status {
+    pub enabled: bool,
+    pub message: Option<String>,
+    pub since: Option<String>,
+}
+
+static STATUS: OnceLock<RwLock<Status>> = OnceLock::new();
+
+fn status_lock() -> &'static RwLock<Status> {
+    STATUS.get_or_init(|| RwLock::new(Status { enabled: false, message: None, since: None }))
+}
+
+pub fn status() -> Status {
+    status_lock().read().unwrap().clone()
+}
+
+pub fn is_enabled() -> bool {
+    status_lock().read().unwrap().enabled
+}
+
+pub fn enable(message: Option<String>) -> Status {
+    let mut status = status_lock().write().unwrap();
+    let since = status.since.clone().filter(|_| status.enabled).unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+    *status = Status {
+        enabled: true,
+        message: Some(message.filter(|m| !m.trim().is_empty()).unwrap_or_else(|| DEFAULT_MESSAGE.to_string())),
+        since: Some(since),
+    };
+    status.clone()
+}
+
+pub fn disable() -> Status {
+    let mut status = status_lock().write().unwrap();
+    *status = Status { enabled: false, message: None, since: None };
+    status.clone()
+}
+
+// Admins keep working, and must still be able to sign in; the frontend pages themselves
+// are left alone so the app can show the message
+pub fn applies_to(method: &str, path: &str) -> bool {
+    let api = path.starts_with("/api/") || path == "/opds" || path.starts_with("/opds/");
+    let sign_in = (method == "POST" && (path == "/api/auth/login" || path == "/api/auth/2fa/verify"))
+        || (method == "GET" && path.starts_with("/api/auth/oauth/"));
+    api && !sign_in
+}
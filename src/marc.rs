@@ -0,0 +1,201 @@
+// Minimal MARC21 reader for catalog imports. Understands ISO 2709 binary records and
+// MARCXML, and extracts only the fields the books table needs.
+pub struct MarcBook {
+    pub title: String,
+    pub author: String,
+    pub isbn: String,
+    pub publication_year: Option<i32>,
+    pub genre: Option<String>,
+}
+
+struct Field {
+    tag: String,
+    subfields: Vec<(char, String)>,
+}
+
+const RECORD_TERMINATOR: char = '\x1D';
+const FIELD_TERMINATOR: char = '\x1E';
+const SUBFIELD_DELIMITER: char = '\x1F';
+
+// One entry per record found in the file; a record that can't be mapped yields an error message
+pub fn parse(data: &str) -> Vec<Result<MarcBook, String>> {
+    let records = if data.trim_start().starts_with('<') {
+        parse_xml(data)
+    } else {
+        parse_binary(data)
+    };
+    records.into_iter().map(|r| r.and_then(|fields| to_book(&fields))).collect()
+}
+
+fn parse_binary(data: &str) -> Vec<Result<Vec<Field>, String>> {
+    data.split(RECORD_TERMINATOR)
+        .map(|r| r.trim_start_matches(['\r', '\n']))
+        .filter(|r| !r.trim().is_empty())
+        .map(parse_binary_record)
+        .collect()
+}
+
+fn parse_binary_record(record: &str) -> Result<Vec<Field>, String> {
+    let base_address: usize = record
+        .get(12..17)
+        .and_then(|s| s.parse().ok())
+        .ok_or("Invalid record leader")?;
+    let directory = record.get(24..base_address.saturating_sub(1)).ok_or("Invalid record directory")?;
+    let data = record.get(base_address..).ok_or("Record is truncated")?;
+
+    // Pair directory tags with fields in order rather than trusting byte offsets,
+    // which don't survive re-encoding of non-UTF-8 records
+    let tags: Vec<&str> = (0..directory.len() / 12).filter_map(|i| directory.get(i * 12..i * 12 + 3)).collect();
+    let fields = tags
+        .iter()
+        .zip(data.split(FIELD_TERMINATOR))
+        .map(|(tag, raw)| Field {
+            tag: tag.to_string(),
+            subfields: if tag.starts_with("00") {
+                vec![(' ', raw.to_string())]
+            } else {
+                raw.split(SUBFIELD_DELIMITER)
+                    .skip(1)
+                    .filter_map(|sf| {
+                        let mut chars = sf.chars();
+                        chars.next().map(|code| (code, chars.as_str().to_string()))
+                    })
+                    .collect()
+            },
+        })
+        .collect();
+
+    Ok(fields)
+}
+
+fn parse_xml(data: &str) -> Vec<Result<Vec<Field>, String>> {
+    let mut records = Vec::new();
+    let mut rest = data;
+
+    while let Some((_, body, after)) = next_element(rest, "record") {
+        let mut fields = Vec::new();
+        let mut inner = body;
+        while let Some((attrs, field_body, after_field)) = next_element(inner, "datafield") {
+            let subfields = elements(field_body, "subfield")
+                .into_iter()
+                .filter_map(|(attrs, text)| attribute(attrs, "code").and_then(|c| c.chars().next()).map(|c| (c, unescape(text))))
+                .collect();
+            if let Some(tag) = attribute(attrs, "tag") {
+                fields.push(Field { tag, subfields });
+            }
+            inner = after_field;
+        }
+        records.push(Ok(fields));
+        rest = after;
+    }
+
+    records
+}
+
+// Finds the next <name ...>...</name> element, allowing a namespace prefix such as <marc:record>.
+// Returns the attribute text, the inner content, and the remaining input.
+fn next_element<'a>(input: &'a str, name: &str) -> Option<(&'a str, &'a str, &'a str)> {
+    let mut search = input;
+    loop {
+        let start = search.find('<')?;
+        let tag_text = &search[start + 1..];
+        let tag_end = tag_text.find('>')?;
+        let open = &tag_text[..tag_end];
+        let tag_name = open.split_whitespace().next().unwrap_or("");
+        let local = tag_name.rsplit(':').next().unwrap_or("");
+
+        if local == name && !open.starts_with('/') {
+            let attrs = open[tag_name.len()..].trim_end_matches('/');
+            let content = &tag_text[tag_end + 1..];
+            if open.ends_with('/') {
+                return Some((attrs, "", content));
+            }
+            let close = format!("</{}>", tag_name);
+            let close_at = content.find(&close)?;
+            return Some((attrs, &content[..close_at], &content[close_at + close.len()..]));
+        }
+        search = &tag_text[tag_end + 1..];
+    }
+}
+
+fn elements<'a>(input: &'a str, name: &str) -> Vec<(&'a str, &'a str)> {
+    let mut found = Vec::new();
+    let mut rest = input;
+    while let Some((attrs, body, after)) = next_element(rest, name) {
+        found.push((attrs, body));
+        rest = after;
+    }
+    found
+}
+
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        let pattern = format!("{}={}", name, quote);
+        if let Some(start) = attrs.find(&pattern) {
+            let value = &attrs[start + pattern.len()..];
+            return value.find(quote).map(|end| unescape(&value[..end]));
+        }
+    }
+    None
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn subfield<'a>(fields: &'a [Field], tag: &str, code: char) -> Option<&'a str> {
+    fields
+        .iter()
+        .filter(|f| f.tag == tag)
+        .flat_map(|f| f.subfields.iter())
+        .find(|(c, value)| *c == code && !value.trim().is_empty())
+        .map(|(_, value)| value.as_str())
+}
+
+// Strips the ISBD punctuation cataloguers leave at the end of subfields ("Title :", "Author,")
+fn clean(value: &str) -> String {
+    value.trim().trim_end_matches([' ', '/', ':', ';', ',', '.', '=']).trim().to_string()
+}
+
+fn to_book(fields: &[Field]) -> Result<MarcBook, String> {
+    let title = match (subfield(fields, "245", 'a'), subfield(fields, "245", 'b')) {
+        (Some(a), Some(b)) => format!("{}: {}", clean(a), clean(b)),
+        (Some(a), None) => clean(a),
+        _ => return Err("Missing title (245 $a)".to_string()),
+    };
+
+    let author = ["100", "110", "700"]
+        .iter()
+        .find_map(|tag| subfield(fields, tag, 'a'))
+        .map(clean)
+        .ok_or("Missing author (100 $a)")?;
+
+    // 020 $a may carry a qualifier, e.g. "9780140449136 (pbk.)"
+    let isbn = subfield(fields, "020", 'a')
+        .and_then(|v| v.split_whitespace().next())
+        .map(|v| v.chars().filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x').collect::<String>())
+        .filter(|v| v.len() == 10 || v.len() == 13)
+        .ok_or("Missing or invalid ISBN (020 $a)")?;
+
+    let publication_year = ["260", "264"]
+        .iter()
+        .find_map(|tag| subfield(fields, tag, 'c'))
+        .and_then(first_year);
+
+    let genre = subfield(fields, "655", 'a').or_else(|| subfield(fields, "650", 'a')).map(clean);
+
+    Ok(MarcBook { title, author, isbn, publication_year, genre })
+}
+
+// Dates appear as "c1998.", "[2004]", or "2010-2012"; take the first four-digit run
+fn first_year(value: &str) -> Option<i32> {
+    let digits: Vec<char> = value.chars().collect();
+    digits
+        .windows(4)
+        .find(|w| w.iter().all(|c| c.is_ascii_digit()))
+        .and_then(|w| w.iter().collect::<String>().parse().ok())
+}
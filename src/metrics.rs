@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::models::{MetricsSummary, RouteMetrics};
+
+// In-process request and domain metrics, exported in the Prometheus text format and as a JSON
+// summary. Nothing is persisted, so the numbers start over when the server restarts.
+
+// Upper bounds, in seconds, of the latency histogram buckets; slower requests fall in +Inf
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// Paths are folded into templates, but a client probing random URLs could still create series
+// without end; past this many, new ones are counted under a single "other" route
+const MAX_SERIES: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Counter {
+    Borrows,
+    Returns,
+    FailedLogins,
+}
+
+impl Counter {
+    const ALL: [Counter; 3] = [Counter::Borrows, Counter::Returns, Counter::FailedLogins];
+
+    fn name(self) -> &'static str {
+        match self {
+            Counter::Borrows => "borrows",
+            Counter::Returns => "returns",
+            Counter::FailedLogins => "failed_logins",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Counter::Borrows => "Books borrowed",
+            Counter::Returns => "Books returned",
+            Counter::FailedLogins => "Sign-in attempts rejected for a wrong username or password",
+        }
+    }
+}
+
+static COUNTERS: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+#[derive(Default)]
+struct Histogram {
+    // Per bucket, not cumulative; the last slot is +Inf
+    buckets: [u64; BUCKETS.len() + 1],
+    count: u64,
+    sum: f64,
+    max: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += seconds;
+        self.max = self.max.max(seconds);
+    }
+
+    // Upper bound of the bucket holding the quantile, or the slowest request seen when that
+    // is in the +Inf bucket
+    fn quantile(&self, q: f64) -> f64 {
+        let target = (self.count as f64 * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return BUCKETS.get(bucket).copied().unwrap_or(self.max).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+type Series = (String, String, u16);
+
+fn requests() -> &'static Mutex<BTreeMap<Series, Histogram>> {
+    static REQUESTS: OnceLock<Mutex<BTreeMap<Series, Histogram>>> = OnceLock::new();
+    REQUESTS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+pub fn increment(counter: Counter) {
+    COUNTERS[counter as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+// `unmatched` is for requests no route took, which all share one series
+pub fn record_request(method: &str, path: &str, status: u16, unmatched: bool, elapsed: Duration) {
+    let route = if unmatched { "unmatched".to_string() } else { route_template(path) };
+    let mut requests = requests().lock().unwrap();
+    let mut series = (method.to_string(), route, status);
+    if !requests.contains_key(&series) && requests.len() >= MAX_SERIES {
+        series.1 = "other".to_string();
+    }
+    requests.entry(series).or_default().observe(elapsed.as_secs_f64());
+}
+
+// The query string is dropped and any segment with a digit in it (ids, card numbers, dated
+// file names) becomes :id, so /api/books/12 and /api/books/40 share a series
+fn route_template(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or(path);
+    path.split('/')
+        .map(|segment| if segment.chars().any(|c| c.is_ascii_digit()) { ":id" } else { segment })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn prometheus() -> String {
+    let mut out = String::new();
+    out.push_str("# HELP library_http_request_duration_seconds Request latency by method, route and status\n");
+    out.push_str("# TYPE library_http_request_duration_seconds histogram\n");
+    for ((method, route, status), histogram) in requests().lock().unwrap().iter() {
+        let labels = format!("method=\"{}\",route=\"{}\",status=\"{}\"", escape(method), escape(route), status);
+        let mut cumulative = 0;
+        for (bucket, count) in histogram.buckets.iter().enumerate() {
+            cumulative += count;
+            let bound = BUCKETS.get(bucket).map(|b| b.to_string()).unwrap_or_else(|| "+Inf".to_string());
+            out.push_str(&format!("library_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}\n", labels, bound, cumulative));
+        }
+        out.push_str(&format!("library_http_request_duration_seconds_sum{{{}}} {}\n", labels, histogram.sum));
+        out.push_str(&format!("library_http_request_duration_seconds_count{{{}}} {}\n", labels, histogram.count));
+    }
+
+    for counter in Counter::ALL {
+        out.push_str(&format!("# HELP library_{}_total {}\n", counter.name(), counter.help()));
+        out.push_str(&format!("# TYPE library_{}_total counter\n", counter.name()));
+        out.push_str(&format!("library_{}_total {}\n", counter.name(), COUNTERS[counter as usize].load(Ordering::Relaxed)));
+    }
+    out
+}
+
+// Label values are quoted, so backslashes, quotes and line breaks need escaping
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// One entry per method and route, with its statuses folded together: how many requests, how
+// many failed, and latency percentiles estimated from the histogram buckets
+pub fn summary() -> MetricsSummary {
+    let mut routes: BTreeMap<(String, String), (Histogram, BTreeMap<String, u64>)> = BTreeMap::new();
+    for ((method, route, status), histogram) in requests().lock().unwrap().iter() {
+        let (merged, statuses) = routes.entry((method.clone(), route.clone())).or_default();
+        for (bucket, count) in histogram.buckets.iter().enumerate() {
+            merged.buckets[bucket] += count;
+        }
+        merged.count += histogram.count;
+        merged.sum += histogram.sum;
+        merged.max = merged.max.max(histogram.max);
+        *statuses.entry(status.to_string()).or_default() += histogram.count;
+    }
+
+    let millis = |seconds: f64| (seconds * 100_000.0).round() / 100.0;
+    let routes = routes
+        .into_iter()
+        .map(|((method, route), (histogram, statuses))| {
+            let count_where = |failed: fn(u16) -> bool| {
+                statuses.iter().filter(|(status, _)| status.parse().is_ok_and(failed)).map(|(_, count)| count).sum::<u64>()
+            };
+            let server_errors = count_where(|status| status >= 500);
+            let client_errors = count_where(|status| (400..500).contains(&status));
+            RouteMetrics {
+                method,
+                route,
+                requests: histogram.count,
+                client_errors,
+                server_errors,
+                error_rate: server_errors as f64 / histogram.count.max(1) as f64,
+                mean_ms: millis(histogram.sum / histogram.count.max(1) as f64),
+                p50_ms: millis(histogram.quantile(0.5)),
+                p95_ms: millis(histogram.quantile(0.95)),
+                p99_ms: millis(histogram.quantile(0.99)),
+                max_ms: millis(histogram.max),
+                statuses,
+            }
+        })
+        .collect();
+
+    let counters = Counter::ALL
+        .iter()
+        .map(|&counter| (counter.name().to_string(), COUNTERS[counter as usize].load(Ordering::Relaxed)))
+        .collect();
+    MetricsSummary { routes, counters }
+}
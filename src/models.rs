@@ -8,6 +8,18 @@ pub struct User {
     #[serde(skip_serializing)]
     pub password_hash: String,
     pub role: String,
+    pub display_name: Option<String>,
+    pub email_verified: bool,
+    pub is_active: bool,
+    pub anonymized_at: Option<String>,
+    pub card_number: Option<String>,
+    pub member_type: String,
+    pub membership_expires_at: Option<String>,
+    pub suspended_at: Option<String>,
+    pub suspension_reason: Option<String>,
+    pub home_branch_id: Option<i64>,
+    pub approval_status: String,
+    pub two_factor_enabled: bool,
     pub created_at: String,
 }
 
@@ -22,6 +34,16 @@ pub struct Book {
     pub total_copies: i32,
     pub available_copies: i32,
     pub created_at: String,
+    pub tags: Vec<String>,
+    pub on_order: bool,
+    pub item_type: String,
+    pub issue_number: Option<String>,
+    pub platform: Option<String>,
+    pub loan_period_days: Option<i64>,
+    pub loanable: bool,
+    pub series_id: Option<i64>,
+    pub series_position: Option<i64>,
+    pub version: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,6 +58,111 @@ pub struct LendingRecordWithDetails {
     pub due_date: String,
     pub returned_at: Option<String>,
     pub status: String,
+    pub recalled_at: Option<String>,
+    pub branch_id: i64,
+    // Overdue escalation reached so far: reminder, second_notice or billed
+    pub escalation_level: Option<String>,
+}
+
+// Staff remarks on a loan, e.g. the condition of the copy at checkout
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LendingNote {
+    pub id: i64,
+    pub lending_record_id: i64,
+    pub author_id: i64,
+    pub author: String,
+    pub note: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateLendingNoteRequest {
+    pub note: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GenreCount {
+    pub genre: String,
+    pub loans: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadingStats {
+    pub year: String,
+    pub books_read_this_year: i64,
+    pub total_loans: i64,
+    pub average_loan_days: Option<f64>,
+    pub favorite_genres: Vec<GenreCount>,
+    pub current_streak_months: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserSummary {
+    pub user: User,
+    pub total_loans: i64,
+    pub current_loans: i64,
+    pub overdue_count: i64,
+    pub total_fined: f64,
+    pub unpaid_fines: f64,
+    pub top_genres: Vec<GenreCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: i64,
+    pub created_at: String,
+    pub expires_at: String,
+    pub last_used_at: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub impersonated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PushSubscription {
+    pub id: i64,
+    pub endpoint: String,
+    #[serde(skip_serializing)]
+    pub p256dh: String,
+    #[serde(skip_serializing)]
+    pub auth: String,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+}
+
+// The browser's PushSubscription, as returned by its toJSON()
+#[derive(Debug, Deserialize)]
+pub struct PushSubscriptionRequest {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushSubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: i64,
+    pub channel: String,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor_id: Option<i64>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<i64>,
+    pub details: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -43,6 +170,7 @@ pub struct RegisterRequest {
     pub username: String,
     pub email: String,
     pub password: String,
+    pub invite_code: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,12 +179,38 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileRequest {
+    pub email: Option<String>,
+    pub display_name: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
     pub token: String,
     pub user: User,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: Option<String>,
+}
+
+// Turning two-factor off takes the password as well, so a stolen session alone can't do it
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorDisableRequest {
+    pub password: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorVerifyRequest {
+    pub challenge: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateBookRequest {
     pub title: String,
@@ -65,6 +219,9 @@ pub struct CreateBookRequest {
     pub publication_year: Option<i32>,
     pub genre: Option<String>,
     pub total_copies: i32,
+    pub item_type: Option<String>,
+    pub issue_number: Option<String>,
+    pub platform: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -75,4 +232,941 @@ pub struct UpdateBookRequest {
     pub publication_year: Option<i32>,
     pub genre: Option<String>,
     pub total_copies: Option<i32>,
-}
\ No newline at end of file
+    pub item_type: Option<String>,
+    pub issue_number: Option<String>,
+    pub platform: Option<String>,
+    pub loan_period_days: Option<i64>,
+    pub loanable: Option<bool>,
+    pub series_id: Option<i64>,
+    pub series_position: Option<i64>,
+    // The version the caller last saw; the update is refused if the book has changed since
+    pub version: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Series {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub volume_count: i64,
+    pub created_at: String,
+}
+
+// A series with its volumes in reading order
+#[derive(Debug, Serialize)]
+pub struct SeriesDetails {
+    #[serde(flatten)]
+    pub series: Series,
+    pub volumes: Vec<Book>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSeriesRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateSeriesRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WishlistEntry {
+    pub book_id: i64,
+    pub title: String,
+    pub author: String,
+    pub available_copies: i32,
+    pub notify: bool,
+    pub notified_at: Option<String>,
+    pub added_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddToWishlistRequest {
+    pub notify: Option<bool>,
+}
+
+// A one-time request to hear when a copy is on the shelf, without holding one
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookWatch {
+    pub book_id: i64,
+    pub title: String,
+    pub author: String,
+    pub available_copies: i32,
+    pub created_at: String,
+}
+
+// A catalog search: free text plus the field filters of the advanced query syntax
+#[derive(Debug, Default)]
+pub struct BookQuery {
+    pub text: String,
+    pub author: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub tag: Option<String>,
+    pub isbn: Option<String>,
+    pub item_type: Option<String>,
+    pub year_from: Option<i32>,
+    pub year_to: Option<i32>,
+    pub available: Option<bool>,
+}
+
+// A search hit; score is 1.0 for a direct match and lower for a near miss
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub book: Book,
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: i64,
+}
+
+// Counts over a result set, for rendering search filter sidebars
+#[derive(Debug, Serialize)]
+pub struct SearchFacets {
+    pub genre: Vec<FacetCount>,
+    pub author: Vec<FacetCount>,
+    pub decade: Vec<FacetCount>,
+    pub available: Vec<FacetCount>,
+}
+
+// A search box completion; kind is "title" or "author"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchSuggestion {
+    pub text: String,
+    pub kind: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthorFollow {
+    pub author: String,
+    pub book_count: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadingList {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub entry_count: i64,
+    pub created_at: String,
+}
+
+// A reading list with its books in list order
+#[derive(Debug, Serialize)]
+pub struct ReadingListDetails {
+    #[serde(flatten)]
+    pub list: ReadingList,
+    pub entries: Vec<Book>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReadingListRequest {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub book_ids: Vec<i64>,
+}
+
+// book_ids, when given, replaces the list's entries in the given order
+#[derive(Debug, Deserialize)]
+pub struct UpdateReadingListRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub book_ids: Option<Vec<i64>>,
+}
+
+// One issue of a periodical title; the issue is a catalog item of its own with its own copies
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PeriodicalIssue {
+    pub id: i64,
+    pub title_id: i64,
+    pub title: String,
+    pub volume: Option<String>,
+    pub number: String,
+    pub issue_date: String,
+    pub status: String,
+    pub received_at: Option<String>,
+    pub total_copies: i32,
+    pub available_copies: i32,
+    pub loan_period_days: Option<i64>,
+    pub loanable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateIssueRequest {
+    pub volume: Option<String>,
+    pub number: String,
+    pub issue_date: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReceiveIssueRequest {
+    pub copies: Option<i64>,
+    pub branch_id: Option<i64>,
+}
+
+// A member's request that the library buy a book
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub id: i64,
+    pub user_id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub email: String,
+    pub title: String,
+    pub author: String,
+    pub isbn: Option<String>,
+    pub reason: Option<String>,
+    pub status: String,
+    pub decision_note: Option<String>,
+    pub decided_by: Option<i64>,
+    pub decided_at: Option<String>,
+    pub book_id: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSuggestionRequest {
+    pub title: String,
+    pub author: String,
+    pub isbn: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecideSuggestionRequest {
+    pub note: Option<String>,
+    pub add_to_catalog: Option<bool>,
+    pub genre: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Branch {
+    pub id: i64,
+    pub name: String,
+    pub code: String,
+    pub address: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBranchRequest {
+    pub name: String,
+    pub code: String,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateBranchRequest {
+    pub name: Option<String>,
+    pub code: Option<String>,
+    pub address: Option<String>,
+}
+
+// Copies of one book at one branch
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BranchHolding {
+    pub branch_id: i64,
+    pub branch_name: String,
+    pub copies: i64,
+    pub available_copies: i64,
+    pub location_id: Option<i64>,
+    pub location: Option<String>,
+    pub call_number_prefix: Option<String>,
+}
+
+// A shelf within a branch where copies are kept
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Location {
+    pub id: i64,
+    pub branch_id: i64,
+    pub branch_name: String,
+    pub room: String,
+    pub shelf: String,
+    pub call_number_prefix: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateLocationRequest {
+    pub branch_id: i64,
+    pub room: String,
+    pub shelf: String,
+    pub call_number_prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateLocationRequest {
+    pub room: Option<String>,
+    pub shelf: Option<String>,
+    pub call_number_prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetLocationRequest {
+    pub location_id: Option<i64>,
+}
+
+// One title expected on a shelf, for inventory walks
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShelfListEntry {
+    pub book_id: i64,
+    pub title: String,
+    pub author: String,
+    pub isbn: String,
+    pub copies: i64,
+    pub on_loan: i64,
+    pub in_transit: i64,
+    pub expected_on_shelf: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetBranchCopiesRequest {
+    pub copies: i64,
+}
+
+// A member's request to have a copy sent from one branch to another
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Transfer {
+    pub id: i64,
+    pub book_id: i64,
+    pub title: String,
+    pub author: String,
+    pub user_id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub email: String,
+    pub from_branch_id: i64,
+    pub from_branch: String,
+    pub to_branch_id: i64,
+    pub to_branch: String,
+    pub status: String,
+    pub handled_by: Option<i64>,
+    pub created_at: String,
+    pub shipped_at: Option<String>,
+    pub arrived_at: Option<String>,
+    pub cancelled_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTransferRequest {
+    pub book_id: i64,
+    pub from_branch_id: Option<i64>,
+    pub to_branch_id: Option<i64>,
+}
+
+// A book a member offers to give the library
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Donation {
+    pub id: i64,
+    pub user_id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub email: String,
+    pub title: String,
+    pub author: String,
+    pub isbn: String,
+    pub publication_year: Option<i32>,
+    pub genre: Option<String>,
+    pub copies: i64,
+    pub notes: Option<String>,
+    pub status: String,
+    pub decision_note: Option<String>,
+    pub decided_by: Option<i64>,
+    pub decided_at: Option<String>,
+    pub book_id: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDonationRequest {
+    pub title: String,
+    pub author: String,
+    pub isbn: String,
+    pub publication_year: Option<i32>,
+    pub genre: Option<String>,
+    pub copies: Option<i64>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecideDonationRequest {
+    pub copies: Option<i64>,
+    pub branch_id: Option<i64>,
+    pub note: Option<String>,
+}
+
+// Copies added to the collection, with the donor credited when there is one
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Acquisition {
+    pub id: i64,
+    pub book_id: i64,
+    pub title: String,
+    pub copies: i64,
+    pub source: String,
+    pub donor_id: Option<i64>,
+    pub donor: Option<String>,
+    pub donation_id: Option<i64>,
+    pub recorded_by: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Announcement {
+    pub id: i64,
+    pub title: String,
+    pub message: String,
+    pub level: String,
+    pub starts_at: String,
+    pub ends_at: Option<String>,
+    pub created_by: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAnnouncementRequest {
+    pub title: String,
+    pub message: String,
+    pub level: Option<String>,
+    pub starts_at: Option<String>,
+    pub ends_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAnnouncementRequest {
+    pub title: Option<String>,
+    pub message: Option<String>,
+    pub level: Option<String>,
+    pub starts_at: Option<String>,
+    // Some(None) for an explicit null, which clears the end like an empty string does
+    #[serde(default, deserialize_with = "present")]
+    pub ends_at: Option<Option<String>>,
+}
+
+// Tells a field sent as null apart from one left out, which serde otherwise reads alike
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+// A one-off message to a group of members, delivered in batches by the broadcast_delivery job.
+// The counts track delivery progress; sent + failed + skipped + pending = total
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Broadcast {
+    pub id: i64,
+    pub subject: String,
+    pub body: String,
+    pub audience: String,
+    pub status: String,
+    pub total: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub skipped: i64,
+    pub pending: i64,
+    pub created_by: i64,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBroadcastRequest {
+    pub subject: String,
+    pub body: String,
+    pub audience: Option<String>,
+}
+
+// What the retention purge does to one kind of record. `action` is keep, anonymize or delete;
+// `records` counts what was (or on a dry run, would be) affected
+#[derive(Debug, Serialize)]
+pub struct RetentionItem {
+    pub action: String,
+    pub older_than: Option<String>,
+    pub records: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub lending_records: RetentionItem,
+    pub reader_history: RetentionItem,
+    pub notifications: RetentionItem,
+    pub access_log: RetentionItem,
+    pub events: RetentionItem,
+}
+
+// A row the foreign key check found pointing at a missing parent; `rowid` is absent for
+// WITHOUT ROWID tables
+#[derive(Debug, Serialize)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+}
+
+// `ok` only when the file is sound, every reference resolves and the schema is the one this
+// build expects
+#[derive(Debug, Serialize)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    pub schema_version: i64,
+    pub expected_schema_version: i64,
+    // What PRAGMA integrity_check reported, empty for a sound file
+    pub problems: Vec<String>,
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+    pub checked_at: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteMetrics {
+    pub method: String,
+    pub route: String,
+    pub requests: u64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    // Share of requests answered with a 5xx status
+    pub error_rate: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub statuses: std::collections::BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetricsSummary {
+    pub routes: Vec<RouteMetrics>,
+    pub counters: std::collections::BTreeMap<String, u64>,
+}
+
+// A scheduled backup file in LIBRARY_BACKUP_DIR
+#[derive(Debug, Serialize)]
+pub struct StoredBackup {
+    pub name: String,
+    pub size: u64,
+    pub created_at: Option<String>,
+}
+
+// Days the library is closed, inclusive; dates are YYYY-MM-DD
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Closure {
+    pub id: i64,
+    pub starts_on: String,
+    pub ends_on: String,
+    pub reason: String,
+    pub created_by: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateClosureRequest {
+    pub starts_on: String,
+    pub ends_on: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateClosureRequest {
+    pub starts_on: Option<String>,
+    pub ends_on: Option<String>,
+    pub reason: Option<String>,
+}
+
+// Per-patron-class overrides; None means "use the global setting"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemberType {
+    pub name: String,
+    pub loan_period_days: Option<i64>,
+    pub max_concurrent_loans: Option<i64>,
+    pub fine_per_day: Option<f64>,
+}
+
+// Kinds of catalog item; None means "use the member's loan period"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ItemType {
+    pub name: String,
+    pub loan_period_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ItemTypePolicyRequest {
+    pub loan_period_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoanPolicy {
+    pub member_type: String,
+    pub loan_period_days: i64,
+    pub max_concurrent_loans: i64,
+    pub fine_per_day: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetMemberTypeRequest {
+    pub member_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRoleRequest {
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetHomeBranchRequest {
+    pub branch_id: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationPreferencesRequest {
+    pub digest: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetupRequest {
+    // The one-time token printed in the server log
+    pub token: String,
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LanguagePreferenceRequest {
+    // A language code from /api/languages, or null to follow Accept-Language
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MemberTypePolicyRequest {
+    pub loan_period_days: Option<i64>,
+    pub max_concurrent_loans: Option<i64>,
+    pub fine_per_day: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenewMembershipRequest {
+    pub days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecallRequest {
+    pub due_date: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExtensionRequest {
+    pub id: i64,
+    pub lending_record_id: i64,
+    pub user_id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub email: String,
+    pub title: String,
+    pub due_date: String,
+    pub reason: String,
+    pub requested_days: i64,
+    pub status: String,
+    pub decision_note: Option<String>,
+    pub decided_by: Option<i64>,
+    pub decided_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExtensionRequest {
+    pub reason: String,
+    pub days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecideExtensionRequest {
+    pub days: Option<i64>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Fine {
+    pub id: i64,
+    pub user_id: i64,
+    pub lending_record_id: i64,
+    pub title: String,
+    pub amount: f64,
+    pub amount_paid: f64,
+    pub status: String,
+    pub reason: String,
+    pub created_at: String,
+    pub paid_at: Option<String>,
+    // Still growing nightly while the item is overdue; settled when it is returned
+    pub accruing: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FinePayment {
+    pub id: i64,
+    pub fine_id: i64,
+    pub amount: f64,
+    pub method: String,
+    pub note: Option<String>,
+    pub recorded_by: i64,
+    pub paid_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PayFineRequest {
+    pub amount: f64,
+    pub method: String,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FineBalance {
+    pub user_id: i64,
+    // ISO code of the currency the amounts are in
+    pub currency: String,
+    pub total_fined: f64,
+    pub total_paid: f64,
+    pub balance: f64,
+    pub fines: Vec<Fine>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReservationWithDetails {
+    pub id: i64,
+    pub user_id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub email: String,
+    pub book_id: i64,
+    pub title: String,
+    pub author: String,
+    pub status: String,
+    pub queue_position: Option<i64>,
+    pub created_at: String,
+    pub ready_at: Option<String>,
+    pub expires_at: Option<String>,
+    pub fulfilled_at: Option<String>,
+}
+
+// What importing one record did: created a book, found its ISBN already catalogued, or failed
+#[derive(Debug)]
+pub enum ImportOutcome {
+    Created(i64),
+    Skipped(i64),
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct ReturnOutcome {
+    pub fine: f64,
+    pub book_id: i64,
+}
+// A book whose stored available_copies disagreed with its lending records
+#[derive(Debug, Serialize)]
+pub struct AvailabilityCorrection {
+    pub book_id: i64,
+    pub title: String,
+    pub total_copies: i32,
+    pub recorded: i32,
+    pub actual: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccessLogEntry {
+    pub id: i64,
+    pub method: String,
+    pub path: String,
+    pub status: i64,
+    pub user_id: Option<i64>,
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub latency_ms: i64,
+    pub created_at: String,
+}
+
+// Search, filters, sorting and paging for the admin user list; every filter is optional.
+// `sort` is one of db::USER_SORTS and `limit` None returns every match
+#[derive(Debug, Default)]
+pub struct UserQuery {
+    pub text: Option<String>,
+    pub role: Option<String>,
+    pub active: Option<bool>,
+    pub sort: String,
+    pub descending: bool,
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
+// Filters, sorting and paging for the admin list of active loans; every filter is optional.
+// `sort` is one of db::LENDING_SORTS and `limit` None returns every match
+#[derive(Debug, Default)]
+pub struct LendingQuery {
+    pub username: Option<String>,
+    pub title: Option<String>,
+    pub branch_id: Option<i64>,
+    pub borrowed_from: Option<String>,
+    pub borrowed_to: Option<String>,
+    pub overdue_only: bool,
+    pub sort: String,
+    pub descending: bool,
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
+// Filters for the access log; every field is optional
+#[derive(Debug, Default)]
+pub struct AccessLogQuery {
+    pub user_id: Option<i64>,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<i64>,
+    pub ip: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: i64,
+}
+
+// A long-lived API token; the key itself is only shown once, when it is created
+#[derive(Debug, Serialize)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+// One entry in the append-only feed that integrations poll; `data` is the event's own payload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Event {
+    pub id: i64,
+    pub event_type: String,
+    pub data: serde_json::Value,
+    pub created_at: String,
+}
+
+// Incoming-webhook URLs are credentials, so only a hint of the URL is ever returned
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: i64,
+    pub service: String,
+    #[serde(skip_serializing)]
+    pub url: String,
+    pub url_hint: String,
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub created_by: Option<i64>,
+    pub created_at: String,
+    pub last_delivery_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub service: String,
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub events: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+// A single-use registration code; the code itself is only shown once, when it is created
+#[derive(Debug, Serialize)]
+pub struct Invite {
+    pub id: i64,
+    pub role: String,
+    // Set on invites from a bulk import, which are for one particular account
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub member_type: Option<String>,
+    pub card_number: Option<String>,
+    pub created_by: Option<i64>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub used_at: Option<String>,
+    pub used_by: Option<i64>,
+}
+
+// One row of a bulk member import
+#[derive(Debug)]
+pub struct ImportedMember {
+    pub username: String,
+    pub email: String,
+    pub member_type: Option<String>,
+    pub card_number: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegistrationDecisionRequest {
+    pub reason: Option<String>,
+}
+
+// A library's edited wording for one of the notify module's templates
+#[derive(Debug, Serialize)]
+pub struct EmailTemplate {
+    pub key: String,
+    pub subject: String,
+    pub body: String,
+    pub updated_by: Option<i64>,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateEmailTemplateRequest {
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeUsersRequest {
+    pub primary_id: i64,
+    pub duplicate_id: i64,
+}
+
+// How many records a merge moved onto the primary account
+#[derive(Debug, Serialize)]
+pub struct UserMerge {
+    pub lending_records: u64,
+    pub fines: u64,
+    pub reservations: u64,
+    pub cancelled_reservations: u64,
+    pub extension_requests: u64,
+    pub transfers: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteRequest {
+    pub role: Option<String>,
+    pub expires_in_days: Option<i64>,
+}
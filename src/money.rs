@@ -0,0 +1,81 @@
+use crate::settings;
+
+// Money written for people: fine amounts in receipts, notices and messages, using the library's
+// currency symbol and the separators of its formatting locale. JSON keeps plain numbers and
+// reports the currency code alongside
+
+struct Locale {
+    name: &'static str,
+    decimal: char,
+    group: char,
+    // Symbol after the number ("12,50 €") rather than before ("$12.50")
+    symbol_after: bool,
+    // Space between the symbol and the number
+    spaced: bool,
+}
+
+const LOCALES: &[Locale] = &[
+    Locale { name: "en-US", decimal: '.', group: ',', symbol_after: false, spaced: false },
+    Locale { name: "en-GB", decimal: '.', group: ',', symbol_after: false, spaced: false },
+    Locale { name: "en-CA", decimal: '.', group: ',', symbol_after: false, spaced: false },
+    Locale { name: "en-AU", decimal: '.', group: ',', symbol_after: false, spaced: false },
+    Locale { name: "de-DE", decimal: ',', group: '.', symbol_after: true, spaced: true },
+    Locale { name: "fr-FR", decimal: ',', group: '\u{a0}', symbol_after: true, spaced: true },
+    Locale { name: "es-ES", decimal: ',', group: '.', symbol_after: true, spaced: true },
+    Locale { name: "it-IT", decimal: ',', group: '.', symbol_after: true, spaced: true },
+    Locale { name: "nl-NL", decimal: ',', group: '.', symbol_after: false, spaced: true },
+    Locale { name: "pt-BR", decimal: ',', group: '.', symbol_after: false, spaced: true },
+    Locale { name: "sv-SE", decimal: ',', group: '\u{a0}', symbol_after: true, spaced: true },
+    Locale { name: "ja-JP", decimal: '.', group: ',', symbol_after: false, spaced: false },
+];
+
+pub fn locales() -> Vec<&'static str> {
+    LOCALES.iter().map(|l| l.name).collect()
+}
+
+pub fn is_locale(name: &str) -> bool {
+    LOCALES.iter().any(|l| l.name == name)
+}
+
+// Digits after the decimal point; most currencies have two
+fn minor_units(code: &str) -> usize {
+    match code {
+        "JPY" | "KRW" | "ISK" | "CLP" | "VND" | "UGX" | "PYG" => 0,
+        "BHD" | "JOD" | "KWD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+// An amount in the library's currency, e.g. "$1,234.50" or "1.234,50 €"
+pub fn format(amount: f64) -> String {
+    format_with(amount, &settings::currency_code(), &settings::currency_symbol(), &settings::money_locale())
+}
+
+fn format_with(amount: f64, code: &str, symbol: &str, locale: &str) -> String {
+    let locale = LOCALES.iter().find(|l| l.name == locale).unwrap_or(&LOCALES[0]);
+    let digits = format!("{:.*}", minor_units(code), amount.abs());
+    let (whole, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+
+    let mut number = String::new();
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            number.push(locale.group);
+        }
+        number.push(c);
+    }
+    if !fraction.is_empty() {
+        number.push(locale.decimal);
+        number.push_str(fraction);
+    }
+    if amount < 0.0 && digits.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        number.insert(0, '-');
+    }
+
+    let symbol = if symbol.is_empty() { code } else { symbol };
+    let space = if locale.spaced { "\u{a0}" } else { "" };
+    if locale.symbol_after {
+        format!("{}{}{}", number, space, symbol)
+    } else {
+        format!("{}{}{}", symbol, space, number)
+    }
+}
@@ -0,0 +1,493 @@
+use chrono::{Duration, Utc};
+use sqlx::SqlitePool;
+
+use crate::models::{Book, Donation, ExtensionRequest, LendingRecordWithDetails, PushSubscription, ReservationWithDetails, Suggestion, Transfer, User};
+use crate::push::{self, Delivery};
+use crate::{config, db, i18n, money, settings};
+
+// Messages libraries can reword through /api/admin/templates. The wording here is the default;
+// an edited copy in the database takes its place. Placeholders are written {{name}}
+pub struct Template {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub variables: &'static [&'static str],
+    pub subject: &'static str,
+    pub body: &'static str,
+}
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        key: "due_reminder",
+        description: "Sent reminder_days_before_due days before a loan is due",
+        variables: &["username", "title", "author", "due_date"],
+        subject: "Your loan is due soon",
+        body: "Hello {{username}},\n\n\"{{title}}\" by {{author}} is due on {{due_date}}. Please return it on time.\n",
+    },
+    Template {
+        key: "overdue_notice",
+        description: "Sent when an overdue loan reaches overdue_reminder_days past due",
+        variables: &["username", "title", "author", "due_date", "days_overdue"],
+        subject: "Your loan is overdue",
+        body: "Hello {{username}},\n\n\"{{title}}\" by {{author}} was due on {{due_date}} and is now overdue. Please return it as soon as possible; fines may apply.\n",
+    },
+    Template {
+        key: "overdue_second_notice",
+        description: "Sent when an overdue loan reaches overdue_second_notice_days past due",
+        variables: &["username", "title", "author", "due_date", "days_overdue"],
+        subject: "Second notice: your loan is overdue",
+        body: "Hello {{username}},\n\n\"{{title}}\" by {{author}} was due on {{due_date}} and is now {{days_overdue}} days overdue. Please return it promptly; if it is not returned you will be billed for the item.\n",
+    },
+    Template {
+        key: "overdue_billed",
+        description: "Sent when an overdue loan reaches overdue_billing_days past due and the member is billed",
+        variables: &["username", "title", "author", "due_date", "days_overdue", "amount"],
+        subject: "You have been billed for an overdue item",
+        body: "Hello {{username}},\n\n\"{{title}}\" by {{author}} was due on {{due_date}} and is now {{days_overdue}} days overdue. We have added a charge of {{amount}} to your account for the item. Please contact the library.\n",
+    },
+    Template {
+        key: "hold_ready",
+        description: "Sent when a reserved copy is waiting for pickup",
+        variables: &["username", "title", "author", "hold_until"],
+        subject: "Your reserved book is ready for pickup",
+        body: "Hello {{username}},\n\n\"{{title}}\" by {{author}} is ready for pickup. We will hold it for you until {{hold_until}}.\n",
+    },
+];
+
+pub fn template(key: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|t| t.key == key)
+}
+
+// Replaces each {{name}} with its value. Unknown names are left as written, so a typo shows
+// up in the message rather than silently vanishing
+pub fn render(text: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find("}}") else { break };
+        let name = rest[start + 2..start + len].trim();
+        match values.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => out.push_str(value),
+            None => out.push_str(&rest[start..start + len + 2]),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Checks an edited template only uses the placeholders its message provides
+pub fn validate(template: &Template, text: &str) -> Result<(), String> {
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let len = rest[start..].find("}}").ok_or("Unclosed {{ placeholder")?;
+        let name = rest[start + 2..start + len].trim();
+        if !template.variables.contains(&name) {
+            return Err(format!("Unknown placeholder {{{{{}}}}}; available: {}", name, template.variables.join(", ")));
+        }
+        rest = &rest[start + len + 2..];
+    }
+    Ok(())
+}
+
+// Subject and body for a templated message, using the library's wording when it has one
+async fn compose(pool: &SqlitePool, key: &str, values: &[(&str, &str)]) -> Result<(String, String), sqlx::Error> {
+    let (subject, body) = match db::get_email_template(pool, key).await? {
+        Some(custom) => (custom.subject, custom.body),
+        None => {
+            let default = template(key).expect("unknown email template");
+            (default.subject.to_string(), default.body.to_string())
+        }
+    };
+    Ok((render(&subject, values), render(&body, values)))
+}
+
+// A message in the member's chosen language, if they chose one and it has a translation
+async fn localize(pool: &SqlitePool, user_id: i64, subject: &str, body: &str) -> Result<(String, String), sqlx::Error> {
+    Ok(match db::get_user_language(pool, user_id).await? {
+        Some(language) => (i18n::translate(&language, subject), i18n::translate(&language, body)),
+        None => (subject.to_string(), body.to_string()),
+    })
+}
+
+pub async fn send_email(pool: &SqlitePool, user_id: Option<i64>, to: &str, subject: &str, body: &str) -> Result<(), sqlx::Error> {
+    match user_id {
+        Some(id) => {
+            let (subject, body) = localize(pool, id, subject, body).await?;
+            deliver_email(pool, user_id, to, &subject, &body).await
+        }
+        None => deliver_email(pool, None, to, subject, body).await,
+    }
+}
+
+// Outgoing messages are recorded in the notifications table, which acts as the
+// delivery log; the message is also written to stdout so it is visible without a mail relay
+async fn deliver_email(pool: &SqlitePool, user_id: Option<i64>, to: &str, subject: &str, body: &str) -> Result<(), sqlx::Error> {
+    db::create_notification(pool, user_id, "email", to, subject, body).await?;
+    println!("[email] to={} subject={:?}\n{}", to, subject, body);
+    Ok(())
+}
+
+pub async fn vapid_private_key(pool: &SqlitePool) -> Result<String, sqlx::Error> {
+    match &config::get().vapid_private_key {
+        Some(key) => Ok(key.clone()),
+        None => db::get_or_create_secret(pool, "vapid_private_key", &push::generate_private_key()).await,
+    }
+}
+
+// Alerts the member's subscribed browsers. Delivery happens in the background so a slow push
+// service never holds up the caller; subscriptions the service reports gone are dropped
+pub async fn send_push(pool: &SqlitePool, user_id: i64, title: &str, body: &str) -> Result<(), sqlx::Error> {
+    let subscriptions = db::get_push_subscriptions(pool, user_id).await?;
+    if subscriptions.is_empty() {
+        return Ok(());
+    }
+    let (title, body) = localize(pool, user_id, title, body).await?;
+    let private_key = vapid_private_key(pool).await?;
+    let subject = config::get().vapid_subject.clone().unwrap_or_else(|| config::get().public_url.clone());
+
+    for subscription in subscriptions {
+        tokio::spawn(deliver_push(pool.clone(), user_id, subscription, private_key.clone(), subject.clone(), title.clone(), body.clone()));
+    }
+    Ok(())
+}
+
+async fn deliver_push(pool: SqlitePool, user_id: i64, subscription: PushSubscription, private_key: String, subject: String, title: String, body: String) {
+    let endpoint = subscription.endpoint.clone();
+    // Read by the service worker in frontend/sw.js
+    let payload = serde_json::json!({ "title": title, "body": body, "url": "/lender.html" }).to_string();
+    let outcome = tokio::task::spawn_blocking(move || {
+        push::send(&private_key, &subject, &subscription.endpoint, &subscription.p256dh, &subscription.auth, payload.as_bytes())
+    }).await;
+
+    let result = match outcome {
+        Ok(Ok(Delivery::Sent)) => {
+            let service = endpoint.trim_start_matches("https://").split('/').next().unwrap_or_default();
+            db::create_notification(&pool, Some(user_id), "push", service, &title, &body).await.map(|_| ())
+        }
+        Ok(Ok(Delivery::Gone)) => db::delete_push_subscription_by_endpoint(&pool, &endpoint).await,
+        Ok(Err(e)) => {
+            eprintln!("Failed to deliver push notification: {}", e);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Push delivery task failed: {:?}", e);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to record push delivery: {:?}", e);
+    }
+}
+
+// Member-facing notices: sent straight away, or held for the daily digest when the member
+// has chosen one. Account and security messages, and anything with a deadline sooner than the
+// next digest (holds ready for pickup, recalls), use send_email directly and are never held
+pub async fn send_notice(pool: &SqlitePool, user_id: i64, to: &str, subject: &str, body: &str) -> Result<(), sqlx::Error> {
+    let (subject, body) = localize(pool, user_id, subject, body).await?;
+    if db::wants_digest(pool, user_id).await? {
+        db::queue_digest_notification(pool, user_id, to, &subject, &body).await?;
+        return Ok(());
+    }
+    deliver_email(pool, Some(user_id), to, &subject, &body).await
+}
+
+// Combines each member's held notices into one email. Notices queued before a member switched
+// back to immediate delivery still go out this way
+pub async fn send_digests(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+    let pending = db::get_pending_digest_notifications(pool).await?;
+    let mut sent = 0;
+
+    for batch in pending.chunk_by(|a, b| a.1 == b.1) {
+        let user_id = batch[0].1;
+        let last_id = batch[batch.len() - 1].0;
+        let Some(user) = db::get_user_by_id(pool, user_id).await? else { continue };
+
+        let mut body = format!("Hello {},\n\nHere is what happened with your library account since your last digest:\n", user.username);
+        if let Some(language) = db::get_user_language(pool, user_id).await? {
+            body = i18n::translate(&language, &body);
+        }
+        for (_, _, subject, text) in batch {
+            // Each notice's own greeting, a one-line "Hello name," in whatever language it was
+            // sent in ("Hola, name:" in Spanish), is redundant inside the digest
+            let text = text
+                .split_once("\n\n")
+                .filter(|(greeting, _)| !greeting.contains('\n') && (greeting.ends_with(',') || greeting.ends_with(':')))
+                .map(|(_, rest)| rest)
+                .unwrap_or(text);
+            body.push_str(&format!("\n== {} ==\n{}", subject, text));
+        }
+        let subject = if batch.len() == 1 { "Your library digest: 1 update".to_string() } else { format!("Your library digest: {} updates", batch.len()) };
+        send_email(pool, Some(user.id), &user.email, &subject, &body).await?;
+        db::mark_digest_sent(pool, user_id, last_id).await?;
+        sent += 1;
+    }
+
+    Ok(sent)
+}
+
+// Audiences an admin broadcast can be sent to
+pub const BROADCAST_AUDIENCES: &[&str] = &["all", "overdue", "holds_ready"];
+
+// Recipients delivered per run of the broadcast_delivery job
+const BROADCAST_BATCH_SIZE: i64 = 500;
+
+// Delivers the next batch of pending broadcast messages. `{{username}}` in the subject or body
+// is filled in per member. Members deactivated or anonymized since the broadcast was queued are
+// skipped; a failed send is recorded against that recipient and not retried
+pub async fn deliver_broadcasts(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+    let pending = db::get_pending_broadcast_recipients(pool, BROADCAST_BATCH_SIZE).await?;
+    let mut sent = 0;
+
+    for (broadcast_id, user_id, subject, body) in pending {
+        db::start_broadcast(pool, broadcast_id).await?;
+        let user = db::get_user_by_id(pool, user_id).await?.filter(|u| u.is_active && u.anonymized_at.is_none());
+        let Some(user) = user else {
+            db::set_broadcast_recipient_status(pool, broadcast_id, user_id, "skipped").await?;
+            continue;
+        };
+
+        let values = [("username", user.username.as_str())];
+        let status = match send_notice(pool, user.id, &user.email, &render(&subject, &values), &render(&body, &values)).await {
+            Ok(()) => {
+                sent += 1;
+                "sent"
+            }
+            Err(e) => {
+                eprintln!("Failed to deliver broadcast {} to user {}: {:?}", broadcast_id, user_id, e);
+                "failed"
+            }
+        };
+        db::set_broadcast_recipient_status(pool, broadcast_id, user_id, status).await?;
+    }
+
+    db::finish_broadcasts(pool).await?;
+    Ok(sent)
+}
+
+// Reminder for loans coming due soon; each loan is reminded at most once
+pub async fn send_due_reminders(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+    let before = (crate::clock::now() + Duration::days(settings::reminder_days_before_due())).to_rfc3339();
+    let records = db::get_loans_due_for_reminder(pool, &before).await?;
+    let mut sent = 0;
+
+    for record in records {
+        let Some(user) = db::get_user_by_id(pool, record.user_id).await? else { continue };
+        let (subject, body) = compose(pool, "due_reminder", &[
+            ("username", &user.username),
+            ("title", &record.title),
+            ("author", &record.author),
+            ("due_date", &record.due_date[..10]),
+        ]).await?;
+        send_notice(pool, user.id, &user.email, &subject, &body).await?;
+        send_push(pool, user.id, "Due soon", &format!("\"{}\" is due on {}", record.title, &record.due_date[..10])).await?;
+        db::mark_reminder_sent(pool, record.id).await?;
+        sent += 1;
+    }
+
+    Ok(sent)
+}
+
+// Overdue escalation levels, in order
+pub const ESCALATION_LEVELS: &[&str] = &["reminder", "second_notice", "billed"];
+
+// Moves overdue loans up the escalation levels as they pass each level's day threshold and sends
+// that level's notice. A loan that passes several thresholds at once gets only the latest notice
+pub async fn escalate_overdue_loans(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+    let thresholds = [
+        settings::overdue_reminder_days(),
+        settings::overdue_second_notice_days(),
+        settings::overdue_billing_days(),
+    ];
+    let records = db::get_loans_to_escalate(pool).await?;
+    let mut escalated = 0;
+
+    for record in records {
+        let days_overdue = chrono::DateTime::parse_from_rfc3339(&record.due_date)
+            .map(|due| (crate::clock::now() - due.with_timezone(&Utc)).num_days().max(0))
+            .unwrap_or(0);
+        let current = ESCALATION_LEVELS.iter().position(|l| Some(*l) == record.escalation_level.as_deref());
+        let Some(target) = thresholds.iter().rposition(|&days| days_overdue >= days) else { continue };
+        if current.is_some_and(|current| current >= target) {
+            continue;
+        }
+        let Some(user) = db::get_user_by_id(pool, record.user_id).await? else { continue };
+
+        let days = days_overdue.to_string();
+        let mut values = vec![
+            ("username", user.username.as_str()),
+            ("title", record.title.as_str()),
+            ("author", record.author.as_str()),
+            ("due_date", &record.due_date[..10]),
+            ("days_overdue", days.as_str()),
+        ];
+        match ESCALATION_LEVELS[target] {
+            "reminder" => {
+                let (subject, body) = compose(pool, "overdue_notice", &values).await?;
+                send_notice(pool, user.id, &user.email, &subject, &body).await?;
+                db::set_escalation_level(pool, record.id, "reminder").await?;
+            }
+            "second_notice" => {
+                let (subject, body) = compose(pool, "overdue_second_notice", &values).await?;
+                send_email(pool, Some(user.id), &user.email, &subject, &body).await?;
+                db::set_escalation_level(pool, record.id, "second_notice").await?;
+            }
+            _ => {
+                let amount = settings::overdue_billing_amount();
+                let amount_text = money::format(amount);
+                values.push(("amount", amount_text.as_str()));
+                let (subject, body) = compose(pool, "overdue_billed", &values).await?;
+                let reason = format!("Billed for unreturned item, {} days overdue", days_overdue);
+                db::bill_overdue_loan(pool, &record, amount, &reason).await?;
+                send_email(pool, Some(user.id), &user.email, &subject, &body).await?;
+            }
+        }
+        escalated += 1;
+    }
+
+    Ok(escalated)
+}
+
+// Warning ahead of membership expiry; reset when the membership is renewed
+pub async fn send_membership_expiry_warnings(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+    let before = (crate::clock::now() + Duration::days(settings::membership_warning_days())).to_rfc3339();
+    let users = db::get_users_with_expiring_membership(pool, &before).await?;
+    let mut sent = 0;
+
+    for user in users {
+        let expires_at = user.membership_expires_at.as_deref().unwrap_or_default();
+        let body = format!(
+            "Hello {},\n\nYour library membership expires on {}. Please renew it at the desk to keep borrowing.\n",
+            user.username, &expires_at[..expires_at.len().min(10)]
+        );
+        send_notice(pool, user.id, &user.email, "Your library membership is expiring", &body).await?;
+        db::mark_membership_warning_sent(pool, user.id).await?;
+        sent += 1;
+    }
+
+    Ok(sent)
+}
+
+pub async fn send_hold_ready(pool: &SqlitePool, reservation: &ReservationWithDetails) -> Result<(), sqlx::Error> {
+    let expires_at = reservation.expires_at.as_deref().unwrap_or_default();
+    let (subject, body) = compose(pool, "hold_ready", &[
+        ("username", &reservation.username),
+        ("title", &reservation.title),
+        ("author", &reservation.author),
+        ("hold_until", &expires_at[..expires_at.len().min(10)]),
+    ]).await?;
+    send_email(pool, Some(reservation.user_id), &reservation.email, &subject, &body).await?;
+    send_push(pool, reservation.user_id, "Ready for pickup", &format!("\"{}\" is held for you until {}", reservation.title, &expires_at[..expires_at.len().min(10)])).await
+}
+
+pub async fn send_hold_expired(pool: &SqlitePool, reservation: &ReservationWithDetails) -> Result<(), sqlx::Error> {
+    let body = format!(
+        "Hello {},\n\nYour hold on \"{}\" by {} was not picked up in time and has expired. The copy has been released to the next reader.\n",
+        reservation.username, reservation.title, reservation.author
+    );
+    send_notice(pool, reservation.user_id, &reservation.email, "Your hold has expired", &body).await
+}
+
+pub async fn send_recall_notice(pool: &SqlitePool, record: &LendingRecordWithDetails, email: &str, reason: Option<&str>) -> Result<(), sqlx::Error> {
+    let reason = reason.map(|r| format!(" Reason: {}.", r)).unwrap_or_default();
+    let body = format!(
+        "Hello {},\n\n\"{}\" by {} has been recalled by the library and is now due on {}.{}\nPlease return it by then to avoid fines.\n",
+        record.username, record.title, record.author, &record.due_date[..10], reason
+    );
+    send_email(pool, Some(record.user_id), email, "A book you borrowed has been recalled", &body).await
+}
+
+pub async fn send_extension_decision(pool: &SqlitePool, request: &ExtensionRequest) -> Result<(), sqlx::Error> {
+    let outcome = if request.status == "approved" {
+        format!("has been approved. \"{}\" is now due on {}.", request.title, &request.due_date[..10])
+    } else {
+        format!("for \"{}\" has been denied. It is still due on {}.", request.title, &request.due_date[..10])
+    };
+    let note = request.decision_note.as_deref().map(|n| format!("\nNote from the library: {}\n", n)).unwrap_or_default();
+    let body = format!("Hello {},\n\nYour extension request {}\n{}", request.username, outcome, note);
+    let subject = if request.status == "approved" { "Your extension request was approved" } else { "Your extension request was denied" };
+    send_notice(pool, request.user_id, &request.email, subject, &body).await
+}
+
+pub async fn send_registration_decision(pool: &SqlitePool, user: &User, approved: bool, reason: Option<&str>) -> Result<(), sqlx::Error> {
+    let outcome = if approved {
+        "has been approved. You can now borrow and reserve books."
+    } else {
+        "was not approved, and the account has been closed."
+    };
+    let note = reason.map(|r| format!("\nNote from the library: {}\n", r)).unwrap_or_default();
+    let body = format!("Hello {},\n\nYour library registration {}\n{}", user.username, outcome, note);
+    let subject = if approved { "Your library registration was approved" } else { "Your library registration was not approved" };
+    send_email(pool, Some(user.id), &user.email, subject, &body).await
+}
+
+pub async fn send_suggestion_decision(pool: &SqlitePool, suggestion: &Suggestion) -> Result<(), sqlx::Error> {
+    let outcome = if suggestion.status == "approved" {
+        if suggestion.book_id.is_some() {
+            "has been approved and the book is now listed in the catalog, where you can reserve it."
+        } else {
+            "has been approved."
+        }
+    } else {
+        "has been declined."
+    };
+    let note = suggestion.decision_note.as_deref().map(|n| format!("\nNote from the library: {}\n", n)).unwrap_or_default();
+    let body = format!(
+        "Hello {},\n\nYour suggestion that the library buy \"{}\" by {} {}\n{}",
+        suggestion.username, suggestion.title, suggestion.author, outcome, note
+    );
+    let subject = if suggestion.status == "approved" { "Your book suggestion was approved" } else { "Your book suggestion was declined" };
+    send_notice(pool, suggestion.user_id, &suggestion.email, subject, &body).await
+}
+
+pub async fn send_donation_decision(pool: &SqlitePool, donation: &Donation) -> Result<(), sqlx::Error> {
+    let outcome = if donation.status == "accepted" {
+        format!("has been accepted and {} added to the collection. Thank you!", if donation.copies == 1 { "1 copy was".to_string() } else { format!("{} copies were", donation.copies) })
+    } else {
+        "could not be accepted.".to_string()
+    };
+    let note = donation.decision_note.as_deref().map(|n| format!("\nNote from the library: {}\n", n)).unwrap_or_default();
+    let body = format!(
+        "Hello {},\n\nYour donation of \"{}\" by {} {}\n{}",
+        donation.username, donation.title, donation.author, outcome, note
+    );
+    let subject = if donation.status == "accepted" { "Your donation was accepted" } else { "Your donation was declined" };
+    send_notice(pool, donation.user_id, &donation.email, subject, &body).await
+}
+
+pub async fn send_transfer_update(pool: &SqlitePool, transfer: &Transfer) -> Result<(), sqlx::Error> {
+    let (subject, outcome) = match transfer.status.as_str() {
+        "in_transit" => ("Your transfer is on its way", format!("is on its way from {}. We will let you know when it is ready for pickup.", transfer.from_branch)),
+        "arrived" => ("Your transfer has arrived", "has arrived and is on the shelf there.".to_string()),
+        _ => ("Your transfer was cancelled", "could not be sent and the request has been cancelled.".to_string()),
+    };
+    let body = format!(
+        "Hello {},\n\n\"{}\" by {}, which you asked to have sent to {}, {}\n",
+        transfer.username, transfer.title, transfer.author, transfer.to_branch, outcome
+    );
+    send_notice(pool, transfer.user_id, &transfer.email, subject, &body).await
+}
+
+pub async fn send_wishlist_available(pool: &SqlitePool, user: &User, book: &Book) -> Result<(), sqlx::Error> {
+    let body = format!(
+        "Hello {},\n\n\"{}\" by {}, which is on your wishlist, is now available to borrow.\n",
+        user.username, book.title, book.author
+    );
+    send_notice(pool, user.id, &user.email, "A book on your wishlist is available", &body).await
+}
+
+pub async fn send_watch_available(pool: &SqlitePool, user: &User, book: &Book) -> Result<(), sqlx::Error> {
+    let body = format!(
+        "Hello {},\n\n\"{}\" by {} is back on the shelf. Copies are not held, so borrow it soon if you still want it.\n",
+        user.username, book.title, book.author
+    );
+    send_notice(pool, user.id, &user.email, "A book you are watching is available", &body).await
+}
+
+pub async fn send_new_book_by_author(pool: &SqlitePool, user: &User, book: &Book) -> Result<(), sqlx::Error> {
+    let body = format!(
+        "Hello {},\n\nA new book by {}, an author you follow, has been added to the catalog: \"{}\".\n",
+        user.username, book.author, book.title
+    );
+    send_notice(pool, user.id, &user.email, &format!("New in the catalog from {}", book.author), &body).await
+}
@@ -0,0 +1,114 @@
+use serde_json::Value;
+
+use crate::{config, http};
+
+// "Sign in with Google/GitHub" through the OAuth2 authorization-code flow. A provider is
+// offered once its client id and secret are configured
+
+pub struct Provider {
+    pub name: &'static str,
+    authorize_url: &'static str,
+    token_url: &'static str,
+    scope: &'static str,
+}
+
+const PROVIDERS: &[Provider] = &[
+    Provider {
+        name: "google",
+        authorize_url: "https://accounts.google.com/o/oauth2/v2/auth",
+        token_url: "https://oauth2.googleapis.com/token",
+        scope: "openid email profile",
+    },
+    Provider {
+        name: "github",
+        authorize_url: "https://github.com/login/oauth/authorize",
+        token_url: "https://github.com/login/oauth/access_token",
+        scope: "read:user user:email",
+    },
+];
+
+// Who the provider says signed in; `email` is only set when the provider has verified it
+pub struct Identity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+pub fn provider(name: &str) -> Option<&'static Provider> {
+    PROVIDERS.iter().find(|p| p.name == name && credentials(p).is_some())
+}
+
+pub fn enabled_providers() -> Vec<&'static str> {
+    PROVIDERS.iter().filter(|p| credentials(p).is_some()).map(|p| p.name).collect()
+}
+
+fn credentials(provider: &Provider) -> Option<&'static (String, String)> {
+    config::get().oauth_clients.iter().find(|(name, _)| name == provider.name).map(|(_, creds)| creds)
+}
+
+pub fn redirect_uri(provider: &Provider) -> String {
+    format!("{}/api/auth/oauth/{}/callback", config::get().public_url.trim_end_matches('/'), provider.name)
+}
+
+pub fn authorization_url(provider: &Provider, state: &str) -> String {
+    let (client_id, _) = credentials(provider).expect("provider is configured");
+    format!(
+        "{}?response_type=code&client_id={}&redirect_uri={}&scope={}&state={}",
+        provider.authorize_url,
+        urlencoding::encode(client_id),
+        urlencoding::encode(&redirect_uri(provider)),
+        urlencoding::encode(provider.scope),
+        urlencoding::encode(state)
+    )
+}
+
+// Trades the authorization code for an access token, then asks the provider who it belongs to.
+// Blocking network I/O, so callers run it off the async runtime
+pub fn identify(provider: &Provider, code: &str) -> Result<Identity, String> {
+    let (client_id, client_secret) = credentials(provider).ok_or("Provider is not configured")?;
+    let form = format!(
+        "grant_type=authorization_code&code={}&redirect_uri={}&client_id={}&client_secret={}",
+        urlencoding::encode(code),
+        urlencoding::encode(&redirect_uri(provider)),
+        urlencoding::encode(client_id),
+        urlencoding::encode(client_secret)
+    );
+    let token = https_request("POST", provider.token_url, &[("Content-Type", "application/x-www-form-urlencoded")], &form)?;
+    let access_token = token["access_token"].as_str().ok_or("Provider did not return an access token")?;
+    let bearer = format!("Bearer {}", access_token);
+
+    match provider.name {
+        "google" => {
+            let info = https_request("GET", "https://openidconnect.googleapis.com/v1/userinfo", &[("Authorization", &bearer)], "")?;
+            Ok(Identity {
+                subject: info["sub"].as_str().ok_or("Provider did not identify the user")?.to_string(),
+                email: info["email"].as_str().filter(|_| info["email_verified"].as_bool() == Some(true)).map(str::to_lowercase),
+                name: info["name"].as_str().map(str::to_string),
+            })
+        }
+        "github" => {
+            let user = https_request("GET", "https://api.github.com/user", &[("Authorization", &bearer)], "")?;
+            let emails = https_request("GET", "https://api.github.com/user/emails", &[("Authorization", &bearer)], "")?;
+            let email = emails.as_array().into_iter().flatten()
+                .find(|e| e["primary"].as_bool() == Some(true) && e["verified"].as_bool() == Some(true))
+                .and_then(|e| e["email"].as_str())
+                .map(str::to_lowercase);
+            Ok(Identity {
+                subject: user["id"].as_i64().ok_or("Provider did not identify the user")?.to_string(),
+                email,
+                name: user["name"].as_str().or(user["login"].as_str()).map(str::to_string),
+            })
+        }
+        _ => Err("Unsupported provider".to_string()),
+    }
+}
+
+// Provider API calls answer with JSON; anything but a 2xx is an error
+fn https_request(method: &str, url: &str, headers: &[(&str, &str)], body: &str) -> Result<Value, String> {
+    let headers = [headers, &[("Accept", "application/json")]].concat();
+    let response = http::request(method, url, &headers, body.as_bytes(), http::Reach::Public)?;
+    if !(200..300).contains(&response.status) {
+        return Err(format!("{} responded with status {}", url, response.status));
+    }
+    serde_json::from_slice(&response.body).map_err(|_| format!("{} returned an unexpected response", url))
+}
@@ -0,0 +1,118 @@
+use chrono::Utc;
+
+use crate::models::Book;
+
+// OPDS 1.2 catalog documents (Atom feeds) so e-reader apps can browse the holdings.
+// Books are physical copies, so entries carry metadata and availability but no acquisition links.
+pub const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+pub const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+pub const OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+
+pub fn root_feed(base: &str) -> String {
+    let now = Utc::now().to_rfc3339();
+    let entries = [
+        ("new", "New Arrivals", "Recently added to the library", "http://opds-spec.org/sort/new"),
+        ("all", "All Books", "The full catalog by title", "subsection"),
+    ]
+    .iter()
+    .map(|(path, title, content, rel)| {
+        format!(
+            r#"  <entry>
+    <title>{title}</title>
+    <id>{base}/opds/{path}</id>
+    <updated>{now}</updated>
+    <content type="text">{content}</content>
+    <link rel="{rel}" href="{base}/opds/{path}" type="{ACQUISITION_TYPE}"/>
+  </entry>
+"#,
+            title = escape(title),
+            content = escape(content),
+            base = escape(base),
+        )
+    })
+    .collect::<String>();
+
+    feed(base, &format!("{}/opds", base), "Library Catalog", NAVIGATION_TYPE, &now, &entries)
+}
+
+pub fn acquisition_feed(base: &str, self_href: &str, title: &str, books: &[Book]) -> String {
+    let now = Utc::now().to_rfc3339();
+    let entries = books.iter().map(book_entry).collect::<String>();
+    feed(base, self_href, title, ACQUISITION_TYPE, &now, &entries)
+}
+
+pub fn opensearch_description(base: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>Library</ShortName>
+  <Description>Search the library catalog by title, author, ISBN, or genre</Description>
+  <Url type="{ACQUISITION_TYPE}" template="{base}/opds/search?q={{searchTerms}}"/>
+</OpenSearchDescription>
+"#,
+        base = escape(base),
+    )
+}
+
+fn feed(base: &str, self_href: &str, title: &str, kind: &str, updated: &str, entries: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/terms/" xmlns:opds="http://opds-spec.org/2010/catalog">
+  <id>{self_href}</id>
+  <title>{title}</title>
+  <updated>{updated}</updated>
+  <author><name>Library</name></author>
+  <link rel="self" href="{self_href}" type="{kind}"/>
+  <link rel="start" href="{base}/opds" type="{NAVIGATION_TYPE}"/>
+  <link rel="search" href="{base}/opds/opensearch.xml" type="{OPENSEARCH_TYPE}"/>
+{entries}</feed>
+"#,
+        self_href = escape(self_href),
+        title = escape(title),
+        base = escape(base),
+    )
+}
+
+fn book_entry(book: &Book) -> String {
+    let mut extra = String::new();
+    if let Some(year) = book.publication_year {
+        extra.push_str(&format!("    <dc:issued>{}</dc:issued>\n", year));
+    }
+    if let Some(genre) = book.genre.as_deref().filter(|g| !g.is_empty()) {
+        extra.push_str(&format!("    <category term=\"{0}\" label=\"{0}\"/>\n", escape(genre)));
+    }
+
+    format!(
+        r#"  <entry>
+    <title>{title}</title>
+    <id>urn:isbn:{isbn}</id>
+    <updated>{updated}</updated>
+    <author><name>{author}</name></author>
+    <dc:identifier>urn:isbn:{isbn}</dc:identifier>
+{extra}    <content type="text">{available} of {total} copies available</content>
+  </entry>
+"#,
+        title = escape(&book.title),
+        isbn = escape(&book.isbn),
+        updated = atom_time(&book.created_at),
+        author = escape(&book.author),
+        available = book.available_copies,
+        total = book.total_copies,
+    )
+}
+
+// Book timestamps come from SQLite's CURRENT_TIMESTAMP ("YYYY-MM-DD HH:MM:SS", UTC);
+// Atom requires RFC 3339
+fn atom_time(value: &str) -> String {
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .map(|t| t.and_utc().to_rfc3339())
+        .unwrap_or_else(|_| value.to_string())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
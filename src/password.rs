@@ -0,0 +1,102 @@
+use std::sync::OnceLock;
+use sha1::{Digest, Sha1};
+
+use crate::config;
+use crate::settings;
+
+// Rules a new password must pass. Each failure gets its own message so the member
+// knows what to change.
+pub fn check(password: &str, username: &str) -> Result<(), String> {
+    let min_length = settings::password_min_length();
+    if (password.chars().count() as i64) < min_length {
+        return Err(format!("Password must be at least {} characters long", min_length));
+    }
+
+    let username = username.trim().to_lowercase();
+    if username.len() >= 3 && password.to_lowercase().contains(&username) {
+        return Err("Password must not contain the username".to_string());
+    }
+
+    let bits = entropy_bits(password);
+    let min_bits = settings::password_min_entropy_bits();
+    if bits < min_bits as f64 {
+        return Err(format!(
+            "Password is too easy to guess (about {} bits of entropy, at least {} required); make it longer or mix upper and lower case letters, digits and symbols",
+            bits.floor(),
+            min_bits
+        ));
+    }
+
+    if breach_filter().is_some_and(|filter| filter.contains(password)) {
+        return Err("Password appears in a list of breached passwords; choose a different one".to_string());
+    }
+
+    Ok(())
+}
+
+// Brute-force estimate: the alphabet implied by the character classes used, raised to the
+// length. Runs of the same character only count once, so "aaaaaaaa" scores like "a"
+fn entropy_bits(password: &str) -> f64 {
+    let mut alphabet = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) { alphabet += 26; }
+    if password.chars().any(|c| c.is_ascii_uppercase()) { alphabet += 26; }
+    if password.chars().any(|c| c.is_ascii_digit()) { alphabet += 10; }
+    if password.chars().any(|c| c.is_ascii_punctuation() || c == ' ') { alphabet += 33; }
+    if !password.is_ascii() { alphabet += 100; }
+    if alphabet == 0 {
+        return 0.0;
+    }
+
+    let chars: Vec<char> = password.chars().collect();
+    let length = chars.iter().enumerate().filter(|(i, c)| *i == 0 || chars[i - 1] != **c).count();
+    length as f64 * (alphabet as f64).log2()
+}
+
+// Bloom filter of breached passwords, built offline from a HIBP-style list of SHA-1 hashes.
+// File layout: bit count (u64 LE), hash count (u32 LE), then the bit array. Bit positions for
+// a password come from its SHA-1 digest by double hashing: (h1 + i * h2) mod bits, with h1 and
+// h2 the digest's first and second 8 bytes read as u64 LE
+struct BloomFilter {
+    bits: Vec<u8>,
+    bit_count: u64,
+    hash_count: u32,
+}
+
+impl BloomFilter {
+    fn parse(data: Vec<u8>) -> Option<BloomFilter> {
+        let bit_count = u64::from_le_bytes(data.get(..8)?.try_into().ok()?);
+        let hash_count = u32::from_le_bytes(data.get(8..12)?.try_into().ok()?);
+        let bits = data[12..].to_vec();
+        if bit_count == 0 || hash_count == 0 || (bits.len() as u64) * 8 < bit_count {
+            return None;
+        }
+        Some(BloomFilter { bits, bit_count, hash_count })
+    }
+
+    fn contains(&self, password: &str) -> bool {
+        let digest = Sha1::digest(password.as_bytes());
+        let h1 = u64::from_le_bytes(digest[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap());
+        (0..self.hash_count as u64).all(|i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % self.bit_count;
+            self.bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+        })
+    }
+}
+
+static BREACH_FILTER: OnceLock<Option<BloomFilter>> = OnceLock::new();
+
+// Loaded on first use; a missing or malformed file turns the check off rather than
+// blocking every registration
+fn breach_filter() -> Option<&'static BloomFilter> {
+    BREACH_FILTER
+        .get_or_init(|| {
+            let path = config::get().password_breach_filter.as_ref()?;
+            let filter = std::fs::read(path).ok().and_then(BloomFilter::parse);
+            if filter.is_none() {
+                eprintln!("Breached password filter {} could not be loaded; the check is off", path.display());
+            }
+            filter
+        })
+        .as_ref()
+}
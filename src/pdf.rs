@@ -0,0 +1,78 @@
+// Just enough PDF 1.4 for the printable slips and label sheets: pages of text and filled
+// rectangles in the standard fonts. Output is plain ASCII so it can travel as text
+
+// Fonts every page can use: /F1 Courier, /F2 Helvetica-Bold, /F3 Helvetica
+const FONTS: &[&str] = &["Courier", "Helvetica-Bold", "Helvetica"];
+
+pub struct Page {
+    pub width: f64,
+    pub height: f64,
+    // Content stream operators, e.g. from `text` and `rect`
+    pub content: String,
+}
+
+pub fn document(pages: &[Page]) -> String {
+    // Objects: catalog, page tree, the fonts, then a page and its content stream per page
+    let font_base = 3;
+    let page_base = font_base + FONTS.len();
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", page_base + i * 2)).collect();
+    let font_refs: String = (0..FONTS.len()).map(|i| format!("/F{} {} 0 R ", i + 1, font_base + i)).collect();
+
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()),
+    ];
+    for font in FONTS {
+        objects.push(format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", font));
+    }
+    for (i, page) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /Font << {}>> >> /Contents {} 0 R >>",
+            page.width, page.height, font_refs, page_base + i * 2 + 1
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{}endstream", page.content.len(), page.content));
+    }
+
+    let mut out = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+    let xref = out.len();
+    out.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        out.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    out.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref));
+    out
+}
+
+// One line of text with its baseline starting at (x, y); `font` is 1, 2 or 3 as in FONTS
+pub fn text(font: usize, size: f64, x: f64, y: f64, text: &str) -> String {
+    format!("BT /F{} {} Tf {:.2} {:.2} Td ({}) Tj ET\n", font, size, x, y, escape(text))
+}
+
+pub fn rect(x: f64, y: f64, width: f64, height: f64) -> String {
+    format!("{:.3} {:.3} {:.3} {:.3} re f\n", x, y, width, height)
+}
+
+// PDF string literal body: escapes the delimiters and writes Latin-1 and the euro sign as octal
+// escapes. Other characters print as '?'
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\u{a0}'..='\u{ff}' => out.push_str(&format!("\\{:03o}", c as u32)),
+            // WinAnsiEncoding puts the euro sign at 0x80
+            '€' => out.push_str("\\200"),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
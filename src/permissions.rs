@@ -0,0 +1,50 @@
+use crate::models::User;
+
+// What a staff action needs; handlers ask for one of these rather than checking roles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    // Read-only admin listings and their CSV exports
+    ViewReports,
+    // Books, series, reading lists, imports, shelving and acquisitions decisions
+    ManageCatalog,
+    // Loans, fines, extensions and transfers
+    ManageCirculation,
+    // Member accounts, member types, roles and broadcast messages
+    ManageMembers,
+    // Settings, branches, closures, announcements, jobs, backups and maintenance mode
+    ManageSystem,
+}
+
+// Every role and what it may do. A new role only needs a line here
+const ROLES: &[(&str, &[Permission])] = &[
+    ("admin", &[
+        Permission::ViewReports,
+        Permission::ManageCatalog,
+        Permission::ManageCirculation,
+        Permission::ManageMembers,
+        Permission::ManageSystem,
+    ]),
+    ("reports", &[Permission::ViewReports]),
+    ("lender", &[]),
+];
+
+pub fn is_role(role: &str) -> bool {
+    ROLES.iter().any(|(name, _)| *name == role)
+}
+
+pub fn has_permission(role: &str, permission: Permission) -> bool {
+    ROLES.iter().any(|(name, granted)| *name == role && granted.contains(&permission))
+}
+
+// Any role granted at least one permission
+pub fn is_staff(role: &str) -> bool {
+    ROLES.iter().any(|(name, granted)| *name == role && !granted.is_empty())
+}
+
+pub fn authorize(user: &User, permission: Permission) -> Result<(), String> {
+    if has_permission(&user.role, permission) {
+        Ok(())
+    } else {
+        Err("Forbidden".to_string())
+    }
+}
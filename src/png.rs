@@ -0,0 +1,67 @@
+// Just enough PNG for generated black-and-white images: 1-bit grayscale, stored (uncompressed)
+// deflate blocks, no ancillary chunks
+
+pub const CONTENT_TYPE: &str = "image/png";
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+pub fn monochrome(width: usize, height: usize, dark: impl Fn(usize, usize) -> bool) -> Vec<u8> {
+    // Each scanline is a filter byte (0, none) then the pixels packed eight to a byte, 0 for black
+    let row_bytes = width.div_ceil(8);
+    let mut raw = Vec::with_capacity((row_bytes + 1) * height);
+    for y in 0..height {
+        raw.push(0);
+        let mut row = vec![0xffu8; row_bytes];
+        for x in 0..width {
+            if dark(x, y) {
+                row[x / 8] &= !(0x80 >> (x % 8));
+            }
+        }
+        raw.extend_from_slice(&row);
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth 1, color type 0 (grayscale), default compression, filtering and no interlace
+    header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+    let mut out = SIGNATURE.to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crate::gzip::crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream of uncompressed deflate blocks; the images are small enough not to need more
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(0xffff).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        out.push(if i + 1 == blocks.len() { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
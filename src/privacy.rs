@@ -0,0 +1,90 @@
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+
+use crate::{db, money, settings};
+use crate::models::User;
+
+// Everything the service stores about a member, for data-portability requests
+pub async fn export_user_data(pool: &SqlitePool, user: &User) -> Result<Value, sqlx::Error> {
+    let sessions = db::get_user_sessions(pool, user.id).await?;
+    let api_keys = db::get_api_keys(pool, user.id).await?;
+    let linked_logins: Vec<Value> = db::get_oauth_identities(pool, user.id)
+        .await?
+        .into_iter()
+        .map(|(provider, email, created_at)| json!({ "provider": provider, "email": email, "linked_at": created_at }))
+        .collect();
+    let lending_history = db::get_user_lending_history(pool, user.id).await?;
+    let lending_notes = db::get_lending_notes_for_borrower(pool, user.id).await?;
+    let notifications = db::get_user_notifications(pool, user.id).await?;
+    let digest = db::wants_digest(pool, user.id).await?;
+    let language = db::get_user_language(pool, user.id).await?;
+    let push_subscriptions = db::get_push_subscriptions(pool, user.id).await?;
+    let audit_log = db::get_audit_entries_for_user(pool, user.id).await?;
+    let reservations = db::get_user_reservations(pool, user.id, false).await?;
+    let extension_requests = db::get_user_extension_requests(pool, user.id).await?;
+    let suggestions = db::get_user_suggestions(pool, user.id).await?;
+    let donations = db::get_user_donations(pool, user.id).await?;
+    let transfers = db::get_user_transfers(pool, user.id).await?;
+    let wishlist = db::get_wishlist(pool, user.id).await?;
+    let watches = db::get_user_watches(pool, user.id).await?;
+    let favorites = db::get_favorites(pool, user.id).await?;
+    let followed_authors = db::get_followed_authors(pool, user.id).await?;
+    let fines = db::get_user_fines(pool, user.id).await?;
+    let fine_payments = db::get_user_fine_payments(pool, user.id).await?;
+
+    Ok(json!({
+        "exported_at": Utc::now().to_rfc3339(),
+        "profile": user,
+        "sessions": sessions,
+        "api_keys": api_keys,
+        "linked_logins": linked_logins,
+        "lending_history": lending_history,
+        "lending_notes": lending_notes,
+        "reservations": reservations,
+        "extension_requests": extension_requests,
+        "suggestions": suggestions,
+        "donations": donations,
+        "transfers": transfers,
+        "wishlist": wishlist,
+        "watches": watches,
+        "favorites": favorites,
+        "followed_authors": followed_authors,
+        "currency": settings::currency_code(),
+        "fines": fines,
+        "fine_payments": fine_payments,
+        "notification_preferences": { "digest": digest },
+        "language": language,
+        "push_subscriptions": push_subscriptions,
+        "notifications": notifications,
+        "audit_log": audit_log,
+    }))
+}
+
+// Reasons an account cannot be erased yet; erasure must not strand borrowed copies or unpaid fines
+pub async fn erasure_blocker(pool: &SqlitePool, user_id: i64) -> Result<Option<String>, sqlx::Error> {
+    let active_loans = db::count_active_loans(pool, user_id).await?;
+    if active_loans > 0 {
+        return Ok(Some(format!("Account has {} active loans; return them first", active_loans)));
+    }
+    let reservations = db::count_active_reservations(pool, user_id).await?;
+    if reservations > 0 {
+        return Ok(Some(format!("Account has {} active reservations; cancel them first", reservations)));
+    }
+    let transfers = db::get_user_transfers(pool, user_id).await?
+        .iter()
+        .filter(|t| t.status == "requested" || t.status == "in_transit")
+        .count();
+    if transfers > 0 {
+        return Ok(Some(format!("Account has {} transfers under way; wait for them or cancel them first", transfers)));
+    }
+    let balance = db::get_unpaid_fine_balance(pool, user_id).await?;
+    if balance > 0.0 {
+        return Ok(Some(format!("Account has unpaid fines of {}; settle them first", money::format(balance))));
+    }
+    Ok(None)
+}
+
+pub async fn erase_user(pool: &SqlitePool, user_id: i64) -> Result<(), sqlx::Error> {
+    db::anonymize_user(pool, user_id).await
+}
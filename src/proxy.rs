@@ -0,0 +1,80 @@
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::cidr::Cidr;
+use crate::config;
+
+tokio::task_local! {
+    static CLIENT_IP: Option<String>;
+}
+
+// Runs a request handler with its client address available to current_ip(), so records written
+// deep inside handlers (audit entries) carry it without every call site passing it along
+pub async fn scope<F: Future>(ip: Option<String>, handler: F) -> F::Output {
+    CLIENT_IP.scope(ip, handler).await
+}
+
+// Client address of the request being handled; None outside a request (background jobs)
+pub fn current_ip() -> Option<String> {
+    CLIENT_IP.try_with(|ip| ip.clone()).ok().flatten()
+}
+
+// The client address for a request. Forwarding headers are only believed when the socket peer
+// is a trusted proxy; the chain is then walked from the nearest hop outwards, and the first
+// address that is not itself a trusted proxy is the client. Anything further left was written
+// by the client and could be made up. A hop that isn't an address (`unknown`, an obfuscated
+// identifier) ends the walk at the proxy that reported it
+pub fn client_ip(request: &str, peer: SocketAddr) -> String {
+    resolve(request, peer.ip(), &config::get().trusted_proxies).to_string()
+}
+
+fn resolve(request: &str, peer: IpAddr, trusted: &[Cidr]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|range| range.contains(ip));
+    let mut client = peer.to_canonical();
+    if !is_trusted(client) {
+        return client;
+    }
+
+    for hop in forwarded_chain(request).iter().rev() {
+        match parse_hop(hop) {
+            Some(ip) => client = ip.to_canonical(),
+            None => break,
+        }
+        if !is_trusted(client) {
+            break;
+        }
+    }
+    client
+}
+
+// Addresses from the RFC 7239 Forwarded header (its `for=` parameters), falling back to
+// X-Forwarded-For, in the order the proxies appended them
+fn forwarded_chain(request: &str) -> Vec<String> {
+    if let Some(forwarded) = crate::request_header(request, "forwarded") {
+        return forwarded
+            .split(',')
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (name, value) = pair.split_once('=')?;
+                    name.trim().eq_ignore_ascii_case("for").then(|| value.trim().trim_matches('"').to_string())
+                })
+            })
+            .collect();
+    }
+
+    crate::request_header(request, "x-forwarded-for")
+        .map(|value| value.split(',').map(|hop| hop.trim().to_string()).collect())
+        .unwrap_or_default()
+}
+
+// Accepts a bare address, IPv4 with a port, or bracketed IPv6 with an optional port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    if let Some(rest) = hop.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    hop.parse().ok().or_else(|| {
+        let (address, port) = hop.rsplit_once(':')?;
+        port.parse::<u16>().ok()?;
+        address.parse::<std::net::Ipv4Addr>().ok().map(IpAddr::V4)
+    })
+}
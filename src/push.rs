@@ -0,0 +1,178 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::derive::Deriver;
+use openssl::ec::{EcGroup, EcKey, EcPoint, PointConversionForm};
+use openssl::ecdsa::EcdsaSig;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::symm::{encrypt_aead, Cipher};
+use sha2::{Digest, Sha256};
+
+use crate::http;
+
+// Web Push (RFC 8030) to browsers that have subscribed through the service worker. Messages are
+// encrypted for the subscription (RFC 8291, aes128gcm) and the server identifies itself to the
+// push service with a VAPID token (RFC 8292) signed by the application server key
+
+// Push services keep undelivered messages this long, in seconds
+const TTL_SECONDS: u32 = 86_400;
+// Push services accept 4096-byte bodies; the encryption header, padding delimiter and tag take 103
+const RECORD_SIZE: u32 = 4096;
+pub const MAX_PAYLOAD: usize = 3993;
+
+pub enum Delivery {
+    Sent,
+    // The push service no longer knows the subscription; it should be forgotten
+    Gone,
+}
+
+fn group() -> Result<EcGroup, String> {
+    EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).map_err(|e| e.to_string())
+}
+
+// A new application server key: the P-256 private scalar, base64url encoded
+pub fn generate_private_key() -> String {
+    let key = group().and_then(|group| EcKey::generate(&group).map_err(|e| e.to_string())).expect("P-256 key generation");
+    URL_SAFE_NO_PAD.encode(key.private_key().to_vec_padded(32).expect("32-byte scalar"))
+}
+
+fn load_private_key(encoded: &str) -> Result<EcKey<Private>, String> {
+    let group = group()?;
+    let scalar = URL_SAFE_NO_PAD.decode(encoded.trim()).map_err(|_| "VAPID private key is not base64url".to_string())?;
+    let scalar = BigNum::from_slice(&scalar).map_err(|e| e.to_string())?;
+    let mut ctx = BigNumContext::new().map_err(|e| e.to_string())?;
+    let mut point = EcPoint::new(&group).map_err(|e| e.to_string())?;
+    point.mul_generator2(&group, &scalar, &mut ctx).map_err(|e| e.to_string())?;
+    let key = EcKey::from_private_components(&group, &scalar, &point).map_err(|e| e.to_string())?;
+    key.check_key().map_err(|_| "VAPID private key is not a valid P-256 key".to_string())?;
+    Ok(key)
+}
+
+fn public_key_bytes(key: &EcKey<Private>) -> Result<Vec<u8>, String> {
+    let group = group()?;
+    let mut ctx = BigNumContext::new().map_err(|e| e.to_string())?;
+    key.public_key().to_bytes(&group, PointConversionForm::UNCOMPRESSED, &mut ctx).map_err(|e| e.to_string())
+}
+
+// The application server key browsers need to subscribe, base64url encoded
+pub fn public_key(private_key: &str) -> Result<String, String> {
+    Ok(URL_SAFE_NO_PAD.encode(public_key_bytes(&load_private_key(private_key)?)?))
+}
+
+// Subscription keys as the browser hands them out: p256dh a 65-byte uncompressed P-256 point,
+// auth 16 random bytes, both base64url
+pub fn check_subscription_keys(p256dh: &str, auth: &str) -> Result<(), String> {
+    let p256dh = URL_SAFE_NO_PAD.decode(p256dh.trim_end_matches('=')).map_err(|_| "p256dh is not base64url")?;
+    let auth = URL_SAFE_NO_PAD.decode(auth.trim_end_matches('=')).map_err(|_| "auth is not base64url")?;
+    let group = group()?;
+    let mut ctx = BigNumContext::new().map_err(|e| e.to_string())?;
+    if p256dh.len() != 65 || EcPoint::from_bytes(&group, &p256dh, &mut ctx).is_err() {
+        return Err("p256dh is not a P-256 public key".to_string());
+    }
+    if auth.len() != 16 {
+        return Err("auth must be 16 bytes".to_string());
+    }
+    Ok(())
+}
+
+// Sends one message; blocking, so callers run it off the async runtime
+pub fn send(private_key: &str, subject: &str, endpoint: &str, p256dh: &str, auth: &str, payload: &[u8]) -> Result<Delivery, String> {
+    let server_key = load_private_key(private_key)?;
+    let body = encrypt(p256dh, auth, payload)?;
+    let authorization = vapid_authorization(&server_key, subject, endpoint)?;
+    let ttl = TTL_SECONDS.to_string();
+
+    let status = http::post(endpoint, &[
+        ("Authorization", &authorization),
+        ("Content-Encoding", "aes128gcm"),
+        ("Content-Type", "application/octet-stream"),
+        ("TTL", &ttl),
+    ], &body)?;
+
+    match status {
+        200..=299 => Ok(Delivery::Sent),
+        404 | 410 => Ok(Delivery::Gone),
+        _ => Err(format!("Push service responded with status {}", status)),
+    }
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+// Single-record aes128gcm body: salt, record size, the ephemeral public key, then the ciphertext
+fn encrypt(p256dh: &str, auth: &str, payload: &[u8]) -> Result<Vec<u8>, String> {
+    if payload.len() > MAX_PAYLOAD {
+        return Err("Push message is too long".to_string());
+    }
+    let group = group()?;
+    let mut ctx = BigNumContext::new().map_err(|e| e.to_string())?;
+    let ua_public = URL_SAFE_NO_PAD.decode(p256dh.trim_end_matches('=')).map_err(|_| "Invalid subscription key")?;
+    let auth_secret = URL_SAFE_NO_PAD.decode(auth.trim_end_matches('=')).map_err(|_| "Invalid subscription secret")?;
+    let peer = EcPoint::from_bytes(&group, &ua_public, &mut ctx).map_err(|_| "Invalid subscription key")?;
+    let peer = PKey::from_ec_key(EcKey::from_public_key(&group, &peer).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+
+    let ephemeral = EcKey::generate(&group).map_err(|e| e.to_string())?;
+    let as_public = public_key_bytes(&ephemeral)?;
+    let ephemeral = PKey::from_ec_key(ephemeral).map_err(|e| e.to_string())?;
+    let mut deriver = Deriver::new(&ephemeral).map_err(|e| e.to_string())?;
+    deriver.set_peer(&peer).map_err(|e| e.to_string())?;
+    let shared_secret = deriver.derive_to_vec().map_err(|e| e.to_string())?;
+
+    // HKDF steps from RFC 8291 section 3.4; every output fits in one HMAC block
+    let prk_key = hmac_sha256(&auth_secret, &[&shared_secret]);
+    let ikm = hmac_sha256(&prk_key, &[b"WebPush: info\0", &ua_public, &as_public, &[1]]);
+    let salt: [u8; 16] = rand_bytes()?;
+    let prk = hmac_sha256(&salt, &[&ikm]);
+    let cek = hmac_sha256(&prk, &[b"Content-Encoding: aes128gcm\0", &[1]]);
+    let nonce = hmac_sha256(&prk, &[b"Content-Encoding: nonce\0", &[1]]);
+
+    // 0x02 marks the last (and only) record
+    let mut plaintext = payload.to_vec();
+    plaintext.push(2);
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(Cipher::aes_128_gcm(), &cek[..16], Some(&nonce[..12]), &[], &plaintext, &mut tag).map_err(|e| e.to_string())?;
+
+    let mut body = Vec::with_capacity(86 + ciphertext.len() + tag.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.len() as u8);
+    body.extend_from_slice(&as_public);
+    body.extend_from_slice(&ciphertext);
+    body.extend_from_slice(&tag);
+    Ok(body)
+}
+
+fn rand_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0u8; N];
+    openssl::rand::rand_bytes(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+// "vapid t=<ES256 JWT>, k=<server public key>", with the JWT audience set to the push service origin
+fn vapid_authorization(key: &EcKey<Private>, subject: &str, endpoint: &str) -> Result<String, String> {
+    let rest = endpoint.strip_prefix("https://").ok_or("Only https push endpoints are supported")?;
+    let audience = format!("https://{}", rest.split('/').next().unwrap_or(rest));
+    let expires = chrono::Utc::now().timestamp() + 12 * 3600;
+
+    let header = URL_SAFE_NO_PAD.encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+    let claims = URL_SAFE_NO_PAD.encode(serde_json::json!({ "aud": audience, "exp": expires, "sub": subject }).to_string());
+    let signing_input = format!("{}.{}", header, claims);
+
+    let signature = EcdsaSig::sign(&Sha256::digest(signing_input.as_bytes()), key).map_err(|e| e.to_string())?;
+    let mut raw = signature.r().to_vec_padded(32).map_err(|e| e.to_string())?;
+    raw.extend(signature.s().to_vec_padded(32).map_err(|e| e.to_string())?);
+
+    Ok(format!(
+        "vapid t={}.{}, k={}",
+        signing_input,
+        URL_SAFE_NO_PAD.encode(raw),
+        URL_SAFE_NO_PAD.encode(public_key_bytes(key)?)
+    ))
+}
@@ -0,0 +1,369 @@
+use crate::png;
+
+// QR Code symbols (ISO/IEC 18004) for short links: byte mode at error correction level M,
+// versions 1 to 10, which is room for up to 213 bytes
+
+pub struct Code {
+    pub size: usize,
+    modules: Vec<bool>,
+}
+
+impl Code {
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+}
+
+// Per version: error correction codewords per block, then the number of blocks in the first
+// group and their data codewords. Blocks in the second group hold one data codeword more
+const BLOCKS: [(usize, usize, usize, usize); 10] = [
+    (10, 1, 16, 0),
+    (16, 1, 28, 0),
+    (26, 1, 44, 0),
+    (18, 2, 32, 0),
+    (24, 2, 43, 0),
+    (16, 4, 27, 0),
+    (18, 4, 31, 0),
+    (22, 2, 38, 2),
+    (22, 3, 36, 2),
+    (26, 4, 43, 1),
+];
+
+// Centre rows/columns of the alignment patterns per version
+const ALIGNMENT: [&[usize]; 10] = [
+    &[],
+    &[6, 18],
+    &[6, 22],
+    &[6, 26],
+    &[6, 30],
+    &[6, 34],
+    &[6, 22, 38],
+    &[6, 24, 42],
+    &[6, 26, 46],
+    &[6, 28, 50],
+];
+
+// Light modules required around the symbol
+const QUIET_ZONE: usize = 4;
+
+pub fn encode(data: &[u8]) -> Option<Code> {
+    let (version, count_bits) = (1..=BLOCKS.len()).map(|v| (v, if v < 10 { 8 } else { 16 })).find(|&(v, count_bits)| {
+        4 + count_bits + data.len() * 8 <= data_codewords(v) * 8
+    })?;
+
+    // Mode indicator, character count, the bytes, then terminator and padding to capacity
+    let capacity = data_codewords(version) * 8;
+    let mut bits = Vec::with_capacity(capacity);
+    push_bits(&mut bits, 0b0100, 4);
+    push_bits(&mut bits, data.len() as u32, count_bits);
+    for &byte in data {
+        push_bits(&mut bits, byte as u32, 8);
+    }
+    let terminator = (capacity - bits.len()).min(4);
+    push_bits(&mut bits, 0, terminator);
+    let pad = (8 - bits.len() % 8) % 8;
+    push_bits(&mut bits, 0, pad);
+    for pad in [0xec, 0x11].iter().cycle() {
+        if bits.len() >= capacity {
+            break;
+        }
+        push_bits(&mut bits, *pad, 8);
+    }
+    let codewords: Vec<u8> = bits.chunks(8).map(|byte| byte.iter().fold(0, |acc, &b| (acc << 1) | b as u8)).collect();
+
+    let mut code = Symbol::new(version);
+    code.place(&interleave(version, &codewords));
+
+    let mask = (0..8).min_by_key(|&mask| {
+        let mut candidate = code.clone();
+        candidate.apply_mask(mask);
+        candidate.draw_format(mask);
+        candidate.penalty()
+    })?;
+    code.apply_mask(mask);
+    code.draw_format(mask);
+    Some(Code { size: code.size, modules: code.modules })
+}
+
+// The symbol as a PNG with each module `scale` pixels square, quiet zone included
+pub fn png(code: &Code, scale: usize) -> Vec<u8> {
+    let side = (code.size + QUIET_ZONE * 2) * scale;
+    png::monochrome(side, side, |x, y| {
+        let (x, y) = (x / scale, y / scale);
+        (QUIET_ZONE..QUIET_ZONE + code.size).contains(&x)
+            && (QUIET_ZONE..QUIET_ZONE + code.size).contains(&y)
+            && code.is_dark(x - QUIET_ZONE, y - QUIET_ZONE)
+    })
+}
+
+fn data_codewords(version: usize) -> usize {
+    let (_, group1, per_block, group2) = BLOCKS[version - 1];
+    group1 * per_block + group2 * (per_block + 1)
+}
+
+fn push_bits(bits: &mut Vec<bool>, value: u32, count: usize) {
+    for i in (0..count).rev() {
+        bits.push((value >> i) & 1 == 1);
+    }
+}
+
+// Splits the data into blocks, adds each block's error correction and interleaves the lot
+fn interleave(version: usize, codewords: &[u8]) -> Vec<u8> {
+    let (ec_len, group1, per_block, group2) = BLOCKS[version - 1];
+    let divisor = rs_divisor(ec_len);
+    let mut blocks = Vec::new();
+    let mut rest = codewords;
+    for i in 0..group1 + group2 {
+        let (block, tail) = rest.split_at(if i < group1 { per_block } else { per_block + 1 });
+        blocks.push((block, rs_remainder(block, &divisor)));
+        rest = tail;
+    }
+
+    let mut out = Vec::new();
+    for i in 0..=per_block {
+        out.extend(blocks.iter().filter_map(|(data, _)| data.get(i)));
+    }
+    for i in 0..ec_len {
+        out.extend(blocks.iter().map(|(_, ec)| ec[i]));
+    }
+    out
+}
+
+// Reed-Solomon over GF(2^8) with the QR field polynomial x^8 + x^4 + x^3 + x^2 + 1
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u16 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11d);
+        z ^= ((y >> i) & 1) as u16 * x as u16;
+    }
+    z as u8
+}
+
+// Generator polynomial coefficients, highest power first with the leading 1 left out
+fn rs_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_mul(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_mul(root, 2);
+    }
+    result
+}
+
+fn rs_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_mul(d, factor);
+        }
+    }
+    result
+}
+
+#[derive(Clone)]
+struct Symbol {
+    size: usize,
+    modules: Vec<bool>,
+    // Finder, timing, alignment, format and version modules, which data and masks skip
+    function: Vec<bool>,
+}
+
+impl Symbol {
+    fn new(version: usize) -> Self {
+        let size = version * 4 + 17;
+        let mut symbol = Symbol { size, modules: vec![false; size * size], function: vec![false; size * size] };
+        for i in 0..size {
+            symbol.set_function(6, i, i % 2 == 0);
+            symbol.set_function(i, 6, i % 2 == 0);
+        }
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            symbol.draw_finder(x, y);
+        }
+        let centres = ALIGNMENT[version - 1];
+        let last = centres.len().saturating_sub(1);
+        for (i, &x) in centres.iter().enumerate() {
+            for (j, &y) in centres.iter().enumerate() {
+                // The corners that hold finder patterns get none
+                if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                    continue;
+                }
+                for dy in 0..5 {
+                    for dx in 0..5 {
+                        let ring = (dx as isize - 2).abs().max((dy as isize - 2).abs());
+                        symbol.set_function(x + dx - 2, y + dy - 2, ring != 1);
+                    }
+                }
+            }
+        }
+        // Reserve the format areas until the mask is chosen, then the version blocks
+        symbol.draw_format(0);
+        if version >= 7 {
+            let mut rem = version as u32;
+            for _ in 0..12 {
+                rem = (rem << 1) ^ ((rem >> 11) * 0x1f25);
+            }
+            let bits = (version as u32) << 12 | rem;
+            for i in 0..18 {
+                let dark = (bits >> i) & 1 == 1;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                symbol.set_function(a, b, dark);
+                symbol.set_function(b, a, dark);
+            }
+        }
+        symbol
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    // A finder pattern centred on (x, y) with its light separator, clipped to the symbol
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4isize..=4 {
+            for dx in -4isize..=4 {
+                let (px, py) = (x as isize + dx, y as isize + dy);
+                if px < 0 || py < 0 || px >= self.size as isize || py >= self.size as isize {
+                    continue;
+                }
+                let ring = dx.abs().max(dy.abs());
+                self.set_function(px as usize, py as usize, ring != 2 && ring != 4);
+            }
+        }
+    }
+
+    // Level M with the mask number, BCH-protected, in both copies around the finders
+    fn draw_format(&mut self, mask: u32) {
+        let data = mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = ((data << 10) | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+        let size = self.size;
+
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        // The module above the lower copy is always dark
+        self.set_function(8, size - 8, true);
+    }
+
+    // Codeword bits in the zigzag order: two-module columns from the right, alternately upward
+    // and downward, stepping over the vertical timing pattern
+    fn place(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let total = codewords.len() * 8;
+        let mut i = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vert } else { vert };
+                    if !self.function[y * size + x] && i < total {
+                        self.modules[y * size + x] = (codewords[i / 8] >> (7 - i % 8)) & 1 == 1;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if flip && !self.function[y * self.size + x] {
+                    self.modules[y * self.size + x] ^= true;
+                }
+            }
+        }
+    }
+
+    // The standard's four penalty rules, lower is easier to scan
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let at = |x: usize, y: usize| self.modules[y * size + x];
+        let mut score = 0;
+
+        let lines: Vec<Vec<bool>> = (0..size)
+            .map(|y| (0..size).map(|x| at(x, y)).collect())
+            .chain((0..size).map(|x| (0..size).map(|y| at(x, y)).collect()))
+            .collect();
+        const FINDER_LIKE: [bool; 11] = [true, false, true, true, true, false, true, false, false, false, false];
+        for line in &lines {
+            // Runs of five or more modules of one color
+            let mut run = 1;
+            for i in 1..=line.len() {
+                if i < line.len() && line[i] == line[i - 1] {
+                    run += 1;
+                } else {
+                    if run >= 5 {
+                        score += run - 2;
+                    }
+                    run = 1;
+                }
+            }
+            // Patterns that look like a finder with light space beside them
+            for window in line.windows(11) {
+                if window == FINDER_LIKE || window.iter().rev().eq(FINDER_LIKE.iter()) {
+                    score += 40;
+                }
+            }
+        }
+
+        // 2x2 blocks of one color
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = at(x, y);
+                if at(x + 1, y) == c && at(x, y + 1) == c && at(x + 1, y + 1) == c {
+                    score += 3;
+                }
+            }
+        }
+
+        // Balance of dark and light
+        let dark = self.modules.iter().filter(|&&m| m).count();
+        let percent = dark * 100 / self.modules.len();
+        score + percent.abs_diff(50) / 5 * 10
+    }
+}
@@ -0,0 +1,101 @@
+// Byte ranges (206 Partial Content) for responses that advertise `Accept-Ranges: bytes`: static
+// files, images and stored backups, whose bytes don't change between requests, so an interrupted
+// download can pick up where it stopped. Ranges apply to the response as sent, after any gzip
+// encoding, which is deterministic for the same body
+
+pub struct Requested {
+    spec: String,
+    if_range: Option<String>,
+}
+
+// The Range and If-Range headers of a GET request, if it has a Range header
+pub fn requested(request: &str) -> Option<Requested> {
+    if !request.starts_with("GET ") {
+        return None;
+    }
+    Some(Requested {
+        spec: crate::request_header(request, "range")?.to_string(),
+        if_range: crate::request_header(request, "if-range").map(str::to_string),
+    })
+}
+
+// Cuts a 200 response down to the requested range. Multiple ranges, malformed specs and an
+// If-Range that doesn't match the response's strong ETag all get the whole response, which
+// HTTP allows; a range starting past the end gets 416
+pub fn apply(requested: &Requested, response: Vec<u8>) -> Vec<u8> {
+    if !response.starts_with(b"HTTP/1.1 200 ") {
+        return response;
+    }
+    let Some(head_end) = response.windows(4).position(|w| w == b"\r\n\r\n") else { return response };
+    let head = String::from_utf8_lossy(&response[..head_end]).into_owned();
+    let header = |name: &str| {
+        head.lines().skip(1).find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+        })
+    };
+    if !header("accept-ranges").is_some_and(|value| value.eq_ignore_ascii_case("bytes")) {
+        return response;
+    }
+    if let Some(if_range) = &requested.if_range {
+        if if_range.starts_with("W/") || header("etag").as_deref() != Some(if_range.as_str()) {
+            return response;
+        }
+    }
+
+    let body = &response[head_end + 4..];
+    let Some(range) = parse(&requested.spec, body.len()) else { return response };
+    let Ok((start, end)) = range else {
+        let mut unsatisfiable = crate::error_response(416, "Range Not Satisfiable");
+        let status_end = unsatisfiable.find("\r\n").unwrap_or(unsatisfiable.len());
+        unsatisfiable.insert_str(status_end, &format!("\r\nContent-Range: bytes */{}", body.len()));
+        return unsatisfiable.into_bytes();
+    };
+
+    let mut out = Vec::with_capacity(head_end + end - start + 64);
+    out.extend_from_slice(b"HTTP/1.1 206 Partial Content");
+    for line in head.lines().skip(1) {
+        out.extend_from_slice(b"\r\n");
+        match line.split_once(':') {
+            Some((name, _)) if name.trim().eq_ignore_ascii_case("content-length") => {
+                out.extend_from_slice(format!("Content-Length: {}", end - start + 1).as_bytes());
+            }
+            _ => out.extend_from_slice(line.as_bytes()),
+        }
+    }
+    out.extend_from_slice(format!("\r\nContent-Range: bytes {}-{}/{}\r\n\r\n", start, end, body.len()).as_bytes());
+    out.extend_from_slice(&body[start..=end]);
+    out
+}
+
+// bytes=first-last, bytes=first- or bytes=-suffix_length as an inclusive (start, end); None to
+// ignore the header, Err when the range is well formed but lies outside the body
+fn parse(spec: &str, len: usize) -> Option<Result<(usize, usize), ()>> {
+    let range = spec.trim().strip_prefix("bytes=")?.trim();
+    if range.contains(',') {
+        return None;
+    }
+    let (first, last) = range.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        let suffix: usize = last.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        return Some(Ok((len - suffix.min(len), len - 1)));
+    }
+
+    let start: usize = first.parse().ok()?;
+    let end = match last {
+        "" => usize::MAX,
+        last => last.parse().ok()?,
+    };
+    if end < start {
+        return None;
+    }
+    if start >= len {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end.min(len - 1))))
+}
@@ -0,0 +1,129 @@
+use crate::{money, pdf};
+
+// Printable slips for the desk: one loan's checkout or return, as HTML for the browser's print
+// dialog or as a single-page PDF sized for 80 mm receipt printers
+
+pub struct Receipt {
+    pub record_id: i64,
+    pub member: String,
+    pub card_number: Option<String>,
+    pub title: String,
+    pub author: String,
+    pub branch: String,
+    pub borrowed_at: String,
+    pub due_date: String,
+    pub returned_at: Option<String>,
+    // Fine assessed for this loan, if any
+    pub fine: Option<f64>,
+}
+
+impl Receipt {
+    fn heading(&self) -> &'static str {
+        if self.returned_at.is_some() { "Return receipt" } else { "Checkout receipt" }
+    }
+
+    // (label, value) rows shared by both formats
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        let mut rows = vec![
+            ("Loan", format!("#{}", self.record_id)),
+            ("Member", self.member.clone()),
+        ];
+        if let Some(card) = &self.card_number {
+            rows.push(("Card", card.clone()));
+        }
+        rows.push(("Item", self.title.clone()));
+        rows.push(("Author", self.author.clone()));
+        rows.push(("Branch", self.branch.clone()));
+        rows.push(("Borrowed", day(&self.borrowed_at)));
+        match &self.returned_at {
+            Some(returned_at) => rows.push(("Returned", day(returned_at))),
+            None => rows.push(("Due", day(&self.due_date))),
+        }
+        if let Some(fine) = self.fine.filter(|f| *f > 0.0) {
+            rows.push(("Fine", money::format(fine)));
+        }
+        rows
+    }
+}
+
+fn day(timestamp: &str) -> String {
+    timestamp.chars().take(10).collect()
+}
+
+pub const HTML_TYPE: &str = "text/html; charset=utf-8";
+pub const PDF_TYPE: &str = "application/pdf";
+
+pub fn html(receipt: &Receipt) -> String {
+    let rows: String = receipt
+        .rows()
+        .iter()
+        .map(|(label, value)| format!("<tr><th>{}</th><td>{}</td></tr>\n", label, escape_html(value)))
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n<title>{heading} #{id}</title>\n<style>\n\
+         body {{ font-family: sans-serif; width: 72mm; margin: 4mm; font-size: 12px; }}\n\
+         h1 {{ font-size: 16px; text-align: center; }}\n\
+         th {{ text-align: left; padding-right: 8px; vertical-align: top; }}\n\
+         p {{ text-align: center; margin-top: 12px; }}\n\
+         </style>\n</head>\n<body onload=\"window.print()\">\n<h1>{heading}</h1>\n<table>\n{rows}</table>\n<p>Thank you for using the library</p>\n</body>\n</html>\n",
+        heading = receipt.heading(),
+        id = receipt.record_id,
+        rows = rows,
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Page width of an 80 mm roll in points; the height grows with the number of rows
+const PAGE_WIDTH: f64 = 227.0;
+const LINE_HEIGHT: f64 = 14.0;
+const MARGIN: f64 = 14.0;
+// Rows are set in 9 pt Courier: a 9-character label column, then values wrapped to fit the roll
+const LABEL_CHARS: usize = 9;
+const WRAP_CHARS: usize = 27;
+
+pub fn pdf(receipt: &Receipt) -> String {
+    let mut lines = Vec::new();
+    for (label, value) in receipt.rows() {
+        for (i, chunk) in wrap(&value, WRAP_CHARS).into_iter().enumerate() {
+            lines.push(format!("{:<width$}{}", if i == 0 { label } else { "" }, chunk, width = LABEL_CHARS));
+        }
+    }
+
+    let height = MARGIN * 2.0 + LINE_HEIGHT * (lines.len() as f64 + 4.0);
+    let mut content = String::new();
+    let mut y = height - MARGIN - LINE_HEIGHT;
+    content.push_str(&pdf::text(2, 13.0, MARGIN, y, receipt.heading()));
+    y -= LINE_HEIGHT * 1.5;
+    for line in &lines {
+        content.push_str(&pdf::text(1, 9.0, MARGIN, y, line));
+        y -= LINE_HEIGHT;
+    }
+    y -= LINE_HEIGHT * 0.5;
+    content.push_str(&pdf::text(1, 9.0, MARGIN, y, "Thank you for using the library"));
+
+    pdf::document(&[pdf::Page { width: PAGE_WIDTH, height, content }])
+}
+
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let words = text.split_whitespace().flat_map(|word| {
+        // Words wider than the roll are cut
+        let chars: Vec<char> = word.chars().collect();
+        chars.chunks(width).map(|c| c.iter().collect::<String>()).collect::<Vec<_>>()
+    });
+    for word in words {
+        let current = lines.last_mut().unwrap();
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            lines.push(word);
+        } else {
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(&word);
+        }
+    }
+    lines
+}
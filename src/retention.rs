@@ -0,0 +1,135 @@
+use chrono::{DateTime, Duration, Months, Utc};
+use sqlx::SqlitePool;
+
+use crate::{config, db, settings};
+use crate::models::{RetentionItem, RetentionReport};
+
+// Applies the retention settings: returned loans are anonymized or deleted and sent
+// notifications deleted once older than their configured number of years, and access log
+// entries and feed events once past LIBRARY_ACCESS_LOG_RETENTION_DAYS and
+// LIBRARY_EVENT_RETENTION_DAYS. In privacy mode returned loans are also
+// detached from their readers after reader_privacy_days. With `dry_run` nothing is changed and
+// the report counts what would be
+pub async fn purge(pool: &SqlitePool, dry_run: bool) -> Result<RetentionReport, sqlx::Error> {
+    let now = crate::clock::now();
+
+    let lending_records = match years_before(now, settings::lending_retention_years()) {
+        None => keep(),
+        Some(before) => {
+            let action = settings::lending_retention_action();
+            let before = before.to_rfc3339();
+            let records = match (dry_run, action.as_str()) {
+                (true, _) => db::count_expired_loans(pool, &before, &action).await?,
+                (false, "delete") => db::delete_expired_loans(pool, &before).await?,
+                (false, _) => db::anonymize_expired_loans(pool, &before).await?,
+            };
+            RetentionItem { action, older_than: Some(before), records }
+        }
+    };
+
+    // Runs after the lending retention, which has already dealt with the oldest loans; a dry run
+    // leaves those out of the count
+    let reader_history = match settings::reader_privacy_days() {
+        days if days <= 0 => keep(),
+        days => {
+            let before = (now - Duration::days(days)).to_rfc3339();
+            let records = if dry_run {
+                let overlap = match &lending_records.older_than {
+                    Some(retained) => db::count_expired_loans(pool, retained.min(&before), "anonymize").await?,
+                    None => 0,
+                };
+                db::count_expired_loans(pool, &before, "anonymize").await? - overlap
+            } else {
+                db::anonymize_expired_loans(pool, &before).await?
+            };
+            RetentionItem { action: "anonymize".to_string(), older_than: Some(before), records }
+        }
+    };
+
+    let notifications = match years_before(now, settings::notification_retention_years()) {
+        None => keep(),
+        Some(before) => {
+            // Notifications are stamped by SQLite's CURRENT_TIMESTAMP
+            let before = before.format("%Y-%m-%d %H:%M:%S").to_string();
+            let records = if dry_run {
+                db::count_notifications_before(pool, &before).await?
+            } else {
+                db::delete_notifications_before(pool, &before).await?
+            };
+            RetentionItem { action: "delete".to_string(), older_than: Some(before), records }
+        }
+    };
+
+    let before = (now - Duration::days(config::get().access_log_retention_days)).to_rfc3339();
+    let records = if dry_run {
+        db::count_access_log_before(pool, &before).await?
+    } else {
+        db::delete_access_log_before(pool, &before).await?
+    };
+    let access_log = RetentionItem { action: "delete".to_string(), older_than: Some(before), records };
+
+    // Loan events name the member, so the feed only reaches back as far as pollers need it
+    let before = (now - Duration::days(config::get().event_retention_days)).to_rfc3339();
+    let records = if dry_run {
+        db::count_events_before(pool, &before).await?
+    } else {
+        db::delete_events_before(pool, &before).await?
+    };
+    let events = RetentionItem { action: "delete".to_string(), older_than: Some(before), records };
+
+    Ok(RetentionReport { dry_run, lending_records, reader_history, notifications, access_log, events })
+}
+
+fn years_before(now: DateTime<Utc>, years: i64) -> Option<DateTime<Utc>> {
+    if years <= 0 {
+        return None;
+    }
+    now.checked_sub_months(Months::new(u32::try_from(years * 12).ok()?))
+}
+
+fn keep() -> RetentionItem {
+    RetentionItem { action: "keep".to_string(), older_than: None, records: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::db;
+    use crate::test_support::{self, BookFactory, UserFactory};
+
+    async fn event_user_ids(pool: &sqlx::SqlitePool) -> Vec<Value> {
+        let events = db::get_events_after(pool, 0, Some("loan"), 100).await.unwrap();
+        events.into_iter().map(|e| e.data["user_id"].clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn anonymizing_strips_the_reader_from_loan_events() {
+        let pool = test_support::pool().await;
+        let book = BookFactory::default().insert(&pool).await;
+        let (reader, token) = UserFactory::default().signed_in(&pool).await;
+
+        let (status, body) = test_support::call(&pool, "POST", &format!("/api/lending/borrow/{}", book.id), Some(&token), None).await;
+        assert_eq!(status, 201);
+        let record_id = body["record_id"].as_i64().unwrap();
+        let (status, _) = test_support::call(&pool, "POST", &format!("/api/lending/return/{}", record_id), Some(&token), None).await;
+        assert_eq!(status, 200);
+        assert_eq!(event_user_ids(&pool).await, vec![Value::from(reader.id); 2]);
+
+        let later = (crate::clock::now() + chrono::Duration::days(1)).to_rfc3339();
+        assert_eq!(db::anonymize_expired_loans(&pool, &later).await.unwrap(), 1);
+        assert_eq!(event_user_ids(&pool).await, vec![Value::Null; 2]);
+    }
+
+    #[tokio::test]
+    async fn erasing_a_member_strips_them_from_loan_events() {
+        let pool = test_support::pool().await;
+        let book = BookFactory::default().insert(&pool).await;
+        let (reader, token) = UserFactory::default().signed_in(&pool).await;
+
+        let (status, _) = test_support::call(&pool, "POST", &format!("/api/lending/borrow/{}", book.id), Some(&token), None).await;
+        assert_eq!(status, 201);
+        db::anonymize_user(&pool, reader.id).await.unwrap();
+        assert_eq!(event_user_ids(&pool).await, vec![Value::Null]);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::csv;
+use crate::models::ImportedMember;
+
+// Reads member lists for bulk sign-up (a school class, a reading group), matching columns by
+// header name so spreadsheets exported with extra or reordered columns still load
+pub fn parse(text: &str) -> Result<Vec<Result<ImportedMember, String>>, String> {
+    let mut rows = csv::parse(text).into_iter();
+    let header = rows.next().ok_or("The file is empty")?;
+    let columns = Columns::new(&header)?;
+    Ok(rows.map(|row| columns.member(&row)).collect())
+}
+
+struct Columns {
+    username: usize,
+    email: usize,
+    member_type: Option<usize>,
+    card_number: Option<usize>,
+}
+
+impl Columns {
+    fn new(header: &[String]) -> Result<Columns, String> {
+        let find = |names: &[&str]| header.iter().position(|h| names.iter().any(|n| h.trim().eq_ignore_ascii_case(n)));
+
+        Ok(Columns {
+            username: find(&["username", "user name", "login"]).ok_or("Missing username column")?,
+            email: find(&["email", "e-mail", "email address"]).ok_or("Missing email column")?,
+            member_type: find(&["member_type", "member type", "type"]),
+            card_number: find(&["card_number", "card number", "card"]),
+        })
+    }
+
+    fn member(&self, row: &[String]) -> Result<ImportedMember, String> {
+        let get = |i: usize| row.get(i).map(|v| v.trim()).unwrap_or("");
+        let optional = |i: Option<usize>| i.map(get).filter(|v| !v.is_empty()).map(str::to_string);
+
+        let username = get(self.username).to_string();
+        if username.is_empty() {
+            return Err("Missing username".to_string());
+        }
+
+        let email = get(self.email).to_string();
+        if email.is_empty() || !email.contains('@') {
+            return Err("Missing or invalid email".to_string());
+        }
+
+        Ok(ImportedMember {
+            username,
+            email,
+            member_type: optional(self.member_type).map(|t| t.to_lowercase()),
+            card_number: optional(self.card_number),
+        })
+    }
+}
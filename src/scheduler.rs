@@ -0,0 +1,291 @@
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+// When a job should run: a fixed interval or a five-field cron expression (UTC)
+pub enum Schedule {
+    Every(Duration),
+    Cron(CronExpr),
+}
+
+impl Schedule {
+    pub fn cron(expr: &str) -> Result<Schedule, String> {
+        CronExpr::parse(expr).map(Schedule::Cron)
+    }
+
+    fn next_after(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Schedule::Every(interval) => after + *interval,
+            Schedule::Cron(expr) => expr.next_after(after),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Schedule::Every(interval) => format!("every {}s", interval.num_seconds()),
+            Schedule::Cron(expr) => format!("cron {}", expr.source),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub running: bool,
+    pub run_count: u64,
+    pub last_run_at: Option<String>,
+    pub last_success_at: Option<String>,
+    pub last_error: Option<String>,
+    pub next_run_at: Option<String>,
+}
+
+static JOBS: OnceLock<Mutex<Vec<JobStatus>>> = OnceLock::new();
+
+fn jobs() -> &'static Mutex<Vec<JobStatus>> {
+    JOBS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn update_status(name: &str, f: impl FnOnce(&mut JobStatus)) {
+    let mut jobs = jobs().lock().unwrap();
+    if let Some(status) = jobs.iter_mut().find(|j| j.name == name) {
+        f(status);
+    }
+}
+
+pub fn statuses() -> Vec<JobStatus> {
+    jobs().lock().unwrap().clone()
+}
+
+// Register a recurring job and start running it on the tokio runtime
+pub fn register<F, Fut>(name: &str, schedule: Schedule, pool: SqlitePool, job: F)
+where
+    F: Fn(SqlitePool) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), String>> + Send + 'static,
+{
+    let name = name.to_string();
+    jobs().lock().unwrap().push(JobStatus {
+        name: name.clone(),
+        schedule: schedule.describe(),
+        running: false,
+        run_count: 0,
+        last_run_at: None,
+        last_success_at: None,
+        last_error: None,
+        next_run_at: None,
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let next = schedule.next_after(Utc::now());
+            update_status(&name, |s| s.next_run_at = Some(next.to_rfc3339()));
+
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            update_status(&name, |s| {
+                s.running = true;
+                s.last_run_at = Some(Utc::now().to_rfc3339());
+            });
+
+            // Run on its own task so a panicking job counts as a failed run instead of ending the loop
+            let result = match tokio::spawn(job(pool.clone())).await {
+                Ok(result) => result,
+                Err(e) => Err(if e.is_panic() { format!("panicked: {}", crate::error_reporter::panic_message(&*e.into_panic())) } else { e.to_string() }),
+            };
+
+            let mut was_failing = false;
+            update_status(&name, |s| {
+                was_failing = s.last_error.is_some();
+                s.running = false;
+                s.run_count += 1;
+                match &result {
+                    Ok(_) => {
+                        s.last_success_at = s.last_run_at.clone();
+                        s.last_error = None;
+                    }
+                    Err(e) => s.last_error = Some(e.clone()),
+                }
+            });
+
+            if let Err(e) = result {
+                eprintln!("Job {} failed: {}", name, e);
+                // Only the first failure in a row is reported, so a job failing every minute doesn't flood the channel
+                if !was_failing {
+                    crate::alerts::raise(&pool, "job_failed", "Background job failed", &format!("Job `{}` failed: {}", name, e)).await;
+                }
+            }
+        }
+    });
+}
+
+// Minimal cron support: minute hour day-of-month month day-of-week,
+// each field accepting *, numbers, lists (1,2), ranges (1-5) and steps (*/15).
+// As in standard cron, when both day fields are restricted a day matching either one runs
+pub struct CronExpr {
+    source: String,
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+// Longest each month can be, counting February in a leap year
+const MONTH_DAYS: [u32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> Result<CronExpr, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 cron fields, got {}", fields.len()));
+        }
+
+        let cron = CronExpr {
+            source: expr.to_string(),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays: parse_field(fields[4], 0, 6)?,
+            days_restricted: !fields[2].starts_with('*'),
+            weekdays_restricted: !fields[4].starts_with('*'),
+        };
+
+        // A day of the month none of the months have, like 0 0 31 2 *, would never run
+        let reachable = cron.weekdays_restricted
+            || cron.months.iter().any(|&m| cron.days.iter().any(|&d| d <= MONTH_DAYS[m as usize - 1]));
+        if !reachable {
+            return Err(format!("Cron expression never matches: {}", expr));
+        }
+        Ok(cron)
+    }
+
+    fn day_matches(&self, t: DateTime<Utc>) -> bool {
+        let day = self.days.contains(&t.day());
+        let weekday = self.weekdays.contains(&t.weekday().num_days_from_sunday());
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    fn next_after(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        let mut t = after.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(after) + Duration::minutes(1);
+        // Skips whole days and hours that can't match; February 29 may be eight years away
+        let limit = t + Duration::days(366 * 8);
+        while t < limit {
+            if !self.months.contains(&t.month()) || !self.day_matches(t) {
+                t = start_of_hour(t) - Duration::hours(t.hour() as i64) + Duration::days(1);
+            } else if !self.hours.contains(&t.hour()) {
+                t = start_of_hour(t) + Duration::hours(1);
+            } else if !self.minutes.contains(&t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return t;
+            }
+        }
+        limit
+    }
+}
+
+fn start_of_hour(t: DateTime<Utc>) -> DateTime<Utc> {
+    t - Duration::minutes(t.minute() as i64)
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let mut values = Vec::new();
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| format!("Invalid step: {}", part))?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("Invalid step: {}", part));
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (
+                a.parse().map_err(|_| format!("Invalid value: {}", part))?,
+                b.parse().map_err(|_| format!("Invalid value: {}", part))?,
+            )
+        } else {
+            let v: u32 = range.parse().map_err(|_| format!("Invalid value: {}", part))?;
+            (v, if part.contains('/') { max } else { v })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("Value out of range: {}", part));
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::{parse_field, CronExpr};
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    fn next(expr: &str, after: &str) -> DateTime<Utc> {
+        CronExpr::parse(expr).unwrap().next_after(at(after))
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(parse_field("*", 0, 6).unwrap(), vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(parse_field("5,1,5", 0, 59).unwrap(), vec![1, 5]);
+        assert_eq!(parse_field("10-12", 0, 23).unwrap(), vec![10, 11, 12]);
+        assert_eq!(parse_field("*/15", 0, 59).unwrap(), vec![0, 15, 30, 45]);
+        assert_eq!(parse_field("20/20", 0, 59).unwrap(), vec![20, 40]);
+        assert_eq!(parse_field("1-10/4", 1, 31).unwrap(), vec![1, 5, 9]);
+        for bad in ["60", "5-1", "*/0", "x", "", "1-", "0"] {
+            assert!(parse_field(bad, 1, 59).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn rejects_expressions_that_never_match() {
+        assert!(CronExpr::parse("0 0 31 2 *").is_err());
+        assert!(CronExpr::parse("0 0 31 4,6,9,11 *").is_err());
+        assert!(CronExpr::parse("0 0 31 2 1").is_ok());
+        assert!(CronExpr::parse("0 0 30-31 2,3 *").is_ok());
+        assert!(CronExpr::parse("0 0 * *").is_err());
+    }
+
+    #[test]
+    fn finds_the_next_run() {
+        assert_eq!(next("0 3 * * *", "2026-03-10T02:59:30Z"), at("2026-03-10T03:00:00Z"));
+        assert_eq!(next("0 3 * * *", "2026-03-10T03:00:00Z"), at("2026-03-11T03:00:00Z"));
+        assert_eq!(next("*/15 * * * *", "2026-03-10T23:59:00Z"), at("2026-03-11T00:00:00Z"));
+        assert_eq!(next("30 9 1 * *", "2026-12-05T00:00:00Z"), at("2027-01-01T09:30:00Z"));
+        // 2026-03-10 is a Tuesday; * in the day of month leaves only the weekday
+        assert_eq!(next("0 0 * * 5", "2026-03-10T00:00:00Z"), at("2026-03-13T00:00:00Z"));
+        assert_eq!(next("0 0 1 * *", "2026-03-10T00:00:00Z"), at("2026-04-01T00:00:00Z"));
+        assert_eq!(next("0 0 29 2 *", "2026-03-10T00:00:00Z"), at("2028-02-29T00:00:00Z"));
+    }
+
+    #[test]
+    fn either_restricted_day_field_matches() {
+        // The 20th or any Friday, whichever comes first
+        assert_eq!(next("0 0 20 * 5", "2026-03-10T00:00:00Z"), at("2026-03-13T00:00:00Z"));
+        assert_eq!(next("0 0 11 * 5", "2026-03-10T00:00:00Z"), at("2026-03-11T00:00:00Z"));
+        assert_eq!(next("0 0 31 2 1", "2026-03-10T00:00:00Z"), at("2027-02-01T00:00:00Z"));
+    }
+}
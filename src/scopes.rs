@@ -0,0 +1,59 @@
+// Scopes an API key can be limited to, and which one each endpoint needs
+
+pub const SCOPES: &[&str] = &[
+    "catalog:read",
+    "catalog:write",
+    "lending:read",
+    "lending:write",
+    "account:read",
+    "account:write",
+    "admin:read",
+    "admin:write",
+];
+
+pub fn is_scope(scope: &str) -> bool {
+    SCOPES.contains(&scope)
+}
+
+const CATALOG: &[&str] = &["/api/books", "/api/series", "/api/lists", "/api/periodicals", "/api/branches", "/api/announcements", "/opds"];
+
+// Catalog endpoints, other than watching a book, which counts as lending
+pub fn is_catalog(path: &str) -> bool {
+    let path = path.split('?').next().unwrap_or(path);
+    if path.starts_with("/api/books/") && path.ends_with("/watch") {
+        return false;
+    }
+    CATALOG.iter().any(|p| path == *p || path.starts_with(&format!("{}/", p)))
+}
+
+// None for endpoints API keys may not use at all: signing in and out, managing API keys,
+// and deleting the account all need a real session
+pub fn required(method: &str, path: &str) -> Option<&'static str> {
+    let path = path.split('?').next().unwrap_or(path);
+    let read = method == "GET";
+    let pick = |read_scope, write_scope| Some(if read { read_scope } else { write_scope });
+
+    if path.starts_with("/api/admin/") || path == "/metrics" {
+        return pick("admin:read", "admin:write");
+    }
+    if path == "/api/auth/me" || path.starts_with("/api/auth/me/") {
+        return if method == "DELETE" { None } else { pick("account:read", "account:write") };
+    }
+    if path.starts_with("/api/auth/") {
+        return None;
+    }
+    if path.starts_with("/api/books/") && path.ends_with("/watch") {
+        return pick("lending:read", "lending:write");
+    }
+
+    const LENDING: &[&str] = &["/api/lending", "/api/wishlist", "/api/favorites", "/api/authors", "/api/transfers", "/api/donations", "/api/suggestions"];
+    let under = |prefixes: &[&str]| prefixes.iter().any(|p| path == *p || path.starts_with(&format!("{}/", p)));
+
+    if is_catalog(path) {
+        pick("catalog:read", "catalog:write")
+    } else if under(LENDING) {
+        pick("lending:read", "lending:write")
+    } else {
+        None
+    }
+}
@@ -0,0 +1,282 @@
+use sqlx::SqlitePool;
+
+use crate::db;
+use crate::models::{Book, BookQuery, FacetCount, SearchFacets, SearchResult};
+
+// Below this many substring matches the search also looks for near misses, so a typo
+// like "Tolkein" still finds Tolkien
+const SPARSE_RESULTS: usize = 5;
+
+// Minimum similarity for a near miss to be listed
+const FUZZY_THRESHOLD: f64 = 0.7;
+
+// Longer queries are refused, and near misses are only sought for the first few words, since
+// each word is compared with every title and author word in the catalog
+pub const MAX_QUERY_CHARS: usize = 200;
+const MAX_FUZZY_TERMS: usize = 8;
+
+// Substring matches score 1.0 and come first in title order; near misses follow, best first.
+// Near misses are only sought for the free text and must still pass every field filter
+pub async fn search(pool: &SqlitePool, query: &str, item_type: Option<&str>) -> Result<Vec<SearchResult>, sqlx::Error> {
+    let mut query = parse_query(query);
+    if query.item_type.is_none() {
+        query.item_type = item_type.map(str::to_string);
+    }
+
+    let mut results: Vec<SearchResult> = db::search_books(pool, &query)
+        .await?
+        .into_iter()
+        .map(|book| SearchResult { book, score: 1.0 })
+        .collect();
+
+    let mut terms = words(&query.text);
+    terms.truncate(MAX_FUZZY_TERMS);
+    if results.len() >= SPARSE_RESULTS || terms.is_empty() {
+        return Ok(results);
+    }
+
+    let filters_only = BookQuery { text: String::new(), ..query };
+    let mut near_misses: Vec<SearchResult> = db::search_books(pool, &filters_only)
+        .await?
+        .into_iter()
+        .filter(|book| !results.iter().any(|r| r.book.id == book.id))
+        .filter_map(|book| {
+            let score = fuzzy_score(&terms, &book);
+            (score >= FUZZY_THRESHOLD).then(|| SearchResult { book, score: (score * 100.0).round() / 100.0 })
+        })
+        .collect();
+    near_misses.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.book.title.cmp(&b.book.title)));
+
+    results.extend(near_misses);
+    Ok(results)
+}
+
+// Splits `author:"le guin" genre:sci-fi year:1960..1980 available:yes dragons` into field
+// filters and free text. Values may be quoted to include spaces. A term with an unknown field
+// or a value that doesn't parse is searched for as plain text instead
+pub fn parse_query(input: &str) -> BookQuery {
+    let mut query = BookQuery::default();
+    let mut text = Vec::new();
+
+    for token in tokens(input) {
+        let Some((field, value)) = token.split_once(':') else {
+            text.push(unquote(&token));
+            continue;
+        };
+        let value = unquote(value);
+        if value.is_empty() || !apply_filter(&mut query, &field.to_lowercase(), &value) {
+            text.push(unquote(&token));
+        }
+    }
+
+    query.text = text.join(" ");
+    query
+}
+
+fn apply_filter(query: &mut BookQuery, field: &str, value: &str) -> bool {
+    match field {
+        "author" => query.author = Some(value.to_string()),
+        "title" => query.title = Some(value.to_string()),
+        "genre" => query.genre = Some(value.to_string()),
+        "tag" => query.tag = Some(value.to_lowercase()),
+        "isbn" => query.isbn = Some(value.to_string()),
+        "type" => query.item_type = Some(value.to_lowercase()),
+        "year" => {
+            let Some((from, to)) = parse_year_range(value) else { return false };
+            query.year_from = from;
+            query.year_to = to;
+        }
+        "available" => match value.to_lowercase().as_str() {
+            "yes" | "true" => query.available = Some(true),
+            "no" | "false" => query.available = Some(false),
+            _ => return false,
+        },
+        _ => return false,
+    }
+    true
+}
+
+// `1975`, `1960..1980`, `1960..` or `..1980`
+fn parse_year_range(value: &str) -> Option<(Option<i32>, Option<i32>)> {
+    let bound = |s: &str| if s.is_empty() { Ok(None) } else { s.parse::<i32>().map(Some) };
+    match value.split_once("..") {
+        Some(("", "")) => None,
+        Some((from, to)) => Some((bound(from).ok()?, bound(to).ok()?)),
+        None => {
+            let year = value.parse::<i32>().ok()?;
+            Some((Some(year), Some(year)))
+        }
+    }
+}
+
+// Whitespace-separated terms, keeping quoted runs together
+fn tokens(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in input.chars() {
+        if c == '"' {
+            quoted = !quoted;
+            current.push(c);
+        } else if c.is_whitespace() && !quoted {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+fn unquote(value: &str) -> String {
+    value.replace('"', "").trim().to_string()
+}
+
+// Facet values match what the filter syntax accepts: decades read "1960s" and availability
+// "yes" or "no". Books without a genre or year are left out of those facets
+pub fn facets(results: &[SearchResult]) -> SearchFacets {
+    let books = || results.iter().map(|r| &r.book);
+
+    SearchFacets {
+        genre: count_values(books().filter_map(|b| b.genre.clone()).filter(|g| !g.trim().is_empty())),
+        author: count_values(books().map(|b| b.author.clone())),
+        decade: count_values(books().filter_map(|b| b.publication_year).map(|y| format!("{}s", y - y.rem_euclid(10)))),
+        available: count_values(books().map(|b| if b.available_copies > 0 { "yes" } else { "no" }.to_string())),
+    }
+}
+
+// Most common first, ties in value order
+fn count_values(values: impl Iterator<Item = String>) -> Vec<FacetCount> {
+    let mut counts: Vec<FacetCount> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|c| c.value == value) {
+            Some(facet) => facet.count += 1,
+            None => counts.push(FacetCount { value, count: 1 }),
+        }
+    }
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    counts
+}
+
+// Average over the query's words of each word's best match among the book's title and author
+// words. Word lengths alone cap how similar two words can be, so pairs that can't beat a word's
+// best match so far are skipped, and so are books that can't reach the threshold at all
+fn fuzzy_score(terms: &[String], book: &Book) -> f64 {
+    let candidates: Vec<(usize, String)> = words(&book.title)
+        .into_iter()
+        .chain(words(&book.author))
+        .map(|word| (word.chars().count(), word))
+        .collect();
+    if candidates.is_empty() || terms.is_empty() {
+        return 0.0;
+    }
+    let terms: Vec<(usize, &String)> = terms.iter().map(|term| (term.chars().count(), term)).collect();
+
+    let reachable: f64 = terms
+        .iter()
+        .map(|(len, _)| candidates.iter().map(|(other, _)| similarity_bound(*len, *other)).fold(0.0, f64::max))
+        .sum();
+    if reachable / (terms.len() as f64) < FUZZY_THRESHOLD {
+        return 0.0;
+    }
+
+    let total: f64 = terms
+        .iter()
+        .map(|(len, term)| {
+            candidates.iter().fold(0.0, |best: f64, (other, word)| {
+                if similarity_bound(*len, *other) <= best {
+                    best
+                } else {
+                    best.max(similarity(term, word))
+                }
+            })
+        })
+        .sum();
+    total / terms.len() as f64
+}
+
+// The most similar words of these lengths can be: every letter one is longer by is an edit
+fn similarity_bound(a: usize, b: usize) -> f64 {
+    let longest = a.max(b);
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - a.abs_diff(b) as f64 / longest as f64
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// 1.0 for identical words, falling towards 0.0 as the edit distance approaches the word length
+fn similarity(a: &str, b: &str) -> f64 {
+    let longest = a.chars().count().max(b.chars().count());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - edit_distance(a, b) as f64 / longest as f64
+}
+
+// Levenshtein distance that also counts swapping two adjacent letters as a single edit
+// (optimal string alignment), since transpositions are the most common typo
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, BookFactory};
+
+    #[tokio::test]
+    async fn near_misses_literal_wildcards_and_length_cap() {
+        let pool = test_support::pool().await;
+        BookFactory::default().with_title("The Hobbit").with_author("J. R. R. Tolkien").insert(&pool).await;
+        BookFactory::default().with_title("Dune").with_author("Frank Herbert").insert(&pool).await;
+
+        let results = super::search(&pool, "tolkein hobit", None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].book.title, "The Hobbit");
+        assert!(results[0].score >= super::FUZZY_THRESHOLD && results[0].score < 1.0);
+
+        let results = super::search(&pool, "title:_", None).await.unwrap();
+        assert!(results.is_empty());
+        BookFactory::default().with_title("100% Wolf").insert(&pool).await;
+        let results = super::search(&pool, "title:0%", None).await.unwrap();
+        assert_eq!(results.len(), 1);
+
+        let long = "a".repeat(super::MAX_QUERY_CHARS + 1);
+        let (status, _) = test_support::call(&pool, "GET", &format!("/api/books/search?q={}", long), None, None).await;
+        assert_eq!(status, 400);
+    }
+}
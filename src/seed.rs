@@ -0,0 +1,151 @@
+use sqlx::SqlitePool;
+
+use crate::{auth, db, settings};
+use crate::models::CreateBookRequest;
+
+// Demo data for `--seed-demo`: a catalog, a member of each role, loans (some overdue) and a
+// queue of reservations, so a fresh database has something to look at. Every demo account
+// signs in with DEMO_PASSWORD
+
+pub const DEMO_PASSWORD: &str = "demo1234";
+
+// Title, author, year, genre, copies
+const BOOKS: &[(&str, &str, i32, &str, i32)] = &[
+    ("Pride and Prejudice", "Jane Austen", 1813, "Classics", 3),
+    ("Emma", "Jane Austen", 1815, "Classics", 1),
+    ("Jane Eyre", "Charlotte Brontë", 1847, "Classics", 2),
+    ("Wuthering Heights", "Emily Brontë", 1847, "Classics", 1),
+    ("Middlemarch", "George Eliot", 1871, "Classics", 1),
+    ("Great Expectations", "Charles Dickens", 1861, "Classics", 2),
+    ("Moby-Dick", "Herman Melville", 1851, "Classics", 1),
+    ("Crime and Punishment", "Fyodor Dostoevsky", 1866, "Classics", 2),
+    ("Anna Karenina", "Leo Tolstoy", 1878, "Classics", 1),
+    ("The Great Gatsby", "F. Scott Fitzgerald", 1925, "Fiction", 3),
+    ("To the Lighthouse", "Virginia Woolf", 1927, "Fiction", 1),
+    ("Nineteen Eighty-Four", "George Orwell", 1949, "Science Fiction", 4),
+    ("Brave New World", "Aldous Huxley", 1932, "Science Fiction", 2),
+    ("Fahrenheit 451", "Ray Bradbury", 1953, "Science Fiction", 2),
+    ("The Left Hand of Darkness", "Ursula K. Le Guin", 1969, "Science Fiction", 1),
+    ("Dune", "Frank Herbert", 1965, "Science Fiction", 3),
+    ("Foundation", "Isaac Asimov", 1951, "Science Fiction", 2),
+    ("The Hobbit", "J. R. R. Tolkien", 1937, "Fantasy", 3),
+    ("A Wizard of Earthsea", "Ursula K. Le Guin", 1968, "Fantasy", 1),
+    ("The Name of the Wind", "Patrick Rothfuss", 2007, "Fantasy", 2),
+    ("The Hound of the Baskervilles", "Arthur Conan Doyle", 1902, "Mystery", 2),
+    ("The Murder of Roger Ackroyd", "Agatha Christie", 1926, "Mystery", 1),
+    ("The Big Sleep", "Raymond Chandler", 1939, "Mystery", 1),
+    ("Beloved", "Toni Morrison", 1987, "Fiction", 2),
+    ("One Hundred Years of Solitude", "Gabriel García Márquez", 1967, "Fiction", 2),
+    ("Things Fall Apart", "Chinua Achebe", 1958, "Fiction", 2),
+    ("The Remains of the Day", "Kazuo Ishiguro", 1989, "Fiction", 1),
+    ("A Brief History of Time", "Stephen Hawking", 1988, "Science", 2),
+    ("The Selfish Gene", "Richard Dawkins", 1976, "Science", 1),
+    ("Silent Spring", "Rachel Carson", 1962, "Science", 1),
+    ("Sapiens", "Yuval Noah Harari", 2011, "History", 3),
+    ("The Guns of August", "Barbara W. Tuchman", 1962, "History", 1),
+    ("Thinking, Fast and Slow", "Daniel Kahneman", 2011, "Psychology", 2),
+    ("The Diary of a Young Girl", "Anne Frank", 1947, "Biography", 2),
+    ("Long Walk to Freedom", "Nelson Mandela", 1994, "Biography", 1),
+    ("Where the Wild Things Are", "Maurice Sendak", 1963, "Children", 2),
+];
+
+// Username, display name, role
+const USERS: &[(&str, &str, &str)] = &[
+    ("demo_admin", "Dana Admin", "admin"),
+    ("demo_reports", "Riley Reports", "reports"),
+    ("alice", "Alice Moreau", "lender"),
+    ("bob", "Bob Okafor", "lender"),
+    ("carol", "Carol Lindqvist", "lender"),
+    ("dev", "Dev Patel", "lender"),
+    ("erin", "Erin Walsh", "lender"),
+    ("farid", "Farid Haddad", "lender"),
+];
+
+// Borrower, book, days ago it was borrowed. Loans that started longer ago than the loan period
+// are overdue by the time the seed finishes
+const LOANS: &[(&str, &str, i64)] = &[
+    ("alice", "Nineteen Eighty-Four", 3),
+    ("alice", "The Hobbit", 10),
+    ("bob", "Dune", 5),
+    ("bob", "Emma", 40),
+    ("carol", "Sapiens", 1),
+    ("carol", "Wuthering Heights", 25),
+    ("dev", "The Big Sleep", 30),
+    ("dev", "Foundation", 7),
+    ("erin", "Beloved", 2),
+    ("erin", "A Wizard of Earthsea", 50),
+    ("farid", "Moby-Dick", 12),
+];
+
+// Member, book; each is a book whose copies are all out
+const RESERVATIONS: &[(&str, &str)] = &[
+    ("carol", "Emma"),
+    ("dev", "Emma"),
+    ("farid", "A Wizard of Earthsea"),
+    ("alice", "Wuthering Heights"),
+    ("bob", "The Big Sleep"),
+];
+
+pub struct Seeded {
+    pub books: usize,
+    pub users: usize,
+    pub loans: usize,
+    pub overdue: u64,
+    pub reservations: usize,
+}
+
+// Adds the demo data, or returns None without touching anything when it is already there
+pub async fn demo(pool: &SqlitePool) -> Result<Option<Seeded>, Box<dyn std::error::Error>> {
+    if db::get_user_by_username(pool, USERS[0].0).await?.is_some() {
+        return Ok(None);
+    }
+
+    let mut book_ids = std::collections::HashMap::new();
+    for (i, &(title, author, year, genre, copies)) in BOOKS.iter().enumerate() {
+        let request = CreateBookRequest {
+            title: title.to_string(),
+            author: author.to_string(),
+            isbn: demo_isbn(i),
+            publication_year: Some(year),
+            genre: Some(genre.to_string()),
+            total_copies: copies,
+            item_type: None,
+            issue_number: None,
+            platform: None,
+        };
+        book_ids.insert(title, db::create_book(pool, &request).await?);
+    }
+
+    let password_hash = auth::hash_password(DEMO_PASSWORD).map_err(|e| e.to_string())?;
+    let mut user_ids = std::collections::HashMap::new();
+    for &(username, display_name, role) in USERS {
+        let email = format!("{}@demo.library.test", username);
+        let id = db::create_user(pool, username, &email, &password_hash, role, "approved").await?;
+        db::update_profile(pool, id, &email, true, Some(display_name)).await?;
+        user_ids.insert(username, id);
+    }
+
+    let loan_period_days = settings::loan_period_days();
+    for &(username, title, days_ago) in LOANS {
+        let record_id = db::borrow_book(pool, user_ids[username], book_ids[title], db::MAIN_BRANCH_ID, loan_period_days).await?;
+        db::backdate_loan(pool, record_id, days_ago).await?;
+    }
+    let overdue = db::mark_overdue_records(pool).await?;
+
+    for &(username, title) in RESERVATIONS {
+        db::create_reservation(pool, user_ids[username], book_ids[title]).await?;
+    }
+
+    Ok(Some(Seeded { books: BOOKS.len(), users: USERS.len(), loans: LOANS.len(), overdue, reservations: RESERVATIONS.len() }))
+}
+
+// A valid ISBN-13 in the 979-8 range, numbered by position in BOOKS
+fn demo_isbn(index: usize) -> String {
+    let digits = format!("979800000{:03}", index);
+    let sum: u32 = digits
+        .chars()
+        .enumerate()
+        .map(|(i, c)| c.to_digit(10).unwrap() * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    format!("{}{}", digits, (10 - sum % 10) % 10)
+}
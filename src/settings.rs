@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::{OnceLock, RwLock};
+use serde_json::{json, Map, Value};
+use sqlx::SqlitePool;
+
+use crate::{db, money};
+use crate::models::LoanPolicy;
+
+// Runtime-tunable library policies, persisted in the settings table and cached in memory
+// Numbers carry the range an admin may set; days stay well inside what chrono can add to a date
+enum Kind {
+    Integer(RangeInclusive<i64>),
+    Decimal(RangeInclusive<f64>),
+    // Text accepted when the check passes
+    Text(fn(&str) -> bool),
+}
+
+struct Definition {
+    key: &'static str,
+    default: &'static str,
+    kind: Kind,
+}
+
+const DEFINITIONS: &[Definition] = &[
+    Definition { key: "loan_period_days", default: "14", kind: Kind::Integer(1..=3650) },
+    Definition { key: "fine_per_day", default: "0.25", kind: Kind::Decimal(0.0..=1000.0) },
+    Definition { key: "max_fine_per_item", default: "10.0", kind: Kind::Decimal(0.0..=100000.0) },
+    Definition { key: "currency_code", default: "USD", kind: Kind::Text(is_currency_code) },
+    Definition { key: "currency_symbol", default: "$", kind: Kind::Text(is_currency_symbol) },
+    Definition { key: "money_locale", default: "en-US", kind: Kind::Text(money::is_locale) },
+    Definition { key: "max_renewals", default: "2", kind: Kind::Integer(0..=100) },
+    Definition { key: "max_concurrent_loans", default: "10", kind: Kind::Integer(1..=1000) },
+    Definition { key: "reservation_hold_days", default: "3", kind: Kind::Integer(1..=365) },
+    Definition { key: "recall_notice_days", default: "7", kind: Kind::Integer(1..=365) },
+    Definition { key: "extension_days", default: "7", kind: Kind::Integer(1..=365) },
+    Definition { key: "membership_period_days", default: "365", kind: Kind::Integer(1..=3650) },
+    Definition { key: "reminder_days_before_due", default: "2", kind: Kind::Integer(0..=365) },
+    Definition { key: "membership_warning_days", default: "14", kind: Kind::Integer(0..=365) },
+    Definition { key: "suspension_overdue_threshold", default: "3", kind: Kind::Integer(0..=1000) },
+    Definition { key: "suspension_fine_threshold", default: "10.0", kind: Kind::Decimal(0.0..=100000.0) },
+    Definition { key: "password_min_length", default: "8", kind: Kind::Integer(1..=128) },
+    Definition { key: "password_min_entropy_bits", default: "40", kind: Kind::Integer(0..=256) },
+    Definition { key: "overdue_spike_threshold", default: "10", kind: Kind::Integer(0..=100000) },
+    Definition { key: "overdue_reminder_days", default: "0", kind: Kind::Integer(0..=3650) },
+    Definition { key: "overdue_second_notice_days", default: "14", kind: Kind::Integer(0..=3650) },
+    Definition { key: "overdue_billing_days", default: "30", kind: Kind::Integer(0..=3650) },
+    Definition { key: "overdue_billing_amount", default: "25.0", kind: Kind::Decimal(0.0..=100000.0) },
+    Definition { key: "lending_retention_years", default: "0", kind: Kind::Integer(0..=100) },
+    Definition { key: "lending_retention_action", default: "anonymize", kind: Kind::Text(is_retention_action) },
+    Definition { key: "notification_retention_years", default: "0", kind: Kind::Integer(0..=100) },
+    Definition { key: "reader_privacy_days", default: "0", kind: Kind::Integer(0..=3650) },
+    Definition {
+        key: "content_security_policy",
+        default: "default-src 'self'; script-src 'self' 'unsafe-inline' https://cdn.tailwindcss.com; style-src 'self' 'unsafe-inline'; img-src 'self' data:; frame-ancestors 'none'; base-uri 'self'; form-action 'self'",
+        kind: Kind::Text(is_header_value),
+    },
+    Definition { key: "referrer_policy", default: "strict-origin-when-cross-origin", kind: Kind::Text(is_referrer_policy) },
+    Definition { key: "hsts_max_age", default: "31536000", kind: Kind::Integer(0..=63072000) },
+];
+
+static CACHE: OnceLock<RwLock<HashMap<String, String>>> = OnceLock::new();
+
+fn cache() -> &'static RwLock<HashMap<String, String>> {
+    CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn definition(key: &str) -> Option<&'static Definition> {
+    DEFINITIONS.iter().find(|d| d.key == key)
+}
+
+pub async fn load(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let stored = db::get_all_settings(pool).await?;
+    let mut cache = cache().write().unwrap();
+    cache.clear();
+    for (key, value) in stored {
+        if definition(&key).is_some() {
+            cache.insert(key, value);
+        }
+    }
+    Ok(())
+}
+
+fn raw(key: &str) -> String {
+    if let Some(value) = cache().read().unwrap().get(key) {
+        return value.clone();
+    }
+    definition(key).map(|d| d.default.to_string()).unwrap_or_default()
+}
+
+// ISO 4217 style: three capital letters
+fn is_currency_code(text: &str) -> bool {
+    text.len() == 3 && text.chars().all(|c| c.is_ascii_uppercase())
+}
+
+fn is_currency_symbol(text: &str) -> bool {
+    !text.is_empty() && text.chars().count() <= 8 && !text.chars().any(char::is_control)
+}
+
+// Anything that fits on one header line; empty leaves the header out
+fn is_header_value(text: &str) -> bool {
+    text.len() <= 2048 && !text.chars().any(char::is_control)
+}
+
+fn is_referrer_policy(text: &str) -> bool {
+    matches!(
+        text,
+        "" | "no-referrer"
+            | "no-referrer-when-downgrade"
+            | "origin"
+            | "origin-when-cross-origin"
+            | "same-origin"
+            | "strict-origin"
+            | "strict-origin-when-cross-origin"
+            | "unsafe-url"
+    )
+}
+
+fn is_retention_action(text: &str) -> bool {
+    matches!(text, "anonymize" | "delete")
+}
+
+fn integer(key: &str) -> i64 {
+    raw(key).parse().unwrap_or(0)
+}
+
+fn decimal(key: &str) -> f64 {
+    raw(key).parse().unwrap_or(0.0)
+}
+
+pub fn loan_period_days() -> i64 {
+    integer("loan_period_days")
+}
+
+pub fn max_concurrent_loans() -> i64 {
+    integer("max_concurrent_loans")
+}
+
+pub fn reservation_hold_days() -> i64 {
+    integer("reservation_hold_days")
+}
+
+pub fn recall_notice_days() -> i64 {
+    integer("recall_notice_days")
+}
+
+pub fn extension_days() -> i64 {
+    integer("extension_days")
+}
+
+pub fn membership_period_days() -> i64 {
+    integer("membership_period_days")
+}
+
+pub fn reminder_days_before_due() -> i64 {
+    integer("reminder_days_before_due")
+}
+
+pub fn membership_warning_days() -> i64 {
+    integer("membership_warning_days")
+}
+
+pub fn suspension_overdue_threshold() -> i64 {
+    integer("suspension_overdue_threshold")
+}
+
+pub fn suspension_fine_threshold() -> f64 {
+    decimal("suspension_fine_threshold")
+}
+
+pub fn password_min_length() -> i64 {
+    integer("password_min_length")
+}
+
+pub fn password_min_entropy_bits() -> i64 {
+    integer("password_min_entropy_bits")
+}
+
+pub fn overdue_spike_threshold() -> i64 {
+    integer("overdue_spike_threshold")
+}
+
+// Days past due at which an overdue loan reaches each escalation level
+pub fn overdue_reminder_days() -> i64 {
+    integer("overdue_reminder_days")
+}
+
+pub fn overdue_second_notice_days() -> i64 {
+    integer("overdue_second_notice_days")
+}
+
+pub fn overdue_billing_days() -> i64 {
+    integer("overdue_billing_days")
+}
+
+// Charge added to the member's fines when a loan is billed
+pub fn overdue_billing_amount() -> f64 {
+    decimal("overdue_billing_amount")
+}
+
+pub fn fine_per_day() -> f64 {
+    decimal("fine_per_day")
+}
+
+pub fn currency_code() -> String {
+    raw("currency_code")
+}
+
+pub fn currency_symbol() -> String {
+    raw("currency_symbol")
+}
+
+// Locale whose separators and symbol placement money is written with, e.g. en-US or de-DE
+pub fn money_locale() -> String {
+    raw("money_locale")
+}
+
+// Most a single late item can be fined; 0 for no cap
+pub fn max_fine_per_item() -> f64 {
+    decimal("max_fine_per_item")
+}
+
+// Years a returned loan is kept before the retention purge applies lending_retention_action
+// (anonymize or delete) to it; 0 keeps loans indefinitely
+pub fn lending_retention_years() -> i64 {
+    integer("lending_retention_years")
+}
+
+pub fn lending_retention_action() -> String {
+    raw("lending_retention_action")
+}
+
+// Privacy mode: days after return before a loan is detached from its reader; 0 keeps
+// reading history
+pub fn reader_privacy_days() -> i64 {
+    integer("reader_privacy_days")
+}
+
+// Years sent notifications are kept; 0 keeps them indefinitely
+pub fn notification_retention_years() -> i64 {
+    integer("notification_retention_years")
+}
+
+// Security headers sent with every response; an empty policy leaves its header out
+pub fn content_security_policy() -> String {
+    raw("content_security_policy")
+}
+
+pub fn referrer_policy() -> String {
+    raw("referrer_policy")
+}
+
+// Seconds browsers should insist on HTTPS; only sent when LIBRARY_PUBLIC_URL is https, 0 turns it off
+pub fn hsts_max_age() -> i64 {
+    integer("hsts_max_age")
+}
+
+// Effective loan policy for a member type: its overrides layered on the global settings
+pub async fn loan_policy(pool: &SqlitePool, member_type: &str) -> Result<LoanPolicy, sqlx::Error> {
+    let overrides = db::get_member_type(pool, member_type).await?;
+    let overrides = overrides.as_ref();
+
+    Ok(LoanPolicy {
+        member_type: member_type.to_string(),
+        loan_period_days: overrides.and_then(|t| t.loan_period_days).unwrap_or_else(loan_period_days),
+        max_concurrent_loans: overrides.and_then(|t| t.max_concurrent_loans).unwrap_or_else(max_concurrent_loans),
+        fine_per_day: overrides.and_then(|t| t.fine_per_day).unwrap_or_else(fine_per_day),
+    })
+}
+
+pub fn all() -> Value {
+    let mut map = Map::new();
+    for def in DEFINITIONS {
+        let value = match def.kind {
+            Kind::Integer(_) => json!(integer(def.key)),
+            Kind::Decimal(_) => json!(decimal(def.key)),
+            Kind::Text(_) => json!(raw(def.key)),
+        };
+        map.insert(def.key.to_string(), value);
+    }
+    Value::Object(map)
+}
+
+// Validate every entry first so a bad value doesn't leave a partial update behind
+pub async fn update(pool: &SqlitePool, values: &Map<String, Value>) -> Result<(), String> {
+    let mut validated = Vec::new();
+    for (key, value) in values {
+        let def = definition(key).ok_or_else(|| format!("Unknown setting: {}", key))?;
+        let text = match &def.kind {
+            Kind::Integer(range) => value.as_i64().filter(|v| range.contains(v)).map(|v| v.to_string()),
+            Kind::Decimal(range) => value.as_f64().filter(|v| range.contains(v)).map(|v| v.to_string()),
+            Kind::Text(check) => value.as_str().map(str::trim).filter(|v| check(v)).map(str::to_string),
+        };
+        let text = text.ok_or_else(|| match (&def.kind, def.key) {
+            (Kind::Integer(range), _) => format!("Invalid value for {}; expected a whole number from {} to {}", key, range.start(), range.end()),
+            (Kind::Decimal(range), _) => format!("Invalid value for {}; expected a number from {} to {}", key, range.start(), range.end()),
+            (_, "money_locale") => format!("Invalid value for money_locale; expected one of: {}", money::locales().join(", ")),
+            (_, "lending_retention_action") => "Invalid value for lending_retention_action; expected anonymize or delete".to_string(),
+            _ => format!("Invalid value for {}", key),
+        })?;
+        validated.push((def.key, text));
+    }
+
+    // The cache only changes once the values are committed
+    db::upsert_settings(pool, &validated).await.map_err(|_| "Failed to save settings".to_string())?;
+
+    let mut cache = cache().write().unwrap();
+    for (key, value) in validated {
+        cache.insert(key.to_string(), value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn rejects_values_outside_the_range() {
+        let pool = test_support::pool().await;
+        for (key, value) in [("loan_period_days", json!(0)), ("loan_period_days", json!(1_000_000_000)), ("max_concurrent_loans", json!(0)), ("password_min_length", json!(0)), ("fine_per_day", json!(-1.0))] {
+            let values = json!({ key: value });
+            let err = super::update(&pool, values.as_object().unwrap()).await.unwrap_err();
+            assert!(err.starts_with(&format!("Invalid value for {}", key)), "{}", err);
+        }
+        assert_eq!(super::loan_period_days(), 14);
+    }
+}
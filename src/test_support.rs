@@ -0,0 +1,296 @@
+// A toolkit for tests to come as much as for the ones here, so not every builder is used yet
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+
+use crate::models::{Book, CreateBookRequest, User};
+use crate::{auth, db};
+
+// Fixtures for tests: a migrated in-memory database, factories that insert rows with sensible
+// defaults, and helpers that put a request through the router. Factories give each row a
+// unique ISBN or username, so tests can insert as many as they need without naming them
+
+// Password of every UserFactory account unless with_password says otherwise
+pub const PASSWORD: &str = "password123";
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(1);
+
+fn next() -> u64 {
+    SEQUENCE.fetch_add(1, Ordering::Relaxed)
+}
+
+// A fresh, empty database with the full schema. Each in-memory connection is its
+// own database, so the pool keeps exactly one
+pub async fn pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to open in-memory database");
+    db::migrate(pool).await.expect("Failed to create schema")
+}
+
+pub struct BookFactory {
+    title: String,
+    author: String,
+    isbn: Option<String>,
+    genre: Option<String>,
+    publication_year: Option<i32>,
+    item_type: Option<String>,
+    copies: i32,
+}
+
+impl Default for BookFactory {
+    fn default() -> Self {
+        BookFactory {
+            title: format!("Test Book {}", next()),
+            author: "Test Author".to_string(),
+            isbn: None,
+            genre: None,
+            publication_year: None,
+            item_type: None,
+            copies: 1,
+        }
+    }
+}
+
+impl BookFactory {
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = author.to_string();
+        self
+    }
+
+    pub fn with_isbn(mut self, isbn: &str) -> Self {
+        self.isbn = Some(isbn.to_string());
+        self
+    }
+
+    pub fn with_genre(mut self, genre: &str) -> Self {
+        self.genre = Some(genre.to_string());
+        self
+    }
+
+    pub fn with_year(mut self, year: i32) -> Self {
+        self.publication_year = Some(year);
+        self
+    }
+
+    pub fn with_item_type(mut self, item_type: &str) -> Self {
+        self.item_type = Some(item_type.to_string());
+        self
+    }
+
+    pub fn with_copies(mut self, copies: i32) -> Self {
+        self.copies = copies;
+        self
+    }
+
+    pub async fn insert(self, pool: &SqlitePool) -> Book {
+        let request = CreateBookRequest {
+            title: self.title,
+            author: self.author,
+            isbn: self.isbn.unwrap_or_else(|| format!("978{:010}", next())),
+            publication_year: self.publication_year,
+            genre: self.genre,
+            total_copies: self.copies,
+            item_type: self.item_type,
+            issue_number: None,
+            platform: None,
+        };
+        let id = db::create_book(pool, &request).await.expect("Failed to insert book");
+        db::get_book_by_id(pool, id).await.expect("Failed to load book").expect("Inserted book is missing")
+    }
+}
+
+pub struct UserFactory {
+    username: String,
+    role: String,
+    password: String,
+    approval_status: String,
+}
+
+impl Default for UserFactory {
+    fn default() -> Self {
+        UserFactory {
+            username: format!("user{}", next()),
+            role: "lender".to_string(),
+            password: PASSWORD.to_string(),
+            approval_status: "approved".to_string(),
+        }
+    }
+}
+
+impl UserFactory {
+    pub fn admin() -> Self {
+        UserFactory::default().with_role("admin")
+    }
+
+    pub fn with_username(mut self, username: &str) -> Self {
+        self.username = username.to_string();
+        self
+    }
+
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.role = role.to_string();
+        self
+    }
+
+    pub fn with_password(mut self, password: &str) -> Self {
+        self.password = password.to_string();
+        self
+    }
+
+    // "pending" for an account waiting on staff approval
+    pub fn with_approval_status(mut self, status: &str) -> Self {
+        self.approval_status = status.to_string();
+        self
+    }
+
+    pub async fn insert(self, pool: &SqlitePool) -> User {
+        let email = format!("{}@example.test", self.username);
+        let hash = auth::hash_password(&self.password).expect("Failed to hash password");
+        let id = db::create_user(pool, &self.username, &email, &hash, &self.role, &self.approval_status)
+            .await
+            .expect("Failed to insert user");
+        db::get_user_by_id(pool, id).await.expect("Failed to load user").expect("Inserted user is missing")
+    }
+
+    // Inserts the user and signs them in, returning the session token as well
+    pub async fn signed_in(self, pool: &SqlitePool) -> (User, String) {
+        let user = self.insert(pool).await;
+        let token = session(pool, &user).await;
+        (user, token)
+    }
+}
+
+// A loan of one copy from the main branch, taken `days_ago` days back; one older than the loan
+// period is marked overdue
+pub struct LoanFactory {
+    user_id: i64,
+    book_id: i64,
+    loan_period_days: i64,
+    days_ago: i64,
+}
+
+impl LoanFactory {
+    pub fn new(user: &User, book: &Book) -> Self {
+        LoanFactory { user_id: user.id, book_id: book.id, loan_period_days: 14, days_ago: 0 }
+    }
+
+    pub fn with_loan_period(mut self, days: i64) -> Self {
+        self.loan_period_days = days;
+        self
+    }
+
+    pub fn days_ago(mut self, days: i64) -> Self {
+        self.days_ago = days;
+        self
+    }
+
+    // The lending record id
+    pub async fn insert(self, pool: &SqlitePool) -> i64 {
+        let id = db::borrow_book(pool, self.user_id, self.book_id, db::MAIN_BRANCH_ID, self.loan_period_days)
+            .await
+            .expect("Failed to insert loan");
+        if self.days_ago > 0 {
+            db::backdate_loan(pool, id, self.days_ago).await.expect("Failed to backdate loan");
+            db::mark_overdue_records(pool).await.expect("Failed to mark overdue loans");
+        }
+        id
+    }
+}
+
+// A session token for the user, as a login would issue
+pub async fn session(pool: &SqlitePool, user: &User) -> String {
+    let token = auth::generate_token();
+    db::create_session(pool, user.id, &token, Some("test"), Some("127.0.0.1"))
+        .await
+        .expect("Failed to create session");
+    token
+}
+
+// A raw HTTP request as handle_connection would read it, with the token as a bearer credential
+pub fn request(method: &str, path: &str, token: Option<&str>, body: Option<&str>) -> String {
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n", method, path);
+    if let Some(token) = token {
+        request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+    }
+    if let Some(body) = body {
+        request.push_str(&format!("Content-Type: application/json\r\nContent-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+    request.push_str(body.unwrap_or(""));
+    request
+}
+
+// Routes a request and returns the status with the body parsed as JSON (null when it isn't)
+pub async fn call(pool: &SqlitePool, method: &str, path: &str, token: Option<&str>, body: Option<&str>) -> (u16, serde_json::Value) {
+    let response = crate::route_request(&request(method, path, token, body), pool, Some("127.0.0.1")).await;
+    let status = crate::response_status(&response);
+    let body = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .and_then(|end| serde_json::from_slice(&response[end + 4..]).ok())
+        .unwrap_or(serde_json::Value::Null);
+    (status, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn book_factory_creates_copies() {
+        let pool = pool().await;
+        let book = BookFactory::default().with_copies(3).insert(&pool).await;
+        assert_eq!(book.total_copies, 3);
+        assert_eq!(book.available_copies, 3);
+    }
+
+    #[tokio::test]
+    async fn factories_give_unique_defaults() {
+        let pool = pool().await;
+        let first = BookFactory::default().insert(&pool).await;
+        let second = BookFactory::default().insert(&pool).await;
+        assert_ne!(first.isbn, second.isbn);
+
+        let first = UserFactory::default().insert(&pool).await;
+        let second = UserFactory::default().insert(&pool).await;
+        assert_ne!(first.username, second.username);
+    }
+
+    #[tokio::test]
+    async fn session_authenticates_requests() {
+        let pool = pool().await;
+        let (user, token) = UserFactory::default().signed_in(&pool).await;
+
+        let (status, body) = call(&pool, "GET", "/api/auth/me", Some(&token), None).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["username"], user.username.as_str());
+
+        let (status, _) = call(&pool, "GET", "/api/auth/me", None, None).await;
+        assert_eq!(status, 401);
+    }
+
+    #[tokio::test]
+    async fn old_loans_are_overdue() {
+        let pool = pool().await;
+        let user = UserFactory::default().insert(&pool).await;
+        let book = BookFactory::default().with_copies(2).insert(&pool).await;
+        LoanFactory::new(&user, &book).days_ago(30).insert(&pool).await;
+        LoanFactory::new(&user, &book).insert(&pool).await;
+
+        let overdue = db::get_overdue_books(&pool, None, None).await.unwrap();
+        assert_eq!(overdue.len(), 1);
+        let book = db::get_book_by_id(&pool, book.id).await.unwrap().unwrap();
+        assert_eq!(book.available_copies, 0);
+    }
+}
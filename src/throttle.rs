@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+
+// Failed logins make later attempts from the same address and against the same account wait.
+// Counts live in memory and are saved to the database periodically, so a restart doesn't
+// wipe them.
+
+// Failures allowed before any delay, then the delay doubles with each further failure
+const FREE_FAILURES: u32 = 3;
+const BASE_DELAY_MS: u64 = 500;
+const MAX_DELAY_MS: u64 = 30_000;
+// A key is forgotten once it has gone this long without a failure
+const FORGET_AFTER_MINUTES: i64 = 15;
+
+struct Entry {
+    failures: u32,
+    last_failure: DateTime<Utc>,
+}
+
+static FAILURES: OnceLock<Mutex<HashMap<String, Entry>>> = OnceLock::new();
+
+fn failures() -> &'static Mutex<HashMap<String, Entry>> {
+    FAILURES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+pub fn account_key(username: &str) -> String {
+    format!("user:{}", username.trim().to_lowercase())
+}
+
+fn is_stale(entry: &Entry, now: DateTime<Utc>) -> bool {
+    now - entry.last_failure > chrono::Duration::minutes(FORGET_AFTER_MINUTES)
+}
+
+fn backoff(failures: u32) -> Duration {
+    if failures < FREE_FAILURES {
+        return Duration::ZERO;
+    }
+    let doublings = (failures - FREE_FAILURES).min(16);
+    Duration::from_millis((BASE_DELAY_MS << doublings).min(MAX_DELAY_MS))
+}
+
+// How long until another login may be tried: the longest wait still owed by any of the keys,
+// counted from each key's latest failure
+pub fn retry_after(keys: &[String]) -> Option<Duration> {
+    let now = crate::clock::now();
+    let failures = failures().lock().unwrap();
+    keys.iter()
+        .filter_map(|key| failures.get(key))
+        .filter(|entry| !is_stale(entry, now))
+        .filter_map(|entry| {
+            let until = entry.last_failure + chrono::Duration::from_std(backoff(entry.failures)).ok()?;
+            (until - now).to_std().ok().filter(|wait| !wait.is_zero())
+        })
+        .max()
+}
+
+pub fn record_failure(keys: &[String]) {
+    let now = crate::clock::now();
+    let mut failures = failures().lock().unwrap();
+    for key in keys {
+        let entry = failures.entry(key.clone()).or_insert(Entry { failures: 0, last_failure: now });
+        if is_stale(entry, now) {
+            entry.failures = 0;
+        }
+        entry.failures += 1;
+        entry.last_failure = now;
+    }
+}
+
+pub fn clear(key: &str) {
+    failures().lock().unwrap().remove(key);
+}
+
+// Takes back one failure counted for an attempt that turned out to succeed
+pub fn forgive(key: &str) {
+    let mut failures = failures().lock().unwrap();
+    if let Some(entry) = failures.get_mut(key) {
+        entry.failures = entry.failures.saturating_sub(1);
+        if entry.failures == 0 {
+            failures.remove(key);
+        }
+    }
+}
+
+// Drops forgotten keys and returns the rest as (key, failures, last failure) for saving
+pub fn snapshot() -> Vec<(String, i64, String)> {
+    let now = crate::clock::now();
+    let mut failures = failures().lock().unwrap();
+    failures.retain(|_, entry| !is_stale(entry, now));
+    failures
+        .iter()
+        .map(|(key, entry)| (key.clone(), entry.failures as i64, entry.last_failure.to_rfc3339()))
+        .collect()
+}
+
+pub fn restore(saved: Vec<(String, i64, String)>) {
+    let mut failures = failures().lock().unwrap();
+    for (key, count, last_failure) in saved {
+        if let Ok(last_failure) = DateTime::parse_from_rfc3339(&last_failure) {
+            failures.insert(key, Entry { failures: count.max(0) as u32, last_failure: last_failure.with_timezone(&Utc) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::clock::{self, ManualClock};
+
+    #[tokio::test]
+    async fn backs_off_after_the_free_failures() {
+        let manual = ManualClock::starting_at(Utc::now());
+        clock::scope(Arc::new(manual.clone()), async {
+            let keys = vec![account_key("throttle-test")];
+            for _ in 0..FREE_FAILURES - 1 {
+                record_failure(&keys);
+            }
+            assert_eq!(retry_after(&keys), None);
+
+            record_failure(&keys);
+            assert_eq!(retry_after(&keys), Some(Duration::from_millis(BASE_DELAY_MS)));
+            record_failure(&keys);
+            manual.advance(chrono::Duration::milliseconds(BASE_DELAY_MS as i64));
+            assert_eq!(retry_after(&keys), Some(Duration::from_millis(BASE_DELAY_MS)));
+            manual.advance(chrono::Duration::milliseconds(BASE_DELAY_MS as i64));
+            assert_eq!(retry_after(&keys), None);
+
+            forgive(&keys[0]);
+            forgive(&keys[0]);
+            record_failure(&keys);
+            assert_eq!(retry_after(&keys), Some(Duration::from_millis(BASE_DELAY_MS)));
+
+            manual.advance(chrono::Duration::minutes(FORGET_AFTER_MINUTES + 1));
+            record_failure(&keys);
+            assert_eq!(retry_after(&keys), None);
+            clear(&keys[0]);
+        })
+        .await;
+    }
+}